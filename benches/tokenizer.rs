@@ -0,0 +1,15 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+fn tokenizer(c: &mut Criterion) {
+    let input = include_str!("all-packages.nix");
+    let mut group = c.benchmark_group("tokenizer");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.sample_size(30);
+    group.bench_with_input("all-packages", input, move |b, input| {
+        b.iter(|| rnix::tokenizer::Tokenizer::new(input).count())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, tokenizer);
+criterion_main!(benches);
@@ -0,0 +1,159 @@
+//! Compare this parser with `nix-instantiate --parse` on a corpus of files,
+//! reporting files which only one of them accepts, or which they parse
+//! differently as printed by `rnix::dump::nix`, along with a minimized input
+//! showing the difference.
+//!
+//! Usage: `cargo run --example differential -- [paths...]`, which defaults to
+//! `test_data/parser`. Does nothing if `nix-instantiate` isn't installed.
+
+use std::{
+    env,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// How a parser handled an input: accepted with the canonical form of the
+/// expression if it's known, or rejected with a message
+type Outcome = Result<Option<String>, String>;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    if Command::new("nix-instantiate").arg("--version").output().is_err() {
+        println!("nix-instantiate not found, skipping");
+        return Ok(());
+    }
+    let mut paths: Vec<String> = env::args().skip(1).collect();
+    if paths.is_empty() {
+        paths.push("test_data/parser".into());
+    }
+    let mut files = Vec::new();
+    for path in &paths {
+        collect(Path::new(path), &mut files)?;
+    }
+    files.sort();
+
+    let scratch = env::temp_dir().join(format!("rnix-differential-{}.nix", std::process::id()));
+    let mut divergences = 0;
+    for file in &files {
+        let source = fs::read_to_string(file)?;
+        let (ours, theirs) = (rnix_outcome(&scratch, &source), nix_outcome(&scratch, &source)?);
+        if ours.is_ok() == theirs.is_ok() {
+            if !parsed_differently(&ours, &theirs) {
+                continue;
+            }
+            divergences += 1;
+            println!("{}: parsed differently", file.display());
+            println!("  rnix: {}", describe(&ours));
+            println!("  nix:  {}", describe(&theirs));
+            let minimized = minimize(&source, |candidate| {
+                Ok(parsed_differently(
+                    &rnix_outcome(&scratch, candidate),
+                    &nix_outcome(&scratch, candidate)?,
+                ))
+            })?;
+            println!("  minimized: {:?}", minimized);
+            continue;
+        }
+        divergences += 1;
+        println!("{}:", file.display());
+        println!("  rnix: {}", describe(&ours));
+        println!("  nix:  {}", describe(&theirs));
+        // Keep the parser which accepts the input the same while minimizing
+        let minimized = minimize(&source, |candidate| {
+            Ok(rnix_outcome(&scratch, candidate).is_ok() == ours.is_ok()
+                && nix_outcome(&scratch, candidate)?.is_ok() == theirs.is_ok())
+        })?;
+        println!("  minimized: {:?}", minimized);
+    }
+    let _ = fs::remove_file(&scratch);
+
+    println!("{} files checked, {} divergences", files.len(), divergences);
+    if divergences > 0 {
+        return Err("the parsers disagree".into());
+    }
+    Ok(())
+}
+
+fn collect(path: &Path, files: &mut Vec<PathBuf>) -> Result<(), Box<dyn Error>> {
+    if path.is_dir() {
+        for entry in path.read_dir()? {
+            collect(&entry?.path(), files)?;
+        }
+    } else if path.extension().and_then(|s| s.to_str()) == Some("nix") {
+        files.push(path.to_path_buf());
+    }
+    Ok(())
+}
+
+fn describe(outcome: &Outcome) -> String {
+    match outcome {
+        Ok(Some(printed)) => format!("accepted as {}", printed),
+        Ok(None) => "accepted".into(),
+        Err(message) => format!("rejected: {}", message),
+    }
+}
+
+/// Whether both parsers accepted the input, but printed it differently
+fn parsed_differently(ours: &Outcome, theirs: &Outcome) -> bool {
+    matches!((ours, theirs), (Ok(Some(ours)), Ok(Some(theirs))) if ours != theirs)
+}
+
+fn rnix_outcome(scratch: &Path, source: &str) -> Outcome {
+    let parse = rnix::Root::parse(source);
+    match parse.errors().first() {
+        None => {
+            let base_dir = scratch.parent().and_then(Path::to_str).unwrap_or("/");
+            let home_dir = env::var("HOME").unwrap_or_default();
+            Ok(rnix::dump::nix(&parse.syntax(), base_dir, &home_dir))
+        }
+        Some(error) => Err(error.to_string()),
+    }
+}
+
+/// Run `nix-instantiate --parse` on the source. Nix resolves variables while
+/// parsing, which isn't a syntax error, so undefined variables count as
+/// accepted, though without a canonical form to compare.
+fn nix_outcome(scratch: &Path, source: &str) -> Result<Outcome, Box<dyn Error>> {
+    fs::write(scratch, source)?;
+    let output = Command::new("nix-instantiate").arg("--parse").arg(scratch).output()?;
+    if output.status.success() {
+        return Ok(Ok(Some(String::from_utf8_lossy(&output.stdout).trim_end().to_string())));
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if stderr.contains("undefined variable") {
+        return Ok(Ok(None));
+    }
+    let message = stderr.lines().find(|line| line.contains("error")).unwrap_or("").trim();
+    Ok(Err(message.to_string()))
+}
+
+/// Remove as many tokens as possible while the parsers still disagree, by
+/// trying to remove chunks of tokens of decreasing size
+fn minimize(
+    source: &str,
+    mut diverges: impl FnMut(&str) -> Result<bool, Box<dyn Error>>,
+) -> Result<String, Box<dyn Error>> {
+    let mut tokens: Vec<String> =
+        rnix::tokenize(source).into_iter().map(|(_, text)| text.to_string()).collect();
+    let mut chunk = tokens.len() / 2;
+    while chunk > 0 {
+        let mut removed = false;
+        let mut start = 0;
+        while start < tokens.len() {
+            let end = (start + chunk).min(tokens.len());
+            let candidate: String =
+                tokens[..start].iter().chain(&tokens[end..]).map(String::as_str).collect();
+            if diverges(&candidate)? {
+                tokens.drain(start..end);
+                removed = true;
+            } else {
+                start += chunk;
+            }
+        }
+        if !removed {
+            chunk /= 2;
+        }
+    }
+    Ok(tokens.concat())
+}
@@ -22,8 +22,10 @@ fn main() {
             ParseError::Unexpected(range) => range,
             ParseError::UnexpectedExtra(range) => range,
             ParseError::UnexpectedWanted(_, range, _) => range,
-            ParseError::UnexpectedDoubleBind(range) => range,
+            ParseError::UnexpectedDoubleBind(range, _) => range,
             ParseError::DuplicatedArgs(range, _) => range,
+            ParseError::IntegerOverflow(range) => range,
+            ParseError::InvalidToken(range) => range,
             err => {
                 eprintln!("error: {}", err);
                 continue;
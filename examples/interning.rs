@@ -0,0 +1,81 @@
+//! Measure how much sharing a `NodeCache` between the parses of many files
+//! saves, by counting the distinct tokens and the bytes of their text:
+//!
+//! ```text
+//! cargo run --release --example interning -- path/to/nixpkgs
+//! ```
+//!
+//! Without arguments, the files in `test_data` and `benches` are used. Every
+//! parse already shares identical tokens within a file, so most of the
+//! savings come from that. For these files, it printed:
+//!
+//! ```text
+//! 122 files, 167717 tokens
+//! separate caches: 22626 distinct tokens, 429975 bytes of text
+//! shared cache: 21822 distinct tokens, 428726 bytes of text
+//! ```
+
+use std::{
+    collections::HashSet,
+    env,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use rnix::{NodeCache, Root, SyntaxNode};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut paths: Vec<String> = env::args().skip(1).collect();
+    if paths.is_empty() {
+        paths = vec!["test_data".into(), "benches".into()];
+    }
+    let mut files = Vec::new();
+    for path in &paths {
+        collect(Path::new(path), &mut files)?;
+    }
+    let sources = files.iter().map(fs::read_to_string).collect::<Result<Vec<String>, _>>()?;
+
+    // The trees are kept alive so the addresses of their tokens stay unique
+    let separate: Vec<SyntaxNode> =
+        sources.iter().map(|source| Root::parse(source).syntax()).collect();
+    let mut cache = NodeCache::default();
+    let shared: Vec<SyntaxNode> =
+        sources.iter().map(|source| Root::parse_with_cache(source, &mut cache).syntax()).collect();
+
+    let total = separate
+        .iter()
+        .flat_map(|tree| tree.descendants_with_tokens())
+        .filter(|element| element.as_token().is_some())
+        .count();
+    println!("{} files, {} tokens", files.len(), total);
+    for (name, trees) in [("separate caches", &separate), ("shared cache", &shared)] {
+        let (tokens, bytes) = distinct_tokens(trees);
+        println!("{}: {} distinct tokens, {} bytes of text", name, tokens, bytes);
+    }
+    Ok(())
+}
+
+fn distinct_tokens(trees: &[SyntaxNode]) -> (usize, usize) {
+    let mut seen = HashSet::new();
+    let mut bytes = 0;
+    for token in trees.iter().flat_map(|tree| tree.descendants_with_tokens()) {
+        if let Some(token) = token.into_token() {
+            if seen.insert(token.green() as *const _) {
+                bytes += token.text().len();
+            }
+        }
+    }
+    (seen.len(), bytes)
+}
+
+fn collect(path: &Path, files: &mut Vec<PathBuf>) -> Result<(), Box<dyn Error>> {
+    if path.is_dir() {
+        for entry in fs::read_dir(path)? {
+            collect(&entry?.path(), files)?;
+        }
+    } else if path.extension().is_some_and(|ext| ext == "nix") {
+        files.push(path.to_path_buf());
+    }
+    Ok(())
+}
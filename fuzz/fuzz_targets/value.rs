@@ -0,0 +1,17 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use rnix::{analysis::eval_const, ast, value::Value, Root};
+use rowan::ast::AstNode;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(text) = std::str::from_utf8(data) {
+        let _ = text.parse::<Value>();
+        let root = Root::parse(text);
+        for expr in root.syntax().descendants().filter_map(ast::Expr::cast) {
+            if let Some(value) = eval_const(&expr) {
+                let _ = value.to_string().parse::<Value>();
+            }
+        }
+    }
+});
@@ -0,0 +1,137 @@
+//! Higher level analyses on top of the AST, such as extracting package metadata
+
+mod attr_index;
+mod callpackage;
+mod completion;
+mod definition;
+mod dependencies;
+mod duplicates;
+mod entry_point;
+mod eval;
+mod fetchers;
+mod folding;
+mod hover;
+mod inlay;
+mod meta;
+mod metrics;
+mod module;
+mod overlay;
+mod scope;
+mod selection;
+mod semantic;
+mod signature;
+mod usage;
+
+use rowan::ast::AstNode;
+
+use crate::ast::{self, InterpolPart};
+
+pub use attr_index::attr_index;
+pub use callpackage::{
+    callpackage_args, callpackage_calls, CallPackageArgs, CallPackageCall, Formal, UnexpectedArg,
+};
+pub use completion::{completion_context, CompletionContext, CompletionPosition};
+pub use definition::{definition, DefSite};
+pub use dependencies::{dependencies, Dependency, DEPENDENCY_ATTRS};
+pub(crate) use duplicates::{duplicate_args, duplicate_attrs};
+pub use entry_point::{entry_point, EntryPoint, EntryShape};
+pub use eval::{const_attr_name, const_attrpath_names, eval_const};
+pub(crate) use fetchers::str_content_range;
+pub use fetchers::{fetcher_calls, FetcherArg, FetcherCall, FETCHERS};
+pub use folding::{folding_ranges, FoldingKind, FoldingRange};
+pub use hover::{hover, Hover};
+pub use inlay::{inlay_hints, InlayHint, InlayHintKind};
+pub use meta::{package_meta, MetaRef, MetaText, PackageMeta};
+pub use metrics::{metrics, BindingMetrics, ExprMetrics, Metrics};
+pub use module::{module, Module};
+pub use overlay::{overlay, Overlay, OverlayAttr, OverrideCall, OverrideKind};
+pub(crate) use scope::is_global;
+pub use scope::{Binding, BindingId, BindingKind, GlobalScope, Resolution, Resolver, GLOBALS};
+pub use selection::selection_ranges;
+pub use semantic::{
+    encode_semantic_tokens, lexical_tokens, semantic_tokens, SemanticToken, SemanticTokenKind,
+    SemanticTokensCache, SemanticTokensEdit,
+};
+pub use signature::{signature_help, ParamInfo, SignatureHelp};
+pub use usage::{binding_graph, BindingGraph};
+
+/// Return the name of an attribute if it is known statically, i.e. if it is
+/// an identifier or a string without interpolations.
+pub(crate) fn attr_name(attr: &ast::Attr) -> Option<String> {
+    attr.static_name()
+}
+
+/// Return the names of all attributes in an attrpath, if all of them are
+/// known statically.
+pub(crate) fn attrpath_names(attrpath: &ast::Attrpath) -> Option<Vec<String>> {
+    attrpath.static_names()
+}
+
+/// Return the full path of a binding, including the attrpaths of all bindings
+/// it is nested in. Anything between the bindings, like function calls, is
+/// looked through, so `hash` in `src = fetchurl { hash = ...; };` results in
+/// `src.hash`.
+pub(crate) fn qualified_attrpath(entry: &ast::AttrpathValue) -> Option<Vec<String>> {
+    let mut path = attrpath_names(&entry.attrpath()?)?;
+    for ancestor in entry.syntax().ancestors().skip(1).filter_map(ast::AttrpathValue::cast) {
+        let mut outer = attrpath_names(&ancestor.attrpath()?)?;
+        outer.append(&mut path);
+        path = outer;
+    }
+    Some(path)
+}
+
+/// Return the value of a string, if it doesn't contain any interpolations.
+pub(crate) fn static_str(s: &ast::Str) -> Option<String> {
+    let mut value = String::new();
+    for part in s.normalized_parts() {
+        match part {
+            InterpolPart::Literal(lit) => value.push_str(&lit),
+            InterpolPart::Interpolation(_) => return None,
+        }
+    }
+    Some(value)
+}
+
+/// Skip any parentheses around an expression.
+pub(crate) fn strip_parens(mut expr: ast::Expr) -> ast::Expr {
+    while let ast::Expr::Paren(paren) = &expr {
+        match paren.expr() {
+            Some(inner) => expr = inner,
+            None => break,
+        }
+    }
+    expr
+}
+
+/// Return the attribute path referenced by an expression such as `a` or
+/// `a.b.c`. Selects with an `or` default are not considered a plain reference.
+pub(crate) fn reference_path(expr: &ast::Expr) -> Option<Vec<String>> {
+    match expr {
+        ast::Expr::Ident(ident) => Some(vec![ident.ident_token()?.text().to_string()]),
+        ast::Expr::Select(select) if select.or_token().is_none() => {
+            let mut path = reference_path(&select.expr()?)?;
+            path.extend(attrpath_names(&select.attrpath()?)?);
+            Some(path)
+        }
+        ast::Expr::Paren(paren) => reference_path(&paren.expr()?),
+        _ => None,
+    }
+}
+
+/// The name of the function being called in an application spine, e.g.
+/// `mkDerivation` in `stdenv.mkDerivation { ... }`.
+pub(crate) fn callee_name(apply: &ast::Apply) -> Option<String> {
+    let mut lambda = apply.lambda()?;
+    while let ast::Expr::Apply(inner) = lambda {
+        lambda = inner.lambda()?;
+    }
+    reference_path(&lambda)?.pop()
+}
+
+/// Iterate over all descendants of `node` (including itself) of the given type.
+pub(crate) fn descendants<N: AstNode<Language = crate::NixLanguage>>(
+    node: &crate::SyntaxNode,
+) -> impl Iterator<Item = N> {
+    node.descendants().filter_map(N::cast)
+}
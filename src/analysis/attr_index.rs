@@ -0,0 +1,127 @@
+use std::collections::BTreeMap;
+
+use rowan::ast::AstNode;
+
+use crate::ast::{self, HasEntry};
+
+use super::{const_attr_name, strip_parens, DefSite};
+
+/// Map the full paths of the attributes a file defines, like
+/// `["services", "nginx", "enable"]`, to the places defining them.
+///
+/// The attributes are those of the attribute set the file evaluates to, after
+/// looking through the functions, `let`, `with` and `assert` around it, and of
+/// the attribute sets nested in it. Nested and dotted definitions are merged,
+/// so `a.b = 1;` and `a = { b = 1; };` both define `a` and `a.b`, and a path
+/// defined more than once has all of its definitions in order. Attributes
+/// after an interpolation like `${x}` can't be known and are left out, unless
+/// it is a constant string like `${"x"}`, see [`const_attr_name`].
+pub fn attr_index(root: &ast::Root) -> BTreeMap<Vec<String>, Vec<DefSite>> {
+    let mut index = BTreeMap::new();
+    let mut expr = root.expr();
+    while let Some(inner) = expr.take().map(strip_parens) {
+        expr = match inner {
+            ast::Expr::Lambda(lambda) => lambda.body(),
+            ast::Expr::LetIn(let_in) => let_in.body(),
+            ast::Expr::With(with) => with.body(),
+            ast::Expr::Assert(assert) => assert.body(),
+            ast::Expr::AttrSet(set) => {
+                index_set(&set, &[], &mut index);
+                None
+            }
+            _ => None,
+        };
+    }
+    index
+}
+
+fn index_set(
+    set: &ast::AttrSet,
+    prefix: &[String],
+    index: &mut BTreeMap<Vec<String>, Vec<DefSite>>,
+) {
+    for entry in set.entries() {
+        match entry {
+            ast::Entry::AttrpathValue(entry) => {
+                let Some(attrpath) = entry.attrpath() else { continue };
+                let mut path = prefix.to_vec();
+                let mut all_static = true;
+                for attr in attrpath.attrs() {
+                    let Some(name) = const_attr_name(&attr) else {
+                        all_static = false;
+                        break;
+                    };
+                    path.push(name);
+                    index
+                        .entry(path.clone())
+                        .or_default()
+                        .push(DefSite::new(attr.syntax().clone()));
+                }
+                if let Some(ast::Expr::AttrSet(set)) = entry.value().map(strip_parens) {
+                    if all_static {
+                        index_set(&set, &path, index);
+                    }
+                }
+            }
+            ast::Entry::Inherit(inherit) => {
+                for attr in inherit.attrs() {
+                    if let Some(name) = const_attr_name(&attr) {
+                        let mut path = prefix.to_vec();
+                        path.push(name);
+                        index.entry(path).or_default().push(DefSite::new(attr.syntax().clone()));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Root;
+
+    fn index(src: &str) -> Vec<(String, Vec<usize>)> {
+        attr_index(&Root::parse(src).ok().unwrap())
+            .into_iter()
+            .map(|(path, sites)| {
+                let starts = sites.iter().map(|site| usize::from(site.range.start())).collect();
+                (path.join("."), starts)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn merged() {
+        let src = "{ config, ... }: { services.nginx.enable = true; services.nginx = { user = \"a\"; }; }";
+        let paths = |list: &[(&str, &[usize])]| -> Vec<(String, Vec<usize>)> {
+            list.iter().map(|(path, starts)| (path.to_string(), starts.to_vec())).collect()
+        };
+        assert_eq!(
+            index(src),
+            paths(&[
+                ("services", &[19, 49]),
+                ("services.nginx", &[28, 58]),
+                ("services.nginx.enable", &[34]),
+                ("services.nginx.user", &[68]),
+            ])
+        );
+    }
+
+    #[test]
+    fn inherits_and_dynamic() {
+        let src = "let x = 1; in with x; ({ inherit (x) a; \"b c\".d = 1; e.${f}.g = 2; })";
+        assert_eq!(
+            index(src).into_iter().map(|(path, _)| path).collect::<Vec<_>>(),
+            ["a", "b c", "b c.d", "e"]
+        );
+        assert_eq!(
+            index(r#"{ ${"a" + "b"}.${"c"} = 1; }"#)
+                .into_iter()
+                .map(|(path, _)| path)
+                .collect::<Vec<_>>(),
+            ["ab", "ab.c"]
+        );
+        assert!(index("[ { a = 1; } ]").is_empty());
+    }
+}
@@ -0,0 +1,188 @@
+use rowan::{ast::AstNode, TextRange};
+
+use crate::{
+    ast::{self, HasEntry},
+    SyntaxNode,
+};
+
+use super::{attr_name, callee_name, descendants, strip_parens};
+
+/// A formal parameter of a lambda pattern, like `foo ? null`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Formal {
+    pub name: String,
+    /// The range of the parameter's identifier
+    pub range: TextRange,
+    pub default: Option<ast::Expr>,
+}
+
+/// The arguments accepted by a function of the form `{ lib, stdenv, foo ? null }: ...`,
+/// as used with `callPackage`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CallPackageArgs {
+    pub lambda: ast::Lambda,
+    pub formals: Vec<Formal>,
+    /// Whether the pattern has an ellipsis, so it accepts any argument
+    pub ellipsis: bool,
+    /// The name bound with `args@{ ... }` or `{ ... }@args`
+    pub bind: Option<String>,
+}
+
+/// An argument passed to a function which doesn't accept it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnexpectedArg {
+    pub name: String,
+    /// The range of the attribute name
+    pub range: TextRange,
+}
+
+impl CallPackageArgs {
+    pub fn formal(&self, name: &str) -> Option<&Formal> {
+        self.formals.iter().find(|formal| formal.name == name)
+    }
+
+    /// Check an explicitly passed argument set, as in `callPackage ./foo.nix { bar = 1; }`,
+    /// and return all the arguments which aren't accepted.
+    pub fn unexpected_args(&self, args: &ast::AttrSet) -> Vec<UnexpectedArg> {
+        if self.ellipsis {
+            return Vec::new();
+        }
+        let attrs = args
+            .attrpath_values()
+            .filter_map(|entry| entry.attrpath()?.attrs().next())
+            .chain(args.inherits().flat_map(|inherit| inherit.attrs()));
+        attrs
+            .filter_map(|attr| {
+                let name = attr_name(&attr)?;
+                match self.formal(&name) {
+                    Some(_) => None,
+                    None => Some(UnexpectedArg { name, range: attr.syntax().text_range() }),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Return the arguments of a function with a pattern. The node may be either
+/// the root of a file or an expression.
+pub fn callpackage_args(node: &SyntaxNode) -> Option<CallPackageArgs> {
+    let expr = match ast::Root::cast(node.clone()) {
+        Some(root) => root.expr()?,
+        None => ast::Expr::cast(node.clone())?,
+    };
+    let lambda = match strip_parens(expr) {
+        ast::Expr::Lambda(lambda) => lambda,
+        _ => return None,
+    };
+    let pattern = match lambda.param()? {
+        ast::Param::Pattern(pattern) => pattern,
+        ast::Param::IdentParam(_) => return None,
+    };
+
+    let formals = pattern_formals(&pattern);
+    let ellipsis = pattern.ellipsis_token().is_some();
+    let bind = pattern
+        .pat_bind()
+        .and_then(|bind| bind.ident())
+        .and_then(|ident| Some(ident.ident_token()?.text().to_string()));
+
+    Some(CallPackageArgs { lambda, formals, ellipsis, bind })
+}
+
+pub(crate) fn pattern_formals(pattern: &ast::Pattern) -> Vec<Formal> {
+    pattern
+        .pat_entries()
+        .filter_map(|entry| {
+            let ident = entry.ident()?;
+            Some(Formal {
+                name: ident.ident_token()?.text().to_string(),
+                range: ident.syntax().text_range(),
+                default: entry.default(),
+            })
+        })
+        .collect()
+}
+
+/// A call like `callPackage ./foo.nix { bar = 1; }`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CallPackageCall {
+    pub apply: ast::Apply,
+    /// The function to call, usually a path
+    pub target: ast::Expr,
+    /// The explicitly passed arguments, if they are given as an attrset
+    pub args: Option<ast::AttrSet>,
+}
+
+impl CallPackageCall {
+    /// If the target is a lambda defined in place, check the arguments passed
+    /// to it. Other targets have to be resolved by the caller and checked with
+    /// [`CallPackageArgs::unexpected_args`].
+    pub fn unexpected_args(&self) -> Vec<UnexpectedArg> {
+        match (callpackage_args(self.target.syntax()), &self.args) {
+            (Some(formals), Some(args)) => formals.unexpected_args(args),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Find all `callPackage` and `callPackages` calls in a tree
+pub fn callpackage_calls(node: &SyntaxNode) -> Vec<CallPackageCall> {
+    descendants::<ast::Apply>(node)
+        .filter(|apply| {
+            matches!(callee_name(apply).as_deref(), Some("callPackage" | "callPackages"))
+        })
+        .filter_map(|apply| {
+            // `callPackage target args` is `(callPackage target) args`
+            match apply.lambda()? {
+                ast::Expr::Apply(inner) => {
+                    let args = match strip_parens(apply.argument()?) {
+                        ast::Expr::AttrSet(set) => Some(set),
+                        _ => None,
+                    };
+                    Some(CallPackageCall { target: inner.argument()?, args, apply })
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Root;
+
+    #[test]
+    fn formals() {
+        let src = "{ lib, stdenv, foo ? null, ... }@args: stdenv.mkDerivation { }";
+        let root = Root::parse(src).ok().unwrap();
+        let args = callpackage_args(root.syntax()).unwrap();
+        let names: Vec<_> = args.formals.iter().map(|formal| formal.name.as_str()).collect();
+        assert_eq!(names, ["lib", "stdenv", "foo"]);
+        assert_eq!(args.formal("foo").unwrap().default.as_ref().unwrap().to_string(), "null");
+        assert_eq!(&src[args.formal("lib").unwrap().range], "lib");
+        assert!(args.ellipsis);
+        assert_eq!(args.bind.as_deref(), Some("args"));
+
+        let root = Root::parse("x: x").ok().unwrap();
+        assert!(callpackage_args(root.syntax()).is_none());
+    }
+
+    #[test]
+    fn call_sites() {
+        let src = r#"{
+  a = callPackage ./a.nix { };
+  b = pkgs.callPackage ({ foo, bar ? 1 }: foo) { foo = 1; inherit baz; "qux" = 2; };
+}"#;
+        let root = Root::parse(src).ok().unwrap();
+        let calls = callpackage_calls(root.syntax());
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].target.to_string(), "./a.nix");
+        assert!(calls[0].unexpected_args().is_empty());
+
+        let unexpected = calls[1].unexpected_args();
+        let names: Vec<_> = unexpected.iter().map(|arg| arg.name.as_str()).collect();
+        assert_eq!(names, ["qux", "baz"]);
+        assert_eq!(&src[unexpected[0].range], "\"qux\"");
+    }
+}
@@ -0,0 +1,237 @@
+use rowan::{ast::AstNode, TextRange, TextSize};
+
+use crate::{ast, Root, SyntaxKind::*, SyntaxNode, SyntaxToken};
+
+use super::{attr_name, reference_path, Resolver};
+
+/// What can be written at a position
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CompletionPosition {
+    /// Any expression, like after `=` or `in`
+    Expr,
+    /// The name of a binding in an attrset or `let`
+    AttrKey,
+    /// An attribute selected from an expression, as in `a.b` or `a ? b`.
+    /// `path` is the statically known path selected from, like `["a"]`, and is
+    /// empty if it isn't known.
+    Select { path: Vec<String> },
+    /// A formal parameter of a lambda pattern
+    PatFormal,
+    /// A name after `inherit`
+    InheritName,
+    /// Nothing can be completed, e.g. inside a comment, a string or the name
+    /// of a lambda parameter
+    None,
+}
+
+/// The syntactic context at a cursor, as needed by completion engines
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompletionContext {
+    pub position: CompletionPosition,
+    /// The part of the identifier before the cursor, if the cursor is on one
+    pub prefix: String,
+    /// The range a completion should replace: the whole identifier at the
+    /// cursor, or an empty range at the cursor
+    pub range: TextRange,
+    /// The names in scope at the cursor, innermost first. Globals like
+    /// `builtins` are not included.
+    pub names: Vec<String>,
+    /// The `with` expressions the cursor is in, innermost first
+    pub withs: Vec<ast::With>,
+}
+
+/// Find out what may be written at `offset` in `source`. The source doesn't
+/// need to be valid, as it usually isn't while it is being edited.
+pub fn completion_context(source: &str, offset: TextSize) -> CompletionContext {
+    let root = Root::parse(source).syntax();
+    let left = root.token_at_offset(offset).left_biased();
+
+    let ident = left.clone().filter(|token| token.kind() == TOKEN_IDENT);
+    let (position, prefix, range, anchor) = match ident {
+        Some(token) => {
+            let node = token.parent().unwrap();
+            let start = token.text_range().start();
+            let prefix = token.text()[..usize::from(offset - start)].to_string();
+            (ident_position(&node, start), prefix, token.text_range(), node)
+        }
+        None => {
+            let prev = left.and_then(|token| {
+                if inside(&token, offset) {
+                    Some(token)
+                } else {
+                    skip_trivia(token)
+                }
+            });
+            let anchor =
+                prev.as_ref().and_then(|token| token.parent()).unwrap_or_else(|| root.clone());
+            let position = match prev {
+                Some(token) => token_position(&token, offset),
+                None => CompletionPosition::Expr,
+            };
+            (position, String::new(), TextRange::empty(offset), anchor)
+        }
+    };
+
+    let resolver = Resolver::new(&root);
+    let mut names: Vec<String> = Vec::new();
+    let mut withs = Vec::new();
+    for ancestor in anchor.ancestors() {
+        for binding in resolver.bindings().filter(|binding| binding.scope == ancestor) {
+            if !names.contains(&binding.name) {
+                names.push(binding.name.clone());
+            }
+        }
+        if let Some(with) = ast::With::cast(ancestor) {
+            let in_namespace =
+                with.namespace().is_some_and(|ns| ns.syntax().text_range().contains_range(range));
+            if !in_namespace {
+                withs.push(with);
+            }
+        }
+    }
+
+    CompletionContext { position, prefix, range, names, withs }
+}
+
+/// Whether a token contains the offset, excluding its start
+fn inside(token: &SyntaxToken, offset: TextSize) -> bool {
+    let range = token.text_range();
+    range.start() < offset && (offset < range.end() || token.kind() == TOKEN_COMMENT)
+}
+
+fn skip_trivia(token: SyntaxToken) -> Option<SyntaxToken> {
+    let mut token = Some(token);
+    while let Some(current) = token {
+        if !current.kind().is_trivia() {
+            return Some(current);
+        }
+        token = current.prev_token();
+    }
+    None
+}
+
+/// The position of an identifier at `start`, based on where it is in the tree
+fn ident_position(ident: &SyntaxNode, start: TextSize) -> CompletionPosition {
+    let parent = match ident.parent() {
+        Some(parent) => parent,
+        None => return CompletionPosition::Expr,
+    };
+    match parent.kind() {
+        NODE_ATTRPATH => match parent.parent() {
+            Some(grandparent) => attr_position(&grandparent, start),
+            None => CompletionPosition::None,
+        },
+        NODE_INHERIT => CompletionPosition::InheritName,
+        NODE_PAT_ENTRY => CompletionPosition::PatFormal,
+        NODE_PAT_BIND | NODE_IDENT_PARAM => CompletionPosition::None,
+        _ => CompletionPosition::Expr,
+    }
+}
+
+/// The position of an attribute in the attrpath of `parent`, which is either
+/// the key of a binding or selected from an expression
+fn attr_position(parent: &SyntaxNode, start: TextSize) -> CompletionPosition {
+    let expr = match parent.kind() {
+        NODE_ATTRPATH_VALUE => return CompletionPosition::AttrKey,
+        NODE_SELECT => ast::Select::cast(parent.clone()).and_then(|select| select.expr()),
+        NODE_HAS_ATTR => ast::HasAttr::cast(parent.clone()).and_then(|has_attr| has_attr.expr()),
+        _ => return CompletionPosition::None,
+    };
+    let before = parent
+        .children()
+        .find_map(ast::Attrpath::cast)
+        .into_iter()
+        .flat_map(|attrpath| attrpath.attrs())
+        .take_while(|attr| attr.syntax().text_range().end() <= start)
+        .map(|attr| attr_name(&attr));
+    let path = expr
+        .and_then(|expr| reference_path(&expr))
+        .and_then(|mut path| {
+            path.extend(before.collect::<Option<Vec<_>>>()?);
+            Some(path)
+        })
+        .unwrap_or_default();
+    CompletionPosition::Select { path }
+}
+
+/// The position after `token`, or inside it if it contains the offset
+fn token_position(token: &SyntaxToken, offset: TextSize) -> CompletionPosition {
+    let parent = match token.parent() {
+        Some(parent) => parent,
+        None => return CompletionPosition::Expr,
+    };
+    match (token.kind(), parent.kind()) {
+        (TOKEN_COMMENT, _) => CompletionPosition::None,
+        (TOKEN_STRING_START | TOKEN_STRING_CONTENT, _) => CompletionPosition::None,
+        (kind, _) if kind.is_literal() => CompletionPosition::None,
+        (T!['{'], NODE_ATTR_SET | NODE_LEGACY_LET) | (T![rec] | T![let], _) => {
+            CompletionPosition::AttrKey
+        }
+        (T![;], NODE_ATTRPATH_VALUE | NODE_INHERIT) => CompletionPosition::AttrKey,
+        (T!['{'] | T![,], NODE_PATTERN) => CompletionPosition::PatFormal,
+        (T![inherit], _) | (T![')'], NODE_INHERIT_FROM) => CompletionPosition::InheritName,
+        (T![.] | T![?], NODE_ATTRPATH) => match parent.parent() {
+            Some(grandparent) => attr_position(&grandparent, offset),
+            None => CompletionPosition::None,
+        },
+        (T![.] | T![?], _) => attr_position(&parent, offset),
+        (T![@], _) | (T!['}'], NODE_PATTERN) => CompletionPosition::None,
+        // Another name after `inherit a`
+        (TOKEN_IDENT, NODE_IDENT)
+            if parent.parent().is_some_and(|parent| parent.kind() == NODE_INHERIT) =>
+        {
+            CompletionPosition::InheritName
+        }
+        _ => CompletionPosition::Expr,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn complete(src: &str) -> CompletionContext {
+        let offset = src.find('|').unwrap();
+        let src = src.replacen('|', "", 1);
+        completion_context(&src, TextSize::from(offset as u32))
+    }
+
+    fn position(src: &str) -> CompletionPosition {
+        complete(src).position
+    }
+
+    #[test]
+    fn positions() {
+        use CompletionPosition::*;
+
+        assert_eq!(position("{ a = 1; |"), AttrKey);
+        assert_eq!(position("{ a = 1; b|"), AttrKey);
+        assert_eq!(position("let |"), AttrKey);
+        assert_eq!(position("{ a = |"), Expr);
+        assert_eq!(position("with x; |"), Expr);
+        assert_eq!(position("{ a, |"), PatFormal);
+        assert_eq!(position("{ a, b| }: 1"), PatFormal);
+        assert_eq!(position("{ inherit |"), InheritName);
+        assert_eq!(position("{ inherit (x) a |"), InheritName);
+        assert_eq!(position("# comment|"), None);
+        assert_eq!(position("\"str|\""), None);
+        assert_eq!(position("\"${|}\""), Expr);
+        assert_eq!(position("x|: x"), None);
+        assert_eq!(position("let x = 1; in x.|"), Select { path: vec!["x".into()] });
+        assert_eq!(position("a.b.c|"), Select { path: vec!["a".into(), "b".into()] });
+        assert_eq!(position("a ? |"), Select { path: vec!["a".into()] });
+    }
+
+    #[test]
+    fn scope() {
+        let ctx = complete("{ lib, pkgs }: let a = 1; in with pkgs; [ (x: lib.|) ]");
+        assert_eq!(ctx.position, CompletionPosition::Select { path: vec!["lib".into()] });
+        assert_eq!(ctx.names, ["x", "a", "lib", "pkgs"]);
+        assert_eq!(ctx.withs.len(), 1);
+
+        let ctx = complete("f (x: hel|lo");
+        assert_eq!(ctx.prefix, "hel");
+        assert_eq!(ctx.range, TextRange::new(6.into(), 11.into()));
+        assert_eq!(ctx.names, ["x"]);
+    }
+}
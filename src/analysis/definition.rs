@@ -0,0 +1,142 @@
+use rowan::{ast::AstNode, TextRange, TextSize};
+
+use crate::{ast, SyntaxKind::*, SyntaxNode};
+
+use super::{attr_name, attrpath_names, strip_parens, BindingKind, Resolver};
+
+/// The place a name is defined
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DefSite {
+    pub name: String,
+    /// The defining node, like the `Ident` of a lambda parameter or the `Attr`
+    /// of a binding
+    pub node: SyntaxNode,
+    pub range: TextRange,
+}
+
+impl DefSite {
+    pub(super) fn new(node: SyntaxNode) -> Self {
+        let name = match ast::Attr::cast(node.clone()) {
+            Some(attr) => attr_name(&attr).unwrap_or_else(|| node.to_string()),
+            None => node.to_string(),
+        };
+        Self { name, range: node.text_range(), node }
+    }
+}
+
+/// Find the definition of the name at `offset` in a tree.
+///
+/// Besides variables, this resolves attributes selected from `let` and `rec`
+/// bindings, so in `let a.b = 1; in a.b` the second `b` resolves to the first.
+/// Names at their definition resolve to themselves.
+pub fn definition(root: &SyntaxNode, offset: TextSize) -> Option<DefSite> {
+    let token = root
+        .token_at_offset(offset)
+        .find(|token| matches!(token.kind(), TOKEN_IDENT | TOKEN_OR))?;
+    let ident = ast::Ident::cast(token.parent()?)?;
+    let resolver = Resolver::new(root);
+
+    if let Some(binding) =
+        resolver.definition(&ident).or_else(|| resolver.binding_at(ident.syntax()))
+    {
+        return Some(DefSite::new(binding.node.clone()));
+    }
+
+    // An attribute in `a.b.c` or `a ? b.c`
+    let attrpath = ast::Attrpath::cast(ident.syntax().parent()?)?;
+    let parent = attrpath.syntax().parent()?;
+    let expr = match parent.kind() {
+        NODE_SELECT => ast::Select::cast(parent)?.expr()?,
+        NODE_HAS_ATTR => ast::HasAttr::cast(parent)?.expr()?,
+        _ => return None,
+    };
+    let base = match strip_parens(expr) {
+        ast::Expr::Ident(base) => base,
+        _ => return None,
+    };
+    let binding = resolver.definition(&base)?;
+    if !matches!(
+        binding.kind,
+        BindingKind::LetIn | BindingKind::LegacyLet | BindingKind::RecAttrSet
+    ) {
+        return None;
+    }
+
+    let mut path = vec![binding.name.clone()];
+    for attr in attrpath.attrs() {
+        path.push(attr_name(&attr)?);
+        if attr.syntax() == ident.syntax() {
+            break;
+        }
+    }
+    lookup(&binding.scope, &path).map(DefSite::new)
+}
+
+/// Find the attribute defining `path` in the entries of a set, descending into
+/// attrset literals.
+fn lookup(node: &SyntaxNode, path: &[String]) -> Option<SyntaxNode> {
+    let entries = node.children().filter_map(ast::AttrpathValue::cast);
+    for entry in entries {
+        let attrpath = match entry.attrpath() {
+            Some(attrpath) => attrpath,
+            None => continue,
+        };
+        let names = match attrpath_names(&attrpath) {
+            Some(names) => names,
+            None => continue,
+        };
+        if names.len() >= path.len() && names[..path.len()] == *path {
+            return Some(attrpath.attrs().nth(path.len() - 1)?.syntax().clone());
+        }
+        if path.starts_with(&names) {
+            if let Some(ast::Expr::AttrSet(set)) = entry.value().map(strip_parens) {
+                if let Some(found) = lookup(set.syntax(), &path[names.len()..]) {
+                    return Some(found);
+                }
+            }
+        }
+    }
+
+    if let [name] = path {
+        let inherits = node.children().filter_map(ast::Inherit::cast);
+        return inherits
+            .flat_map(|inherit| inherit.attrs())
+            .find(|attr| attr_name(attr).as_ref() == Some(name))
+            .map(|attr| attr.syntax().clone());
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Root;
+
+    fn def(src: &str, offset: u32) -> Option<(String, usize)> {
+        let root = Root::parse(src).ok().unwrap();
+        let site = definition(root.syntax(), offset.into())?;
+        Some((site.name, usize::from(site.range.start())))
+    }
+
+    #[test]
+    fn variables() {
+        assert_eq!(def("a: b: a", 6), Some(("a".into(), 0)));
+        assert_eq!(def("a: b: a", 7), Some(("a".into(), 0)));
+        assert_eq!(def("a: b: a", 3), Some(("b".into(), 3)));
+        assert_eq!(def("x: y", 3), None);
+        assert_eq!(def("x: 1", 3), None);
+    }
+
+    #[test]
+    fn attr_segments() {
+        let src = "let a.b = { c = 1; }; a.d = 2; in [ a.b.c a.d a.e ]";
+        assert_eq!(def(src, 40), Some(("c".into(), 12)));
+        assert_eq!(def(src, 38), Some(("b".into(), 6)));
+        assert_eq!(def(src, 44), Some(("d".into(), 24)));
+        assert_eq!(def(src, 48), None);
+        assert_eq!(
+            def("rec { inherit (x) a; b = c.a; c = { inherit a; }; }", 27),
+            Some(("a".into(), 44))
+        );
+    }
+}
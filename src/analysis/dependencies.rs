@@ -0,0 +1,125 @@
+use rowan::{ast::AstNode, TextRange};
+
+use crate::{ast, SyntaxNode};
+
+use super::{attrpath_names, callee_name, descendants, strip_parens};
+
+/// Names of the attributes recognized as lists of dependencies
+pub const DEPENDENCY_ATTRS: &[&str] = &[
+    "buildInputs",
+    "checkInputs",
+    "nativeBuildInputs",
+    "nativeCheckInputs",
+    "propagatedBuildInputs",
+    "propagatedNativeBuildInputs",
+];
+
+/// An element of a list of dependencies, like `openssl` in
+/// `buildInputs = [ openssl ];`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Dependency {
+    /// The attribute listing the dependency, one of [`DEPENDENCY_ATTRS`]
+    pub attr: String,
+    /// The element, without parentheses. Usually an identifier like `zlib`,
+    /// a selection like `python3.pkgs.requests` or a call.
+    pub expr: ast::Expr,
+    pub range: TextRange,
+    /// Whether the element is only included under a condition, through
+    /// `lib.optional`, `lib.optionals` or `if`
+    pub conditional: bool,
+}
+
+/// Find the elements of all lists of dependencies in a tree. The lists may be
+/// concatenated with `++`, wrapped in `lib.optionals` or `if`, or written
+/// under a `with`. Elements of anything else, like `old.buildInputs`, can't be
+/// known and are left out.
+pub fn dependencies(node: &SyntaxNode) -> Vec<Dependency> {
+    let mut out = Vec::new();
+    for entry in descendants::<ast::AttrpathValue>(node) {
+        let Some(names) = entry.attrpath().and_then(|attrpath| attrpath_names(&attrpath)) else {
+            continue;
+        };
+        let [attr] = &names[..] else { continue };
+        if !DEPENDENCY_ATTRS.contains(&attr.as_str()) {
+            continue;
+        }
+        if let Some(value) = entry.value() {
+            collect(attr, value, false, &mut out);
+        }
+    }
+    out
+}
+
+fn collect(attr: &str, expr: ast::Expr, conditional: bool, out: &mut Vec<Dependency>) {
+    let mut push = |expr: ast::Expr, conditional| {
+        let expr = strip_parens(expr);
+        let range = expr.syntax().text_range();
+        out.push(Dependency { attr: attr.to_string(), expr, range, conditional });
+    };
+    match strip_parens(expr) {
+        ast::Expr::List(list) => list.items().for_each(|item| push(item, conditional)),
+        ast::Expr::BinOp(op) if op.operator() == Some(ast::BinOpKind::Concat) => {
+            for side in [op.lhs(), op.rhs()].into_iter().flatten() {
+                collect(attr, side, conditional, out);
+            }
+        }
+        ast::Expr::IfElse(if_else) => {
+            for branch in [if_else.body(), if_else.else_body()].into_iter().flatten() {
+                collect(attr, branch, true, out);
+            }
+        }
+        ast::Expr::With(with) => {
+            if let Some(body) = with.body() {
+                collect(attr, body, conditional, out);
+            }
+        }
+        // `lib.optionals cond [ ... ]` and `lib.optional cond x`
+        ast::Expr::Apply(apply) => {
+            let Some(argument) = apply.argument() else { return };
+            let applied_once = matches!(apply.lambda(), Some(ast::Expr::Apply(_)));
+            match callee_name(&apply).as_deref() {
+                Some("optionals") if applied_once => collect(attr, argument, true, out),
+                Some("optional") if applied_once => push(argument, true),
+                _ => (),
+            }
+        }
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Root;
+
+    #[test]
+    fn lists() {
+        let src = r#"stdenv.mkDerivation {
+  nativeBuildInputs = [ pkg-config (python3.withPackages (ps: [ ps.six ])) ];
+  buildInputs = with pkgs; [ zlib ]
+    ++ lib.optionals stdenv.isLinux [ systemd libcap ]
+    ++ lib.optional withSsl openssl
+    ++ (if stdenv.isDarwin then [ darwin.Security ] else [ ])
+    ++ old.buildInputs;
+  passthru.buildInputs = [ ignored ];
+  propagatedBuildInputs = [ python3.pkgs.requests ];
+}"#;
+        let root = Root::parse(src).ok().unwrap();
+        let deps = dependencies(root.syntax());
+        let deps: Vec<_> =
+            deps.iter().map(|dep| (dep.attr.as_str(), &src[dep.range], dep.conditional)).collect();
+        assert_eq!(
+            deps,
+            [
+                ("nativeBuildInputs", "pkg-config", false),
+                ("nativeBuildInputs", "python3.withPackages (ps: [ ps.six ])", false),
+                ("buildInputs", "zlib", false),
+                ("buildInputs", "systemd", true),
+                ("buildInputs", "libcap", true),
+                ("buildInputs", "openssl", true),
+                ("buildInputs", "darwin.Security", true),
+                ("propagatedBuildInputs", "python3.pkgs.requests", false),
+            ]
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::{HashMap, HashSet};
+
+use rowan::{ast::AstNode, TextRange};
+
+use crate::{ast, SyntaxKind::*, SyntaxNode};
+
+use super::{const_attr_name, const_attrpath_names, strip_parens};
+
+/// Find the attributes which are defined more than once in the same attrset
+/// or `let`, with the ranges of the later definitions and their full paths.
+/// Nix merges nested attrset literals, as in `a.b = 1; a = { c = 2; };`, and
+/// `${"a"}` defines `a`.
+pub(crate) fn duplicate_attrs(root: &SyntaxNode) -> Vec<(TextRange, Vec<String>)> {
+    let mut duplicates = Vec::new();
+    for node in root.descendants() {
+        let is_scope = matches!(node.kind(), NODE_ATTR_SET | NODE_LET_IN | NODE_LEGACY_LET);
+        // Nested attrset literals are checked as part of their parent, also
+        // in parentheses
+        let nested = node.kind() == NODE_ATTR_SET
+            && node
+                .ancestors()
+                .skip(1)
+                .find(|ancestor| ancestor.kind() != NODE_PAREN)
+                .is_some_and(|parent| parent.kind() == NODE_ATTRPATH_VALUE);
+        if is_scope && !nested {
+            check_entries(&mut duplicates, &node, &[], &mut HashMap::new());
+        }
+    }
+    duplicates
+}
+
+/// Find the arguments which are listed more than once in the same function
+/// pattern, like `x` in `x@{ x }: x`, with their ranges
+pub(crate) fn duplicate_args(root: &SyntaxNode) -> Vec<(TextRange, String)> {
+    let mut duplicates = Vec::new();
+    for pattern in root.descendants().filter_map(ast::Pattern::cast) {
+        let bind = pattern.pat_bind().and_then(|bind| bind.ident());
+        let idents = bind.into_iter().chain(pattern.pat_entries().filter_map(|e| e.ident()));
+        let mut seen = HashSet::new();
+        for ident in idents {
+            let name = match ident.ident_token() {
+                Some(token) => token.text().to_string(),
+                None => continue,
+            };
+            if !seen.insert(name.clone()) {
+                duplicates.push((ident.syntax().text_range(), name));
+            }
+        }
+    }
+    duplicates
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Def {
+    /// A value which isn't an attrset literal, or an inherited attribute
+    Leaf,
+    /// An attrset literal, which may be merged with others
+    Set,
+    /// A parent of a dotted attrpath, like `a` in `a.b = 1;`
+    Implicit,
+}
+
+fn check_entries(
+    duplicates: &mut Vec<(TextRange, Vec<String>)>,
+    node: &SyntaxNode,
+    prefix: &[String],
+    defs: &mut HashMap<Vec<String>, Def>,
+) {
+    for child in node.children() {
+        if let Some(inherit) = ast::Inherit::cast(child.clone()) {
+            for attr in inherit.attrs() {
+                if let Some(name) = const_attr_name(&attr) {
+                    let path = [prefix, &[name]].concat();
+                    define(duplicates, defs, path, Def::Leaf, attr.syntax().text_range());
+                }
+            }
+        } else if let Some(entry) = ast::AttrpathValue::cast(child) {
+            let attrpath = match entry.attrpath() {
+                Some(attrpath) => attrpath,
+                None => continue,
+            };
+            let names = match const_attrpath_names(&attrpath) {
+                Some(names) => names,
+                None => continue,
+            };
+            let path = [prefix, &names].concat();
+            let range = attrpath.syntax().text_range();
+            match entry.value().map(strip_parens) {
+                Some(ast::Expr::AttrSet(set)) => {
+                    if define(duplicates, defs, path.clone(), Def::Set, range) {
+                        check_entries(duplicates, set.syntax(), &path, defs);
+                    } else {
+                        // Still check the set on its own
+                        check_entries(duplicates, set.syntax(), &path, &mut HashMap::new());
+                    }
+                }
+                _ => {
+                    define(duplicates, defs, path, Def::Leaf, range);
+                }
+            }
+        }
+    }
+}
+
+fn define(
+    duplicates: &mut Vec<(TextRange, Vec<String>)>,
+    defs: &mut HashMap<Vec<String>, Def>,
+    path: Vec<String>,
+    def: Def,
+    range: TextRange,
+) -> bool {
+    let conflict = (1..path.len()).any(|len| defs.get(&path[..len]) == Some(&Def::Leaf))
+        || match (defs.get(&path), def) {
+            (None, _) => false,
+            (Some(Def::Leaf), _) | (Some(_), Def::Leaf) => true,
+            (Some(_), _) => false,
+        };
+    if conflict {
+        duplicates.push((range, path));
+        return false;
+    }
+
+    for len in 1..path.len() {
+        defs.entry(path[..len].to_vec()).or_insert(Def::Implicit);
+    }
+    let existing = defs.entry(path).or_insert(def);
+    if def == Def::Set {
+        *existing = Def::Set;
+    }
+    true
+}
@@ -0,0 +1,183 @@
+use rowan::ast::AstNode;
+
+use crate::{
+    ast::{self, HasEntry},
+    SyntaxNode,
+};
+
+use super::{attrpath_names, callee_name, callpackage_args, strip_parens, CallPackageArgs, Formal};
+
+/// The top-level shape of a file evaluated on its own, like `default.nix` or
+/// `shell.nix`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EntryPoint {
+    /// The arguments of the function the file evaluates to, if it has a
+    /// pattern like `{ pkgs ? import <nixpkgs> { } }: ...`
+    pub args: Option<CallPackageArgs>,
+    /// The name of the parameter of a function like `pkgs: ...`
+    pub param: Option<String>,
+    pub shape: EntryShape,
+}
+
+/// What a file evaluates to, after calling its function and looking through
+/// `let`, `with` and `assert`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EntryShape {
+    /// A derivation-like call, like `pkgs.mkShell { ... }`
+    Derivation {
+        apply: ast::Apply,
+        /// The name of the function, like `mkShell`
+        function: String,
+    },
+    /// An attribute set, like the `default.nix` of a repository with several
+    /// packages
+    AttrSet {
+        set: ast::AttrSet,
+        /// The paths of the attributes whose values are derivation-like, in
+        /// order
+        derivations: Vec<Vec<String>>,
+    },
+    /// A function returning another function, like an overlay
+    Lambda(ast::Lambda),
+    Other(ast::Expr),
+}
+
+impl EntryPoint {
+    pub fn is_function(&self) -> bool {
+        self.args.is_some() || self.param.is_some()
+    }
+
+    /// The formals without a default, which have to be passed when calling
+    /// the file. A file whose formals all have defaults can be called with
+    /// `{ }`.
+    pub fn required_args(&self) -> Vec<&Formal> {
+        match &self.args {
+            Some(args) => args.formals.iter().filter(|formal| formal.default.is_none()).collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Report the top-level shape of a file. The node may be either the root of a
+/// file or an expression.
+pub fn entry_point(node: &SyntaxNode) -> Option<EntryPoint> {
+    let mut expr = match ast::Root::cast(node.clone()) {
+        Some(root) => root.expr()?,
+        None => ast::Expr::cast(node.clone())?,
+    };
+    let mut args = None;
+    let mut param = None;
+    if let ast::Expr::Lambda(lambda) = strip_parens(expr.clone()) {
+        match lambda.param()? {
+            ast::Param::Pattern(_) => args = callpackage_args(lambda.syntax()),
+            ast::Param::IdentParam(ident) => {
+                param = Some(ident.ident()?.ident_token()?.text().to_string())
+            }
+        }
+        expr = lambda.body()?;
+    }
+
+    let shape = loop {
+        expr = match strip_parens(expr) {
+            ast::Expr::LetIn(let_in) => let_in.body()?,
+            ast::Expr::With(with) => with.body()?,
+            ast::Expr::Assert(assert) => assert.body()?,
+            ast::Expr::Apply(apply) => match derivation_function(&apply) {
+                Some(function) => break EntryShape::Derivation { apply, function },
+                None => break EntryShape::Other(ast::Expr::Apply(apply)),
+            },
+            ast::Expr::AttrSet(set) => {
+                let derivations = set
+                    .attrpath_values()
+                    .filter(|entry| match entry.value().map(strip_parens) {
+                        Some(ast::Expr::Apply(apply)) => derivation_function(&apply).is_some(),
+                        _ => false,
+                    })
+                    .filter_map(|entry| attrpath_names(&entry.attrpath()?))
+                    .collect();
+                break EntryShape::AttrSet { set, derivations };
+            }
+            ast::Expr::Lambda(lambda) => break EntryShape::Lambda(lambda),
+            other => break EntryShape::Other(other),
+        };
+    };
+    Some(EntryPoint { args, param, shape })
+}
+
+/// The name of the function called, if it looks like it returns a derivation:
+/// `derivation`, `callPackage`, and functions like `mkDerivation`, `mkShell`
+/// or `buildPythonPackage`
+fn derivation_function(apply: &ast::Apply) -> Option<String> {
+    let name = callee_name(apply)?;
+    let prefixed = |prefix: &str| {
+        name.strip_prefix(prefix)
+            .and_then(|rest| rest.chars().next())
+            .is_some_and(char::is_uppercase)
+    };
+    let derivation_like = matches!(name.as_str(), "derivation" | "callPackage" | "callPackages")
+        || prefixed("mk")
+        || prefixed("build");
+    derivation_like.then_some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Root;
+
+    fn parse(src: &str) -> EntryPoint {
+        let root = Root::parse(src).ok().unwrap();
+        entry_point(root.syntax()).unwrap()
+    }
+
+    #[test]
+    fn shell() {
+        let src = r#"{ pkgs ? import <nixpkgs> { }, withTests ? false, extra }:
+with pkgs;
+mkShell { packages = [ hello ]; }"#;
+        let entry = parse(src);
+        assert!(entry.is_function());
+        let names: Vec<_> =
+            entry.args.iter().flat_map(|args| &args.formals).map(|f| &f.name).collect();
+        assert_eq!(names, ["pkgs", "withTests", "extra"]);
+        let pkgs = entry.args.as_ref().unwrap().formal("pkgs").unwrap();
+        assert_eq!(pkgs.default.as_ref().unwrap().to_string(), "import <nixpkgs> { }");
+        let required: Vec<_> = entry.required_args().iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(required, ["extra"]);
+        match entry.shape {
+            EntryShape::Derivation { apply, function } => {
+                assert_eq!(function, "mkShell");
+                assert_eq!(apply.to_string(), "mkShell { packages = [ hello ]; }");
+            }
+            shape => panic!("unexpected shape {:?}", shape),
+        }
+    }
+
+    #[test]
+    fn shapes() {
+        let src = r#"pkgs: let lib = pkgs.lib; in {
+  hello = pkgs.callPackage ./hello.nix { };
+  tools.fmt = (pkgs.stdenv.mkDerivation { pname = "fmt"; });
+  version = "1.0";
+  shell = pkgs.mkShell { };
+}"#;
+        let entry = parse(src);
+        assert_eq!((entry.args, entry.param.as_deref()), (None, Some("pkgs")));
+        match entry.shape {
+            EntryShape::AttrSet { derivations, .. } => {
+                assert_eq!(derivations, [vec!["hello"], vec!["tools", "fmt"], vec!["shell"]]);
+            }
+            shape => panic!("unexpected shape {:?}", shape),
+        }
+
+        let entry = parse("final: prev: { }");
+        assert!(matches!(entry.shape, EntryShape::Lambda(_)));
+        let entry = parse("import ./default.nix { }");
+        assert!(!entry.is_function());
+        assert!(matches!(entry.shape, EntryShape::Other(_)));
+        let entry = parse("{ lib }: assert lib != null; derivation { }");
+        assert!(
+            matches!(entry.shape, EntryShape::Derivation { function, .. } if function == "derivation")
+        );
+    }
+}
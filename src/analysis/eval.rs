@@ -0,0 +1,192 @@
+use rowan::ast::AstNode;
+
+use crate::{
+    ast::{self, BinOpKind, InterpolPart, LiteralKind, UnaryOpKind},
+    value::{Anchor, Raw, Value},
+};
+
+use super::{static_str, strip_parens};
+
+/// Evaluate an expression which only consists of literals and arithmetic on
+/// them, like `60 * 60` or `"foo" + "bar"`. Anything else, including
+/// variables, results in `None`. Operations which would fail in Nix, like
+/// integer overflow or division by zero, result in `None` as well.
+///
+/// Values of literals keep their source text, see [`Value::raw`].
+pub fn eval_const(expr: &ast::Expr) -> Option<Value> {
+    let raw = || expr.syntax().text().to_string();
+    match expr {
+        ast::Expr::Literal(literal) => {
+            let value = match literal.kind() {
+                LiteralKind::Float(float) => Value::from(float.value().ok()?),
+                LiteralKind::Integer(integer) => Value::from(integer.value().ok()?),
+                LiteralKind::Uri(uri) => Value::Uri(uri.to_string()),
+            };
+            Some(value.with_raw(&raw()))
+        }
+        ast::Expr::Str(s) => Some(Value::from(static_str(s)?).with_raw(&raw())),
+        ast::Expr::Path(path) => {
+            let mut text = String::new();
+            for part in path.parts() {
+                match part {
+                    InterpolPart::Literal(literal) => text.push_str(&literal.to_string()),
+                    InterpolPart::Interpolation(_) => return None,
+                }
+            }
+            Some(Value::from_path(&text).with_raw(&text))
+        }
+        ast::Expr::Paren(paren) => eval_const(&paren.expr()?),
+        ast::Expr::UnaryOp(op) => match (op.operator()?, eval_const(&op.expr()?)?) {
+            (UnaryOpKind::Negate, Value::Integer(value, _)) => value.checked_neg().map(Value::from),
+            (UnaryOpKind::Negate, Value::Float(value, _)) => Some(Value::from(-value)),
+            _ => None,
+        },
+        ast::Expr::BinOp(op) => {
+            let lhs = eval_const(&op.lhs()?)?;
+            let rhs = eval_const(&op.rhs()?)?;
+            binary(op.operator()?, lhs, rhs)
+        }
+        _ => None,
+    }
+}
+
+/// The name of an attribute, if it is static or an interpolation which
+/// evaluates to a constant string with [`eval_const`], like `${"foo"}` or
+/// `"pre${"fi" + "x"}"`. Nix only counts the former as static while parsing,
+/// but all of them define the same attribute as the plain name.
+pub fn const_attr_name(attr: &ast::Attr) -> Option<String> {
+    match attr {
+        ast::Attr::Ident(_) => attr.static_name(),
+        ast::Attr::Str(s) => const_str(s),
+        ast::Attr::Dynamic(dynamic) => const_string(&dynamic.expr()?),
+    }
+}
+
+/// The names of all attributes in an attrpath, if all of them are constant,
+/// see [`const_attr_name`]
+pub fn const_attrpath_names(attrpath: &ast::Attrpath) -> Option<Vec<String>> {
+    attrpath.attrs().map(|attr| const_attr_name(&attr)).collect()
+}
+
+fn const_string(expr: &ast::Expr) -> Option<String> {
+    match strip_parens(expr.clone()) {
+        ast::Expr::Str(s) => const_str(&s),
+        expr => match eval_const(&expr)? {
+            Value::String(text, _) | Value::Uri(text) => Some(text),
+            _ => None,
+        },
+    }
+}
+
+/// The value of a string whose interpolations are constant strings
+fn const_str(s: &ast::Str) -> Option<String> {
+    let mut value = String::new();
+    for part in s.normalized_parts() {
+        match part {
+            InterpolPart::Literal(literal) => value.push_str(&literal),
+            InterpolPart::Interpolation(interpol) => {
+                value.push_str(&const_string(&interpol.expr()?)?)
+            }
+        }
+    }
+    Some(value)
+}
+
+fn binary(op: BinOpKind, lhs: Value, rhs: Value) -> Option<Value> {
+    use Value::*;
+
+    match (op, lhs, rhs) {
+        // URIs are strings
+        (op, Uri(a), rhs) => binary(op, Value::from(a), rhs),
+        (op, lhs, Uri(b)) => binary(op, lhs, Value::from(b)),
+        (BinOpKind::Add, Integer(a, _), Integer(b, _)) => a.checked_add(b).map(Value::from),
+        (BinOpKind::Sub, Integer(a, _), Integer(b, _)) => a.checked_sub(b).map(Value::from),
+        (BinOpKind::Mul, Integer(a, _), Integer(b, _)) => a.checked_mul(b).map(Value::from),
+        // Nix truncates towards zero, like Rust
+        (BinOpKind::Div, Integer(a, _), Integer(b, _)) => a.checked_div(b).map(Value::from),
+        (op, Integer(a, _), Float(b, _)) => binary(op, Value::from(a as f64), Value::from(b)),
+        (op, Float(a, _), Integer(b, _)) => binary(op, Value::from(a), Value::from(b as f64)),
+        (BinOpKind::Add, Float(a, _), Float(b, _)) => Some(Value::from(a + b)),
+        (BinOpKind::Sub, Float(a, _), Float(b, _)) => Some(Value::from(a - b)),
+        (BinOpKind::Mul, Float(a, _), Float(b, _)) => Some(Value::from(a * b)),
+        (BinOpKind::Div, Float(_, _), Float(0.0, _)) => None,
+        (BinOpKind::Div, Float(a, _), Float(b, _)) => Some(Value::from(a / b)),
+        (BinOpKind::Add, String(a, _), String(b, _)) => Some(Value::from(a + &b)),
+        // Appending to a path only results in a valid path if the string
+        // doesn't turn a store path into something else. Like literals, the
+        // result is normalized.
+        (BinOpKind::Add, Path(anchor, a, _), String(b, _)) if anchor != Anchor::Store => {
+            Path(anchor, a + &b, Raw::default()).normalize()
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Root;
+
+    fn eval(src: &str) -> Option<Value> {
+        eval_const(&Root::parse(src).ok().unwrap().expr().unwrap())
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(eval("60 * 60 * 24"), Some(Value::from(86400)));
+        assert_eq!(eval("-7 / 2"), Some(Value::from(-3)));
+        assert_eq!(eval("1 + 0.5"), Some(Value::from(1.5)));
+        assert_eq!(eval("1 / 0"), None);
+        assert_eq!(eval("9223372036854775807 + 1"), None);
+        assert_eq!(eval("(1 + x)"), None);
+    }
+
+    #[test]
+    fn strings_and_paths() {
+        assert_eq!(eval(r#""foo" + "bar\n""#), Some(Value::from("foobar\n")));
+        assert_eq!(eval(r#""${x}""#), None);
+        assert_eq!(eval("./foo + \"/bar\""), Some(Value::from_path("foo/bar")));
+        assert_eq!(eval("./foo + \"/../bar/\""), Some(Value::from_path("bar")));
+        assert_eq!(eval("./foo/${x}"), None);
+        assert_eq!(eval("https://nixos.org"), Some(Value::Uri("https://nixos.org".into())));
+        assert_eq!(eval("https://nixos.org + \"/a\""), Some(Value::from("https://nixos.org/a")));
+    }
+
+    #[test]
+    fn attr_names() {
+        let root = Root::parse(r#"{ a.${"b"}."c${"d" + "e"}".${"f${"g"}"}.${x}."${y}" = 1; }"#);
+        let attrpath = root.tree().syntax().descendants().find_map(ast::Attrpath::cast).unwrap();
+        let names: Vec<Option<String>> =
+            attrpath.attrs().map(|attr| const_attr_name(&attr)).collect();
+        assert_eq!(
+            names,
+            [Some("a".into()), Some("b".into()), Some("cde".into()), Some("fg".into()), None, None]
+        );
+        assert_eq!(const_attrpath_names(&attrpath), None);
+    }
+
+    #[test]
+    fn raw() {
+        let float = eval("1.0e3").unwrap();
+        assert_eq!(float, Value::from(1000.0));
+        assert_eq!((float.raw(), float.to_string().as_str()), (Some("1.0e3"), "1.0e3"));
+        for literal in ["0012", "''\n  a\n''", r#""a\tb""#, "./foo/../bar", "<nixpkgs>"] {
+            assert_eq!(eval(literal).unwrap().raw(), Some(literal));
+        }
+        assert_eq!(eval("(12)").unwrap().raw(), Some("12"));
+        assert_eq!(eval("-1").unwrap().raw(), None);
+        assert_eq!(eval("1 + 1").unwrap().to_string(), "2");
+    }
+
+    #[test]
+    fn malformed() {
+        // Found by fuzzing, these used to panic
+        for src in ["/9. ${3(", "/..\\${", "<>$${x\\", "/x ${{ @*\"ee", "./a/${"] {
+            let root = Root::parse(src);
+            assert!(!root.errors().is_empty(), "{:?}", src);
+            for expr in root.syntax().descendants().filter_map(ast::Expr::cast) {
+                eval_const(&expr);
+            }
+        }
+    }
+}
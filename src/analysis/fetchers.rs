@@ -0,0 +1,186 @@
+use rowan::{ast::AstNode, TextRange};
+
+use crate::{
+    ast::{self, HasEntry},
+    SyntaxKind::*,
+    SyntaxNode,
+};
+
+use super::{attrpath_names, callee_name, descendants, static_str, strip_parens};
+
+/// Names of the functions recognized as fetchers
+pub const FETCHERS: &[&str] = &[
+    "fetchCrate",
+    "fetchFromBitbucket",
+    "fetchFromGitHub",
+    "fetchFromGitLab",
+    "fetchFromGitea",
+    "fetchFromRepoOrCz",
+    "fetchFromSavannah",
+    "fetchFromSourcehut",
+    "fetchGit",
+    "fetchPypi",
+    "fetchTarball",
+    "fetchgit",
+    "fetchhg",
+    "fetchpatch",
+    "fetchpatch2",
+    "fetchsvn",
+    "fetchurl",
+    "fetchzip",
+];
+
+/// An argument passed to a fetcher, like `sha256 = "...";`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FetcherArg {
+    pub name: String,
+    /// The decoded value, if it is a string without interpolations
+    pub value: Option<String>,
+    /// The range of the value expression, including quotes for strings
+    pub range: TextRange,
+    /// For strings without interpolations, the range of the string contents
+    /// between the quotes. Replacing this range keeps the quoting intact.
+    pub content_range: Option<TextRange>,
+}
+
+/// A call to one of the [`FETCHERS`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FetcherCall {
+    /// The name of the fetcher, e.g. `fetchFromGitHub`
+    pub fetcher: String,
+    pub apply: ast::Apply,
+    /// All arguments with a static, single-segment name. A plain string
+    /// argument, as in `builtins.fetchTarball "https://..."`, is named `url`.
+    pub args: Vec<FetcherArg>,
+}
+
+impl FetcherCall {
+    /// Get an argument by name
+    pub fn arg(&self, name: &str) -> Option<&FetcherArg> {
+        self.args.iter().find(|arg| arg.name == name)
+    }
+
+    pub fn url(&self) -> Option<&FetcherArg> {
+        self.arg("url")
+    }
+    pub fn owner(&self) -> Option<&FetcherArg> {
+        self.arg("owner")
+    }
+    pub fn repo(&self) -> Option<&FetcherArg> {
+        self.arg("repo")
+    }
+    pub fn rev(&self) -> Option<&FetcherArg> {
+        self.arg("rev")
+    }
+    /// The hash of the fetched source, which is either `hash` (SRI) or any of
+    /// the legacy `sha256`/`sha512`/`sha1` arguments.
+    pub fn hash(&self) -> Option<&FetcherArg> {
+        ["hash", "sha256", "sha512", "sha1"].iter().find_map(|name| self.arg(name))
+    }
+}
+
+/// Find all fetcher calls in a tree
+pub fn fetcher_calls(node: &SyntaxNode) -> Vec<FetcherCall> {
+    descendants::<ast::Apply>(node).filter_map(fetcher_call).collect()
+}
+
+fn fetcher_call(apply: ast::Apply) -> Option<FetcherCall> {
+    let fetcher = callee_name(&apply)?;
+    if !FETCHERS.contains(&fetcher.as_str()) {
+        return None;
+    }
+
+    let args = match strip_parens(apply.argument()?) {
+        ast::Expr::AttrSet(set) => set
+            .attrpath_values()
+            .filter_map(|entry| {
+                let names = attrpath_names(&entry.attrpath()?)?;
+                match <[String; 1]>::try_from(names) {
+                    Ok([name]) => Some(arg(name, entry.value()?)),
+                    Err(_) => None,
+                }
+            })
+            .collect(),
+        value @ ast::Expr::Str(_) => vec![arg("url".to_string(), value)],
+        _ => return None,
+    };
+
+    Some(FetcherCall { fetcher, apply, args })
+}
+
+fn arg(name: String, value: ast::Expr) -> FetcherArg {
+    let range = value.syntax().text_range();
+    match strip_parens(value) {
+        ast::Expr::Str(s) => {
+            let value = static_str(&s);
+            let content_range = value.as_ref().and_then(|_| str_content_range(&s));
+            FetcherArg { name, value, range, content_range }
+        }
+        _ => FetcherArg { name, value: None, range, content_range: None },
+    }
+}
+
+/// The range between the opening and closing quotes of a string
+pub(crate) fn str_content_range(s: &ast::Str) -> Option<TextRange> {
+    let mut tokens = s.syntax().children_with_tokens().filter_map(|it| it.into_token());
+    let start = tokens.find(|t| t.kind() == TOKEN_STRING_START)?;
+    let end = tokens.find(|t| t.kind() == TOKEN_STRING_END)?;
+    Some(TextRange::new(start.text_range().end(), end.text_range().start()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Root;
+
+    #[test]
+    fn fetch_from_github() {
+        let src = r#"
+{
+  src = fetchFromGitHub {
+    owner = "nix-community";
+    repo = "rnix-parser";
+    rev = "v${version}";
+    hash = "sha256-AAAA=";
+  };
+}
+"#;
+        let root = Root::parse(src).ok().unwrap();
+        let calls = fetcher_calls(root.syntax());
+        assert_eq!(calls.len(), 1);
+        let call = &calls[0];
+        assert_eq!(call.fetcher, "fetchFromGitHub");
+        assert_eq!(call.owner().unwrap().value.as_deref(), Some("nix-community"));
+        assert_eq!(call.repo().unwrap().value.as_deref(), Some("rnix-parser"));
+
+        let rev = call.rev().unwrap();
+        assert_eq!(rev.value, None);
+        assert_eq!(&src[rev.range], "\"v${version}\"");
+        assert_eq!(rev.content_range, None);
+
+        let hash = call.hash().unwrap();
+        assert_eq!(&src[hash.range], "\"sha256-AAAA=\"");
+        assert_eq!(&src[hash.content_range.unwrap()], "sha256-AAAA=");
+    }
+
+    #[test]
+    fn fetchurl_and_builtins() {
+        let src = r#"[
+  (pkgs.fetchurl { url = "https://example.org/a.tar.gz"; sha256 = ''0abc''; })
+  (builtins.fetchTarball "https://example.org/b.tar.gz")
+  (notAFetcher { url = "x"; })
+]"#;
+        let root = Root::parse(src).ok().unwrap();
+        let calls = fetcher_calls(root.syntax());
+        assert_eq!(calls.len(), 2);
+
+        assert_eq!(calls[0].fetcher, "fetchurl");
+        assert_eq!(calls[0].url().unwrap().value.as_deref(), Some("https://example.org/a.tar.gz"));
+        let hash = calls[0].hash().unwrap();
+        assert_eq!(hash.name, "sha256");
+        assert_eq!(&src[hash.content_range.unwrap()], "0abc");
+
+        assert_eq!(calls[1].fetcher, "fetchTarball");
+        assert_eq!(calls[1].url().unwrap().value.as_deref(), Some("https://example.org/b.tar.gz"));
+    }
+}
@@ -0,0 +1,186 @@
+use rowan::{TextRange, TextSize};
+
+use crate::{line_index::LineIndex, tokenizer::tokenize, NodeOrToken, SyntaxKind::*, SyntaxNode};
+
+/// What a folding range contains
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FoldingKind {
+    /// A block comment, or consecutive line comments
+    Comment,
+    /// A multiline expression like an attribute set or a string
+    Region,
+}
+
+/// A part of the source which can be folded in an editor
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FoldingRange {
+    pub range: TextRange,
+    pub kind: FoldingKind,
+}
+
+/// Find all ranges spanning multiple lines which can be folded, sorted by
+/// position.
+///
+/// In trees with syntax errors, brackets and strings which aren't nodes of
+/// their own are matched up by their tokens instead, so the ranges don't go
+/// away while the source is being edited.
+pub fn folding_ranges(root: &SyntaxNode) -> Vec<FoldingRange> {
+    let text = root.text().to_string();
+    let index = LineIndex::new(&text);
+    let multiline = |range: TextRange| index.line(range.start()) < index.line(range.end());
+    let mut ranges = Vec::new();
+    // The range of the line comments on consecutive lines before the current
+    // token, and the line of the last one
+    let mut comments: Option<(TextRange, u32)> = None;
+
+    for element in root.descendants_with_tokens() {
+        let token = match element {
+            NodeOrToken::Node(node) => {
+                if matches!(
+                    node.kind(),
+                    NODE_ATTR_SET
+                        | NODE_LEGACY_LET
+                        | NODE_LET_IN
+                        | NODE_LIST
+                        | NODE_PAREN
+                        | NODE_PATTERN
+                        | NODE_STRING
+                ) && multiline(node.text_range())
+                {
+                    ranges
+                        .push(FoldingRange { range: node.text_range(), kind: FoldingKind::Region });
+                }
+                continue;
+            }
+            NodeOrToken::Token(token) => token,
+        };
+        match token.kind() {
+            TOKEN_COMMENT if token.text().starts_with('#') => {
+                let line = index.line(token.text_range().start());
+                comments = match comments {
+                    Some((range, last)) if last + 1 == line => {
+                        Some((range.cover(token.text_range()), line))
+                    }
+                    previous => {
+                        ranges.extend(comment_range(previous, multiline));
+                        Some((token.text_range(), line))
+                    }
+                };
+            }
+            TOKEN_COMMENT if multiline(token.text_range()) => {
+                ranges.push(FoldingRange { range: token.text_range(), kind: FoldingKind::Comment });
+            }
+            TOKEN_WHITESPACE => (),
+            _ => ranges.extend(comment_range(comments.take(), multiline)),
+        }
+    }
+    ranges.extend(comment_range(comments, multiline));
+    if root
+        .descendants_with_tokens()
+        .any(|element| matches!(element.kind(), NODE_ERROR | TOKEN_ERROR))
+    {
+        let start = root.text_range().start();
+        let mut regions = lexical_regions(&text);
+        regions.sort_by_key(|range| (range.start(), std::cmp::Reverse(range.end())));
+        for range in regions {
+            let range = range + start;
+            // Nodes cut short by an error, like an unclosed string, are
+            // extended to the end of their brackets
+            if let Some(node) = ranges.iter_mut().find(|other| other.range.start() == range.start())
+            {
+                node.range = node.range.cover(range);
+                continue;
+            }
+            // Keep the ranges nested
+            let fits = |other: &FoldingRange| {
+                other.range.contains_range(range)
+                    || range.contains_range(other.range)
+                    || other.range.intersect(range).is_none_or(|common| common.is_empty())
+            };
+            if multiline(range - start) && ranges.iter().all(fits) {
+                ranges.push(FoldingRange { range, kind: FoldingKind::Region });
+            }
+        }
+    }
+    ranges.sort_by_key(|folding| (folding.range.start(), std::cmp::Reverse(folding.range.end())));
+    ranges
+}
+
+fn comment_range(
+    comments: Option<(TextRange, u32)>,
+    multiline: impl Fn(TextRange) -> bool,
+) -> Option<FoldingRange> {
+    let (range, _) = comments?;
+    multiline(range).then_some(FoldingRange { range, kind: FoldingKind::Comment })
+}
+
+/// The ranges between matching brackets and string delimiters, found by
+/// the tokenizer alone. Unclosed ones run to the last token.
+fn lexical_regions(source: &str) -> Vec<TextRange> {
+    let mut regions = Vec::new();
+    let mut open = Vec::new();
+    let mut offset = TextSize::from(0);
+    let mut end = offset;
+    for (kind, text) in tokenize(source) {
+        let range = TextRange::at(offset, TextSize::of(text));
+        offset = range.end();
+        if kind != TOKEN_WHITESPACE {
+            end = range.end();
+        }
+        let closes = match kind {
+            TOKEN_L_BRACE | TOKEN_L_BRACK | TOKEN_L_PAREN | TOKEN_INTERPOL_START
+            | TOKEN_STRING_START => {
+                open.push((kind, range.start()));
+                continue;
+            }
+            TOKEN_R_BRACE => TOKEN_L_BRACE,
+            TOKEN_R_BRACK => TOKEN_L_BRACK,
+            TOKEN_R_PAREN => TOKEN_L_PAREN,
+            TOKEN_INTERPOL_END => TOKEN_INTERPOL_START,
+            TOKEN_STRING_END => TOKEN_STRING_START,
+            _ => continue,
+        };
+        if let Some(&(opening, start)) = open.last() {
+            if opening == closes {
+                open.pop();
+                regions.push(TextRange::new(start, range.end()));
+            }
+        }
+    }
+    regions.extend(open.into_iter().map(|(_, start)| TextRange::new(start, end)));
+    regions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Root;
+
+    #[test]
+    fn ranges() {
+        let src = "# a\n# b\n{\n  x = [ 1 ];\n  /* c\n  */\n  y = ''\n  '';\n}\n# d";
+        let root = Root::parse(src).syntax();
+        let ranges: Vec<_> = folding_ranges(&root)
+            .into_iter()
+            .map(|folding| (&src[folding.range], folding.kind))
+            .collect();
+        assert_eq!(
+            ranges,
+            [
+                ("# a\n# b", FoldingKind::Comment),
+                (&src[8..src.len() - 4], FoldingKind::Region),
+                ("/* c\n  */", FoldingKind::Comment),
+                ("''\n  ''", FoldingKind::Region),
+            ]
+        );
+    }
+
+    #[test]
+    fn broken() {
+        let src = "{\n  a = [\n    1\n  ) ];\n  b = ''\n    x\n";
+        let root = Root::parse(src).syntax();
+        let ranges: Vec<_> =
+            folding_ranges(&root).into_iter().map(|folding| &src[folding.range]).collect();
+        assert_eq!(ranges, [src, "[\n    1\n  ) ]", &src[src.find("''").unwrap()..]]);
+    }
+}
@@ -0,0 +1,151 @@
+use rowan::{ast::AstNode, TextRange, TextSize};
+
+use crate::{ast, value::Value, SyntaxKind, SyntaxKind::*, SyntaxNode};
+
+use super::{definition, eval_const, DefSite};
+
+/// A summary of the expression under the cursor
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hover {
+    /// The kind of the innermost expression at the offset
+    pub kind: SyntaxKind,
+    pub range: TextRange,
+    /// Where the name at the offset is defined, if it is one
+    pub definition: Option<DefSite>,
+    /// The comments directly in front of the definition
+    pub doc: Option<String>,
+    /// The value of the expression, or of the binding it refers to, if it is
+    /// constant
+    pub value: Option<Value>,
+}
+
+/// Summarize the expression at `offset`, for showing it in an editor
+pub fn hover(root: &SyntaxNode, offset: TextSize) -> Option<Hover> {
+    let token = root.token_at_offset(offset).max_by_key(|token| match token.kind() {
+        TOKEN_IDENT => 2,
+        kind if kind.is_trivia() => 0,
+        _ => 1,
+    })?;
+    let expr = token.parent_ancestors().find_map(ast::Expr::cast)?;
+
+    let definition = definition(root, offset);
+    let doc = definition.as_ref().and_then(|site| doc_comment(&site.node));
+    let value = match definition.as_ref().and_then(|site| bound_value(&site.node)) {
+        Some(bound) => eval_const(&bound),
+        None => eval_const(&expr),
+    };
+
+    Some(Hover {
+        kind: expr.syntax().kind(),
+        range: expr.syntax().text_range(),
+        definition,
+        doc,
+        value,
+    })
+}
+
+/// The value of `a` in `a = ...;`
+fn bound_value(attr: &SyntaxNode) -> Option<ast::Expr> {
+    let attrpath = ast::Attrpath::cast(attr.parent()?)?;
+    if attrpath.attrs().count() != 1 {
+        return None;
+    }
+    ast::AttrpathValue::cast(attrpath.syntax().parent()?)?.value()
+}
+
+/// Collect the comments in front of the binding or parameter defining a name,
+/// without any blank lines in between
+fn doc_comment(def: &SyntaxNode) -> Option<String> {
+    let owner = def
+        .ancestors()
+        .find(|node| {
+            matches!(node.kind(), NODE_ATTRPATH_VALUE | NODE_INHERIT | NODE_PAT_ENTRY | NODE_LAMBDA)
+        })
+        .unwrap_or_else(|| def.clone());
+
+    let mut comments = Vec::new();
+    let mut token = owner.first_token()?.prev_token();
+    while let Some(current) = token {
+        match current.kind() {
+            TOKEN_WHITESPACE if current.text().matches('\n').count() > 1 => break,
+            TOKEN_WHITESPACE => (),
+            TOKEN_COMMENT => comments.push(current.text().to_string()),
+            _ => break,
+        }
+        token = current.prev_token();
+    }
+    if comments.is_empty() {
+        return None;
+    }
+
+    let lines: Vec<String> = comments
+        .iter()
+        .rev()
+        .map(|comment| match comment.strip_prefix('#') {
+            Some(line) => line.strip_prefix(' ').unwrap_or(line).to_string(),
+            None => {
+                let inner = comment.trim_start_matches("/*").trim_start_matches('*');
+                let inner = inner.strip_suffix("*/").unwrap_or(inner);
+                let lines: Vec<&str> = inner
+                    .trim()
+                    .lines()
+                    .map(|line| {
+                        let line = line.trim_start();
+                        line.strip_prefix("* ").or_else(|| line.strip_prefix('*')).unwrap_or(line)
+                    })
+                    .collect();
+                lines.join("\n")
+            }
+        })
+        .collect();
+    Some(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Root;
+
+    fn hover_at(src: &str) -> Hover {
+        let offset = src.find('|').unwrap();
+        let src = src.replacen('|', "", 1);
+        let root = Root::parse(&src).syntax();
+        hover(&root, TextSize::from(offset as u32)).unwrap()
+    }
+
+    #[test]
+    fn bindings() {
+        let src = "let
+  # Seconds per day.
+  # Used for caching.
+  day = 60 * 60 * 24;
+
+  /**
+   * Not a doc comment of `x`
+   */
+
+  x = 1;
+in d|ay + x";
+        let hover = hover_at(src);
+        assert_eq!(hover.kind, NODE_IDENT);
+        assert_eq!(hover.doc.as_deref(), Some("Seconds per day.\nUsed for caching."));
+        assert_eq!(hover.value, Some(Value::from(86400)));
+        assert_eq!(hover.definition.unwrap().name, "day");
+
+        let hover = hover_at("let /** The answer */ x = 42; in |x");
+        assert_eq!(hover.doc.as_deref(), Some("The answer"));
+        assert_eq!(hover.value, Some(Value::from(42)));
+    }
+
+    #[test]
+    fn expressions() {
+        let hover = hover_at("f (\"a\" |+ \"b\")");
+        assert_eq!(hover.kind, NODE_BIN_OP);
+        assert_eq!(hover.value, Some(Value::from("ab")));
+        assert_eq!(hover.definition, None);
+
+        let hover = hover_at("{ x ? 1 }: |x");
+        assert_eq!(hover.value, None);
+        assert_eq!(hover.definition.unwrap().range, TextRange::new(2.into(), 3.into()));
+    }
+}
@@ -0,0 +1,169 @@
+use rowan::{ast::AstNode, TextRange};
+
+use crate::{
+    ast,
+    value::{Anchor, Value},
+    SyntaxNode,
+};
+
+use super::{
+    attr_name, descendants, eval_const,
+    signature::{curried_params, local_lambda},
+    strip_parens, ParamInfo, Resolver,
+};
+
+/// What an inlay hint shows
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum InlayHintKind {
+    /// The name of the parameter an argument is passed to, like `a:` in
+    /// `f a: 1`
+    ParameterName,
+    /// A formal with a default which isn't passed, like `b ? 1` in
+    /// `f { a = 1; }` for `f = { a, b ? 1 }: ...`
+    FormalDefault,
+    /// What a search path lookup like `<nixpkgs>` resolves to
+    SearchPath,
+}
+
+/// A label to show in the source text
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct InlayHint {
+    /// The range the hint refers to. The hint is shown before the range for
+    /// parameter names, and after it otherwise.
+    pub range: TextRange,
+    pub label: String,
+    pub kind: InlayHintKind,
+}
+
+/// Collect inlay hints for a tree.
+///
+/// Hints for arguments are only created for functions defined in the same
+/// tree. Search path lookups are passed to `resolve_search_path`, e.g. with
+/// `nixpkgs/lib` for `<nixpkgs/lib>`, which may return the path to show.
+pub fn inlay_hints(
+    root: &SyntaxNode,
+    mut resolve_search_path: impl FnMut(&str) -> Option<String>,
+) -> Vec<InlayHint> {
+    let resolver = Resolver::new(root);
+    let mut hints = Vec::new();
+
+    for apply in descendants::<ast::Apply>(root) {
+        // Only look at the outermost application of a call
+        if apply.syntax().parent().and_then(ast::Apply::cast).is_some() {
+            continue;
+        }
+        let mut args = Vec::new();
+        let mut callee = ast::Expr::Apply(apply);
+        while let ast::Expr::Apply(inner) = &callee {
+            match (inner.argument(), inner.lambda()) {
+                (Some(arg), Some(lambda)) => {
+                    args.push(arg);
+                    callee = lambda;
+                }
+                _ => break,
+            }
+        }
+        args.reverse();
+        let params = match local_lambda(&resolver, &callee) {
+            Some(lambda) => curried_params(&lambda),
+            None => continue,
+        };
+        for (param, arg) in params.iter().zip(&args) {
+            argument_hints(param, arg, &mut hints);
+        }
+    }
+
+    for path in descendants::<ast::Path>(root) {
+        let lookup = match eval_const(&ast::Expr::Path(path.clone())) {
+            Some(Value::Path(Anchor::Store, lookup, _)) => lookup,
+            _ => continue,
+        };
+        if let Some(label) = resolve_search_path(&lookup) {
+            hints.push(InlayHint {
+                range: path.syntax().text_range(),
+                label,
+                kind: InlayHintKind::SearchPath,
+            });
+        }
+    }
+
+    hints.sort_by_key(|hint| (hint.range.start(), hint.range.end()));
+    hints
+}
+
+fn argument_hints(param: &ParamInfo, arg: &ast::Expr, hints: &mut Vec<InlayHint>) {
+    match param {
+        ParamInfo::Ident(name) => {
+            // `f x` with a parameter named `x` needs no hint
+            if matches!(arg, ast::Expr::Ident(ident) if ident.to_string() == *name) {
+                return;
+            }
+            hints.push(InlayHint {
+                range: arg.syntax().text_range(),
+                label: format!("{}:", name),
+                kind: InlayHintKind::ParameterName,
+            });
+        }
+        ParamInfo::Pattern { formals, .. } => {
+            let set = match strip_parens(arg.clone()) {
+                ast::Expr::AttrSet(set) => set,
+                _ => return,
+            };
+            let passed: Vec<String> = set
+                .syntax()
+                .children()
+                .filter_map(|child| match ast::Entry::cast(child)? {
+                    ast::Entry::AttrpathValue(entry) => {
+                        Some(vec![attr_name(&entry.attrpath()?.attrs().next()?)?])
+                    }
+                    ast::Entry::Inherit(inherit) => {
+                        Some(inherit.attrs().filter_map(|attr| attr_name(&attr)).collect())
+                    }
+                })
+                .flatten()
+                .collect();
+            let close = match set.r_curly_token() {
+                Some(token) => token.text_range(),
+                None => return,
+            };
+            for formal in formals {
+                let default = match &formal.default {
+                    Some(default) if !passed.contains(&formal.name) => default,
+                    _ => continue,
+                };
+                hints.push(InlayHint {
+                    range: TextRange::empty(close.start()),
+                    label: format!("{} ? {}", formal.name, default.syntax()),
+                    kind: InlayHintKind::FormalDefault,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Root;
+
+    #[test]
+    fn hints() {
+        let src = "let
+  f = a: { b, c ? 1, d ? \"x\" }: null;
+in f 1 { b = 2; inherit d; } + f a { } + import <nixpkgs/lib>";
+        let root = Root::parse(src).syntax();
+        let hints = inlay_hints(&root, |lookup| Some(format!("/nix/store/{}", lookup)));
+        let hints: Vec<_> =
+            hints.iter().map(|hint| (&src[hint.range], hint.label.as_str(), hint.kind)).collect();
+        assert_eq!(
+            hints,
+            [
+                ("1", "a:", InlayHintKind::ParameterName),
+                ("", "c ? 1", InlayHintKind::FormalDefault),
+                ("", "c ? 1", InlayHintKind::FormalDefault),
+                ("", "d ? \"x\"", InlayHintKind::FormalDefault),
+                ("<nixpkgs/lib>", "/nix/store/nixpkgs/lib", InlayHintKind::SearchPath),
+            ]
+        );
+    }
+}
@@ -0,0 +1,292 @@
+use rowan::{ast::AstNode, TextRange};
+
+use crate::{
+    ast::{self, BinOpKind, HasEntry},
+    SyntaxKind::*,
+    SyntaxNode,
+};
+
+use super::{attrpath_names, descendants, reference_path, static_str, strip_parens};
+
+/// A string field of a `meta` attrset, like `description` or `homepage`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MetaText {
+    /// The decoded string, if it doesn't contain any interpolations
+    pub value: Option<String>,
+    /// The range of the value expression
+    pub range: TextRange,
+}
+
+/// A reference to an attribute such as `licenses.mit` or `maintainers.foo`.
+///
+/// Namespaces brought into scope with `with` are prepended to single
+/// identifiers, and any leading `lib` (or `stdenv.lib`, `pkgs.lib`) is
+/// stripped, so `lib.licenses.mit`, `with lib; licenses.mit` and
+/// `with lib.licenses; mit` all result in the path `licenses.mit`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MetaRef {
+    pub path: Vec<String>,
+    /// The range of the referencing expression
+    pub range: TextRange,
+}
+
+impl MetaRef {
+    /// The path joined with dots, e.g. `licenses.mit`
+    pub fn dotted(&self) -> String {
+        self.path.join(".")
+    }
+}
+
+/// Package metadata found in the `meta` attribute of a derivation
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackageMeta {
+    /// The attrset passed to the derivation function, which contains `meta`
+    pub owner: ast::AttrSet,
+    /// The range covering all `meta` definitions of the owner. This is usually
+    /// a single `meta = { ... };`, but `meta.description = ...;` is allowed too.
+    pub range: TextRange,
+    pub description: Option<MetaText>,
+    pub homepage: Option<MetaText>,
+    pub license: Vec<MetaRef>,
+    pub maintainers: Vec<MetaRef>,
+}
+
+/// Find all `meta` attributes of derivation-like expressions in a tree.
+///
+/// An attrset counts as derivation-like if it is passed to a function (like
+/// `stdenv.mkDerivation { ... }` or `mkDerivation (finalAttrs: { ... })`), or
+/// if it has a `pname` attribute.
+pub fn package_meta(node: &SyntaxNode) -> Vec<PackageMeta> {
+    descendants::<ast::AttrSet>(node)
+        .filter(is_derivation_like)
+        .filter_map(|set| extract(&set).map(|extracted| extracted.into_meta(set)))
+        .collect()
+}
+
+fn is_derivation_like(set: &ast::AttrSet) -> bool {
+    let has_pname = set
+        .attrpath_values()
+        .filter_map(|entry| attrpath_names(&entry.attrpath()?))
+        .any(|names| names == ["pname"]);
+    if has_pname {
+        return true;
+    }
+
+    let mut node = set.syntax().clone();
+    loop {
+        let parent = match node.parent() {
+            Some(parent) => parent,
+            None => return false,
+        };
+        match parent.kind() {
+            NODE_PAREN => node = parent,
+            // finalAttrs: { ... }
+            NODE_LAMBDA => node = parent,
+            NODE_APPLY => {
+                return ast::Apply::cast(parent)
+                    .and_then(|apply| apply.argument())
+                    .is_some_and(|arg| arg.syntax() == &node)
+            }
+            _ => return false,
+        }
+    }
+}
+
+struct Extracted {
+    range: Option<TextRange>,
+    description: Option<MetaText>,
+    homepage: Option<MetaText>,
+    license: Vec<MetaRef>,
+    maintainers: Vec<MetaRef>,
+}
+
+impl Extracted {
+    fn into_meta(self, owner: ast::AttrSet) -> PackageMeta {
+        let range = self.range.unwrap_or_else(|| owner.syntax().text_range());
+        PackageMeta {
+            owner,
+            range,
+            description: self.description,
+            homepage: self.homepage,
+            license: self.license,
+            maintainers: self.maintainers,
+        }
+    }
+
+    fn field(&mut self, name: &str, value: ast::Expr, prefix: &[String]) {
+        match name {
+            "description" => self.description = Some(text(value)),
+            "homepage" => self.homepage = Some(text(value)),
+            "license" => refs(value, prefix, &mut self.license),
+            "maintainers" => refs(value, prefix, &mut self.maintainers),
+            _ => (),
+        }
+    }
+}
+
+fn extract(set: &ast::AttrSet) -> Option<Extracted> {
+    let mut extracted = Extracted {
+        range: None,
+        description: None,
+        homepage: None,
+        license: Vec::new(),
+        maintainers: Vec::new(),
+    };
+
+    for entry in set.attrpath_values() {
+        let names = match entry.attrpath().and_then(|attrpath| attrpath_names(&attrpath)) {
+            Some(names) if names.first().map(String::as_str) == Some("meta") => names,
+            _ => continue,
+        };
+        let range = entry.syntax().text_range();
+        extracted.range = Some(extracted.range.map_or(range, |r| r.cover(range)));
+
+        let value = match entry.value() {
+            Some(value) => value,
+            None => continue,
+        };
+        match &names[..] {
+            [_] => {
+                let mut prefix = Vec::new();
+                let value = unwrap_with(value, &mut prefix);
+                if let ast::Expr::AttrSet(meta) = value {
+                    for field in meta.attrpath_values() {
+                        let names = field.attrpath().and_then(|attrpath| attrpath_names(&attrpath));
+                        if let (Some([name]), Some(value)) = (names.as_deref(), field.value()) {
+                            extracted.field(name, value, &prefix);
+                        }
+                    }
+                }
+            }
+            [_, name] => extracted.field(name, value, &[]),
+            _ => (),
+        }
+    }
+
+    extracted.range.map(|_| extracted)
+}
+
+/// Skip parentheses and `with` expressions, remembering the namespace of the
+/// innermost `with` in `prefix`.
+fn unwrap_with(expr: ast::Expr, prefix: &mut Vec<String>) -> ast::Expr {
+    let mut expr = strip_parens(expr);
+    while let ast::Expr::With(with) = &expr {
+        if let Some(namespace) = with.namespace().as_ref().and_then(reference_path) {
+            *prefix = strip_lib(namespace);
+        }
+        match with.body() {
+            Some(body) => expr = strip_parens(body),
+            None => break,
+        }
+    }
+    expr
+}
+
+fn strip_lib(mut path: Vec<String>) -> Vec<String> {
+    let len = match &path[..] {
+        [first, ..] if first == "lib" => 1,
+        [first, second, ..] if (first == "stdenv" || first == "pkgs") && second == "lib" => 2,
+        _ => 0,
+    };
+    path.drain(..len);
+    path
+}
+
+fn text(value: ast::Expr) -> MetaText {
+    let value = unwrap_with(value, &mut Vec::new());
+    let range = value.syntax().text_range();
+    match value {
+        ast::Expr::Str(s) => MetaText { value: static_str(&s), range },
+        _ => MetaText { value: None, range },
+    }
+}
+
+fn refs(value: ast::Expr, prefix: &[String], out: &mut Vec<MetaRef>) {
+    let mut prefix = prefix.to_vec();
+    match unwrap_with(value, &mut prefix) {
+        ast::Expr::List(list) => {
+            for item in list.items() {
+                refs(item, &prefix, out);
+            }
+        }
+        ast::Expr::BinOp(op) if op.operator() == Some(BinOpKind::Concat) => {
+            for side in [op.lhs(), op.rhs()].into_iter().flatten() {
+                refs(side, &prefix, out);
+            }
+        }
+        value => {
+            if let Some(path) = reference_path(&value) {
+                let mut path = strip_lib(path);
+                if path.len() == 1 {
+                    path.splice(0..0, prefix);
+                }
+                out.push(MetaRef { path, range: value.syntax().text_range() });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Root;
+
+    fn dotted(refs: &[MetaRef]) -> Vec<String> {
+        refs.iter().map(MetaRef::dotted).collect()
+    }
+
+    #[test]
+    fn mk_derivation() {
+        let src = r#"
+{ lib, stdenv, fetchurl }:
+stdenv.mkDerivation rec {
+  pname = "hello";
+  version = "2.12";
+  meta = with lib; {
+    description = "A program that produces a familiar, friendly greeting";
+    homepage = "https://www.gnu.org/software/hello/manual/";
+    license = licenses.gpl3Plus;
+    maintainers = with maintainers; [ eelco ] ++ teams.gnu.members;
+  };
+}
+"#;
+        let root = Root::parse(src).ok().unwrap();
+        let metas = package_meta(root.syntax());
+        assert_eq!(metas.len(), 1);
+        let meta = &metas[0];
+        assert_eq!(
+            meta.description.as_ref().unwrap().value.as_deref(),
+            Some("A program that produces a familiar, friendly greeting")
+        );
+        let homepage = meta.homepage.as_ref().unwrap();
+        assert_eq!(&src[homepage.range], "\"https://www.gnu.org/software/hello/manual/\"");
+        assert_eq!(dotted(&meta.license), ["licenses.gpl3Plus"]);
+        assert_eq!(dotted(&meta.maintainers), ["maintainers.eelco", "teams.gnu.members"]);
+        assert!(src[meta.range].starts_with("meta = with lib;"));
+    }
+
+    #[test]
+    fn dotted_meta_and_final_attrs() {
+        let src = r#"
+stdenv.mkDerivation (finalAttrs: {
+  meta.description = "desc ${finalAttrs.version}";
+  meta.license = [ lib.licenses.mit stdenv.lib.licenses.asl20 ];
+  meta.maintainers = [ lib.maintainers.alice ];
+})
+"#;
+        let root = Root::parse(src).ok().unwrap();
+        let metas = package_meta(root.syntax());
+        assert_eq!(metas.len(), 1);
+        let meta = &metas[0];
+        assert_eq!(meta.description.as_ref().unwrap().value, None);
+        assert_eq!(dotted(&meta.license), ["licenses.mit", "licenses.asl20"]);
+        assert_eq!(dotted(&meta.maintainers), ["maintainers.alice"]);
+    }
+
+    #[test]
+    fn ignores_plain_sets() {
+        let src = "{ meta = { description = \"not a package\"; }; }";
+        let root = Root::parse(src).ok().unwrap();
+        assert!(package_meta(root.syntax()).is_empty());
+    }
+}
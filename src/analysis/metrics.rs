@@ -0,0 +1,174 @@
+use rowan::{ast::AstNode, TextRange};
+
+use crate::{
+    ast::{self, HasEntry},
+    SyntaxNode,
+};
+
+use super::strip_parens;
+
+/// Complexity measures of a single expression
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExprMetrics {
+    /// The deepest nesting of expressions, not counting parentheses. A single
+    /// literal has a depth of 1.
+    pub max_depth: usize,
+    /// The number of `with` expressions
+    pub with_count: usize,
+    /// The number of entries of each attrset and `let`, in source order
+    pub set_sizes: Vec<usize>,
+    /// The number of curried parameters of each function, in source order.
+    /// `a: b: c` is a single function of arity 2.
+    pub lambda_arities: Vec<usize>,
+    /// The number of syntax nodes, including the expression itself
+    pub node_count: usize,
+}
+
+impl ExprMetrics {
+    /// Compute the metrics of an expression, or any other node
+    pub fn of(node: &SyntaxNode) -> Self {
+        let mut metrics = Self::default();
+        metrics.visit(node, 0);
+        metrics
+    }
+
+    fn visit(&mut self, node: &SyntaxNode, depth: usize) {
+        self.node_count += 1;
+        let depth = match ast::Expr::cast(node.clone()) {
+            Some(ast::Expr::Paren(_)) | None => depth,
+            Some(expr) => {
+                self.record(&expr);
+                depth + 1
+            }
+        };
+        self.max_depth = self.max_depth.max(depth);
+        for child in node.children() {
+            self.visit(&child, depth);
+        }
+    }
+
+    fn record(&mut self, expr: &ast::Expr) {
+        match expr {
+            ast::Expr::With(_) => self.with_count += 1,
+            ast::Expr::AttrSet(set) => self.set_sizes.push(entry_count(set)),
+            ast::Expr::LetIn(let_in) => self.set_sizes.push(entry_count(let_in)),
+            ast::Expr::LegacyLet(let_) => self.set_sizes.push(entry_count(let_)),
+            ast::Expr::Lambda(lambda) => {
+                let is_curried = lambda
+                    .syntax()
+                    .parent()
+                    .and_then(ast::Lambda::cast)
+                    .and_then(|parent| parent.body())
+                    .is_some_and(|body| body.syntax() == lambda.syntax());
+                if !is_curried {
+                    self.lambda_arities.push(arity(lambda));
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Count the entries of a set, with each inherited name counting separately
+fn entry_count(set: &impl HasEntry) -> usize {
+    set.attrpath_values().count()
+        + set.inherits().map(|inherit| inherit.attrs().count()).sum::<usize>()
+}
+
+fn arity(lambda: &ast::Lambda) -> usize {
+    let mut arity = 1;
+    let mut body = lambda.body();
+    while let Some(ast::Expr::Lambda(inner)) = body {
+        arity += 1;
+        body = inner.body();
+    }
+    arity
+}
+
+/// The metrics of a single top-level binding
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BindingMetrics {
+    /// The attrpath of the binding as written, e.g. `passthru.tests`
+    pub name: String,
+    /// The range of the whole binding
+    pub range: TextRange,
+    pub metrics: ExprMetrics,
+}
+
+/// Complexity metrics of a file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Metrics {
+    pub total: ExprMetrics,
+    pub bindings: Vec<BindingMetrics>,
+}
+
+/// Compute complexity metrics for a tree, both in total and for each
+/// top-level binding.
+///
+/// The top-level bindings are the entries of the attrset the file evaluates
+/// to, looking through functions, `let` and `with`, so the bindings of
+/// `{ lib }: with lib; { a = 1; }` are just `a`.
+pub fn metrics(node: &SyntaxNode) -> Metrics {
+    let bindings = top_level_set(node)
+        .map(|set| {
+            set.attrpath_values()
+                .filter_map(|entry| {
+                    Some(BindingMetrics {
+                        name: entry.attrpath()?.syntax().to_string(),
+                        range: entry.syntax().text_range(),
+                        metrics: ExprMetrics::of(entry.value()?.syntax()),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    Metrics { total: ExprMetrics::of(node), bindings }
+}
+
+fn top_level_set(node: &SyntaxNode) -> Option<ast::AttrSet> {
+    let mut expr = match ast::Root::cast(node.clone()) {
+        Some(root) => root.expr()?,
+        None => ast::Expr::cast(node.clone())?,
+    };
+    loop {
+        expr = match strip_parens(expr) {
+            ast::Expr::Lambda(lambda) => lambda.body()?,
+            ast::Expr::LetIn(let_in) => let_in.body()?,
+            ast::Expr::With(with) => with.body()?,
+            ast::Expr::AttrSet(set) => return Some(set),
+            _ => return None,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Root;
+
+    #[test]
+    fn per_binding() {
+        let src = "{ lib, ... }: with lib; {
+  a = 1;
+  b.c = x: y: { inherit x y; z = with y; [ (z) ]; };
+  inherit lib;
+}";
+        let root = Root::parse(src).ok().unwrap();
+        let metrics = metrics(root.syntax());
+        assert_eq!(metrics.total.with_count, 2);
+        assert_eq!(metrics.total.lambda_arities, [1, 2]);
+        assert_eq!(metrics.total.set_sizes, [3, 3]);
+
+        let names: Vec<_> = metrics.bindings.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["a", "b.c"]);
+        assert_eq!(metrics.bindings[0].metrics.max_depth, 1);
+        assert_eq!(metrics.bindings[0].metrics.node_count, 1);
+
+        let b = &metrics.bindings[1].metrics;
+        // lambda > lambda > attrset > with > list > ident
+        assert_eq!(b.max_depth, 6);
+        assert_eq!(b.with_count, 1);
+        assert_eq!(b.lambda_arities, [2]);
+        assert_eq!(b.set_sizes, [3]);
+    }
+}
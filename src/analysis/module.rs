@@ -0,0 +1,147 @@
+use rowan::ast::AstNode;
+
+use crate::{
+    ast::{self, HasEntry},
+    SyntaxNode,
+};
+
+use super::{attr_name, callpackage_args, strip_parens, CallPackageArgs};
+
+/// Attributes of a module which are neither options nor config
+const SPECIAL_ATTRS: &[&str] = &["_class", "_file", "disabledModules", "imports", "key", "meta"];
+
+/// A NixOS or home-manager module, like
+/// `{ config, lib, ... }: { imports = [ ... ]; options = ...; config = ...; }`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Module {
+    /// The arguments of the module, if it is a function
+    pub args: Option<CallPackageArgs>,
+    pub body: ast::AttrSet,
+    /// The elements of the `imports` list, usually paths
+    pub imports: Vec<ast::Expr>,
+    /// The definitions of `options` or of attributes in it, like
+    /// `options.services.foo.enable = mkEnableOption "foo";`
+    pub options: Vec<ast::AttrpathValue>,
+    /// The definitions of `config` or of attributes in it. For a module
+    /// without `options` and `config` this is every definition except
+    /// `imports` and the like, as they are all config.
+    pub config: Vec<ast::AttrpathValue>,
+    /// Whether the module is written without `options` and `config`
+    pub shorthand: bool,
+}
+
+/// Recognize a module. The node may be either the root of a file or an
+/// expression.
+///
+/// A function with a pattern returning an attribute set is taken to be a
+/// module, also when the set is wrapped in `let` or `with`. An attribute set
+/// which isn't returned by a function is only taken to be a module if it
+/// defines `imports`, `options` or `config`.
+pub fn module(node: &SyntaxNode) -> Option<Module> {
+    let mut expr = match ast::Root::cast(node.clone()) {
+        Some(root) => root.expr()?,
+        None => ast::Expr::cast(node.clone())?,
+    };
+    let mut args = None;
+    if let ast::Expr::Lambda(lambda) = strip_parens(expr.clone()) {
+        args = Some(callpackage_args(lambda.syntax())?);
+        expr = lambda.body()?;
+    }
+    let body = loop {
+        expr = match strip_parens(expr) {
+            ast::Expr::AttrSet(set) if set.rec_token().is_none() => break set,
+            ast::Expr::LetIn(let_in) => let_in.body()?,
+            ast::Expr::With(with) => with.body()?,
+            _ => return None,
+        };
+    };
+
+    let mut imports = Vec::new();
+    let mut options = Vec::new();
+    let mut config = Vec::new();
+    let mut other = Vec::new();
+    for entry in body.attrpath_values() {
+        let Some(first) = entry.attrpath().and_then(|attrpath| attrpath.attrs().next()) else {
+            continue;
+        };
+        match attr_name(&first).as_deref() {
+            Some("imports") => {
+                if let Some(ast::Expr::List(list)) = entry.value().map(strip_parens) {
+                    imports.extend(list.items().map(strip_parens));
+                }
+            }
+            Some("options") => options.push(entry),
+            Some("config") => config.push(entry),
+            Some(name) if SPECIAL_ATTRS.contains(&name) => (),
+            _ => other.push(entry),
+        }
+    }
+    let defines_sections = !imports.is_empty() || !options.is_empty() || !config.is_empty();
+    if args.is_none() && !defines_sections {
+        return None;
+    }
+    let shorthand = options.is_empty() && config.is_empty();
+    if shorthand {
+        config = other;
+    }
+    Some(Module { args, body, imports, options, config, shorthand })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Root;
+
+    fn parse(src: &str) -> Option<Module> {
+        let root = Root::parse(src).ok().unwrap();
+        module(root.syntax())
+    }
+
+    fn texts<N: AstNode>(nodes: &[N]) -> Vec<String> {
+        nodes.iter().map(|node| node.syntax().to_string()).collect()
+    }
+
+    #[test]
+    fn sections() {
+        let module = parse(
+            r#"{ config, lib, pkgs, ... }:
+let cfg = config.services.foo; in {
+  imports = [ ./hardware.nix (import ./users.nix) ];
+  options.services.foo.enable = lib.mkEnableOption "foo";
+  options.services.foo.port = lib.mkOption { type = lib.types.port; };
+  config = lib.mkIf cfg.enable { networking.firewall.allowedTCPPorts = [ cfg.port ]; };
+  meta.maintainers = [ ];
+}"#,
+        )
+        .unwrap();
+        let names: Vec<_> =
+            module.args.iter().flat_map(|args| &args.formals).map(|f| &f.name).collect();
+        assert_eq!(names, ["config", "lib", "pkgs"]);
+        assert_eq!(texts(&module.imports), ["./hardware.nix", "import ./users.nix"]);
+        assert_eq!(
+            texts(&module.options),
+            [
+                "options.services.foo.enable = lib.mkEnableOption \"foo\";",
+                "options.services.foo.port = lib.mkOption { type = lib.types.port; };"
+            ]
+        );
+        assert_eq!(module.config.len(), 1);
+        assert!(!module.shorthand);
+    }
+
+    #[test]
+    fn shorthand() {
+        let module =
+            parse("{ pkgs, ... }: { imports = [ ]; environment.systemPackages = [ pkgs.git ]; }")
+                .unwrap();
+        assert!(module.shorthand);
+        assert_eq!(texts(&module.config), ["environment.systemPackages = [ pkgs.git ];"]);
+
+        let module = parse("{ imports = [ ./a.nix ]; }").unwrap();
+        assert_eq!((module.args, texts(&module.imports)), (None, vec!["./a.nix".to_string()]));
+
+        for src in ["{ a = 1; }", "pkgs: { }", "{ lib }: [ ]", "{ }: rec { config = { }; }"] {
+            assert_eq!(parse(src), None, "{}", src);
+        }
+    }
+}
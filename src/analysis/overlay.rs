@@ -0,0 +1,217 @@
+use rowan::{ast::AstNode, TextRange};
+
+use crate::{
+    ast::{self, HasEntry},
+    SyntaxKind::*,
+    SyntaxNode,
+};
+
+use super::{attrpath_names, reference_path, strip_parens, Resolver};
+
+/// A function of the form `final: prev: { ... }`, as used for nixpkgs overlays
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Overlay {
+    pub lambda: ast::Lambda,
+    /// The name of the first parameter, the final package set, usually
+    /// `final` or `self`
+    pub final_name: String,
+    /// The name of the second parameter, the package set before the overlay,
+    /// usually `prev` or `super`
+    pub prev_name: String,
+    pub body: ast::AttrSet,
+    /// The attributes the overlay defines, in order. Attributes with dynamic
+    /// names and attributes defined with `inherit` are left out.
+    pub attrs: Vec<OverlayAttr>,
+}
+
+/// An attribute defined by an overlay
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OverlayAttr {
+    pub path: Vec<String>,
+    /// The range of the attrpath
+    pub range: TextRange,
+    /// Whether the value uses the previous value of the attribute, like
+    /// `hello = prev.hello.overrideAttrs { ... };`. An attribute which doesn't
+    /// either adds a package, or replaces one without looking at it.
+    pub uses_prev: bool,
+    /// The calls to `override` and `overrideAttrs` on attributes of the
+    /// previous package set in the value
+    pub overrides: Vec<OverrideCall>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OverrideKind {
+    /// `.override`, changing the arguments of a package
+    Override,
+    /// `.overrideAttrs`, changing the attributes passed to `mkDerivation`
+    OverrideAttrs,
+}
+
+/// A use of `prev.hello.overrideAttrs` or `prev.hello.override`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OverrideCall {
+    pub kind: OverrideKind,
+    /// The path of the overridden package in the previous package set, like
+    /// `["hello"]`
+    pub target: Vec<String>,
+    /// The range of the selection, like `prev.hello.overrideAttrs`
+    pub range: TextRange,
+}
+
+/// Recognize an overlay. The node may be either the root of a file or an
+/// expression. Any function with two plain parameters returning an attribute
+/// set is taken to be an overlay, also when the set is wrapped in `let` or
+/// `with`.
+pub fn overlay(node: &SyntaxNode) -> Option<Overlay> {
+    let expr = match ast::Root::cast(node.clone()) {
+        Some(root) => root.expr()?,
+        None => ast::Expr::cast(node.clone())?,
+    };
+    let ast::Expr::Lambda(lambda) = strip_parens(expr) else { return None };
+    let ast::Expr::Lambda(inner) = strip_parens(lambda.body()?) else { return None };
+    let param_ident = |lambda: &ast::Lambda| match lambda.param()? {
+        ast::Param::IdentParam(param) => param.ident(),
+        ast::Param::Pattern(_) => None,
+    };
+    let (final_ident, prev_ident) = (param_ident(&lambda)?, param_ident(&inner)?);
+
+    let mut body = inner.body()?;
+    let body = loop {
+        body = match strip_parens(body) {
+            ast::Expr::AttrSet(set) => break set,
+            ast::Expr::LetIn(let_in) => let_in.body()?,
+            ast::Expr::With(with) => with.body()?,
+            _ => return None,
+        };
+    };
+
+    let mut attrs: Vec<(ast::AttrpathValue, OverlayAttr)> = body
+        .attrpath_values()
+        .filter_map(|entry| {
+            let attrpath = entry.attrpath()?;
+            let attr = OverlayAttr {
+                path: attrpath_names(&attrpath)?,
+                range: attrpath.syntax().text_range(),
+                uses_prev: false,
+                overrides: Vec::new(),
+            };
+            Some((entry, attr))
+        })
+        .collect();
+
+    let root = node.ancestors().last().unwrap_or_else(|| node.clone());
+    let resolver = Resolver::new(&root);
+    let prev = resolver.binding_at(prev_ident.syntax())?;
+    for reference in resolver.references(prev.id) {
+        // Go up to the outermost selection, like `prev.hello.overrideAttrs`
+        let mut top = reference.syntax().clone();
+        while let Some(parent) = top.parent() {
+            let selects_top = match parent.kind() {
+                NODE_PAREN => true,
+                NODE_SELECT => ast::Select::cast(parent.clone())
+                    .and_then(|select| select.expr())
+                    .is_some_and(|expr| expr.syntax() == &top),
+                _ => false,
+            };
+            if !selects_top {
+                break;
+            }
+            top = parent;
+        }
+        let Some(path) = ast::Expr::cast(top.clone()).and_then(|expr| reference_path(&expr)) else {
+            continue;
+        };
+        let Some((_, attr)) = attrs
+            .iter_mut()
+            .find(|(entry, _)| top.ancestors().any(|ancestor| &ancestor == entry.syntax()))
+        else {
+            continue;
+        };
+
+        let used = &path[1..];
+        attr.uses_prev |= used.starts_with(&attr.path);
+        let kind = match used.last().map(String::as_str) {
+            Some("override") => OverrideKind::Override,
+            Some("overrideAttrs") => OverrideKind::OverrideAttrs,
+            _ => continue,
+        };
+        if used.len() > 1 {
+            let target = used[..used.len() - 1].to_vec();
+            attr.overrides.push(OverrideCall { kind, target, range: top.text_range() });
+        }
+    }
+
+    let attrs = attrs
+        .into_iter()
+        .map(|(_, mut attr)| {
+            attr.overrides.sort_by_key(|call| call.range.start());
+            attr
+        })
+        .collect();
+    Some(Overlay {
+        lambda,
+        final_name: final_ident.syntax().text().to_string(),
+        prev_name: prev_ident.syntax().text().to_string(),
+        body,
+        attrs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Root;
+
+    #[test]
+    fn attrs() {
+        let src = r#"final: prev: let inherit (prev) lib; in {
+  hello = prev.hello.overrideAttrs (old: { doCheck = false; });
+  python3 = (prev.python3).override { packageOverrides = pyFinal: pyPrev: { }; };
+  my-tool = final.callPackage ./my-tool.nix { inherit (prev) hello; };
+  xorg.libX11 = prev.xorg.libX11.overrideAttrs (_: { prev = 1; });
+  shadowed = let prev = { a.override = 1; }; in prev.a.override;
+  ${"dynamic"} = 1;
+}"#;
+        let root = Root::parse(src).ok().unwrap();
+        let overlay = overlay(root.syntax()).unwrap();
+        assert_eq!((overlay.final_name.as_str(), overlay.prev_name.as_str()), ("final", "prev"));
+
+        let attrs: Vec<String> = overlay
+            .attrs
+            .iter()
+            .map(|attr| {
+                let mut out = format!("{} uses_prev={}", attr.path.join("."), attr.uses_prev);
+                for call in &attr.overrides {
+                    out += &format!(
+                        " {:?}({}) {}",
+                        call.kind,
+                        call.target.join("."),
+                        &src[call.range]
+                    );
+                }
+                out
+            })
+            .collect();
+        assert_eq!(
+            attrs,
+            [
+                "hello uses_prev=true OverrideAttrs(hello) prev.hello.overrideAttrs",
+                "python3 uses_prev=true Override(python3) (prev.python3).override",
+                "my-tool uses_prev=false",
+                "xorg.libX11 uses_prev=true OverrideAttrs(xorg.libX11) prev.xorg.libX11.overrideAttrs",
+                "shadowed uses_prev=false",
+            ]
+        );
+        assert_eq!(&src[overlay.attrs[3].range], "xorg.libX11");
+    }
+
+    #[test]
+    fn not_overlays() {
+        for src in ["{ a }: b: { }", "a: b: c", "a: { }", "self: super: with super; [ ]"] {
+            let root = Root::parse(src).ok().unwrap();
+            assert_eq!(overlay(root.syntax()), None, "{}", src);
+        }
+        let root = Root::parse("self: super: with super; rec { }").ok().unwrap();
+        assert_eq!(overlay(root.syntax()).unwrap().prev_name, "super");
+    }
+}
@@ -0,0 +1,598 @@
+use std::collections::{BTreeSet, HashMap};
+
+use rowan::ast::AstNode;
+
+use crate::{
+    ast,
+    value::Value,
+    SyntaxKind::{self, *},
+    SyntaxNode,
+};
+
+use super::attr_name;
+
+/// Names available in the global scope of every Nix file. Additionally, all
+/// names starting with `__` are considered global since most builtins are
+/// available under an alias like `__add`.
+pub const GLOBALS: &[&str] = &[
+    "abort",
+    "baseNameOf",
+    "break",
+    "builtins",
+    "derivation",
+    "derivationStrict",
+    "dirOf",
+    "false",
+    "fetchGit",
+    "fetchMercurial",
+    "fetchTarball",
+    "fetchTree",
+    "fromTOML",
+    "import",
+    "isNull",
+    "map",
+    "null",
+    "placeholder",
+    "removeAttrs",
+    "scopedImport",
+    "throw",
+    "toString",
+    "true",
+];
+
+pub(crate) fn is_global(name: &str) -> bool {
+    name.starts_with("__") || GLOBALS.contains(&name)
+}
+
+/// The names a [`Resolver`] treats as defined in the global scope. Names
+/// starting with `__` are always global.
+///
+/// Besides the builtins of a Nix version, tools can add the names they inject
+/// into the files they evaluate, like the arguments of a `nix repl` session:
+///
+/// ```
+/// use rnix::analysis::{GlobalScope, Resolution, Resolver};
+/// # use rnix::{ast, Root};
+/// # use rowan::ast::AstNode;
+///
+/// let mut globals = GlobalScope::nix_2_18();
+/// globals.define("pkgs");
+/// let root = Root::parse("pkgs.hello").syntax();
+/// let resolver = Resolver::with_globals(&root, &globals);
+/// let ident = root.descendants().find_map(ast::Ident::cast).unwrap();
+/// assert_eq!(resolver.resolve(&ident), Some(&Resolution::Global));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GlobalScope {
+    names: BTreeSet<String>,
+}
+
+impl GlobalScope {
+    /// No names at all, besides those starting with `__`
+    pub fn empty() -> Self {
+        Self { names: BTreeSet::new() }
+    }
+
+    /// The builtins of Nix 2.3, before `fetchTree` and `break`
+    pub fn nix_2_3() -> Self {
+        let mut scope = Self::latest();
+        scope.undefine("break").undefine("fetchTree");
+        scope
+    }
+
+    /// The builtins of Nix 2.18
+    pub fn nix_2_18() -> Self {
+        Self::latest()
+    }
+
+    /// The builtins of the latest Nix, see [`GLOBALS`]. This is the default.
+    pub fn latest() -> Self {
+        Self { names: GLOBALS.iter().map(|name| name.to_string()).collect() }
+    }
+
+    /// Add a name to the global scope
+    pub fn define(&mut self, name: impl Into<String>) -> &mut Self {
+        self.names.insert(name.into());
+        self
+    }
+
+    /// Remove a name from the global scope
+    pub fn undefine(&mut self, name: &str) -> &mut Self {
+        self.names.remove(name);
+        self
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        name.starts_with("__") || self.names.contains(name)
+    }
+
+    /// The names in the global scope, sorted
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.names.iter().map(String::as_str)
+    }
+}
+
+impl Default for GlobalScope {
+    fn default() -> Self {
+        Self::latest()
+    }
+}
+
+/// Identifies a binding within a [`Resolver`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BindingId(u32);
+
+impl BindingId {
+    /// The position of the binding in [`Resolver::bindings`]
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BindingKind {
+    /// `let a = 1; in ...`
+    LetIn,
+    /// `let { a = 1; body = a; }`
+    LegacyLet,
+    /// `rec { a = 1; }`
+    RecAttrSet,
+    /// `a: ...`
+    Lambda,
+    /// `{ a }: ...`
+    PatEntry,
+    /// `args@{ ... }: ...`
+    PatBind,
+}
+
+/// A name bound somewhere in the tree
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Binding {
+    pub id: BindingId,
+    pub name: String,
+    pub kind: BindingKind,
+    /// The node introducing the scope: a let-in, legacy let, rec attrset or lambda
+    pub scope: SyntaxNode,
+    /// The node defining the name. For lambda parameters this is the `Ident`,
+    /// for attributes the first `Attr` of the attrpath or the inherited `Attr`.
+    pub node: SyntaxNode,
+    /// Whether the binding is introduced with `inherit`
+    pub inherited: bool,
+}
+
+/// What an identifier refers to
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Resolution {
+    /// A binding in the same tree
+    Binding(BindingId),
+    /// No binding was found, but the name may come from any of these `with`
+    /// expressions (innermost first) or, failing that, the global scope.
+    With(Vec<ast::With>),
+    /// A global name, like `true` or `builtins`
+    Global,
+    /// The name is not defined
+    Undefined,
+}
+
+enum Frame {
+    Names(HashMap<String, BindingId>),
+    With(ast::With),
+}
+
+/// Resolves every identifier in a tree to its binding.
+///
+/// This implements Nix's static scoping rules: `let` and `rec` bindings are
+/// visible in all of their values, `inherit x;` refers to the `x` of the
+/// enclosing scope, and `with` never shadows lexical bindings.
+#[derive(Clone, Debug, Default)]
+pub struct Resolver {
+    bindings: Vec<Binding>,
+    definitions: HashMap<SyntaxNode, BindingId>,
+    resolutions: HashMap<SyntaxNode, Resolution>,
+    references: Vec<Vec<ast::Ident>>,
+}
+
+impl Resolver {
+    /// Resolve all identifiers in a tree. This is usually done on the root of
+    /// a file, since names defined outside of `node` are unknown.
+    pub fn new(node: &SyntaxNode) -> Self {
+        Self::with_globals(node, &GlobalScope::default())
+    }
+
+    /// Resolve all identifiers in a tree like [`Resolver::new`], with other
+    /// names in the global scope
+    pub fn with_globals(node: &SyntaxNode, globals: &GlobalScope) -> Self {
+        let mut builder = Builder { resolver: Resolver::default(), frames: Vec::new(), globals };
+        builder.walk(node);
+        builder.resolver
+    }
+
+    /// Iterate over all bindings, in the order they appear in the tree
+    pub fn bindings(&self) -> impl Iterator<Item = &Binding> {
+        self.bindings.iter()
+    }
+
+    pub fn binding(&self, id: BindingId) -> &Binding {
+        &self.bindings[id.0 as usize]
+    }
+
+    /// Get the binding defined by a node, see [`Binding::node`]
+    pub fn binding_at(&self, node: &SyntaxNode) -> Option<&Binding> {
+        self.definitions.get(node).map(|&id| self.binding(id))
+    }
+
+    /// Resolve an identifier. Returns `None` if the identifier isn't used as a
+    /// variable, e.g. because it is an attribute name.
+    pub fn resolve(&self, ident: &ast::Ident) -> Option<&Resolution> {
+        self.resolutions.get(ident.syntax())
+    }
+
+    /// Get the binding an identifier refers to, if it is bound in the tree
+    pub fn definition(&self, ident: &ast::Ident) -> Option<&Binding> {
+        match self.resolve(ident)? {
+            Resolution::Binding(id) => Some(self.binding(*id)),
+            _ => None,
+        }
+    }
+
+    /// All identifiers referring to a binding
+    pub fn references(&self, id: BindingId) -> &[ast::Ident] {
+        &self.references[id.0 as usize]
+    }
+
+    /// The value of an identifier referring to the builtin `true`, `false` or
+    /// `null`. Returns `None` if it's anything else, or bound in the tree like
+    /// in `let true = 0; in true`. `with` can't shadow builtins.
+    pub fn builtin_constant(&self, ident: &ast::Ident) -> Option<Value> {
+        match self.resolve(ident)? {
+            Resolution::Global | Resolution::With(_) => {
+                Value::from_keyword(ident.ident_token()?.text())
+            }
+            Resolution::Binding(_) | Resolution::Undefined => None,
+        }
+    }
+
+    /// Iterate over all identifiers used as variables and their resolution
+    pub fn resolutions(&self) -> impl Iterator<Item = (ast::Ident, &Resolution)> {
+        self.resolutions
+            .iter()
+            .filter_map(|(node, res)| Some((ast::Ident::cast(node.clone())?, res)))
+    }
+}
+
+struct Builder<'a> {
+    resolver: Resolver,
+    frames: Vec<Frame>,
+    globals: &'a GlobalScope,
+}
+
+impl Builder<'_> {
+    fn define(
+        &mut self,
+        names: &mut HashMap<String, BindingId>,
+        name: String,
+        kind: BindingKind,
+        scope: &SyntaxNode,
+        node: SyntaxNode,
+        inherited: bool,
+    ) {
+        if names.contains_key(&name) {
+            return;
+        }
+        let id = BindingId(self.resolver.bindings.len() as u32);
+        names.insert(name.clone(), id);
+        self.resolver.definitions.insert(node.clone(), id);
+        self.resolver.bindings.push(Binding {
+            id,
+            name,
+            kind,
+            scope: scope.clone(),
+            node,
+            inherited,
+        });
+        self.resolver.references.push(Vec::new());
+    }
+
+    fn reference(&mut self, node: &SyntaxNode) {
+        let ident = match ast::Ident::cast(node.clone()) {
+            Some(ident) => ident,
+            None => return,
+        };
+        let name = match ident.ident_token() {
+            Some(token) => token.text().to_string(),
+            None => return,
+        };
+
+        let mut withs = Vec::new();
+        let mut resolution = None;
+        for frame in self.frames.iter().rev() {
+            match frame {
+                Frame::Names(names) => {
+                    if let Some(&id) = names.get(&name) {
+                        resolution = Some(Resolution::Binding(id));
+                        break;
+                    }
+                }
+                Frame::With(with) => withs.push(with.clone()),
+            }
+        }
+        let resolution = resolution.unwrap_or_else(|| {
+            if !withs.is_empty() {
+                Resolution::With(withs)
+            } else if self.globals.contains(&name) {
+                Resolution::Global
+            } else {
+                Resolution::Undefined
+            }
+        });
+
+        if let Resolution::Binding(id) = resolution {
+            self.resolver.references[id.0 as usize].push(ident);
+        }
+        self.resolver.resolutions.insert(node.clone(), resolution);
+    }
+
+    fn walk(&mut self, node: &SyntaxNode) {
+        match node.kind() {
+            NODE_IDENT => self.reference(node),
+            NODE_LET_IN => self.walk_recursive(node, BindingKind::LetIn),
+            NODE_LEGACY_LET => self.walk_recursive(node, BindingKind::LegacyLet),
+            NODE_ATTR_SET if node.children_with_tokens().any(|it| it.kind() == TOKEN_REC) => {
+                self.walk_recursive(node, BindingKind::RecAttrSet)
+            }
+            NODE_LAMBDA => self.walk_lambda(node),
+            NODE_WITH => {
+                let with = ast::With::cast(node.clone()).unwrap();
+                if let Some(namespace) = with.namespace() {
+                    self.walk(namespace.syntax());
+                }
+                self.frames.push(Frame::With(with.clone()));
+                if let Some(body) = with.body() {
+                    self.walk(body.syntax());
+                }
+                self.frames.pop();
+            }
+            NODE_ATTRPATH => {
+                // Static attribute names aren't variables
+                for child in node.children().filter(|child| child.kind() != NODE_IDENT) {
+                    self.walk(&child);
+                }
+            }
+            NODE_INHERIT => self.walk_inherit(node, true),
+            _ => {
+                for child in node.children() {
+                    self.walk(&child);
+                }
+            }
+        }
+    }
+
+    fn walk_inherit(&mut self, node: &SyntaxNode, resolve_attrs: bool) {
+        let has_from = node.children().any(|child| child.kind() == NODE_INHERIT_FROM);
+        for child in node.children() {
+            match child.kind() {
+                NODE_IDENT if has_from || !resolve_attrs => (),
+                _ => self.walk(&child),
+            }
+        }
+    }
+
+    fn walk_recursive(&mut self, node: &SyntaxNode, kind: BindingKind) {
+        let mut names = HashMap::new();
+        let mut inherited = Vec::new();
+        for child in node.children() {
+            match child.kind() {
+                NODE_ATTRPATH_VALUE => {
+                    let attr = ast::AttrpathValue::cast(child)
+                        .and_then(|entry| entry.attrpath())
+                        .and_then(|attrpath| attrpath.attrs().next());
+                    if let Some(attr) = attr {
+                        if let Some(name) = attr_name(&attr) {
+                            let attr = attr.syntax().clone();
+                            self.define(&mut names, name, kind, node, attr, false);
+                        }
+                    }
+                }
+                NODE_INHERIT => {
+                    let inherit = ast::Inherit::cast(child).unwrap();
+                    for attr in inherit.attrs() {
+                        if let Some(name) = attr_name(&attr) {
+                            let attr = attr.syntax().clone();
+                            self.define(&mut names, name, kind, node, attr.clone(), true);
+                            if inherit.from().is_none() {
+                                inherited.push(attr);
+                            }
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        // `inherit x;` refers to the `x` of the enclosing scope
+        for attr in inherited {
+            self.reference(&attr);
+        }
+
+        self.frames.push(Frame::Names(names));
+        for child in node.children() {
+            match child.kind() {
+                NODE_INHERIT => self.walk_inherit(&child, false),
+                _ => self.walk(&child),
+            }
+        }
+        self.frames.pop();
+    }
+
+    fn walk_lambda(&mut self, node: &SyntaxNode) {
+        let lambda = ast::Lambda::cast(node.clone()).unwrap();
+        let mut names = HashMap::new();
+        let mut defaults = Vec::new();
+        match lambda.param() {
+            Some(ast::Param::IdentParam(param)) => {
+                if let Some(ident) = param.ident() {
+                    if let Some(token) = ident.ident_token() {
+                        let name = token.text().to_string();
+                        let ident = ident.syntax().clone();
+                        self.define(&mut names, name, BindingKind::Lambda, node, ident, false);
+                    }
+                }
+            }
+            Some(ast::Param::Pattern(pattern)) => {
+                let bind = pattern.pat_bind().and_then(|bind| bind.ident());
+                if let Some(ident) = bind {
+                    if let Some(token) = ident.ident_token() {
+                        let name = token.text().to_string();
+                        let ident = ident.syntax().clone();
+                        self.define(&mut names, name, BindingKind::PatBind, node, ident, false);
+                    }
+                }
+                for entry in pattern.pat_entries() {
+                    if let Some(ident) = entry.ident() {
+                        if let Some(token) = ident.ident_token() {
+                            let name = token.text().to_string();
+                            let ident = ident.syntax().clone();
+                            self.define(
+                                &mut names,
+                                name,
+                                BindingKind::PatEntry,
+                                node,
+                                ident,
+                                false,
+                            );
+                        }
+                    }
+                    defaults.extend(entry.default());
+                }
+            }
+            None => (),
+        }
+
+        self.frames.push(Frame::Names(names));
+        for default in defaults {
+            self.walk(default.syntax());
+        }
+        let param_kinds: [SyntaxKind; 2] = [NODE_IDENT_PARAM, NODE_PATTERN];
+        for child in node.children().filter(|child| !param_kinds.contains(&child.kind())) {
+            self.walk(&child);
+        }
+        self.frames.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Root;
+
+    fn resolve_all(src: &str) -> Vec<(String, String)> {
+        let root = Root::parse(src).ok().unwrap();
+        let resolver = Resolver::new(root.syntax());
+        let mut all: Vec<_> = resolver
+            .resolutions()
+            .map(|(ident, res)| {
+                let start = usize::from(ident.syntax().text_range().start());
+                let res = match res {
+                    Resolution::Binding(id) => {
+                        let binding = resolver.binding(*id);
+                        format!(
+                            "{:?}@{}",
+                            binding.kind,
+                            usize::from(binding.node.text_range().start())
+                        )
+                    }
+                    Resolution::With(withs) => format!("with*{}", withs.len()),
+                    Resolution::Global => "global".to_string(),
+                    Resolution::Undefined => "undefined".to_string(),
+                };
+                (start, format!("{}@{}", ident, start), res)
+            })
+            .collect();
+        all.sort();
+        all.into_iter().map(|(_, ident, res)| (ident, res)).collect()
+    }
+
+    fn pairs(expected: &[(&str, &str)]) -> Vec<(String, String)> {
+        expected.iter().map(|(a, b)| (a.to_string(), b.to_string())).collect()
+    }
+
+    #[test]
+    fn let_and_lambda() {
+        assert_eq!(
+            resolve_all("let a = b; b = x: a x y; in a"),
+            pairs(&[
+                ("b@8", "LetIn@11"),
+                ("a@18", "LetIn@4"),
+                ("x@20", "Lambda@15"),
+                ("y@22", "undefined"),
+                ("a@28", "LetIn@4"),
+            ])
+        );
+    }
+
+    #[test]
+    fn inherit_refers_to_outer_scope() {
+        assert_eq!(
+            resolve_all("x: let inherit x; y = x; inherit (y) z; in z"),
+            pairs(&[
+                ("x@15", "Lambda@0"),
+                ("x@22", "LetIn@15"),
+                ("y@34", "LetIn@18"),
+                ("z@43", "LetIn@37"),
+            ])
+        );
+    }
+
+    #[test]
+    fn sets_and_with() {
+        assert_eq!(
+            resolve_all(
+                "{ a, ... }@args: with args; rec { b = a; c = b.c; d = { e = e; }; f = true; }"
+            ),
+            pairs(&[
+                ("args@22", "PatBind@11"),
+                ("a@38", "PatEntry@2"),
+                ("b@45", "RecAttrSet@34"),
+                ("e@60", "with*1"),
+                ("true@70", "with*1"),
+            ])
+        );
+        assert_eq!(resolve_all("{ x = builtins; }"), pairs(&[("builtins@6", "global")]));
+    }
+
+    #[test]
+    fn references() {
+        let root = Root::parse("let a = 1; in [ a a ]").ok().unwrap();
+        let resolver = Resolver::new(root.syntax());
+        let binding = resolver.bindings().next().unwrap();
+        assert_eq!(binding.name, "a");
+        assert_eq!(resolver.references(binding.id).len(), 2);
+        assert_eq!(resolver.binding_at(&binding.node), Some(binding));
+    }
+
+    #[test]
+    fn builtin_constants() {
+        let root = Root::parse("[ true null (with x; false) (let true = 0; in true) truth ]")
+            .ok()
+            .unwrap();
+        let resolver = Resolver::new(root.syntax());
+        let constants: Vec<_> = root
+            .syntax()
+            .descendants()
+            .filter_map(ast::Ident::cast)
+            .filter(|ident| resolver.resolve(ident).is_some())
+            .map(|ident| resolver.builtin_constant(&ident))
+            .collect();
+        assert_eq!(
+            constants,
+            [
+                Some(Value::Bool(true)),
+                Some(Value::Null),
+                None,
+                Some(Value::Bool(false)),
+                None,
+                None
+            ]
+        );
+    }
+}
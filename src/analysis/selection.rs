@@ -0,0 +1,70 @@
+use rowan::{ast::AstNode, TextRange, TextSize};
+
+use crate::{ast, SyntaxNode};
+
+use super::str_content_range;
+
+/// Return the ranges an editor's "expand selection" should step through at
+/// `offset`, from the token at the offset up to the whole file. Every range
+/// strictly contains the previous one.
+///
+/// Besides the ranges of the syntax nodes, this stops at the contents of
+/// strings without their quotes, and at each prefix of an attrpath, so
+/// expanding from `b` in `a.b.c = 1;` selects `a.b` before `a.b.c`.
+pub fn selection_ranges(root: &SyntaxNode, offset: TextSize) -> Vec<TextRange> {
+    let token = match root.token_at_offset(offset).max_by_key(|token| !token.kind().is_trivia()) {
+        Some(token) => token,
+        None => return Vec::new(),
+    };
+    let mut stops = vec![token.text_range()];
+    for node in token.parent_ancestors() {
+        if let Some(s) = ast::Str::cast(node.clone()) {
+            stops.extend(str_content_range(&s));
+        }
+        if let Some(attrpath) = ast::Attrpath::cast(node.clone()) {
+            let start = attrpath.syntax().text_range().start();
+            let prefixes = attrpath
+                .attrs()
+                .map(|attr| TextRange::new(start, attr.syntax().text_range().end()))
+                .filter(|range| range.contains_range(token.text_range()));
+            stops.extend(prefixes);
+        }
+        stops.push(node.text_range());
+    }
+
+    let mut ranges: Vec<TextRange> = Vec::new();
+    for range in stops {
+        match ranges.last() {
+            Some(last) if !range.contains_range(*last) || range == *last => (),
+            _ => ranges.push(range),
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Root;
+
+    fn select(src: &str, offset: u32) -> Vec<&str> {
+        let root = Root::parse(src).ok().unwrap();
+        selection_ranges(root.syntax(), offset.into()).into_iter().map(|r| &src[r]).collect()
+    }
+
+    #[test]
+    fn attrpath_prefixes() {
+        assert_eq!(
+            select("{ a.b.c = 1; d = 2; }", 4),
+            ["b", "a.b", "a.b.c", "a.b.c = 1;", "{ a.b.c = 1; d = 2; }"]
+        );
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(
+            select("f \"hello ${x}\"", 4),
+            ["hello ", "hello ${x}", "\"hello ${x}\"", "f \"hello ${x}\""]
+        );
+    }
+}
@@ -0,0 +1,401 @@
+use rowan::{ast::AstNode, TextRange, TextSize};
+
+use crate::{
+    ast, line_index::LineIndex, tokenizer::tokenize, NodeOrToken, SyntaxKind, SyntaxKind::*,
+    SyntaxNode, SyntaxToken,
+};
+
+use super::{is_global, BindingKind, Resolution, Resolver};
+
+/// The kind of a semantic token, see [`SemanticTokenKind::ALL`] for the legend
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SemanticTokenKind {
+    Keyword,
+    Operator,
+    Comment,
+    String,
+    Number,
+    Path,
+    /// A variable bound with `let` or `rec`, or coming from a `with`
+    Variable,
+    /// A function parameter, including pattern formals
+    Parameter,
+    /// An attribute name
+    Property,
+    /// A global like `builtins` or `true`
+    Builtin,
+}
+
+impl SemanticTokenKind {
+    /// All kinds, ordered by the index used in the LSP encoding
+    pub const ALL: [SemanticTokenKind; 10] = [
+        SemanticTokenKind::Keyword,
+        SemanticTokenKind::Operator,
+        SemanticTokenKind::Comment,
+        SemanticTokenKind::String,
+        SemanticTokenKind::Number,
+        SemanticTokenKind::Path,
+        SemanticTokenKind::Variable,
+        SemanticTokenKind::Parameter,
+        SemanticTokenKind::Property,
+        SemanticTokenKind::Builtin,
+    ];
+
+    /// The name of the token type for the LSP legend
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SemanticTokenKind::Keyword => "keyword",
+            SemanticTokenKind::Operator => "operator",
+            SemanticTokenKind::Comment => "comment",
+            SemanticTokenKind::String => "string",
+            SemanticTokenKind::Number => "number",
+            SemanticTokenKind::Path => "path",
+            SemanticTokenKind::Variable => "variable",
+            SemanticTokenKind::Parameter => "parameter",
+            SemanticTokenKind::Property => "property",
+            SemanticTokenKind::Builtin => "builtin",
+        }
+    }
+}
+
+/// A classified token
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SemanticToken {
+    pub range: TextRange,
+    pub kind: SemanticTokenKind,
+    /// Whether the token is where a name is defined. This is the only token
+    /// modifier, with bit 0 in the LSP encoding.
+    pub definition: bool,
+}
+
+/// Classify all tokens in a tree which overlap with `range`, or all tokens if
+/// it is `None`. Punctuation and whitespace aren't included.
+///
+/// Trees with syntax errors are classified too. Tokens the tree can't tell
+/// anything about, like names in a `NODE_ERROR` or the rest of an unclosed
+/// string, are classified like [`lexical_tokens`] does, so every token that
+/// would be classified in a correct source still is while it is being edited.
+pub fn semantic_tokens(root: &SyntaxNode, range: Option<TextRange>) -> Vec<SemanticToken> {
+    let resolver = Resolver::new(root);
+    let has_errors = root
+        .descendants_with_tokens()
+        .any(|element| matches!(element.kind(), NODE_ERROR | TOKEN_ERROR));
+    // The tree is made of the same tokens as the source, so they can be
+    // looked up by their start
+    let start = root.text_range().start();
+    let fallback: Vec<SemanticToken> = match has_errors {
+        true => lexical_tokens(&root.text().to_string())
+            .into_iter()
+            .map(|token| SemanticToken { range: token.range + start, ..token })
+            .collect(),
+        false => Vec::new(),
+    };
+    let lexical = |token: &SyntaxToken| {
+        let index =
+            fallback.binary_search_by_key(&token.text_range().start(), |t| t.range.start()).ok()?;
+        Some(fallback[index])
+    };
+    root.descendants_with_tokens()
+        .filter_map(NodeOrToken::into_token)
+        .filter(|token| range.is_none_or(|range| token.text_range().intersect(range).is_some()))
+        .filter_map(|token| classify(&resolver, &token).or_else(|| lexical(&token)))
+        .collect()
+}
+
+/// Classify the tokens of a source without parsing it, for sources which
+/// can't be parsed at all or as a fallback for the broken parts of a tree.
+///
+/// Names are told apart by the tokens next to them only: a name after a `.`
+/// or before a `=` is a property, one before a `:` or `@` a parameter, and
+/// globals are builtins. Everything else is classified like in
+/// [`semantic_tokens`], with unclosed strings and comments running to the end.
+pub fn lexical_tokens(source: &str) -> Vec<SemanticToken> {
+    let tokens = tokenize(source);
+    let significant = |kind: &SyntaxKind| !matches!(kind, TOKEN_WHITESPACE | TOKEN_COMMENT);
+    let mut result = Vec::new();
+    let mut offset = TextSize::from(0);
+    let mut strings = 0usize;
+    for (i, &(kind, text)) in tokens.iter().enumerate() {
+        let range = TextRange::at(offset, TextSize::of(text));
+        offset += TextSize::of(text);
+        let kind = match kind {
+            TOKEN_STRING_START => {
+                strings += 1;
+                SemanticTokenKind::String
+            }
+            TOKEN_STRING_END => {
+                strings = strings.saturating_sub(1);
+                SemanticTokenKind::String
+            }
+            TOKEN_ERROR if text.starts_with("/*") => SemanticTokenKind::Comment,
+            TOKEN_ERROR if strings > 0 => SemanticTokenKind::String,
+            TOKEN_IDENT => {
+                let before = tokens[..i].iter().rev().map(|t| t.0).find(significant);
+                let after = tokens[i + 1..].iter().map(|t| t.0).find(significant);
+                match (before, after) {
+                    (Some(TOKEN_DOT), _) | (_, Some(TOKEN_ASSIGN)) => SemanticTokenKind::Property,
+                    (_, Some(TOKEN_COLON | TOKEN_AT)) => SemanticTokenKind::Parameter,
+                    _ if is_global(text) => SemanticTokenKind::Builtin,
+                    _ => SemanticTokenKind::Variable,
+                }
+            }
+            kind => match token_kind(kind) {
+                Some(kind) => kind,
+                None => continue,
+            },
+        };
+        result.push(SemanticToken { range, kind, definition: false });
+    }
+    result
+}
+
+/// The kind of all tokens except names, which depends on where they are
+fn token_kind(kind: SyntaxKind) -> Option<SemanticTokenKind> {
+    Some(match kind {
+        TOKEN_COMMENT => SemanticTokenKind::Comment,
+        TOKEN_ASSERT | TOKEN_ELSE | TOKEN_IF | TOKEN_IN | TOKEN_INHERIT | TOKEN_LET | TOKEN_OR
+        | TOKEN_REC | TOKEN_THEN | TOKEN_WITH => SemanticTokenKind::Keyword,
+        TOKEN_STRING_START | TOKEN_STRING_CONTENT | TOKEN_STRING_END | TOKEN_URI => {
+            SemanticTokenKind::String
+        }
+        TOKEN_INTEGER | TOKEN_FLOAT => SemanticTokenKind::Number,
+        TOKEN_PATH => SemanticTokenKind::Path,
+        TOKEN_CONCAT | TOKEN_INVERT | TOKEN_UPDATE | TOKEN_ADD | TOKEN_SUB | TOKEN_MUL
+        | TOKEN_DIV | TOKEN_AND_AND | TOKEN_EQUAL | TOKEN_IMPLICATION | TOKEN_LESS
+        | TOKEN_LESS_OR_EQ | TOKEN_MORE | TOKEN_MORE_OR_EQ | TOKEN_NOT_EQUAL | TOKEN_OR_OR
+        | TOKEN_PIPE_RIGHT | TOKEN_PIPE_LEFT | TOKEN_QUESTION => SemanticTokenKind::Operator,
+        _ => return None,
+    })
+}
+
+fn classify(resolver: &Resolver, token: &SyntaxToken) -> Option<SemanticToken> {
+    let mut definition = false;
+    let kind = match token.kind() {
+        TOKEN_IDENT => {
+            let ident = ast::Ident::cast(token.parent()?)?;
+            if let Some(binding) = resolver.binding_at(ident.syntax()) {
+                definition = true;
+                binding_kind(binding.kind)
+            } else {
+                match resolver.resolve(&ident) {
+                    Some(Resolution::Binding(id)) => binding_kind(resolver.binding(*id).kind),
+                    Some(Resolution::Global) => SemanticTokenKind::Builtin,
+                    Some(_) => SemanticTokenKind::Variable,
+                    // Not a variable, so an attribute name
+                    None => {
+                        let parent = ident.syntax().parent()?;
+                        definition = parent.kind() == NODE_INHERIT
+                            || parent.parent().is_some_and(|p| p.kind() == NODE_ATTRPATH_VALUE);
+                        SemanticTokenKind::Property
+                    }
+                }
+            }
+        }
+        kind => token_kind(kind)?,
+    };
+    Some(SemanticToken { range: token.text_range(), kind, definition })
+}
+
+fn binding_kind(kind: BindingKind) -> SemanticTokenKind {
+    match kind {
+        BindingKind::Lambda | BindingKind::PatEntry | BindingKind::PatBind => {
+            SemanticTokenKind::Parameter
+        }
+        BindingKind::LetIn | BindingKind::LegacyLet | BindingKind::RecAttrSet => {
+            SemanticTokenKind::Variable
+        }
+    }
+}
+
+/// Encode tokens in the relative format of LSP: five integers per token, for
+/// the line and start column relative to the previous token, the length, the
+/// index of the kind in [`SemanticTokenKind::ALL`], and the modifiers. Columns
+/// are counted in UTF-16 code units, and tokens spanning multiple lines are
+/// split into one token per line.
+pub fn encode_semantic_tokens(source: &str, tokens: &[SemanticToken]) -> Vec<u32> {
+    let index = LineIndex::new(source);
+    let mut data = Vec::with_capacity(tokens.len() * 5);
+    let (mut prev_line, mut prev_col) = (0, 0);
+    for token in tokens {
+        let kind = SemanticTokenKind::ALL.iter().position(|&kind| kind == token.kind).unwrap();
+        for range in index.lines(token.range) {
+            let (line, col) = index.line_col_utf16(range.start());
+            let len = source[range].encode_utf16().count() as u32;
+            let delta_col = if line == prev_line { col - prev_col } else { col };
+            data.extend([line - prev_line, delta_col, len, kind as u32, token.definition as u32]);
+            prev_line = line;
+            prev_col = col;
+        }
+    }
+    data
+}
+
+/// A change to previously sent tokens, as in LSP's `semanticTokens/full/delta`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SemanticTokensEdit {
+    /// The index into the encoded data of the previous result
+    pub start: u32,
+    pub delete_count: u32,
+    pub data: Vec<u32>,
+}
+
+/// Remembers the tokens sent for the last version of a document, so later
+/// requests can be answered with deltas.
+#[derive(Clone, Debug, Default)]
+pub struct SemanticTokensCache {
+    last: Option<(i32, Vec<u32>)>,
+}
+
+impl SemanticTokensCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the encoded tokens of a version of the document. Requesting the
+    /// same version again doesn't recompute them.
+    pub fn full(&mut self, version: i32, source: &str, root: &SyntaxNode) -> Vec<u32> {
+        match &self.last {
+            Some((last, data)) if *last == version => data.clone(),
+            _ => {
+                let data = encode_semantic_tokens(source, &semantic_tokens(root, None));
+                self.last = Some((version, data.clone()));
+                data
+            }
+        }
+    }
+
+    /// Return the edits turning the tokens sent for `previous` into the ones
+    /// of `version`. If `previous` isn't the version the cache knows about,
+    /// `None` is returned and the full tokens need to be sent instead.
+    pub fn delta(
+        &mut self,
+        previous: i32,
+        version: i32,
+        source: &str,
+        root: &SyntaxNode,
+    ) -> Option<Vec<SemanticTokensEdit>> {
+        let old = match self.last.take() {
+            Some((last, data)) if last == previous => data,
+            last => {
+                self.last = last;
+                self.full(version, source, root);
+                return None;
+            }
+        };
+        let new = self.full(version, source, root);
+        Some(diff(&old, &new))
+    }
+}
+
+/// Compute a single edit replacing everything between the common prefix and
+/// suffix of whole tokens
+fn diff(old: &[u32], new: &[u32]) -> Vec<SemanticTokensEdit> {
+    let prefix = old.chunks(5).zip(new.chunks(5)).take_while(|(a, b)| a == b).count();
+    let max_suffix = old.len().min(new.len()) / 5 - prefix;
+    let suffix =
+        old.rchunks(5).zip(new.rchunks(5)).take(max_suffix).take_while(|(a, b)| a == b).count();
+    if prefix * 5 == old.len() && old.len() == new.len() {
+        return Vec::new();
+    }
+    vec![SemanticTokensEdit {
+        start: (prefix * 5) as u32,
+        delete_count: (old.len() - (prefix + suffix) * 5) as u32,
+        data: new[prefix * 5..new.len() - suffix * 5].to_vec(),
+    }]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Root;
+
+    fn kinds(src: &str) -> Vec<(&str, SemanticTokenKind, bool)> {
+        let root = Root::parse(src).syntax();
+        semantic_tokens(&root, None)
+            .into_iter()
+            .map(|token| (&src[token.range], token.kind, token.definition))
+            .collect()
+    }
+
+    #[test]
+    fn classify() {
+        use SemanticTokenKind::*;
+        assert_eq!(
+            kinds("x: let a.b = x; in a.b or true # hi"),
+            [
+                ("x", Parameter, true),
+                ("let", Keyword, false),
+                ("a", Variable, true),
+                ("b", Property, true),
+                ("x", Parameter, false),
+                ("in", Keyword, false),
+                ("a", Variable, false),
+                ("b", Property, false),
+                ("or", Keyword, false),
+                ("true", Builtin, false),
+                ("# hi", Comment, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn broken() {
+        use SemanticTokenKind::*;
+        assert_eq!(
+            kinds("x: [ ) d.e = x; ''\n  a"),
+            [
+                ("x", Parameter, true),
+                ("d", Variable, false),
+                ("e", Property, false),
+                ("x", Parameter, false),
+                ("''", String, false),
+                ("\n  a", String, false),
+            ]
+        );
+        // Every prefix of a source is classified like the tokenizer would
+        let src =
+            "{ pkgs ? import <nixpkgs> { } }: with pkgs; /* a */ \"${hello}\" + ''\n  b'' or 1.5";
+        for end in 0..=src.len() {
+            let src = &src[..end];
+            let classified: Vec<TextRange> =
+                semantic_tokens(&Root::parse(src).syntax(), None).iter().map(|t| t.range).collect();
+            let lexical: Vec<TextRange> = lexical_tokens(src).iter().map(|t| t.range).collect();
+            assert_eq!(classified, lexical, "{:?}", src);
+        }
+    }
+
+    #[test]
+    fn encoding() {
+        let src = "[\n  \"ä${x}\" ''\n  a\n'' 1\n]";
+        let root = Root::parse(src).syntax();
+        let data = encode_semantic_tokens(src, &semantic_tokens(&root, None));
+        #[rustfmt::skip]
+        assert_eq!(data, [
+            1, 2, 1, 3, 0, // "
+            0, 1, 1, 3, 0, // ä, one UTF-16 unit
+            0, 3, 1, 6, 0, // x
+            0, 2, 1, 3, 0, // "
+            0, 2, 2, 3, 0, // ''
+            1, 0, 3, 3, 0, // "  a", split by line
+            1, 0, 2, 3, 0, // ''
+            0, 3, 1, 4, 0, // 1
+        ]);
+    }
+
+    #[test]
+    fn delta() {
+        let mut cache = SemanticTokensCache::new();
+        let v1 = "a: b: [ a b ]";
+        let v2 = "a: b: [ a 1 b ]";
+        let full = cache.full(1, v1, &Root::parse(v1).syntax());
+        assert_eq!(full.len(), 4 * 5);
+        assert_eq!(cache.delta(0, 2, v2, &Root::parse(v2).syntax()), None);
+
+        let mut cache = SemanticTokensCache::new();
+        cache.full(1, v1, &Root::parse(v1).syntax());
+        let edits = cache.delta(1, 2, v2, &Root::parse(v2).syntax()).unwrap();
+        assert_eq!(edits.len(), 1);
+        assert_eq!((edits[0].start, edits[0].delete_count), (15, 0));
+        assert_eq!(edits[0].data, [0, 2, 1, 4, 0]);
+    }
+}
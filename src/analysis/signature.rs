@@ -0,0 +1,179 @@
+use rowan::{ast::AstNode, TextSize};
+
+use crate::{ast, SyntaxNode};
+
+use super::{callpackage::pattern_formals, strip_parens, BindingKind, Formal, Resolver};
+
+/// A parameter of a function
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParamInfo {
+    /// `name: ...`
+    Ident(String),
+    /// `{ a, b ? 1, ... }@bind: ...`
+    Pattern { formals: Vec<Formal>, ellipsis: bool, bind: Option<String> },
+}
+
+/// The signature of a function being called
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignatureHelp {
+    /// The outermost application of the call, e.g. `f a b` rather than `f a`
+    pub apply: ast::Apply,
+    /// The function being called, as written at the call
+    pub callee: ast::Expr,
+    /// The lambda the callee was resolved to
+    pub lambda: ast::Lambda,
+    /// The curried parameters of the function. `a: { b }: ...` has two.
+    pub params: Vec<ParamInfo>,
+    /// The index of the argument the offset is at. This may be past the end
+    /// of `params`, if too many arguments are passed.
+    pub active: usize,
+}
+
+/// Find the signature of the function called at `offset`.
+///
+/// The callee is resolved if it is a lambda, or a variable bound to a lambda
+/// in the same tree. Anything else is passed to `resolve`, which may look up
+/// the function elsewhere, e.g. in another file.
+pub fn signature_help(
+    root: &SyntaxNode,
+    offset: TextSize,
+    mut resolve: impl FnMut(&ast::Expr) -> Option<ast::Lambda>,
+) -> Option<SignatureHelp> {
+    let mut token = root.token_at_offset(offset).left_biased()?;
+    while token.kind().is_trivia() {
+        token = token.prev_token()?;
+    }
+    let mut apply = token.parent_ancestors().find_map(ast::Apply::cast)?;
+    while let Some(parent) = apply.syntax().parent().and_then(ast::Apply::cast) {
+        if parent.lambda()?.syntax() != apply.syntax() {
+            break;
+        }
+        apply = parent;
+    }
+
+    let mut args = Vec::new();
+    let mut callee = ast::Expr::Apply(apply.clone());
+    while let ast::Expr::Apply(inner) = callee {
+        args.push(inner.argument()?);
+        callee = inner.lambda()?;
+    }
+    args.reverse();
+    let active =
+        args.iter().position(|arg| offset <= arg.syntax().text_range().end()).unwrap_or(args.len());
+
+    let lambda = match local_lambda(&Resolver::new(root), &callee) {
+        Some(lambda) => lambda,
+        None => resolve(&callee)?,
+    };
+    let params = curried_params(&lambda);
+    Some(SignatureHelp { apply, callee, lambda, params, active })
+}
+
+/// Resolve a callee to a lambda written in place, or bound with `let` or `rec`
+pub(super) fn local_lambda(resolver: &Resolver, callee: &ast::Expr) -> Option<ast::Lambda> {
+    let value = match strip_parens(callee.clone()) {
+        ast::Expr::Lambda(lambda) => return Some(lambda),
+        ast::Expr::Ident(ident) => {
+            let binding = resolver.definition(&ident)?;
+            if binding.inherited
+                || !matches!(
+                    binding.kind,
+                    BindingKind::LetIn | BindingKind::LegacyLet | BindingKind::RecAttrSet
+                )
+            {
+                return None;
+            }
+            let attrpath = ast::Attrpath::cast(binding.node.parent()?)?;
+            if attrpath.attrs().count() != 1 {
+                return None;
+            }
+            ast::AttrpathValue::cast(attrpath.syntax().parent()?)?.value()?
+        }
+        _ => return None,
+    };
+    match strip_parens(value) {
+        ast::Expr::Lambda(lambda) => Some(lambda),
+        _ => None,
+    }
+}
+
+/// The parameters of a lambda and all lambdas directly in its body
+pub(super) fn curried_params(lambda: &ast::Lambda) -> Vec<ParamInfo> {
+    let mut params = Vec::new();
+    let mut current = Some(lambda.clone());
+    while let Some(lambda) = current {
+        params.extend(lambda.param().map(param_info));
+        current = match lambda.body() {
+            Some(ast::Expr::Lambda(body)) => Some(body),
+            _ => None,
+        };
+    }
+    params
+}
+
+fn param_info(param: ast::Param) -> ParamInfo {
+    match param {
+        ast::Param::IdentParam(param) => {
+            ParamInfo::Ident(param.ident().map(|ident| ident.to_string()).unwrap_or_default())
+        }
+        ast::Param::Pattern(pattern) => ParamInfo::Pattern {
+            formals: pattern_formals(&pattern),
+            ellipsis: pattern.ellipsis_token().is_some(),
+            bind: pattern.pat_bind().and_then(|bind| bind.ident()).map(|ident| ident.to_string()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Root;
+
+    fn help(src: &str) -> Option<SignatureHelp> {
+        let offset = src.find('|').unwrap();
+        let src = src.replacen('|', "", 1);
+        let root = Root::parse(&src).syntax();
+        signature_help(&root, TextSize::from(offset as u32), |_| None)
+    }
+
+    #[test]
+    fn local_functions() {
+        let sig = help("let f = a: { b ? 1, ... }@args: a; in f 1 { |}").unwrap();
+        assert_eq!(sig.callee.to_string(), "f");
+        assert_eq!(sig.active, 1);
+        assert_eq!(sig.params.len(), 2);
+        assert_eq!(sig.params[0], ParamInfo::Ident("a".into()));
+        match &sig.params[1] {
+            ParamInfo::Pattern { formals, ellipsis, bind } => {
+                assert_eq!(formals[0].name, "b");
+                assert!(ellipsis);
+                assert_eq!(bind.as_deref(), Some("args"));
+            }
+            param => panic!("unexpected {:?}", param),
+        }
+
+        let sig = help("(x: y: x) 1 |").unwrap();
+        assert_eq!(sig.active, 1);
+        assert_eq!(sig.params.len(), 2);
+
+        assert!(help("g 1|").is_none());
+    }
+
+    #[test]
+    fn resolve_callback() {
+        let lib = Root::parse("{ lib, stdenv }: { }").tree();
+        let lib = match lib.expr() {
+            Some(ast::Expr::Lambda(lambda)) => lambda,
+            _ => unreachable!(),
+        };
+        let src = "callPackage ./foo.nix";
+        let root = Root::parse(src).syntax();
+        let help = signature_help(&root, TextSize::from(12), |callee| {
+            assert_eq!(callee.to_string(), "callPackage");
+            Some(lib.clone())
+        })
+        .unwrap();
+        assert_eq!(help.active, 0);
+        assert_eq!(help.lambda, lib);
+    }
+}
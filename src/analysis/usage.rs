@@ -0,0 +1,211 @@
+use std::{collections::HashSet, fmt::Write};
+
+use rowan::ast::AstNode;
+
+use crate::{ast, dump::json_string, SyntaxNode};
+
+use super::{BindingId, BindingKind, Resolver};
+
+/// Which bindings of a tree use which others, see [`binding_graph`]
+#[derive(Clone, Debug)]
+pub struct BindingGraph {
+    resolver: Resolver,
+    edges: Vec<(BindingId, BindingId)>,
+    roots: Vec<BindingId>,
+}
+
+/// Build the graph of which bindings flow into which `let` and `rec`
+/// bindings.
+///
+/// There is an edge from a binding to every binding whose value refers to it,
+/// so `let a = 1; b = a; in b` has an edge from `a` to `b`, and
+/// `x: let y = x; in y` one from the formal `x` to `y`. References in the
+/// default of a formal, like `{ a, b ? a }:`, make an edge to the formal. All
+/// other references, like the one in the body of a `let`, make their binding
+/// a root of the graph.
+pub fn binding_graph(node: &SyntaxNode) -> BindingGraph {
+    let resolver = Resolver::new(node);
+    let mut edges = Vec::new();
+    let mut roots = Vec::new();
+    for binding in resolver.bindings() {
+        for reference in resolver.references(binding.id) {
+            let owners = owners(&resolver, reference);
+            if owners.is_empty() && !roots.contains(&binding.id) {
+                roots.push(binding.id);
+            }
+            edges.extend(owners.into_iter().map(|owner| (binding.id, owner)));
+        }
+    }
+    edges.sort();
+    edges.dedup();
+    BindingGraph { resolver, edges, roots }
+}
+
+/// The bindings whose value contains a reference: the innermost binding of a
+/// `let`, a `rec` set or a pattern around it. A reference in `inherit (x) a b;`
+/// belongs to both `a` and `b`.
+fn owners(resolver: &Resolver, reference: &ast::Ident) -> Vec<BindingId> {
+    let defined = |node: &SyntaxNode| resolver.binding_at(node).map(|binding| binding.id);
+    for ancestor in reference.syntax().ancestors() {
+        let owners: Vec<BindingId> = if let Some(entry) = ast::AttrpathValue::cast(ancestor.clone())
+        {
+            let attr = entry.attrpath().and_then(|attrpath| attrpath.attrs().next());
+            attr.and_then(|attr| defined(attr.syntax())).into_iter().collect()
+        } else if let Some(inherit) = ast::Inherit::cast(ancestor.clone()) {
+            match defined(reference.syntax()) {
+                // `inherit x;` refers to an outer `x`
+                Some(id) => vec![id],
+                None => inherit.attrs().filter_map(|attr| defined(attr.syntax())).collect(),
+            }
+        } else if let Some(entry) = ast::PatEntry::cast(ancestor.clone()) {
+            entry.ident().and_then(|ident| defined(ident.syntax())).into_iter().collect()
+        } else {
+            continue;
+        };
+        if !owners.is_empty() {
+            return owners;
+        }
+    }
+    Vec::new()
+}
+
+impl BindingGraph {
+    /// The resolver the graph was built with, which knows the bindings
+    pub fn resolver(&self) -> &Resolver {
+        &self.resolver
+    }
+
+    /// All edges, as pairs of the used binding and the one using it, sorted
+    pub fn edges(&self) -> &[(BindingId, BindingId)] {
+        &self.edges
+    }
+
+    /// The bindings used outside of the values of bindings, in the order they
+    /// appear in the tree
+    pub fn roots(&self) -> &[BindingId] {
+        &self.roots
+    }
+
+    /// The bindings whose values use a binding
+    pub fn users(&self, id: BindingId) -> impl Iterator<Item = BindingId> + '_ {
+        self.edges.iter().filter(move |(from, _)| *from == id).map(|&(_, to)| to)
+    }
+
+    /// The bindings the value of a binding uses
+    pub fn uses(&self, id: BindingId) -> impl Iterator<Item = BindingId> + '_ {
+        self.edges.iter().filter(move |(_, to)| *to == id).map(|&(from, _)| from)
+    }
+
+    /// The `let` bindings which don't contribute to the result: those which
+    /// are neither roots nor used by a root, a `rec` attribute or a formal,
+    /// directly or through other bindings
+    pub fn unused(&self) -> Vec<BindingId> {
+        let mut live: HashSet<BindingId> = self.roots.iter().copied().collect();
+        let mut stack: Vec<BindingId> = self.roots.clone();
+        for binding in self.resolver.bindings() {
+            let exported = match binding.kind {
+                BindingKind::LetIn => false,
+                BindingKind::LegacyLet => binding.name == "body",
+                _ => true,
+            };
+            if exported {
+                live.insert(binding.id);
+                stack.push(binding.id);
+            }
+        }
+        while let Some(id) = stack.pop() {
+            for used in self.uses(id) {
+                if live.insert(used) {
+                    stack.push(used);
+                }
+            }
+        }
+        self.resolver.bindings().map(|binding| binding.id).filter(|id| !live.contains(id)).collect()
+    }
+
+    /// Print the graph in Graphviz format. Formals are drawn as ellipses, other
+    /// bindings as boxes.
+    pub fn dot(&self) -> String {
+        let mut out = String::from("digraph bindings {\n  node [shape=box, fontname=monospace];\n");
+        for binding in self.resolver.bindings() {
+            let mut label = String::new();
+            json_string(&mut label, &binding.name);
+            let shape = match binding.kind {
+                BindingKind::Lambda | BindingKind::PatEntry | BindingKind::PatBind => {
+                    ", shape=ellipse"
+                }
+                BindingKind::LetIn | BindingKind::LegacyLet | BindingKind::RecAttrSet => "",
+            };
+            writeln!(out, "  b{} [label={}{}];", binding.id.index(), label, shape).unwrap();
+        }
+        for (from, to) in &self.edges {
+            writeln!(out, "  b{} -> b{};", from.index(), to.index()).unwrap();
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Root;
+
+    fn names(graph: &BindingGraph, ids: impl IntoIterator<Item = BindingId>) -> Vec<String> {
+        ids.into_iter().map(|id| graph.resolver().binding(id).name.clone()).collect()
+    }
+
+    const SRC: &str = "{ pkgs, version ? \"1\" }:
+let
+  name = \"foo-${version}\";
+  src = pkgs.fetchurl { inherit name; };
+  unused = helper;
+  helper = 1;
+in
+{ inherit src; meta = rec { a = name; b = a; }; }";
+
+    #[test]
+    fn edges() {
+        let root = Root::parse(SRC).ok().unwrap();
+        let graph = binding_graph(root.syntax());
+        let edges: Vec<String> = graph
+            .edges()
+            .iter()
+            .map(|&(from, to)| {
+                format!("{} -> {}", names(&graph, [from])[0], names(&graph, [to])[0])
+            })
+            .collect();
+        assert_eq!(
+            edges,
+            [
+                "pkgs -> src",
+                "version -> name",
+                "name -> src",
+                "name -> a",
+                "helper -> unused",
+                "a -> b"
+            ]
+        );
+        assert_eq!(names(&graph, graph.roots().iter().copied()), ["src"]);
+        assert_eq!(names(&graph, graph.unused()), ["unused", "helper"]);
+
+        let id = |name| graph.resolver().bindings().find(|b| b.name == name).unwrap().id;
+        assert_eq!(names(&graph, graph.uses(id("src"))), ["pkgs", "name"]);
+        assert_eq!(names(&graph, graph.users(id("name"))), ["src", "a"]);
+    }
+
+    #[test]
+    fn dot() {
+        let root = Root::parse("x: let y = x; in y").ok().unwrap();
+        assert_eq!(
+            binding_graph(root.syntax()).dot(),
+            r#"digraph bindings {
+  node [shape=box, fontname=monospace];
+  b0 [label="x", shape=ellipse];
+  b1 [label="y"];
+  b0 -> b1;
+}
+"#
+        );
+    }
+}
@@ -1,5 +1,6 @@
 //! Provides a type system for the AST, in some sense
 
+mod attr_util;
 mod expr_ext;
 mod interpol;
 mod nodes;
@@ -10,6 +11,7 @@ mod tokens;
 
 use crate::{NixLanguage, SyntaxKind, SyntaxToken};
 
+pub use attr_util::AttrSegment;
 pub use expr_ext::LiteralKind;
 pub use interpol::*;
 pub use nodes::*;
@@ -49,7 +51,7 @@ mod support {
     }
 
     pub(super) fn token<N: AstNode, T: AstToken>(parent: &N) -> Option<T> {
-        children_tokens(parent).nth(0)
+        children_tokens(parent).next()
     }
 
     /// Token untyped
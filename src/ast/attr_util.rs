@@ -0,0 +1,105 @@
+use rowan::ast::AstNode as OtherAstNode;
+
+use crate::ast::{self, InterpolPart};
+
+/// What an attribute in an attrpath consists of, with the name of string
+/// attributes decoded. See [`ast::Attr::segment`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AttrSegment {
+    /// A plain identifier like `a` in `a.b = 1;`
+    Ident(ast::Ident),
+    /// A string without interpolations like `"with spaces"`, with its escape
+    /// sequences interpreted and indentation removed
+    StringLiteral { str: ast::Str, decoded: String },
+    /// An interpolation like `${a}`, or a string containing one like `"a${b}"`
+    Dynamic(ast::Attr),
+}
+
+impl AttrSegment {
+    /// The name of the attribute, if it is known without evaluating anything
+    pub fn name(&self) -> Option<String> {
+        match self {
+            // `or` is a keyword, but can still be used as an attribute name
+            AttrSegment::Ident(ident) => Some(ident.syntax().first_token()?.text().to_string()),
+            AttrSegment::StringLiteral { decoded, .. } => Some(decoded.clone()),
+            AttrSegment::Dynamic(_) => None,
+        }
+    }
+}
+
+impl ast::Attr {
+    pub fn segment(&self) -> AttrSegment {
+        match self {
+            ast::Attr::Ident(ident) => AttrSegment::Ident(ident.clone()),
+            ast::Attr::Str(str) => {
+                let mut decoded = String::new();
+                for part in str.normalized_parts() {
+                    match part {
+                        InterpolPart::Literal(literal) => decoded.push_str(&literal),
+                        InterpolPart::Interpolation(_) => {
+                            return AttrSegment::Dynamic(self.clone())
+                        }
+                    }
+                }
+                AttrSegment::StringLiteral { str: str.clone(), decoded }
+            }
+            ast::Attr::Dynamic(_) => AttrSegment::Dynamic(self.clone()),
+        }
+    }
+
+    /// The name of the attribute, if it is an identifier or a string without
+    /// interpolations
+    pub fn static_name(&self) -> Option<String> {
+        self.segment().name()
+    }
+}
+
+impl ast::Attrpath {
+    /// The names of all attributes, if all of them are known statically
+    pub fn static_names(&self) -> Option<Vec<String>> {
+        self.attrs().map(|attr| attr.static_name()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Root;
+
+    use super::*;
+
+    fn attrs(src: &str) -> Vec<ast::Attr> {
+        let root = Root::parse(src).ok().unwrap();
+        root.syntax().descendants().find_map(ast::Attrpath::cast).unwrap().attrs().collect()
+    }
+
+    #[test]
+    fn segments() {
+        let attrs = attrs(r#"{ a."if"."with spaces\n".''  b''.${c}."d${e}".or = 1; }"#);
+        let names: Vec<Option<String>> = attrs.iter().map(ast::Attr::static_name).collect();
+        assert_eq!(
+            names,
+            [
+                Some("a".into()),
+                Some("if".into()),
+                Some("with spaces\n".into()),
+                Some("b".into()),
+                None,
+                None,
+                Some("or".into()),
+            ]
+        );
+        assert!(matches!(attrs[1].segment(), AttrSegment::StringLiteral { .. }));
+        assert!(matches!(attrs[4].segment(), AttrSegment::Dynamic(ast::Attr::Dynamic(_))));
+        assert!(matches!(attrs[5].segment(), AttrSegment::Dynamic(ast::Attr::Str(_))));
+    }
+
+    #[test]
+    fn static_names() {
+        let path = |src: &str| {
+            let root = Root::parse(src).ok().unwrap();
+            root.syntax().descendants().find_map(ast::Attrpath::cast).unwrap().static_names()
+        };
+        assert_eq!(path(r#"x.a."b""#), Some(vec!["a".to_string(), "b".to_string()]));
+        assert_eq!(path("x.a.${b}"), None);
+    }
+}
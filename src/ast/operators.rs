@@ -1,4 +1,7 @@
-use crate::SyntaxKind::{self, *};
+use crate::{
+    grammar::{self, Associativity},
+    SyntaxKind::{self, *},
+};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum BinOpKind {
@@ -19,6 +22,11 @@ pub enum BinOpKind {
     MoreOrEq,
     NotEqual,
     Or,
+
+    /// `x |> f`, only parsed with [`ParserOptions::experimental_pipe`](crate::parser::ParserOptions)
+    PipeRight,
+    /// `f <| x`, only parsed with [`ParserOptions::experimental_pipe`](crate::parser::ParserOptions)
+    PipeLeft,
 }
 
 impl BinOpKind {
@@ -43,9 +51,48 @@ impl BinOpKind {
             TOKEN_NOT_EQUAL => Some(BinOpKind::NotEqual),
             TOKEN_OR_OR => Some(BinOpKind::Or),
 
+            TOKEN_PIPE_RIGHT => Some(BinOpKind::PipeRight),
+            TOKEN_PIPE_LEFT => Some(BinOpKind::PipeLeft),
+
             _ => None,
         }
     }
+
+    /// The token of the operator
+    pub fn to_kind(self) -> SyntaxKind {
+        match self {
+            BinOpKind::Concat => TOKEN_CONCAT,
+            BinOpKind::Update => TOKEN_UPDATE,
+
+            BinOpKind::Add => TOKEN_ADD,
+            BinOpKind::Sub => TOKEN_SUB,
+            BinOpKind::Mul => TOKEN_MUL,
+            BinOpKind::Div => TOKEN_DIV,
+
+            BinOpKind::And => TOKEN_AND_AND,
+            BinOpKind::Equal => TOKEN_EQUAL,
+            BinOpKind::Implication => TOKEN_IMPLICATION,
+            BinOpKind::Less => TOKEN_LESS,
+            BinOpKind::LessOrEq => TOKEN_LESS_OR_EQ,
+            BinOpKind::More => TOKEN_MORE,
+            BinOpKind::MoreOrEq => TOKEN_MORE_OR_EQ,
+            BinOpKind::NotEqual => TOKEN_NOT_EQUAL,
+            BinOpKind::Or => TOKEN_OR_OR,
+
+            BinOpKind::PipeRight => TOKEN_PIPE_RIGHT,
+            BinOpKind::PipeLeft => TOKEN_PIPE_LEFT,
+        }
+    }
+
+    /// The precedence of the operator, see [`grammar::precedence`]
+    pub fn precedence(self) -> usize {
+        grammar::precedence(NODE_BIN_OP, self.to_kind()).expect("operator has a level")
+    }
+
+    /// The associativity of the operator, see [`grammar::level`]
+    pub fn associativity(self) -> Associativity {
+        grammar::level(NODE_BIN_OP, self.to_kind()).expect("operator has a level").associativity
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -63,4 +110,23 @@ impl UnaryOpKind {
             _ => None,
         }
     }
+
+    /// The token of the operator
+    pub fn to_kind(self) -> SyntaxKind {
+        match self {
+            UnaryOpKind::Invert => TOKEN_INVERT,
+            UnaryOpKind::Negate => TOKEN_SUB,
+        }
+    }
+
+    /// The precedence of the operator, see [`grammar::precedence`]
+    pub fn precedence(self) -> usize {
+        grammar::precedence(NODE_UNARY_OP, self.to_kind()).expect("operator has a level")
+    }
+
+    /// The associativity of the operator, which is always
+    /// [`Associativity::Prefix`]
+    pub fn associativity(self) -> Associativity {
+        grammar::level(NODE_UNARY_OP, self.to_kind()).expect("operator has a level").associativity
+    }
 }
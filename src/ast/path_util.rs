@@ -1,4 +1,4 @@
-use crate::{ast::AstToken, kinds::SyntaxKind::*};
+use crate::ast::AstToken;
 use rowan::{ast::AstNode as OtherAstNode, NodeOrToken};
 
 use crate::ast;
@@ -7,14 +7,10 @@ use super::{InterpolPart, PathContent};
 
 impl ast::nodes::Path {
     pub fn parts(&self) -> impl Iterator<Item = InterpolPart<PathContent>> {
-        self.syntax().children_with_tokens().map(|child| match child {
-            NodeOrToken::Token(token) => {
-                assert_eq!(token.kind(), TOKEN_PATH);
-                InterpolPart::Literal(PathContent::cast(token).unwrap())
-            }
-            NodeOrToken::Node(node) => {
-                InterpolPart::Interpolation(ast::Interpol::cast(node.clone()).unwrap())
-            }
+        // Anything else is only there in trees with errors
+        self.syntax().children_with_tokens().filter_map(|child| match child {
+            NodeOrToken::Token(token) => PathContent::cast(token).map(InterpolPart::Literal),
+            NodeOrToken::Node(node) => ast::Interpol::cast(node).map(InterpolPart::Interpolation),
         })
     }
 }
@@ -1,228 +1,61 @@
-use crate::kinds::SyntaxKind::*;
 use rowan::{ast::AstNode as OtherAstNode, NodeOrToken};
 
-use crate::ast;
+use crate::{
+    ast,
+    value::{dedent, unescape_string, Dedented},
+};
 
 use super::{support::children_tokens_u, AstToken, InterpolPart, StrContent};
 
 impl ast::Str {
     pub fn parts(&self) -> impl Iterator<Item = InterpolPart<StrContent>> {
+        // Besides the quotes, anything else is only there in trees with errors
         self.syntax().children_with_tokens().filter_map(|child| match child {
-            NodeOrToken::Token(token) if token.kind() == TOKEN_STRING_CONTENT => {
-                Some(InterpolPart::Literal(StrContent::cast(token).unwrap()))
-            }
-            NodeOrToken::Token(token) => {
-                assert!(token.kind() == TOKEN_STRING_START || token.kind() == TOKEN_STRING_END);
-                None
-            }
-            NodeOrToken::Node(node) => {
-                assert_eq!(node.kind(), NODE_INTERPOL);
-                Some(InterpolPart::Interpolation(ast::Interpol::cast(node.clone()).unwrap()))
-            }
+            NodeOrToken::Token(token) => StrContent::cast(token).map(InterpolPart::Literal),
+            NodeOrToken::Node(node) => ast::Interpol::cast(node).map(InterpolPart::Interpolation),
         })
     }
 
-    pub fn normalized_parts(&self) -> Vec<InterpolPart<String>> {
-        let multiline = children_tokens_u(self).next().map_or(false, |t| t.text() == "''");
-        let mut is_first_literal = true;
-        let mut at_start_of_line = true;
-        let mut min_indent = 1000000;
-        let mut cur_indent = 0;
-        let mut n = 0;
-        let mut first_is_literal = false;
-
-        let parts: Vec<InterpolPart<StrContent>> = self.parts().collect();
-
-        if multiline {
-            for part in &parts {
-                match part {
-                    InterpolPart::Interpolation(_) => {
-                        if at_start_of_line {
-                            at_start_of_line = false;
-                            min_indent = min_indent.min(cur_indent);
-                        }
-                        n += 1;
-                    }
-                    InterpolPart::Literal(literal) => {
-                        let mut token_text = literal.syntax().text();
-
-                        if n == 0 {
-                            first_is_literal = true;
-                        }
-
-                        if is_first_literal && first_is_literal {
-                            is_first_literal = false;
-                            if let Some(p) = token_text.find('\n') {
-                                if token_text[0..p].chars().all(|c| c == ' ') {
-                                    token_text = &token_text[p + 1..]
-                                }
-                            }
-                        }
-
-                        for c in token_text.chars() {
-                            if at_start_of_line {
-                                if c == ' ' {
-                                    cur_indent += 1;
-                                } else if c == '\n' {
-                                    cur_indent = 0;
-                                } else {
-                                    at_start_of_line = false;
-                                    min_indent = min_indent.min(cur_indent);
-                                }
-                            } else if c == '\n' {
-                                at_start_of_line = true;
-                                cur_indent = 0;
-                            }
-                        }
-
-                        n += 1;
-                    }
-                }
-            }
+    /// Remove the indentation of a `''` string like Nix does, see
+    /// [`value::dedent`](crate::value::dedent). Returns `None` for `"` strings.
+    pub fn dedented(&self) -> Option<Dedented> {
+        if children_tokens_u(self).next().is_none_or(|t| t.text() != "''") {
+            return None;
         }
-
-        let mut normalized_parts = Vec::new();
-        let mut cur_dropped = 0;
-        let mut i = 0;
-        is_first_literal = true;
-        at_start_of_line = true;
-
-        for part in parts {
-            match part {
+        let parts: Vec<InterpolPart<StrContent>> = self.parts().collect();
+        let parts: Vec<InterpolPart<&str>> = parts
+            .iter()
+            .map(|part| match part {
+                InterpolPart::Literal(literal) => InterpolPart::Literal(literal.syntax().text()),
                 InterpolPart::Interpolation(interpol) => {
-                    at_start_of_line = false;
-                    cur_dropped = 0;
-                    normalized_parts.push(InterpolPart::Interpolation(interpol));
-                    i += 1;
-                }
-                InterpolPart::Literal(literal) => {
-                    let mut token_text = literal.syntax().text();
-
-                    if multiline {
-                        if is_first_literal && first_is_literal {
-                            is_first_literal = false;
-                            if let Some(p) = token_text.find('\n') {
-                                if token_text[0..p].chars().all(|c| c == ' ') {
-                                    token_text = &token_text[p + 1..];
-                                    if token_text.is_empty() {
-                                        i += 1;
-                                        continue;
-                                    }
-                                }
-                            }
-                        }
-
-                        let mut str = String::new();
-                        for c in token_text.chars() {
-                            if at_start_of_line {
-                                if c == ' ' {
-                                    if cur_dropped >= min_indent {
-                                        str.push(c);
-                                    }
-                                    cur_dropped += 1;
-                                } else if c == '\n' {
-                                    cur_dropped = 0;
-                                    str.push(c);
-                                } else {
-                                    at_start_of_line = false;
-                                    cur_dropped = 0;
-                                    str.push(c);
-                                }
-                            } else {
-                                str.push(c);
-                                if c == '\n' {
-                                    at_start_of_line = true;
-                                }
-                            }
-                        }
-
-                        if i == n - 1 {
-                            if let Some(p) = str.rfind('\n') {
-                                if str[p + 1..].chars().all(|c| c == ' ') {
-                                    str.truncate(p + 1);
-                                }
-                            }
-                        }
-
-                        normalized_parts.push(InterpolPart::Literal(unescape(&str, multiline)));
-                        i += 1;
-                    } else {
-                        normalized_parts
-                            .push(InterpolPart::Literal(unescape(token_text, multiline)));
-                    }
+                    InterpolPart::Interpolation(interpol.clone())
                 }
-            }
-        }
-
-        normalized_parts
+            })
+            .collect();
+        Some(dedent(&parts))
     }
-}
 
-/// Interpret escape sequences in the nix string and return the converted value
-pub fn unescape(input: &str, multiline: bool) -> String {
-    let mut output = String::new();
-    let mut input = input.chars().peekable();
-    loop {
-        match input.next() {
-            None => break,
-            Some('"') if !multiline => break,
-            Some('\\') if !multiline => match input.next() {
-                None => break,
-                Some('n') => output.push('\n'),
-                Some('r') => output.push('\r'),
-                Some('t') => output.push('\t'),
-                Some(c) => output.push(c),
-            },
-            Some('\'') if multiline => match input.next() {
-                None => {
-                    output.push('\'');
-                }
-                Some('\'') => match input.peek() {
-                    Some('\'') => {
-                        input.next().unwrap();
-                        output.push_str("''");
-                    }
-                    Some('$') => {
-                        input.next().unwrap();
-                        output.push('$');
-                    }
-                    Some('\\') => {
-                        input.next().unwrap();
-                        match input.next() {
-                            None => break,
-                            Some('n') => output.push('\n'),
-                            Some('r') => output.push('\r'),
-                            Some('t') => output.push('\t'),
-                            Some(c) => output.push(c),
-                        }
-                    }
-                    _ => break,
-                },
-                Some(c) => {
-                    output.push('\'');
-                    output.push(c);
-                }
-            },
-            Some(c) => output.push(c),
+    pub fn normalized_parts(&self) -> Vec<InterpolPart<String>> {
+        if let Some(dedented) = self.dedented() {
+            return dedented.parts;
         }
+        self.parts()
+            .map(|part| match part {
+                InterpolPart::Literal(literal) => {
+                    InterpolPart::Literal(unescape_string(literal.syntax().text()))
+                }
+                InterpolPart::Interpolation(interpol) => InterpolPart::Interpolation(interpol),
+            })
+            .collect()
     }
-    output
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::Root;
+    use crate::{Root, SyntaxKind::*};
 
     use super::*;
 
-    #[test]
-    fn string_unescapes() {
-        assert_eq!(unescape(r#"Hello\n\"World\" :D"#, false), "Hello\n\"World\" :D");
-        assert_eq!(unescape(r#"\"Hello\""#, false), "\"Hello\"");
-
-        assert_eq!(unescape(r#"Hello''\n'''World''' :D"#, true), "Hello\n''World'' :D");
-        assert_eq!(unescape(r#""Hello""#, true), "\"Hello\"");
-    }
     #[test]
     fn parts_leading_ws() {
         let inp = "''\n  hello\n  world''";
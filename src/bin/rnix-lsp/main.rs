@@ -0,0 +1,378 @@
+//! A minimal language server for Nix, built with `--features lsp`
+//!
+//! Documents are synced in full and reparsed for every request, which keeps
+//! the server a thin layer over the analysis APIs of the library.
+
+use std::{collections::HashMap, error::Error};
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+    notification::{self, Notification as _},
+    request::{self, Request as _},
+    DiagnosticSeverity, FoldingRangeKind, FoldingRangeProviderCapability, GotoDefinitionResponse,
+    InitializeParams, Location, OneOf, Position, PublishDiagnosticsParams, Range,
+    ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+use rnix::{
+    analysis::{self, FoldingKind, Resolver},
+    format::{self, FormatOptions},
+    lint::{LintConfig, Registry, Severity},
+    refactor, LineIndex, Parse, Root, SyntaxKind, SyntaxNode, TextRange, TextSize,
+};
+use rowan::ast::AstNode;
+
+fn main() -> Result<(), Box<dyn Error + Sync + Send>> {
+    let (connection, io_threads) = Connection::stdio();
+    serve(&connection)?;
+    io_threads.join()?;
+    Ok(())
+}
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        rename_provider: Some(OneOf::Left(true)),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+        ..ServerCapabilities::default()
+    }
+}
+
+/// Handle messages until the client shuts the server down
+fn serve(connection: &Connection) -> Result<(), Box<dyn Error + Sync + Send>> {
+    let params = connection.initialize(serde_json::to_value(capabilities())?)?;
+    let _params: InitializeParams = serde_json::from_value(params)?;
+
+    let mut server = Server { documents: HashMap::new(), registry: Registry::builtin() };
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    return Ok(());
+                }
+                connection.sender.send(Message::Response(server.request(request)))?;
+            }
+            Message::Notification(notification) => {
+                if let Some(diagnostics) = server.notification(notification) {
+                    let params = serde_json::to_value(diagnostics)?;
+                    let method = notification::PublishDiagnostics::METHOD.to_string();
+                    connection
+                        .sender
+                        .send(Message::Notification(Notification { method, params }))?;
+                }
+            }
+            Message::Response(_) => (),
+        }
+    }
+    Ok(())
+}
+
+struct Server {
+    documents: HashMap<Url, String>,
+    registry: Registry,
+}
+
+/// A parsed document
+struct Document<'a> {
+    text: &'a str,
+    parse: Parse<Root>,
+    root: SyntaxNode,
+    lines: LineIndex,
+}
+
+impl Document<'_> {
+    fn position(&self, offset: TextSize) -> Position {
+        let (line, character) = self.lines.line_col_utf16(offset);
+        Position::new(line, character)
+    }
+
+    fn offset(&self, position: Position) -> Result<TextSize, (ErrorCode, String)> {
+        self.lines.offset_utf16(position.line, position.character).ok_or_else(|| {
+            let message = format!("invalid position {}:{}", position.line, position.character);
+            (ErrorCode::InvalidParams, message)
+        })
+    }
+
+    fn range(&self, range: TextRange) -> Range {
+        Range::new(self.position(range.start()), self.position(range.end()))
+    }
+
+    fn edit(&self, edit: &rnix::rewrite::TextEdit) -> lsp_types::TextEdit {
+        lsp_types::TextEdit::new(self.range(edit.range), edit.replacement.clone())
+    }
+}
+
+type RequestResult = Result<serde_json::Value, (ErrorCode, String)>;
+
+impl Server {
+    fn document(&self, uri: &Url) -> Result<Document<'_>, (ErrorCode, String)> {
+        let text = self
+            .documents
+            .get(uri)
+            .ok_or_else(|| (ErrorCode::InvalidParams, format!("unknown document {}", uri)))?;
+        let parse = Root::parse(text);
+        let root = parse.syntax();
+        Ok(Document { text, parse, root, lines: LineIndex::new(text) })
+    }
+
+    fn request(&mut self, request: Request) -> Response {
+        let id = request.id.clone();
+        let result = match request.method.as_str() {
+            request::GotoDefinition::METHOD => self.definition(request),
+            request::References::METHOD => self.references(request),
+            request::Rename::METHOD => self.rename(request),
+            request::Formatting::METHOD => self.formatting(request),
+            request::FoldingRangeRequest::METHOD => self.folding(request),
+            method => Err((ErrorCode::MethodNotFound, format!("unsupported request {}", method))),
+        };
+        match result {
+            Ok(result) => Response::new_ok(id, result),
+            Err((code, message)) => Response::new_err(id, code as i32, message),
+        }
+    }
+
+    /// Update the documents, returning the diagnostics to publish if one
+    /// changed
+    fn notification(&mut self, notification: Notification) -> Option<PublishDiagnosticsParams> {
+        let uri = match notification.method.as_str() {
+            notification::DidOpenTextDocument::METHOD => {
+                let params: lsp_types::DidOpenTextDocumentParams = params(notification)?;
+                let uri = params.text_document.uri;
+                self.documents.insert(uri.clone(), params.text_document.text);
+                uri
+            }
+            notification::DidChangeTextDocument::METHOD => {
+                let params: lsp_types::DidChangeTextDocumentParams = params(notification)?;
+                let uri = params.text_document.uri;
+                let text = params.content_changes.into_iter().last()?.text;
+                self.documents.insert(uri.clone(), text);
+                uri
+            }
+            notification::DidCloseTextDocument::METHOD => {
+                let params: lsp_types::DidCloseTextDocumentParams = params(notification)?;
+                self.documents.remove(&params.text_document.uri);
+                return Some(PublishDiagnosticsParams::new(
+                    params.text_document.uri,
+                    Vec::new(),
+                    None,
+                ));
+            }
+            _ => return None,
+        };
+        let doc = self.document(&uri).ok()?;
+        Some(PublishDiagnosticsParams::new(uri.clone(), self.diagnostics(&doc), None))
+    }
+
+    fn diagnostics(&self, doc: &Document) -> Vec<lsp_types::Diagnostic> {
+        let end = TextRange::empty(TextSize::of(doc.text));
+        let mut diagnostics: Vec<lsp_types::Diagnostic> = doc
+            .parse
+            .errors()
+            .iter()
+            .map(|error| lsp_types::Diagnostic {
+                range: doc.range(error.range().unwrap_or(end)),
+                severity: Some(DiagnosticSeverity::ERROR),
+                source: Some("rnix".into()),
+                message: error.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        let lints = self.registry.check(&doc.root, &LintConfig::default());
+        diagnostics.extend(lints.into_iter().map(|diagnostic| lsp_types::Diagnostic {
+            range: doc.range(diagnostic.range),
+            severity: Some(match diagnostic.severity {
+                Severity::Hint => DiagnosticSeverity::HINT,
+                Severity::Warning => DiagnosticSeverity::WARNING,
+                Severity::Error => DiagnosticSeverity::ERROR,
+            }),
+            code: Some(lsp_types::NumberOrString::String(diagnostic.rule.into())),
+            source: Some("rnix".into()),
+            message: diagnostic.message,
+            ..Default::default()
+        }));
+        diagnostics
+    }
+
+    fn definition(&self, request: Request) -> RequestResult {
+        let params: lsp_types::GotoDefinitionParams = extract(request)?;
+        let position = params.text_document_position_params;
+        let doc = self.document(&position.text_document.uri)?;
+        let offset = doc.offset(position.position)?;
+        let response = analysis::definition(&doc.root, offset).map(|site| {
+            GotoDefinitionResponse::Scalar(Location::new(
+                position.text_document.uri,
+                doc.range(site.range),
+            ))
+        });
+        to_value(response)
+    }
+
+    fn references(&self, request: Request) -> RequestResult {
+        let params: lsp_types::ReferenceParams = extract(request)?;
+        let position = params.text_document_position;
+        let doc = self.document(&position.text_document.uri)?;
+        let site = match analysis::definition(&doc.root, doc.offset(position.position)?) {
+            Some(site) => site,
+            None => return to_value(None::<Vec<Location>>),
+        };
+        let mut ranges = Vec::new();
+        if params.context.include_declaration {
+            ranges.push(site.range);
+        }
+        let resolver = Resolver::new(&doc.root);
+        if let Some(binding) = resolver.binding_at(&site.node) {
+            ranges.extend(
+                resolver.references(binding.id).iter().map(|ident| ident.syntax().text_range()),
+            );
+        }
+        let uri = position.text_document.uri;
+        let locations: Vec<Location> =
+            ranges.into_iter().map(|range| Location::new(uri.clone(), doc.range(range))).collect();
+        to_value(locations)
+    }
+
+    fn rename(&self, request: Request) -> RequestResult {
+        let params: lsp_types::RenameParams = extract(request)?;
+        let position = params.text_document_position;
+        let doc = self.document(&position.text_document.uri)?;
+        let offset = doc.offset(position.position)?;
+        let ident = doc
+            .root
+            .token_at_offset(offset)
+            .find(|token| token.kind() == SyntaxKind::TOKEN_IDENT)
+            .and_then(|token| token.parent())
+            .ok_or_else(|| (ErrorCode::InvalidParams, "no name to rename here".to_string()))?;
+        let edits = refactor::rename(&doc.root, &ident, &params.new_name)
+            .map_err(|conflict| (ErrorCode::RequestFailed, conflict.to_string()))?;
+        let edits = edits.iter().map(|edit| doc.edit(edit)).collect();
+        let changes = HashMap::from([(position.text_document.uri, edits)]);
+        to_value(lsp_types::WorkspaceEdit::new(changes))
+    }
+
+    fn formatting(&self, request: Request) -> RequestResult {
+        let params: lsp_types::DocumentFormattingParams = extract(request)?;
+        let doc = self.document(&params.text_document.uri)?;
+        if !doc.parse.errors().is_empty() {
+            return to_value(None::<Vec<lsp_types::TextEdit>>);
+        }
+        let options = FormatOptions { indent_width: params.options.tab_size as usize };
+        let edits: Vec<lsp_types::TextEdit> =
+            format::edits(&doc.root, &options).iter().map(|edit| doc.edit(edit)).collect();
+        to_value(edits)
+    }
+
+    fn folding(&self, request: Request) -> RequestResult {
+        let params: lsp_types::FoldingRangeParams = extract(request)?;
+        let doc = self.document(&params.text_document.uri)?;
+        let ranges: Vec<lsp_types::FoldingRange> = analysis::folding_ranges(&doc.root)
+            .into_iter()
+            .map(|folding| {
+                let range = doc.range(folding.range);
+                lsp_types::FoldingRange {
+                    start_line: range.start.line,
+                    start_character: Some(range.start.character),
+                    end_line: range.end.line,
+                    end_character: Some(range.end.character),
+                    kind: match folding.kind {
+                        FoldingKind::Comment => Some(FoldingRangeKind::Comment),
+                        FoldingKind::Region => Some(FoldingRangeKind::Region),
+                    },
+                    collapsed_text: None,
+                }
+            })
+            .collect();
+        to_value(ranges)
+    }
+}
+
+fn extract<P: serde::de::DeserializeOwned>(request: Request) -> Result<P, (ErrorCode, String)> {
+    serde_json::from_value(request.params)
+        .map_err(|err| (ErrorCode::InvalidParams, format!("invalid parameters: {}", err)))
+}
+
+fn params<P: serde::de::DeserializeOwned>(notification: Notification) -> Option<P> {
+    serde_json::from_value(notification.params).ok()
+}
+
+fn to_value(value: impl serde::Serialize) -> RequestResult {
+    serde_json::to_value(value).map_err(|err| (ErrorCode::InternalError, err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_server::RequestId;
+    use serde_json::{json, Value};
+
+    fn request(client: &Connection, id: i32, method: &str, params: Value) -> Value {
+        let request = Request::new(RequestId::from(id), method.to_string(), params);
+        client.sender.send(Message::Request(request)).unwrap();
+        loop {
+            match client.receiver.recv().unwrap() {
+                Message::Response(response) => {
+                    assert_eq!(response.id, RequestId::from(id));
+                    return response.result.unwrap_or(Value::Null);
+                }
+                _ => continue,
+            }
+        }
+    }
+
+    #[test]
+    fn session() {
+        let (server, client) = Connection::memory();
+        let thread = std::thread::spawn(move || serve(&server).unwrap());
+
+        let init = json!({ "capabilities": {} });
+        request(&client, 0, request::Initialize::METHOD, init);
+        let initialized = Notification::new("initialized".into(), json!({}));
+        client.sender.send(Message::Notification(initialized)).unwrap();
+
+        let uri = "file:///test.nix";
+        let text = "let\nx = 1;\nin [\n  x x ]";
+        let open = json!({ "textDocument": {
+            "uri": uri, "languageId": "nix", "version": 1, "text": text,
+        } });
+        let open = Notification::new(notification::DidOpenTextDocument::METHOD.into(), open);
+        client.sender.send(Message::Notification(open)).unwrap();
+        match client.receiver.recv().unwrap() {
+            Message::Notification(published) => {
+                assert_eq!(published.method, notification::PublishDiagnostics::METHOD);
+                assert_eq!(published.params["diagnostics"], json!([]));
+            }
+            message => panic!("expected diagnostics, got {:?}", message),
+        }
+
+        let position =
+            json!({ "textDocument": { "uri": uri }, "position": { "line": 3, "character": 4 } });
+        let definition = request(&client, 1, request::GotoDefinition::METHOD, position.clone());
+        assert_eq!(definition["range"]["start"], json!({ "line": 1, "character": 0 }));
+
+        let mut references = position.clone();
+        references["context"] = json!({ "includeDeclaration": true });
+        let references = request(&client, 2, request::References::METHOD, references);
+        assert_eq!(references.as_array().unwrap().len(), 3);
+
+        let mut rename = position;
+        rename["newName"] = json!("y");
+        let rename = request(&client, 3, request::Rename::METHOD, rename);
+        assert_eq!(rename["changes"][uri].as_array().unwrap().len(), 3);
+
+        let formatting = json!({ "textDocument": { "uri": uri }, "options": { "tabSize": 2, "insertSpaces": true } });
+        let formatting = request(&client, 4, request::Formatting::METHOD, formatting);
+        assert_eq!(formatting[0]["newText"], json!("\n  "));
+
+        let folding = json!({ "textDocument": { "uri": uri } });
+        let folding = request(&client, 5, request::FoldingRangeRequest::METHOD, folding);
+        assert_eq!(folding.as_array().unwrap().len(), 2);
+
+        request(&client, 6, request::Shutdown::METHOD, Value::Null);
+        client
+            .sender
+            .send(Message::Notification(Notification::new("exit".into(), Value::Null)))
+            .unwrap();
+        thread.join().unwrap();
+    }
+}
@@ -0,0 +1,52 @@
+use std::{env, path::Path, process::ExitCode};
+
+use rnix::{dump, Root, SyntaxNode};
+
+use crate::{read_input, Args, Result};
+
+/// `rnix dump <file> [--format sexpr|tree|json|dot|nix]`
+pub fn run(args: &[String]) -> Result<ExitCode> {
+    let args = Args::parse(args, &[], &["--format"])?;
+    let path = match args.positional.as_slice() {
+        [path] => path,
+        _ => return Err("expected a single file to dump".into()),
+    };
+    let format = args.option("--format").unwrap_or("sexpr");
+    let print: Box<dyn Fn(&SyntaxNode) -> String> = match format {
+        "sexpr" => Box::new(dump::sexpr),
+        "tree" => Box::new(dump::tree),
+        "json" => Box::new(|node| dump::json(node) + "\n"),
+        "dot" => Box::new(dump::dot),
+        "nix" => {
+            let base_dir = base_dir(path)?;
+            let home_dir = env::var("HOME").unwrap_or_default();
+            Box::new(move |node| match dump::nix(node, &base_dir, &home_dir) {
+                Some(printed) => printed + "\n",
+                None => String::new(),
+            })
+        }
+        format => return Err(format!("unknown format `{}`", format)),
+    };
+
+    let content = read_input(path)?;
+    let parse = Root::parse(&content);
+    // Nix doesn't print anything for code with errors either
+    if format != "nix" || parse.errors().is_empty() {
+        print!("{}", print(&parse.syntax()));
+    }
+    for error in parse.errors() {
+        eprintln!("{}: error: {}", path, error);
+    }
+    Ok(if parse.errors().is_empty() { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
+
+/// The directory relative paths are resolved against, like Nix does: the
+/// directory of the file, or the current directory for standard input
+fn base_dir(path: &str) -> Result<String> {
+    let dir = if path == "-" {
+        env::current_dir()
+    } else {
+        Path::new(path).canonicalize().map(|file| file.parent().unwrap_or(&file).to_path_buf())
+    };
+    dir.map(|dir| dir.to_string_lossy().into_owned()).map_err(|err| format!("{}: {}", path, err))
+}
@@ -0,0 +1,325 @@
+use std::{fmt::Write as _, fs, process::ExitCode};
+
+use rnix::{
+    format::{self, FormatOptions},
+    rewrite::{apply_edits, TextEdit},
+    Root,
+};
+
+use crate::{nix_files, read_input, Args, Result};
+
+/// `rnix fmt [--check] [--diff] [--stdin] <paths...>`
+///
+/// Exits with 1 if `--check` or `--diff` find a file which isn't formatted,
+/// and with 2 if a file can't be read or parsed.
+pub fn run(args: &[String]) -> Result<ExitCode> {
+    let args = Args::parse(args, &["--check", "--diff", "--stdin"], &[])?;
+    let check = args.flag("--check");
+    let diff = args.flag("--diff");
+
+    if args.flag("--stdin") {
+        if !args.positional.is_empty() {
+            return Err("`--stdin` can't be combined with paths".into());
+        }
+        let content = read_input("-")?;
+        let edits = edits(&content).map_err(|err| format!("<stdin>: {}", err))?;
+        if diff {
+            print!("{}", unified_diff("<stdin>", &content, &edits));
+        } else if !check {
+            print!("{}", apply_edits(&content, &edits));
+        }
+        return Ok(if edits.is_empty() || !(check || diff) {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        });
+    }
+
+    if args.positional.is_empty() {
+        return Err("expected files or directories to format".into());
+    }
+    let mut unformatted = false;
+    let mut failed = false;
+    for path in nix_files(&args.positional)? {
+        let name = path.display().to_string();
+        let result = read_input(&name).and_then(|content| {
+            let edits = edits(&content).map_err(|err| format!("{}: {}", name, err))?;
+            Ok((content, edits))
+        });
+        let (content, edits) = match result {
+            Ok(result) => result,
+            Err(err) => {
+                eprintln!("rnix: {}", err);
+                failed = true;
+                continue;
+            }
+        };
+        if edits.is_empty() {
+            continue;
+        }
+        unformatted = true;
+        if diff {
+            print!("{}", unified_diff(&name, &content, &edits));
+        } else if check {
+            println!("{}", name);
+        } else if let Err(err) = fs::write(&path, apply_edits(&content, &edits)) {
+            eprintln!("rnix: {}: {}", name, err);
+            failed = true;
+        }
+    }
+
+    Ok(if failed {
+        ExitCode::from(2)
+    } else if unformatted && (check || diff) {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+fn edits(content: &str) -> std::result::Result<Vec<TextEdit>, rnix::parser::ParseError> {
+    let parse = Root::parse(content);
+    if let Some(err) = parse.errors().first() {
+        return Err(err.clone());
+    }
+    Ok(format::edits(&parse.syntax(), &FormatOptions::default()))
+}
+
+/// The number of unchanged lines shown around changes
+const CONTEXT: usize = 3;
+
+/// Render the changes made by formatting as a unified diff. Each edit only
+/// touches the lines it spans, so hunks are built from the edits directly.
+fn unified_diff(name: &str, content: &str, edits: &[TextEdit]) -> String {
+    let line_starts: Vec<usize> =
+        std::iter::once(0).chain(content.match_indices('\n').map(|(i, _)| i + 1)).collect();
+    let line_count = if content.ends_with('\n') || content.is_empty() {
+        line_starts.len() - 1
+    } else {
+        line_starts.len()
+    };
+    let line_of = |offset: usize| line_starts.partition_point(|&start| start <= offset) - 1;
+    let line_start = |line: usize| line_starts.get(line).copied().unwrap_or(content.len());
+
+    let mut edits: Vec<&TextEdit> = edits.iter().collect();
+    edits.sort_by_key(|edit| edit.range.start());
+
+    // Group the edits into hunks of lines, merging hunks whose context overlaps
+    let mut hunks: Vec<(usize, usize, Vec<&TextEdit>)> = Vec::new();
+    for edit in edits {
+        let first = line_of(usize::from(edit.range.start())).min(line_count.saturating_sub(1));
+        let last = line_of(usize::from(edit.range.end())).min(line_count.saturating_sub(1));
+        let first = first.saturating_sub(CONTEXT);
+        let last = (last + CONTEXT).min(line_count.saturating_sub(1));
+        match hunks.last_mut() {
+            Some((_, end, hunk)) if first <= *end + 1 => {
+                *end = (*end).max(last);
+                hunk.push(edit);
+            }
+            _ => hunks.push((first, last, vec![edit])),
+        }
+    }
+
+    let mut out = format!("--- {}\n+++ {}\n", name, name);
+    let mut offset = 0isize;
+    for (first, last, hunk) in hunks {
+        let start = line_start(first);
+        let old = &content[start..line_start(last + 1)];
+        let shifted: Vec<TextEdit> = hunk
+            .iter()
+            .map(|edit| {
+                let range = edit.range - rnix::TextSize::from(start as u32);
+                TextEdit::replace(range, edit.replacement.clone())
+            })
+            .collect();
+        let new = apply_edits(old, &shifted);
+        let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+        let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+
+        let new_first = (first as isize + offset) as usize;
+        // Empty ranges are given by the line before them
+        let start = |first: usize, len: usize| if len == 0 { first } else { first + 1 };
+        writeln!(
+            out,
+            "@@ -{},{} +{},{} @@",
+            start(first, old_lines.len()),
+            old_lines.len(),
+            start(new_first, new_lines.len()),
+            new_lines.len()
+        )
+        .unwrap();
+        let prefix = old_lines.iter().zip(&new_lines).take_while(|(a, b)| a == b).count();
+        let suffix = old_lines[prefix..]
+            .iter()
+            .rev()
+            .zip(new_lines[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let old_changed = &old_lines[prefix..old_lines.len() - suffix];
+        let new_changed = &new_lines[prefix..new_lines.len() - suffix];
+        for line in &old_lines[..prefix] {
+            push_line(&mut out, ' ', line);
+        }
+        if old_changed.len() == new_changed.len() {
+            // Formatting keeps lines apart from the first and last one, so
+            // they can be compared one by one
+            let mut i = 0;
+            while i < old_changed.len() {
+                let end = (i..old_changed.len())
+                    .find(|&j| old_changed[j] == new_changed[j])
+                    .unwrap_or(old_changed.len());
+                if end == i {
+                    push_line(&mut out, ' ', old_changed[i]);
+                    i += 1;
+                    continue;
+                }
+                old_changed[i..end].iter().for_each(|line| push_line(&mut out, '-', line));
+                new_changed[i..end].iter().for_each(|line| push_line(&mut out, '+', line));
+                i = end;
+            }
+        } else {
+            old_changed.iter().for_each(|line| push_line(&mut out, '-', line));
+            new_changed.iter().for_each(|line| push_line(&mut out, '+', line));
+        }
+        for line in &old_lines[old_lines.len() - suffix..] {
+            push_line(&mut out, ' ', line);
+        }
+        offset += new_lines.len() as isize - old_lines.len() as isize;
+    }
+    out
+}
+
+fn push_line(out: &mut String, marker: char, line: &str) {
+    out.push(marker);
+    out.push_str(line);
+    if !line.ends_with('\n') {
+        out.push_str("\n\\ No newline at end of file\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rnix::{TextRange, TextSize};
+
+    use super::*;
+
+    /// Apply a diff to the old text, checking its context and removed lines
+    /// and the line counts of its hunks
+    fn patch(content: &str, diff: &str) -> String {
+        let old: Vec<&str> = content.split_inclusive('\n').collect();
+        let mut lines = diff.split_inclusive('\n').peekable();
+        assert_eq!(lines.next(), Some("--- test.nix\n"));
+        assert_eq!(lines.next(), Some("+++ test.nix\n"));
+
+        let mut out = String::new();
+        let mut pos = 0;
+        while let Some(header) = lines.next() {
+            let ranges = header.strip_prefix("@@ -").unwrap().strip_suffix(" @@\n").unwrap();
+            let (old_range, new_range) = ranges.split_once(" +").unwrap();
+            let parse = |range: &str| -> (usize, usize) {
+                let (start, len) = range.split_once(',').unwrap();
+                (start.parse().unwrap(), len.parse().unwrap())
+            };
+            let ((old_start, old_len), (_, new_len)) = (parse(old_range), parse(new_range));
+            // Empty ranges start at the line before them
+            let first = if old_len == 0 { old_start } else { old_start - 1 };
+            assert!(first >= pos, "{}", header);
+            old[pos..first].iter().for_each(|line| out.push_str(line));
+            pos = first;
+
+            let (mut removed, mut added) = (0, 0);
+            while let Some(line) = lines.next_if(|line| !line.starts_with("@@")) {
+                let (marker, mut text) = line.split_at(1);
+                if lines.next_if_eq(&"\\ No newline at end of file\n").is_some() {
+                    text = text.strip_suffix('\n').unwrap();
+                }
+                if marker != "+" {
+                    assert_eq!(old[pos], text);
+                    pos += 1;
+                    removed += 1;
+                }
+                if marker != "-" {
+                    out.push_str(text);
+                    added += 1;
+                }
+            }
+            assert_eq!((removed, added), (old_len, new_len), "{}", header);
+        }
+        old[pos..].iter().for_each(|line| out.push_str(line));
+        out
+    }
+
+    fn diff(content: &str, edits: &[TextEdit]) -> String {
+        let diff = unified_diff("test.nix", content, edits);
+        assert_eq!(patch(content, &diff), apply_edits(content, edits), "{}", diff);
+        diff
+    }
+
+    fn range(start: u32, end: u32) -> TextRange {
+        TextRange::new(TextSize::from(start), TextSize::from(end))
+    }
+
+    #[test]
+    fn formatted() {
+        let content = "{\na=1;\n  b = 2;\n}\n";
+        let edits = edits(content).unwrap();
+        assert_eq!(
+            diff(content, &edits),
+            "--- test.nix\n+++ test.nix\n@@ -1,4 +1,4 @@\n {\n-a=1;\n+  a=1;\n   b = 2;\n }\n"
+        );
+        assert_eq!(diff(content, &[]), "--- test.nix\n+++ test.nix\n");
+    }
+
+    #[test]
+    fn no_trailing_newline() {
+        assert_eq!(
+            diff("a\nb", &[TextEdit::replace(range(2, 3), "c")]),
+            "--- test.nix\n+++ test.nix\n@@ -1,2 +1,2 @@\n a\n\
+             -b\n\\ No newline at end of file\n+c\n\\ No newline at end of file\n"
+        );
+        assert_eq!(
+            diff("a\nb", &[TextEdit::insert(TextSize::from(3), "\n")]),
+            "--- test.nix\n+++ test.nix\n@@ -1,2 +1,2 @@\n a\n-b\n\\ No newline at end of file\n+b\n"
+        );
+        assert_eq!(
+            diff("a\nb\n", &[TextEdit::delete(range(3, 4))]),
+            "--- test.nix\n+++ test.nix\n@@ -1,2 +1,2 @@\n a\n-b\n+b\n\\ No newline at end of file\n"
+        );
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(
+            diff("", &[TextEdit::insert(TextSize::from(0), "{ }\n")]),
+            "--- test.nix\n+++ test.nix\n@@ -0,0 +1,1 @@\n+{ }\n"
+        );
+        assert_eq!(
+            diff("{ }\n", &[TextEdit::delete(range(0, 4))]),
+            "--- test.nix\n+++ test.nix\n@@ -1,1 +0,0 @@\n-{ }\n"
+        );
+    }
+
+    #[test]
+    fn hunks() {
+        let content: String = (0..20).map(|i| format!("{}\n", i)).collect();
+        let line = |i: u32| content.lines().take(i as usize).map(|l| l.len() as u32 + 1).sum();
+        let change = |i: u32| TextEdit::replace(range(line(i), line(i) + 1), "x");
+        let headers = |edits: &[TextEdit]| -> Vec<String> {
+            diff(&content, edits)
+                .lines()
+                .filter(|l| l.starts_with("@@"))
+                .map(String::from)
+                .collect()
+        };
+
+        // The contexts of changes to lines 1 and 8 touch, and are merged
+        assert_eq!(headers(&[change(1), change(8)]), ["@@ -1,12 +1,12 @@"]);
+        // With one more line between them they are apart
+        assert_eq!(headers(&[change(1), change(9)]), ["@@ -1,5 +1,5 @@", "@@ -7,7 +7,7 @@"]);
+        // Hunks end at the start and end of the file
+        assert_eq!(headers(&[change(0), change(19)]), ["@@ -1,4 +1,4 @@", "@@ -17,4 +17,4 @@"]);
+        // Lines added by an earlier hunk shift the later ones
+        let split = TextEdit::insert(TextSize::from(line(2)), "a\nb\n");
+        assert_eq!(headers(&[change(15), split]), ["@@ -1,6 +1,8 @@", "@@ -13,7 +15,7 @@"]);
+    }
+}
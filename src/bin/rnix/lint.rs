@@ -0,0 +1,155 @@
+use std::{fs, process::ExitCode};
+
+use rnix::{
+    lint::{Diagnostic, LintConfig, Registry, Severity},
+    rewrite::{apply_edits, TextEdit},
+    Root, TextRange,
+};
+use serde_json::json;
+
+use crate::{line_col, nix_files, read_input, Args, Result};
+
+/// `rnix lint [--format human|json] [--severity hint|warning|error] [--fix] [--strict] <paths...>`
+///
+/// `-` reads standard input, which can't be fixed in place.
+///
+/// With `--strict`, deprecated syntax is reported as an error, see
+/// [`LintConfig::strict`].
+///
+/// Exits with 1 if any diagnostics are reported, and with 2 if a file can't
+/// be read or written.
+pub fn run(args: &[String]) -> Result<ExitCode> {
+    let args = Args::parse(args, &["--fix", "--strict"], &["--format", "--severity"])?;
+    let json = match args.option("--format").unwrap_or("human") {
+        "human" => false,
+        "json" => true,
+        format => return Err(format!("unknown format `{}`", format)),
+    };
+    let min_severity = match args.option("--severity").unwrap_or("hint") {
+        "hint" => Severity::Hint,
+        "warning" => Severity::Warning,
+        "error" => Severity::Error,
+        severity => return Err(format!("unknown severity `{}`", severity)),
+    };
+    if args.positional.is_empty() {
+        return Err("expected files or directories to lint".into());
+    }
+    // Fixed source on stdout would be mixed up with the diagnostics
+    if args.flag("--fix") && args.positional.iter().any(|path| path == "-") {
+        return Err("`--fix` can't be combined with `-`".into());
+    }
+
+    let registry = Registry::builtin();
+    let config = if args.flag("--strict") { LintConfig::strict() } else { LintConfig::default() };
+    let mut reported = Vec::new();
+    let mut count = 0;
+    let mut failed = false;
+    for path in nix_files(&args.positional)? {
+        let name = path.display().to_string();
+        let mut content = match read_input(&name) {
+            Ok(content) => content,
+            Err(err) => {
+                eprintln!("rnix: {}", err);
+                failed = true;
+                continue;
+            }
+        };
+        let mut diagnostics = check(&registry, &config, &content);
+
+        if args.flag("--fix") {
+            // Overlapping fixes are left for the next round
+            let original = content.clone();
+            for _ in 0..MAX_FIX_ROUNDS {
+                let edits = fix_edits(diagnostics.iter().filter(|d| d.severity >= min_severity));
+                if edits.is_empty() {
+                    break;
+                }
+                content = apply_edits(&content, &edits);
+                diagnostics = check(&registry, &config, &content);
+            }
+            if content != original {
+                if let Err(err) = fs::write(&path, &content) {
+                    eprintln!("rnix: {}: {}", name, err);
+                    failed = true;
+                    continue;
+                }
+            }
+        }
+
+        for diagnostic in diagnostics.into_iter().filter(|d| d.severity >= min_severity) {
+            count += 1;
+            let (line, column) = line_col(&content, diagnostic.range);
+            if json {
+                reported.push(json!({
+                    "file": name,
+                    "rule": diagnostic.rule,
+                    "severity": diagnostic.severity.to_string(),
+                    "span": {
+                        "start": u32::from(diagnostic.range.start()),
+                        "end": u32::from(diagnostic.range.end()),
+                    },
+                    "line": line,
+                    "column": column,
+                    "message": diagnostic.message,
+                    "fix": diagnostic.fix.as_ref().map(|fix| &fix.message),
+                }));
+            } else {
+                println!(
+                    "{}:{}:{}: {}: {} [{}]",
+                    name, line, column, diagnostic.severity, diagnostic.message, diagnostic.rule
+                );
+            }
+        }
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&reported).unwrap());
+    }
+
+    Ok(if failed {
+        ExitCode::from(2)
+    } else if count > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+/// Run the lints on a text, reporting parse errors as diagnostics of the
+/// `parse` rule
+fn check(registry: &Registry, config: &LintConfig, content: &str) -> Vec<Diagnostic> {
+    let parse = Root::parse(content);
+    let end = TextRange::empty(rnix::TextSize::of(content));
+    let mut diagnostics: Vec<Diagnostic> = parse
+        .errors()
+        .iter()
+        .map(|error| Diagnostic {
+            rule: "parse",
+            severity: Severity::Error,
+            range: error.range().unwrap_or(end),
+            message: error.to_string(),
+            fix: None,
+        })
+        .collect();
+    diagnostics.extend(registry.check(&parse.syntax(), config));
+    diagnostics.sort_by_key(|d| (d.range.start(), d.range.end()));
+    diagnostics
+}
+
+/// How often fixes are applied and the file is checked again
+const MAX_FIX_ROUNDS: usize = 10;
+
+/// Collect the edits of all fixes, leaving out fixes which overlap or touch an
+/// earlier one
+fn fix_edits<'a>(diagnostics: impl Iterator<Item = &'a Diagnostic>) -> Vec<TextEdit> {
+    let mut edits: Vec<TextEdit> = Vec::new();
+    for fix in diagnostics.filter_map(|d| d.fix.as_ref()) {
+        let overlaps = fix
+            .edits
+            .iter()
+            .any(|edit| edits.iter().any(|other| edit.range.intersect(other.range).is_some()));
+        if !overlaps {
+            edits.extend(fix.edits.iter().cloned());
+        }
+    }
+    edits
+}
@@ -0,0 +1,154 @@
+//! The `rnix` command line tool, built with `--features cli`
+
+mod dump;
+mod fmt;
+mod lint;
+mod search;
+mod tokens;
+
+use std::{
+    env, fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use rnix::{LineCol, LineIndex, TextRange};
+
+type Result<T> = std::result::Result<T, String>;
+
+const USAGE: &str = "\
+Usage: rnix <command> [options]
+
+Commands:
+  dump <file> [--format sexpr|tree|json|dot|nix]
+                                           print the syntax tree of a file
+  fmt [--check] [--diff] <paths...>        format files, or all .nix files in directories
+  fmt --stdin [--check]                    format standard input to standard output
+  lint [--format human|json] [--severity hint|warning|error] [--fix] [--strict] <paths...>
+                                           check files with the builtin lints, with
+                                           `--strict` rejecting deprecated syntax
+  search <pattern> [--format human|json] <paths...>
+                                           find code matching a pattern, with `_` and
+                                           `$name` matching any expression
+  tokens <file> [--format human|json]      print the tokens of a file
+
+A file name of `-` reads from standard input.";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("dump") => dump::run(&args[1..]),
+        Some("fmt") => fmt::run(&args[1..]),
+        Some("lint") => lint::run(&args[1..]),
+        Some("search") => search::run(&args[1..]),
+        Some("tokens") => tokens::run(&args[1..]),
+        Some("-h" | "--help") => {
+            println!("{}", USAGE);
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(command) => Err(format!("unknown command `{}`\n\n{}", command, USAGE)),
+        None => Err(USAGE.to_string()),
+    };
+    result.unwrap_or_else(|err| {
+        eprintln!("rnix: {}", err);
+        ExitCode::from(2)
+    })
+}
+
+/// The parsed command line of a subcommand
+struct Args {
+    positional: Vec<String>,
+    flags: Vec<String>,
+    options: Vec<(String, String)>,
+}
+
+impl Args {
+    /// Split arguments into positional ones, the given flags, and the given
+    /// options taking a value, as either `--name value` or `--name=value`
+    fn parse(args: &[String], flags: &[&str], options: &[&str]) -> Result<Self> {
+        let mut parsed = Args { positional: Vec::new(), flags: Vec::new(), options: Vec::new() };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                parsed.positional.push(arg.clone());
+                continue;
+            }
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (arg.as_str(), None),
+            };
+            if flags.contains(&name) && value.is_none() {
+                parsed.flags.push(name.to_string());
+            } else if options.contains(&name) {
+                let value = match value {
+                    Some(value) => value,
+                    None => args.next().ok_or_else(|| format!("`{}` needs a value", name))?.clone(),
+                };
+                parsed.options.push((name.to_string(), value));
+            } else {
+                return Err(format!("unknown option `{}`", arg));
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|flag| flag == name)
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .rev()
+            .find(|(option, _)| option == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Collect the given files, and all `.nix` files in the given directories
+fn nix_files(paths: &[String]) -> Result<Vec<PathBuf>> {
+    fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+        let entries = fs::read_dir(dir).map_err(|err| format!("{}: {}", dir.display(), err))?;
+        let mut entries: Vec<PathBuf> =
+            entries.filter_map(|entry| Some(entry.ok()?.path())).collect();
+        entries.sort();
+        for path in entries {
+            if path.is_dir() {
+                walk(&path, files)?;
+            } else if path.extension().is_some_and(|ext| ext == "nix") {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = Vec::new();
+    for path in paths {
+        let path = Path::new(path);
+        if path.is_dir() {
+            walk(path, &mut files)?;
+        } else {
+            files.push(path.to_path_buf());
+        }
+    }
+    Ok(files)
+}
+
+/// Read a file, or standard input for `-`
+fn read_input(path: &str) -> Result<String> {
+    if path == "-" {
+        let mut content = String::new();
+        io::stdin().read_to_string(&mut content).map_err(|err| format!("stdin: {}", err))?;
+        Ok(content)
+    } else {
+        fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))
+    }
+}
+
+/// The one-based line and column of the start of a range, counting columns in
+/// characters
+fn line_col(content: &str, range: TextRange) -> (usize, usize) {
+    let LineCol { line, col } = LineIndex::new(content).line_col(range.start());
+    (line as usize + 1, col as usize + 1)
+}
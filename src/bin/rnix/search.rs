@@ -0,0 +1,75 @@
+use std::process::ExitCode;
+
+use rnix::{search::Pattern, Root};
+use serde_json::{json, Map, Value};
+
+use crate::{line_col, nix_files, read_input, Args, Result};
+
+/// `rnix search <pattern> [--format human|json] <paths...>`
+///
+/// Exits with 1 if nothing matches, like `grep`, and with 2 if the pattern
+/// isn't valid or a file can't be read.
+pub fn run(args: &[String]) -> Result<ExitCode> {
+    let args = Args::parse(args, &[], &["--format"])?;
+    let json = match args.option("--format").unwrap_or("human") {
+        "human" => false,
+        "json" => true,
+        format => return Err(format!("unknown format `{}`", format)),
+    };
+    let (pattern, paths) = match args.positional.split_first() {
+        Some((pattern, paths)) if !paths.is_empty() => (pattern, paths),
+        _ => return Err("expected a pattern and files or directories to search".into()),
+    };
+    let pattern = Pattern::parse(pattern).map_err(|err| format!("invalid pattern: {}", err))?;
+
+    let mut reported = Vec::new();
+    let mut count = 0;
+    let mut failed = false;
+    for path in nix_files(paths)? {
+        let name = path.display().to_string();
+        let content = match read_input(&name) {
+            Ok(content) => content,
+            Err(err) => {
+                eprintln!("rnix: {}", err);
+                failed = true;
+                continue;
+            }
+        };
+        for found in pattern.find(&Root::parse(&content).syntax()) {
+            count += 1;
+            let (line, column) = line_col(&content, found.range());
+            let text = found.node.text().to_string();
+            if json {
+                let captures: Map<String, Value> = found
+                    .captures
+                    .iter()
+                    .map(|(name, node)| (name.clone(), node.text().to_string().into()))
+                    .collect();
+                reported.push(json!({
+                    "file": name,
+                    "span": {
+                        "start": u32::from(found.range().start()),
+                        "end": u32::from(found.range().end()),
+                    },
+                    "line": line,
+                    "column": column,
+                    "text": text,
+                    "captures": captures,
+                }));
+            } else {
+                println!("{}:{}:{}: {}", name, line, column, text.lines().next().unwrap_or(""));
+            }
+        }
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&reported).unwrap());
+    }
+
+    Ok(if failed {
+        ExitCode::from(2)
+    } else if count == 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
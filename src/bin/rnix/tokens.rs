@@ -0,0 +1,45 @@
+use std::process::ExitCode;
+
+use rnix::{tokenize, SyntaxKind};
+use serde_json::json;
+
+use crate::{read_input, Args, Result};
+
+/// `rnix tokens <file> [--format human|json]`
+pub fn run(args: &[String]) -> Result<ExitCode> {
+    let args = Args::parse(args, &[], &["--format"])?;
+    let path = match args.positional.as_slice() {
+        [path] => path,
+        _ => return Err("expected a single file to tokenize".into()),
+    };
+    let json = match args.option("--format").unwrap_or("human") {
+        "human" => false,
+        "json" => true,
+        format => return Err(format!("unknown format `{}`", format)),
+    };
+
+    let content = read_input(path)?;
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut has_errors = false;
+    for (kind, text) in tokenize(&content) {
+        let end = start + text.len();
+        has_errors |= kind == SyntaxKind::TOKEN_ERROR;
+        if json {
+            tokens.push(json!({
+                "kind": format!("{:?}", kind),
+                "span": { "start": start, "end": end },
+                "text": text,
+                "trivia": kind.is_trivia(),
+            }));
+        } else {
+            let trivia = if kind.is_trivia() { " (trivia)" } else { "" };
+            println!("{}..{} {:?} {:?}{}", start, end, kind, text, trivia);
+        }
+        start = end;
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&tokens).unwrap());
+    }
+    Ok(if has_errors { ExitCode::FAILURE } else { ExitCode::SUCCESS })
+}
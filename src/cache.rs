@@ -0,0 +1,375 @@
+//! A cache of parsed files on disk, for tools which parse the same files on
+//! every run
+//!
+//! Trees are stored in a directory of the user's choosing, in one file per
+//! source named after its [`source_hash`]. Only the kinds and lengths of the
+//! tokens are stored, their text is taken from the source when loading. Every
+//! file starts with the version of this crate and the number of syntax kinds,
+//! and is ignored when either differs, so updating the crate invalidates the
+//! cache without having to clear it.
+
+use std::{
+    fs, io,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+};
+
+use rowan::{GreenNodeBuilder, Language, TextRange, TextSize};
+
+use crate::{
+    hash::source_hash, parser::ParseError, NixLanguage, NodeOrToken, Parse, Root, SyntaxKind,
+    SyntaxNode,
+};
+
+const MAGIC: &[u8] = b"rnix-parse-cache\0";
+
+const START_NODE: u8 = 0;
+const TOKEN: u8 = 1;
+const FINISH_NODE: u8 = 2;
+
+/// A directory of cached parses
+#[derive(Clone, Debug)]
+pub struct ParseCache {
+    dir: PathBuf,
+}
+
+impl ParseCache {
+    /// Use a directory for the cache, which is created when the first tree is
+    /// stored
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Parse a source, loading the tree from the cache if it was stored
+    /// before, and storing it otherwise. The cache is only an optimization, so
+    /// failing to write to it is ignored.
+    pub fn parse(&self, source: &str) -> Parse<Root> {
+        if let Some(parse) = self.load(source) {
+            return parse;
+        }
+        let parse = Root::parse(source);
+        let _ = self.store(source, &parse);
+        parse
+    }
+
+    /// Load the tree of a source, if it is in the cache. Files written by
+    /// another version of this crate and damaged files are treated as missing.
+    pub fn load(&self, source: &str) -> Option<Parse<Root>> {
+        let bytes = fs::read(self.path(source)).ok()?;
+        decode(&bytes, source)
+    }
+
+    /// Store the tree of a source, replacing the one stored before. The file
+    /// is written under another name first, so other processes reading the
+    /// cache at the same time never see a partial file.
+    pub fn store(&self, source: &str, parse: &Parse<Root>) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.path(source);
+        let temp = path.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&temp, encode(parse))?;
+        fs::rename(&temp, &path)
+    }
+
+    fn path(&self, source: &str) -> PathBuf {
+        self.dir.join(source_hash(source).to_string())
+    }
+}
+
+fn header() -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    let version = env!("CARGO_PKG_VERSION");
+    out.extend((version.len() as u32).to_le_bytes());
+    out.extend(version.as_bytes());
+    out.extend((SyntaxKind::__LAST as u16).to_le_bytes());
+    out
+}
+
+fn encode(parse: &Parse<Root>) -> Vec<u8> {
+    let mut out = header();
+    encode_node(&mut out, &parse.syntax());
+    out.extend((parse.errors.len() as u32).to_le_bytes());
+    for error in &parse.errors {
+        encode_error(&mut out, error);
+    }
+    out
+}
+
+fn encode_node(out: &mut Vec<u8>, node: &SyntaxNode) {
+    out.push(START_NODE);
+    out.extend((node.kind() as u16).to_le_bytes());
+    for child in node.children_with_tokens() {
+        match child {
+            NodeOrToken::Node(node) => encode_node(out, &node),
+            NodeOrToken::Token(token) => {
+                out.push(TOKEN);
+                out.extend((token.kind() as u16).to_le_bytes());
+                out.extend(u32::from(token.text_range().len()).to_le_bytes());
+            }
+        }
+    }
+    out.push(FINISH_NODE);
+}
+
+fn encode_range(out: &mut Vec<u8>, range: TextRange) {
+    out.extend(u32::from(range.start()).to_le_bytes());
+    out.extend(u32::from(range.end()).to_le_bytes());
+}
+
+fn encode_kinds(out: &mut Vec<u8>, kinds: &[SyntaxKind]) {
+    out.extend((kinds.len() as u32).to_le_bytes());
+    for &kind in kinds {
+        out.extend((kind as u16).to_le_bytes());
+    }
+}
+
+fn encode_error(out: &mut Vec<u8>, error: &ParseError) {
+    match error {
+        ParseError::Unexpected(range) => {
+            out.push(0);
+            encode_range(out, *range);
+        }
+        ParseError::UnexpectedExtra(range) => {
+            out.push(1);
+            encode_range(out, *range);
+        }
+        ParseError::UnexpectedWanted(got, range, kinds) => {
+            out.push(2);
+            out.extend((*got as u16).to_le_bytes());
+            encode_range(out, *range);
+            encode_kinds(out, kinds);
+        }
+        ParseError::UnexpectedDoubleBind(range, first) => {
+            out.push(3);
+            encode_range(out, *range);
+            encode_range(out, *first);
+        }
+        ParseError::UnexpectedEOF => out.push(4),
+        ParseError::UnexpectedEOFWanted(kinds) => {
+            out.push(5);
+            encode_kinds(out, kinds);
+        }
+        ParseError::DuplicatedArgs(range, ident) => {
+            out.push(6);
+            encode_range(out, *range);
+            out.extend((ident.len() as u32).to_le_bytes());
+            out.extend(ident.as_bytes());
+        }
+        ParseError::RecursionLimitExceeded => out.push(7),
+        ParseError::IntegerOverflow(range) => {
+            out.push(8);
+            encode_range(out, *range);
+        }
+        ParseError::InvalidToken(range) => {
+            out.push(9);
+            encode_range(out, *range);
+        }
+        ParseError::UriLiteral(range) => {
+            out.push(10);
+            encode_range(out, *range);
+        }
+        ParseError::LegacyLet(range) => {
+            out.push(11);
+            encode_range(out, *range);
+        }
+        ParseError::DuplicatedAttr(range, path) => {
+            out.push(12);
+            encode_range(out, *range);
+            out.extend((path.len() as u32).to_le_bytes());
+            out.extend(path.as_bytes());
+        }
+    }
+}
+
+/// Reads the encoding, returning `None` on anything unexpected
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.bytes.len() {
+            return None;
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Some(taken)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_le_bytes(self.take(2)?.try_into().ok()?))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn kind(&mut self) -> Option<SyntaxKind> {
+        let raw = self.u16()?;
+        (raw <= SyntaxKind::__LAST as u16)
+            .then(|| NixLanguage::kind_from_raw(rowan::SyntaxKind(raw)))
+    }
+
+    fn kinds(&mut self) -> Option<Box<[SyntaxKind]>> {
+        let len = self.u32()?;
+        (0..len).map(|_| self.kind()).collect()
+    }
+
+    fn range(&mut self) -> Option<TextRange> {
+        let (start, end) = (self.u32()?, self.u32()?);
+        (start <= end).then(|| TextRange::new(TextSize::from(start), TextSize::from(end)))
+    }
+}
+
+fn decode(bytes: &[u8], source: &str) -> Option<Parse<Root>> {
+    let mut reader = Reader { bytes: bytes.strip_prefix(&header()[..])? };
+
+    // The tree is a single root node, and the loop stops once it is finished
+    if reader.u8()? != START_NODE || reader.kind()? != SyntaxKind::NODE_ROOT {
+        return None;
+    }
+    let mut builder = GreenNodeBuilder::new();
+    builder.start_node(NixLanguage::kind_to_raw(SyntaxKind::NODE_ROOT));
+    let mut depth = 1usize;
+    let mut offset = 0;
+    loop {
+        match reader.u8()? {
+            START_NODE => {
+                builder.start_node(NixLanguage::kind_to_raw(reader.kind()?));
+                depth += 1;
+            }
+            TOKEN => {
+                let kind = reader.kind()?;
+                let end = offset + reader.u32()? as usize;
+                builder.token(NixLanguage::kind_to_raw(kind), source.get(offset..end)?);
+                offset = end;
+            }
+            FINISH_NODE if depth > 0 => {
+                builder.finish_node();
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            _ => return None,
+        }
+    }
+    if offset != source.len() {
+        return None;
+    }
+
+    let count = reader.u32()?;
+    let mut errors = Vec::new();
+    for _ in 0..count {
+        errors.push(decode_error(&mut reader)?);
+    }
+    if !reader.bytes.is_empty() {
+        return None;
+    }
+    Some(Parse { green: builder.finish(), errors, _ty: PhantomData })
+}
+
+fn decode_error(reader: &mut Reader) -> Option<ParseError> {
+    Some(match reader.u8()? {
+        0 => ParseError::Unexpected(reader.range()?),
+        1 => ParseError::UnexpectedExtra(reader.range()?),
+        2 => ParseError::UnexpectedWanted(reader.kind()?, reader.range()?, reader.kinds()?),
+        3 => ParseError::UnexpectedDoubleBind(reader.range()?, reader.range()?),
+        4 => ParseError::UnexpectedEOF,
+        5 => ParseError::UnexpectedEOFWanted(reader.kinds()?),
+        6 => {
+            let range = reader.range()?;
+            let len = reader.u32()? as usize;
+            let ident = String::from_utf8(reader.take(len)?.to_vec()).ok()?;
+            ParseError::DuplicatedArgs(range, ident)
+        }
+        7 => ParseError::RecursionLimitExceeded,
+        8 => ParseError::IntegerOverflow(reader.range()?),
+        9 => ParseError::InvalidToken(reader.range()?),
+        10 => ParseError::UriLiteral(reader.range()?),
+        11 => ParseError::LegacyLet(reader.range()?),
+        12 => {
+            let range = reader.range()?;
+            let len = reader.u32()? as usize;
+            let path = String::from_utf8(reader.take(len)?.to_vec()).ok()?;
+            ParseError::DuplicatedAttr(range, path)
+        }
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_cache(name: &str) -> ParseCache {
+        let dir = std::env::temp_dir().join(format!("rnix-cache-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        ParseCache::new(dir)
+    }
+
+    #[test]
+    fn roundtrip() {
+        let cache = temp_cache("roundtrip");
+        for source in [
+            "{ a = 1; /* comment */ b = \"${c}\"; }\n",
+            "{ a, a }@x@y: 99999999999999999999 + ",
+            "",
+        ] {
+            let parse = Root::parse(source);
+            assert!(cache.load(source).is_none());
+            cache.store(source, &parse).unwrap();
+            let loaded = cache.load(source).unwrap();
+            assert_eq!(loaded.green, parse.green);
+            assert_eq!(loaded.errors(), parse.errors());
+        }
+        fs::remove_dir_all(cache.dir()).unwrap();
+    }
+
+    #[test]
+    fn invalid() {
+        let cache = temp_cache("invalid");
+        let source = "let a = 1; in a";
+        let parse = cache.parse(source);
+        let path = cache.path(source);
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(decode(&bytes, source).map(|parse| parse.green), Some(parse.green.clone()));
+
+        // Another version, truncated files and sources of another length are
+        // misses
+        let mut other = bytes.clone();
+        other[MAGIC.len() + 4] ^= 1;
+        assert!(decode(&other, source).is_none());
+        for len in 0..bytes.len() {
+            assert!(decode(&bytes[..len], source).is_none());
+        }
+        assert!(decode(&bytes, "let a = 1; in a ").is_none());
+
+        // Trees which aren't a single root node are damaged
+        let tree = &bytes[header().len()..];
+        let mut token_first = header();
+        token_first.extend([TOKEN, SyntaxKind::TOKEN_IDENT as u8, 0, 1, 0, 0, 0]);
+        token_first.extend_from_slice(tree);
+        assert!(decode(&token_first, &format!("x{}", source)).is_none());
+        let mut ident_root = bytes.clone();
+        ident_root[header().len() + 1] = SyntaxKind::NODE_IDENT as u8;
+        assert!(decode(&ident_root, source).is_none());
+        let mut unbalanced = header();
+        unbalanced.extend([START_NODE, SyntaxKind::NODE_ROOT as u8, 0, FINISH_NODE, FINISH_NODE]);
+        assert!(decode(&unbalanced, "").is_none());
+        fs::write(&path, &ident_root).unwrap();
+        assert_eq!(cache.parse(source).green, parse.green);
+
+        fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert_eq!(cache.parse(source).green, parse.green);
+        assert_eq!(fs::read(&path).unwrap(), bytes);
+        fs::remove_dir_all(cache.dir()).unwrap();
+    }
+}
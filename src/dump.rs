@@ -0,0 +1,277 @@
+//! Printing syntax trees with their spans, for debugging and for other tools
+
+use std::fmt::Write;
+
+use crate::{NodeOrToken, SyntaxElement, SyntaxKind::TOKEN_WHITESPACE, SyntaxNode, TextRange};
+
+mod diff;
+mod nix;
+
+#[cfg(feature = "json")]
+pub use self::diff::read_json;
+pub use self::{
+    diff::{diff, read_sexpr, DumpDiff, DumpElement, DumpReadError},
+    nix::nix,
+};
+
+/// Print a tree as JSON. Nodes are printed as `{ kind, span, children }` and
+/// tokens as `{ kind, span, text }`, with spans as `{ start, end }` byte
+/// offsets.
+pub fn json(node: &SyntaxNode) -> String {
+    let mut out = String::new();
+    write_json(&mut out, &NodeOrToken::Node(node.clone()));
+    out
+}
+
+fn write_json(out: &mut String, element: &SyntaxElement) {
+    let range = element.text_range();
+    write!(
+        out,
+        r#"{{"kind":"{:?}","span":{{"start":{},"end":{}}},"#,
+        element.kind(),
+        u32::from(range.start()),
+        u32::from(range.end())
+    )
+    .unwrap();
+    match element {
+        NodeOrToken::Node(node) => {
+            out.push_str(r#""children":["#);
+            for (i, child) in node.children_with_tokens().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_json(out, &child);
+            }
+            out.push_str("]}");
+        }
+        NodeOrToken::Token(token) => {
+            out.push_str(r#""text":"#);
+            json_string(out, token.text());
+            out.push('}');
+        }
+    }
+}
+
+/// Write a JSON string literal
+pub(crate) fn json_string(out: &mut String, text: &str) {
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Print the parse errors of a source text and the findings of the builtin
+/// lints as a JSON list of `{ span, severity, source, message }`, for the
+/// bindings to other languages
+#[cfg(any(feature = "python", feature = "wasm"))]
+pub(crate) fn diagnostics_json(source: &str) -> String {
+    use crate::{
+        lint::{LintConfig, Registry},
+        Root, TextSize,
+    };
+
+    let mut out = String::from("[");
+    let mut write_diagnostic = |range: TextRange, severity: &str, source: &str, message: &str| {
+        if out.len() > 1 {
+            out.push(',');
+        }
+        write!(
+            out,
+            r#"{{"span":{{"start":{},"end":{}}},"severity":"#,
+            u32::from(range.start()),
+            u32::from(range.end())
+        )
+        .unwrap();
+        json_string(&mut out, severity);
+        out.push_str(r#","source":"#);
+        json_string(&mut out, source);
+        out.push_str(r#","message":"#);
+        json_string(&mut out, message);
+        out.push('}');
+    };
+    let parse = Root::parse(source);
+    let end = TextRange::empty(TextSize::of(source));
+    for error in parse.errors() {
+        write_diagnostic(error.range().unwrap_or(end), "error", "parse", &error.to_string());
+    }
+    let lints = Registry::builtin().check(&parse.syntax(), &LintConfig::default());
+    for diagnostic in &lints {
+        let severity = diagnostic.severity.to_string();
+        write_diagnostic(diagnostic.range, &severity, diagnostic.rule, &diagnostic.message);
+    }
+    out.push(']');
+    out
+}
+
+/// Print a tree as an S-expression with one element per line, like
+/// `(NODE_IDENT 0..1 (TOKEN_IDENT 0..1 "a"))`
+pub fn sexpr(node: &SyntaxNode) -> String {
+    let mut out = String::new();
+    write_sexpr(&mut out, &NodeOrToken::Node(node.clone()), 0);
+    out.push('\n');
+    out
+}
+
+fn write_sexpr(out: &mut String, element: &SyntaxElement, depth: usize) {
+    if depth > 0 {
+        write!(out, "\n{:indent$}", "", indent = depth * 2).unwrap();
+    }
+    write!(out, "({:?} {}", element.kind(), span(element.text_range())).unwrap();
+    match element {
+        NodeOrToken::Node(node) => {
+            for child in node.children_with_tokens() {
+                write_sexpr(out, &child, depth + 1);
+            }
+        }
+        NodeOrToken::Token(token) => write!(out, " {:?}", token.text()).unwrap(),
+    }
+    out.push(')');
+}
+
+fn span(range: TextRange) -> String {
+    format!("{}..{}", u32::from(range.start()), u32::from(range.end()))
+}
+
+/// Print a tree for reading it, with one element per line indented by its
+/// depth, like `{:#?}` but leaving out whitespace and shortening the text of
+/// long tokens, like `TOKEN_STRING_CONTENT 1..121 "lorem ipsum dolor sit am"… (120 bytes)`
+pub fn tree(node: &SyntaxNode) -> String {
+    let mut out = String::new();
+    write_tree(&mut out, &NodeOrToken::Node(node.clone()), 0);
+    out
+}
+
+fn write_tree(out: &mut String, element: &SyntaxElement, depth: usize) {
+    write!(
+        out,
+        "{:indent$}{:?} {}",
+        "",
+        element.kind(),
+        span(element.text_range()),
+        indent = depth * 2
+    )
+    .unwrap();
+    match element {
+        NodeOrToken::Node(node) => {
+            out.push('\n');
+            for child in node.children_with_tokens() {
+                if child.kind() != TOKEN_WHITESPACE {
+                    write_tree(out, &child, depth + 1);
+                }
+            }
+        }
+        NodeOrToken::Token(token) => {
+            let text = token.text();
+            match text.char_indices().nth(TRUNCATE_AT) {
+                Some((end, _)) => writeln!(out, " {:?}… ({} bytes)", &text[..end], text.len()),
+                None => writeln!(out, " {:?}", text),
+            }
+            .unwrap();
+        }
+    }
+}
+
+/// The number of characters of a token printed by [`tree`]
+const TRUNCATE_AT: usize = 24;
+
+/// Print a tree as a Graphviz graph. Whitespace tokens are left out.
+pub fn dot(node: &SyntaxNode) -> String {
+    let mut out = String::from("digraph ast {\n  node [shape=box, fontname=monospace];\n");
+    let mut next_id = 0;
+    write_dot(&mut out, &NodeOrToken::Node(node.clone()), &mut next_id);
+    out.push_str("}\n");
+    out
+}
+
+fn write_dot(out: &mut String, element: &SyntaxElement, next_id: &mut usize) -> usize {
+    let id = *next_id;
+    *next_id += 1;
+    let mut label = format!("{:?} {}", element.kind(), span(element.text_range()));
+    if let NodeOrToken::Token(token) = element {
+        write!(label, "\n{:?}", token.text()).unwrap();
+    }
+    let mut quoted = String::new();
+    json_string(&mut quoted, &label);
+    writeln!(out, "  n{} [label={}];", id, quoted).unwrap();
+    if let NodeOrToken::Node(node) = element {
+        for child in node.children_with_tokens() {
+            if child.kind().is_trivia() {
+                continue;
+            }
+            let child_id = write_dot(out, &child, next_id);
+            writeln!(out, "  n{} -> n{};", id, child_id).unwrap();
+        }
+    }
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Root;
+
+    #[test]
+    #[cfg(any(feature = "python", feature = "wasm"))]
+    fn diagnostics() {
+        assert_eq!(diagnostics_json("1"), "[]");
+        assert_eq!(
+            diagnostics_json("[ \"\" https://nixos.org"),
+            r#"[{"span":{"start":22,"end":22},"severity":"error","source":"parse","message":"unexpected end of file"},{"span":{"start":5,"end":22},"severity":"warning","source":"uri-literal","message":"unquoted URL"}]"#
+        );
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(
+            json(&Root::parse("a").syntax()),
+            r#"{"kind":"NODE_ROOT","span":{"start":0,"end":1},"children":[{"kind":"NODE_IDENT","span":{"start":0,"end":1},"children":[{"kind":"TOKEN_IDENT","span":{"start":0,"end":1},"text":"a"}]}]}"#
+        );
+        assert_eq!(
+            sexpr(&Root::parse("\"\\\"\"").syntax()),
+            r#"(NODE_ROOT 0..4
+  (NODE_STRING 0..4
+    (TOKEN_STRING_START 0..1 "\"")
+    (TOKEN_STRING_CONTENT 1..3 "\\\"")
+    (TOKEN_STRING_END 3..4 "\"")))
+"#
+        );
+        assert_eq!(
+            Root::parse("[ # the list\n  \"a long string which is shortened\" (").debug_tree(),
+            r##"error: unexpected end of file
+error: unexpected end of file
+error: unexpected end of file
+NODE_ROOT 0..51
+  NODE_LIST 0..51
+    TOKEN_L_BRACK 0..1 "["
+    TOKEN_COMMENT 2..12 "# the list"
+    NODE_STRING 15..49
+      TOKEN_STRING_START 15..16 "\""
+      TOKEN_STRING_CONTENT 16..48 "a long string which is s"… (32 bytes)
+      TOKEN_STRING_END 48..49 "\""
+    NODE_PAREN 50..51
+      TOKEN_L_PAREN 50..51 "("
+"##
+        );
+        assert_eq!(
+            dot(&Root::parse("a").syntax()),
+            r#"digraph ast {
+  node [shape=box, fontname=monospace];
+  n0 [label="NODE_ROOT 0..1"];
+  n1 [label="NODE_IDENT 0..1"];
+  n2 [label="TOKEN_IDENT 0..1\n\"a\""];
+  n1 -> n2;
+  n0 -> n1;
+}
+"#
+        );
+    }
+}
@@ -0,0 +1,297 @@
+use std::{error::Error, fmt, iter::Peekable, str::CharIndices};
+
+use crate::{NodeOrToken, SyntaxElement, SyntaxNode};
+
+/// An element of a dumped tree, read back with [`read_sexpr`] or
+/// [`read_json`], or taken from a tree with [`DumpElement::from_syntax`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DumpElement {
+    pub kind: String,
+    pub start: u32,
+    pub end: u32,
+    /// The text of a token, `None` for nodes
+    pub text: Option<String>,
+    pub children: Vec<DumpElement>,
+}
+
+impl DumpElement {
+    pub fn from_syntax(node: &SyntaxNode) -> Self {
+        Self::from_element(&NodeOrToken::Node(node.clone()))
+    }
+
+    fn from_element(element: &SyntaxElement) -> Self {
+        let range = element.text_range();
+        let (text, children) = match element {
+            NodeOrToken::Node(node) => (
+                None,
+                node.children_with_tokens().map(|child| Self::from_element(&child)).collect(),
+            ),
+            NodeOrToken::Token(token) => (Some(token.text().to_string()), Vec::new()),
+        };
+        Self {
+            kind: format!("{:?}", element.kind()),
+            start: range.start().into(),
+            end: range.end().into(),
+            text,
+            children,
+        }
+    }
+}
+
+impl fmt::Display for DumpElement {
+    /// Print the element without its children, like `(TOKEN_IDENT 0..1 "a")`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({} {}..{}", self.kind, self.start, self.end)?;
+        if let Some(text) = &self.text {
+            write!(f, " {:?}", text)?;
+        }
+        f.write_str(")")
+    }
+}
+
+/// Where two dumped trees first differ
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DumpDiff {
+    /// The indices of the children leading from the roots to the elements
+    /// which differ, along with the kind of the element at each step
+    pub path: Vec<(usize, String)>,
+    /// The differing element of the first tree, or `None` if only the second
+    /// tree has an element there
+    pub left: Option<DumpElement>,
+    pub right: Option<DumpElement>,
+}
+
+impl fmt::Display for DumpDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("trees differ at root")?;
+        for (index, kind) in &self.path {
+            write!(f, " > {}[{}]", kind, index)?;
+        }
+        let side = |element: &Option<DumpElement>| match element {
+            Some(element) => element.to_string(),
+            None => "nothing".into(),
+        };
+        write!(f, ": {} vs {}", side(&self.left), side(&self.right))
+    }
+}
+
+/// Compare two trees, returning the first element in preorder which differs
+/// in its kind, span or text, or which only one of the trees has
+pub fn diff(left: &DumpElement, right: &DumpElement) -> Option<DumpDiff> {
+    let mut path = Vec::new();
+    let (mut left, mut right) = (left, right);
+    loop {
+        let differs = |left: &DumpElement, right: &DumpElement| {
+            (&left.kind, left.start, left.end, &left.text)
+                != (&right.kind, right.start, right.end, &right.text)
+        };
+        if differs(left, right) {
+            return Some(DumpDiff { path, left: Some(left.clone()), right: Some(right.clone()) });
+        }
+        let mut children = left.children.iter().zip(&right.children).enumerate();
+        match children.find(|(_, (l, r))| l != r) {
+            Some((index, (l, r))) => {
+                path.push((index, l.kind.clone()));
+                (left, right) = (l, r);
+            }
+            None if left.children.len() == right.children.len() => return None,
+            None => {
+                let index = left.children.len().min(right.children.len());
+                let left = left.children.get(index).cloned();
+                let right = right.children.get(index).cloned();
+                let kind = left.as_ref().or(right.as_ref()).map(|e| e.kind.clone());
+                path.push((index, kind.unwrap_or_default()));
+                return Some(DumpDiff { path, left, right });
+            }
+        }
+    }
+}
+
+/// A dump which can't be read, at a byte offset
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DumpReadError(pub usize);
+
+impl fmt::Display for DumpReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid dump at offset {}", self.0)
+    }
+}
+
+impl Error for DumpReadError {}
+
+/// Read a tree printed with [`sexpr`](super::sexpr)
+pub fn read_sexpr(dump: &str) -> Result<DumpElement, DumpReadError> {
+    let mut reader = SexprReader { dump, chars: dump.char_indices().peekable() };
+    let element = reader.element()?;
+    reader.skip_whitespace();
+    match reader.chars.next() {
+        None => Ok(element),
+        Some((i, _)) => Err(DumpReadError(i)),
+    }
+}
+
+struct SexprReader<'a> {
+    dump: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl SexprReader<'_> {
+    fn offset(&mut self) -> usize {
+        self.chars.peek().map_or(self.dump.len(), |&(i, _)| i)
+    }
+
+    fn error<T>(&mut self) -> Result<T, DumpReadError> {
+        Err(DumpReadError(self.offset()))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), DumpReadError> {
+        match self.chars.next_if(|&(_, c)| c == expected) {
+            Some(_) => Ok(()),
+            None => self.error(),
+        }
+    }
+
+    fn word(&mut self) -> &str {
+        let start = self.offset();
+        while self.chars.next_if(|(_, c)| !c.is_whitespace() && *c != ')').is_some() {}
+        let end = self.offset();
+        &self.dump[start..end]
+    }
+
+    fn element(&mut self) -> Result<DumpElement, DumpReadError> {
+        self.skip_whitespace();
+        self.expect('(')?;
+        let kind = self.word().to_string();
+        self.expect(' ')?;
+        let span_start = self.offset();
+        let (start, end) = match self.word().split_once("..") {
+            Some((start, end)) => (start.parse().ok(), end.parse().ok()),
+            None => (None, None),
+        };
+        let (Some(start), Some(end)) = (start, end) else { return Err(DumpReadError(span_start)) };
+
+        let mut element = DumpElement { kind, start, end, text: None, children: Vec::new() };
+        if self.chars.next_if(|&(_, c)| c == ' ').is_some() {
+            element.text = Some(self.string()?);
+        }
+        loop {
+            self.skip_whitespace();
+            match self.chars.peek() {
+                Some((_, ')')) => {
+                    self.chars.next();
+                    return Ok(element);
+                }
+                Some((_, '(')) if element.text.is_none() => element.children.push(self.element()?),
+                _ => return self.error(),
+            }
+        }
+    }
+
+    /// Read a string printed with `{:?}`
+    fn string(&mut self) -> Result<String, DumpReadError> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            let Some((_, c)) = self.chars.next() else { return self.error() };
+            match c {
+                '"' => return Ok(out),
+                '\\' => {
+                    let Some((_, c)) = self.chars.next() else { return self.error() };
+                    out.push(match c {
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        '0' => '\0',
+                        'u' => self.unicode_escape()?,
+                        c => c,
+                    });
+                }
+                c => out.push(c),
+            }
+        }
+    }
+
+    fn unicode_escape(&mut self) -> Result<char, DumpReadError> {
+        self.expect('{')?;
+        let start = self.offset();
+        while self.chars.next_if(|(_, c)| c.is_ascii_hexdigit()).is_some() {}
+        let end = self.offset();
+        let c = u32::from_str_radix(&self.dump[start..end], 16).ok().and_then(char::from_u32);
+        self.expect('}')?;
+        c.ok_or(DumpReadError(start))
+    }
+}
+
+/// Read a tree printed with [`json`](super::json)
+#[cfg(feature = "json")]
+pub fn read_json(dump: &str) -> Result<DumpElement, DumpReadError> {
+    use serde_json::Value;
+
+    fn element(value: &Value) -> Option<DumpElement> {
+        let span = value.get("span")?;
+        let children = match value.get("children") {
+            Some(children) => children.as_array()?.iter().map(element).collect::<Option<_>>()?,
+            None => Vec::new(),
+        };
+        Some(DumpElement {
+            kind: value.get("kind")?.as_str()?.to_string(),
+            start: span.get("start")?.as_u64()?.try_into().ok()?,
+            end: span.get("end")?.as_u64()?.try_into().ok()?,
+            text: value.get("text").and_then(Value::as_str).map(String::from),
+            children,
+        })
+    }
+
+    let value: Value =
+        serde_json::from_str(dump).map_err(|err| DumpReadError(json_offset(dump, &err)))?;
+    element(&value).ok_or(DumpReadError(0))
+}
+
+#[cfg(feature = "json")]
+fn json_offset(dump: &str, err: &serde_json::Error) -> usize {
+    let line_start: usize =
+        dump.split_inclusive('\n').take(err.line().saturating_sub(1)).map(str::len).sum();
+    line_start + err.column().saturating_sub(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dump, Root};
+
+    #[test]
+    fn roundtrip() {
+        let root = Root::parse("let a = \"\\\"b\\u{7f}\"; in a # c\n").syntax();
+        let element = DumpElement::from_syntax(&root);
+        assert_eq!(read_sexpr(&dump::sexpr(&root)), Ok(element.clone()));
+        #[cfg(feature = "json")]
+        assert_eq!(read_json(&dump::json(&root)), Ok(element));
+        assert_eq!(read_sexpr("(NODE_ROOT 0..1"), Err(DumpReadError(15)));
+        assert_eq!(read_sexpr("(NODE_ROOT 0.1)"), Err(DumpReadError(11)));
+    }
+
+    #[test]
+    fn first_difference() {
+        let tree = |src: &str| DumpElement::from_syntax(&Root::parse(src).syntax());
+        assert_eq!(diff(&tree("[ a b ]"), &tree("[ a b ]")), None);
+        assert_eq!(
+            diff(&tree("[ a b ]"), &tree("[ a c ]")).unwrap().to_string(),
+            "trees differ at root > NODE_LIST[0] > NODE_IDENT[4] > TOKEN_IDENT[0]: \
+             (TOKEN_IDENT 4..5 \"b\") vs (TOKEN_IDENT 4..5 \"c\")"
+        );
+        assert_eq!(
+            diff(&tree("a"), &tree("a b")).unwrap().to_string(),
+            "trees differ at root: (NODE_ROOT 0..1) vs (NODE_ROOT 0..3)"
+        );
+        let mut shorter = tree("[ a ]");
+        shorter.children[0].children.pop();
+        assert_eq!(
+            diff(&shorter, &tree("[ a ]")).unwrap().to_string(),
+            "trees differ at root > NODE_LIST[0] > TOKEN_R_BRACK[4]: nothing vs (TOKEN_R_BRACK 4..5 \"]\")"
+        );
+    }
+}
@@ -0,0 +1,694 @@
+use std::{collections::BTreeMap, path::Path};
+
+use rowan::ast::AstNode;
+
+use crate::{
+    ast::{self, AstToken, BinOpKind, HasEntry, InterpolPart, LiteralKind, UnaryOpKind},
+    tokenize,
+    value::{escape_string, PathRoots, Value},
+    SyntaxKind, SyntaxNode,
+};
+
+/// Print an expression in the canonical form of `nix-instantiate --parse`.
+///
+/// Like Nix, this removes parentheses, desugars operators into calls of
+/// builtins like `__sub` and `__lessThan`, merges nested attribute paths and
+/// sorts attributes and formals. Relative paths are resolved against
+/// `base_dir`, and paths starting with `~` against `home_dir`. Returns `None`
+/// if the tree contains errors or is missing expressions, though callers
+/// should check the parse errors, as missing tokens can't be noticed here.
+pub fn nix(root: &SyntaxNode, base_dir: &str, home_dir: &str) -> Option<String> {
+    let has_errors = root
+        .descendants_with_tokens()
+        .any(|element| matches!(element.kind(), SyntaxKind::NODE_ERROR | SyntaxKind::TOKEN_ERROR));
+    if has_errors {
+        return None;
+    }
+    let root = ast::Root::cast(root.clone())?;
+    let mut printer = Printer { out: String::new(), base_dir, home_dir };
+    printer.expr(&root.expr()?)?;
+    Some(printer.out)
+}
+
+struct Printer<'a> {
+    out: String,
+    base_dir: &'a str,
+    home_dir: &'a str,
+}
+
+/// The bindings of an attribute set or `let`, after merging attribute paths
+#[derive(Default)]
+struct Bindings {
+    named: BTreeMap<String, Binding>,
+    dynamic: Vec<(ast::Attr, Binding)>,
+}
+
+enum Binding {
+    Value(ast::Expr),
+    /// An attribute set, written as one or created by an attribute path,
+    /// which later bindings can add attributes to
+    Set {
+        rec: bool,
+        bindings: Bindings,
+    },
+    Inherit,
+    InheritFrom(ast::Expr),
+}
+
+impl Bindings {
+    fn collect(entries: &impl HasEntry) -> Option<Self> {
+        let mut bindings = Bindings::default();
+        for entry in entries.entries() {
+            match entry {
+                ast::Entry::Inherit(inherit) => {
+                    let from = match inherit.from() {
+                        Some(from) => Some(from.expr()?),
+                        None => None,
+                    };
+                    for attr in inherit.attrs() {
+                        let binding = match &from {
+                            Some(from) => Binding::InheritFrom(from.clone()),
+                            None => Binding::Inherit,
+                        };
+                        bindings.named.insert(static_name(&attr)?, binding);
+                    }
+                }
+                ast::Entry::AttrpathValue(entry) => {
+                    let attrs: Vec<ast::Attr> = entry.attrpath()?.attrs().collect();
+                    bindings.insert(&attrs, entry.value()?)?;
+                }
+            }
+        }
+        Some(bindings)
+    }
+
+    fn insert(&mut self, attrs: &[ast::Attr], value: ast::Expr) -> Option<()> {
+        let (attr, rest) = attrs.split_first()?;
+        let binding = if rest.is_empty() {
+            Binding::from_value(value)?
+        } else {
+            let mut bindings = Bindings::default();
+            bindings.insert(rest, value)?;
+            Binding::Set { rec: false, bindings }
+        };
+        let Some(name) = static_name(attr) else {
+            self.dynamic.push((attr.clone(), binding));
+            return Some(());
+        };
+        self.define(name, binding)
+    }
+
+    /// Add a binding, merging attribute sets entry by entry like Nix does for
+    /// `a.b.c = 1; a.b.d = 2;`
+    fn define(&mut self, name: String, binding: Binding) -> Option<()> {
+        match (self.named.get_mut(&name), binding) {
+            (None, binding) => {
+                self.named.insert(name, binding);
+            }
+            (Some(Binding::Set { bindings: existing, .. }), Binding::Set { bindings, .. }) => {
+                for (name, binding) in bindings.named {
+                    existing.define(name, binding)?;
+                }
+                existing.dynamic.extend(bindings.dynamic);
+            }
+            // Nix rejects this while parsing
+            _ => return None,
+        }
+        Some(())
+    }
+}
+
+impl Binding {
+    /// Attribute sets are kept open, so that attribute paths can add to them
+    fn from_value(value: ast::Expr) -> Option<Self> {
+        match strip_parens(value)? {
+            ast::Expr::AttrSet(set) => Some(Binding::Set {
+                rec: set.rec_token().is_some(),
+                bindings: Bindings::collect(&set)?,
+            }),
+            value => Some(Binding::Value(value)),
+        }
+    }
+}
+
+fn strip_parens(mut expr: ast::Expr) -> Option<ast::Expr> {
+    while let ast::Expr::Paren(paren) = expr {
+        expr = paren.expr()?;
+    }
+    Some(expr)
+}
+
+/// The name of an attribute, if it's known while parsing. Unlike
+/// [`analysis`](crate::analysis), Nix also counts `${"name"}` as static.
+fn static_name(attr: &ast::Attr) -> Option<String> {
+    let string = match attr {
+        ast::Attr::Ident(ident) => return Some(ident.ident_token()?.text().to_string()),
+        ast::Attr::Str(string) => string.clone(),
+        ast::Attr::Dynamic(dynamic) => match strip_parens(dynamic.expr()?)? {
+            ast::Expr::Str(string) => string,
+            _ => return None,
+        },
+    };
+    match &string.normalized_parts()[..] {
+        [] => Some(String::new()),
+        [InterpolPart::Literal(text)] => Some(text.clone()),
+        _ => None,
+    }
+}
+
+/// Print an attribute name, quoting it unless it's a valid identifier
+fn name(name: &str) -> String {
+    if matches!(tokenize(name)[..], [(SyntaxKind::TOKEN_IDENT, ident)] if ident == name) {
+        name.to_string()
+    } else {
+        format!("\"{}\"", escape_string(name))
+    }
+}
+
+/// Print a float like C++ streams do by default, with six significant digits
+fn float(value: f64) -> String {
+    if value == 0.0 {
+        return "0".into();
+    }
+    let exponent =
+        format!("{:.5e}", value).split_once('e').map_or(0, |(_, exp)| exp.parse().unwrap_or(0));
+    let trim = |digits: String| -> String {
+        if digits.contains('.') {
+            digits.trim_end_matches('0').trim_end_matches('.').to_string()
+        } else {
+            digits
+        }
+    };
+    if !(-4..6).contains(&exponent) {
+        let formatted = format!("{:.5e}", value);
+        let (mantissa, exp) = formatted.split_once('e').unwrap_or((&formatted, "0"));
+        let exp: i32 = exp.parse().unwrap_or(0);
+        format!(
+            "{}e{}{:02}",
+            trim(mantissa.to_string()),
+            if exp < 0 { '-' } else { '+' },
+            exp.abs()
+        )
+    } else {
+        trim(format!("{:.*}", (5 - exponent) as usize, value))
+    }
+}
+
+impl Printer<'_> {
+    fn push(&mut self, text: &str) {
+        self.out.push_str(text);
+    }
+
+    fn string(&mut self, text: &str) {
+        self.out.push('"');
+        self.out.push_str(&escape_string(text));
+        self.out.push('"');
+    }
+
+    fn call(&mut self, function: &str, args: &[&ast::Expr]) -> Option<()> {
+        self.push("(");
+        self.push(function);
+        for arg in args {
+            self.push(" ");
+            self.expr(arg)?;
+        }
+        self.push(")");
+        Some(())
+    }
+
+    fn apply(&mut self, function: ast::Expr, argument: ast::Expr) -> Option<()> {
+        // Nix collects all arguments of a call, even through parentheses
+        let mut args = vec![argument];
+        let mut function = strip_parens(function)?;
+        while let ast::Expr::Apply(inner) = &function {
+            args.push(inner.argument()?);
+            function = strip_parens(inner.lambda()?)?;
+        }
+        self.push("(");
+        self.expr(&function)?;
+        for arg in args.iter().rev() {
+            self.push(" ");
+            self.expr(arg)?;
+        }
+        self.push(")");
+        Some(())
+    }
+
+    fn binary(&mut self, lhs: &ast::Expr, op: &str, rhs: &ast::Expr) -> Option<()> {
+        self.push("(");
+        self.expr(lhs)?;
+        self.push(" ");
+        self.push(op);
+        self.push(" ");
+        self.expr(rhs)?;
+        self.push(")");
+        Some(())
+    }
+
+    fn attrpath(&mut self, attrpath: &ast::Attrpath) -> Option<()> {
+        for (i, attr) in attrpath.attrs().enumerate() {
+            if i > 0 {
+                self.push(".");
+            }
+            match static_name(&attr) {
+                Some(attr) => self.push(&name(&attr)),
+                None => self.dynamic_attr(&attr)?,
+            }
+        }
+        Some(())
+    }
+
+    /// Print bindings like Nix does: plain inherits first, then inherits from
+    /// the same expression grouped together, then the other attributes sorted
+    /// by name and finally dynamic attributes in order
+    fn bindings(&mut self, bindings: &Bindings) -> Option<()> {
+        let inherits: Vec<&String> = bindings
+            .named
+            .iter()
+            .filter(|(_, binding)| matches!(binding, Binding::Inherit))
+            .map(|(name, _)| name)
+            .collect();
+        if !inherits.is_empty() {
+            self.push("inherit");
+            for attr in inherits {
+                self.push(" ");
+                self.push(&name(attr));
+            }
+            self.push("; ");
+        }
+        let mut froms: Vec<(&ast::Expr, Vec<&String>)> = Vec::new();
+        for (attr, binding) in &bindings.named {
+            if let Binding::InheritFrom(from) = binding {
+                match froms.iter_mut().find(|(other, _)| *other == from) {
+                    Some((_, attrs)) => attrs.push(attr),
+                    None => froms.push((from, vec![attr])),
+                }
+            }
+        }
+        froms.sort_by_key(|(from, _)| from.syntax().text_range().start());
+        for (from, attrs) in froms {
+            self.push("inherit (");
+            self.expr(from)?;
+            self.push(")");
+            for attr in attrs {
+                self.push(" ");
+                self.push(&name(attr));
+            }
+            self.push("; ");
+        }
+        for (attr, binding) in &bindings.named {
+            if !matches!(binding, Binding::Inherit | Binding::InheritFrom(_)) {
+                self.push(&name(attr));
+                self.push(" = ");
+                self.binding(binding)?;
+                self.push("; ");
+            }
+        }
+        for (attr, binding) in &bindings.dynamic {
+            self.dynamic_attr(attr)?;
+            self.push(" = ");
+            self.binding(binding)?;
+            self.push("; ");
+        }
+        Some(())
+    }
+
+    fn dynamic_attr(&mut self, attr: &ast::Attr) -> Option<()> {
+        self.push("\"${");
+        match attr {
+            ast::Attr::Dynamic(dynamic) => self.expr(&dynamic.expr()?)?,
+            ast::Attr::Str(string) => self.concat(string.normalized_parts(), false)?,
+            ast::Attr::Ident(_) => return None,
+        }
+        self.push("}\"");
+        Some(())
+    }
+
+    fn binding(&mut self, binding: &Binding) -> Option<()> {
+        match binding {
+            Binding::Value(value) => self.expr(value),
+            Binding::Set { rec, bindings } => self.set(*rec, bindings),
+            Binding::Inherit | Binding::InheritFrom(_) => None,
+        }
+    }
+
+    fn set(&mut self, rec: bool, bindings: &Bindings) -> Option<()> {
+        if rec {
+            self.push("rec ");
+        }
+        self.push("{ ");
+        self.bindings(bindings)?;
+        self.push("}");
+        Some(())
+    }
+
+    /// Print the parts of a string or path, as a string or a concatenation of
+    /// the parts
+    fn concat(&mut self, parts: Vec<InterpolPart<String>>, first_is_path: bool) -> Option<()> {
+        let parts: Vec<InterpolPart<String>> = parts
+            .into_iter()
+            .filter(|part| !matches!(part, InterpolPart::Literal(text) if text.is_empty()))
+            .collect();
+        let single = parts.len() == 1 && matches!(parts[0], InterpolPart::Literal(_));
+        if parts.is_empty() {
+            self.push("\"\"");
+            return Some(());
+        }
+        if !single {
+            self.push("(");
+        }
+        for (i, part) in parts.iter().enumerate() {
+            if i > 0 {
+                self.push(" + ");
+            }
+            match part {
+                InterpolPart::Literal(text) if i == 0 && first_is_path => self.push(text),
+                InterpolPart::Literal(text) => self.string(text),
+                InterpolPart::Interpolation(interpol) => self.expr(&interpol.expr()?)?,
+            }
+        }
+        if !single {
+            self.push(")");
+        }
+        Some(())
+    }
+
+    fn path(&mut self, path: &ast::Path) -> Option<()> {
+        let mut parts: Vec<InterpolPart<String>> = path
+            .parts()
+            .map(|part| match part {
+                InterpolPart::Literal(literal) => {
+                    InterpolPart::Literal(literal.syntax().text().to_string())
+                }
+                InterpolPart::Interpolation(interpol) => InterpolPart::Interpolation(interpol),
+            })
+            .collect();
+        let Some(InterpolPart::Literal(first)) = parts.first_mut() else { return None };
+        if let Some(search) = first.strip_prefix('<').and_then(|p| p.strip_suffix('>')) {
+            self.push("(__findFile __nixPath ");
+            self.string(search);
+            self.push(")");
+            return Some(());
+        }
+        // A trailing slash is kept if the path continues with an interpolation
+        let trailing = if first.ends_with('/') { "/" } else { "" };
+        let roots = PathRoots {
+            base_dir: Path::new(self.base_dir),
+            home_dir: Path::new(self.home_dir),
+            search_dir: None,
+        };
+        let resolved = Value::from_path(first).resolve_path(&roots)?;
+        *first = format!("{}{}", resolved.to_str()?, trailing);
+        self.concat(parts, true)
+    }
+
+    fn expr(&mut self, expr: &ast::Expr) -> Option<()> {
+        match expr {
+            ast::Expr::Paren(paren) => self.expr(&paren.expr()?),
+            ast::Expr::Root(root) => self.expr(&root.expr()?),
+            ast::Expr::Error(_) => None,
+            ast::Expr::Ident(ident) => {
+                self.push(&ident.syntax().text().to_string());
+                Some(())
+            }
+            ast::Expr::Literal(literal) => {
+                match literal.kind() {
+                    LiteralKind::Integer(integer) => self.push(&integer.value().ok()?.to_string()),
+                    LiteralKind::Float(value) => self.push(&float(value.value().ok()?)),
+                    LiteralKind::Uri(uri) => self.string(uri.syntax().text()),
+                }
+                Some(())
+            }
+            ast::Expr::Str(string) => self.concat(string.normalized_parts(), false),
+            ast::Expr::Path(path) => self.path(path),
+            ast::Expr::List(list) => {
+                self.push("[ ");
+                for item in list.items() {
+                    self.expr(&item)?;
+                    self.push(" ");
+                }
+                self.push("]");
+                Some(())
+            }
+            ast::Expr::AttrSet(set) => {
+                self.set(set.rec_token().is_some(), &Bindings::collect(set)?)
+            }
+            ast::Expr::LetIn(let_in) => {
+                self.push("(let ");
+                self.bindings(&Bindings::collect(let_in)?)?;
+                self.push("in ");
+                self.expr(&let_in.body()?)?;
+                self.push(")");
+                Some(())
+            }
+            ast::Expr::LegacyLet(let_) => {
+                // Nix turns `let { body = ...; }` into `rec { body = ...; }.body`
+                self.push("(");
+                self.set(true, &Bindings::collect(let_)?)?;
+                self.push(").body");
+                Some(())
+            }
+            ast::Expr::Apply(apply) => self.apply(apply.lambda()?, apply.argument()?),
+            ast::Expr::Select(select) => {
+                self.push("(");
+                self.expr(&select.expr()?)?;
+                self.push(").");
+                self.attrpath(&select.attrpath()?)?;
+                if let Some(default) = select.default_expr() {
+                    self.push(" or (");
+                    self.expr(&default)?;
+                    self.push(")");
+                }
+                Some(())
+            }
+            ast::Expr::HasAttr(has) => {
+                self.push("((");
+                self.expr(&has.expr()?)?;
+                self.push(") ? ");
+                self.attrpath(&has.attrpath()?)?;
+                self.push(")");
+                Some(())
+            }
+            ast::Expr::Lambda(lambda) => {
+                self.push("(");
+                match lambda.param()? {
+                    ast::Param::IdentParam(param) => {
+                        self.push(&param.ident()?.syntax().text().to_string())
+                    }
+                    ast::Param::Pattern(pattern) => {
+                        let mut entries: Vec<(String, Option<ast::Expr>)> = Vec::new();
+                        for entry in pattern.pat_entries() {
+                            entries.push((
+                                entry.ident()?.syntax().text().to_string(),
+                                entry.default(),
+                            ));
+                        }
+                        entries.sort_by(|a, b| a.0.cmp(&b.0));
+                        self.push("{ ");
+                        for (i, (formal, default)) in entries.iter().enumerate() {
+                            if i > 0 {
+                                self.push(", ");
+                            }
+                            self.push(formal);
+                            if let Some(default) = default {
+                                self.push(" ? ");
+                                self.expr(default)?;
+                            }
+                        }
+                        if pattern.ellipsis_token().is_some() {
+                            if !entries.is_empty() {
+                                self.push(", ");
+                            }
+                            self.push("...");
+                        }
+                        self.push(" }");
+                        if let Some(bind) = pattern.pat_bind() {
+                            self.push(" @ ");
+                            self.push(&bind.ident()?.syntax().text().to_string());
+                        }
+                    }
+                }
+                self.push(": ");
+                self.expr(&lambda.body()?)?;
+                self.push(")");
+                Some(())
+            }
+            ast::Expr::IfElse(if_else) => {
+                self.push("(if ");
+                self.expr(&if_else.condition()?)?;
+                self.push(" then ");
+                self.expr(&if_else.body()?)?;
+                self.push(" else ");
+                self.expr(&if_else.else_body()?)?;
+                self.push(")");
+                Some(())
+            }
+            ast::Expr::With(with) => {
+                self.push("(with ");
+                self.expr(&with.namespace()?)?;
+                self.push("; ");
+                self.expr(&with.body()?)?;
+                self.push(")");
+                Some(())
+            }
+            ast::Expr::Assert(assert) => {
+                self.push("(assert ");
+                self.expr(&assert.condition()?)?;
+                self.push("; ");
+                self.expr(&assert.body()?)?;
+                self.push(")");
+                Some(())
+            }
+            ast::Expr::UnaryOp(unary) => match unary.operator()? {
+                UnaryOpKind::Invert => {
+                    self.push("(! ");
+                    self.expr(&unary.expr()?)?;
+                    self.push(")");
+                    Some(())
+                }
+                UnaryOpKind::Negate => {
+                    self.push("(__sub 0 ");
+                    self.expr(&unary.expr()?)?;
+                    self.push(")");
+                    Some(())
+                }
+            },
+            ast::Expr::BinOp(op) => {
+                let (lhs, rhs) = (op.lhs()?, op.rhs()?);
+                match op.operator()? {
+                    BinOpKind::Concat => self.binary(&lhs, "++", &rhs),
+                    BinOpKind::Update => self.binary(&lhs, "//", &rhs),
+                    BinOpKind::Add => self.binary(&lhs, "+", &rhs),
+                    BinOpKind::Sub => self.call("__sub", &[&lhs, &rhs]),
+                    BinOpKind::Mul => self.call("__mul", &[&lhs, &rhs]),
+                    BinOpKind::Div => self.call("__div", &[&lhs, &rhs]),
+                    BinOpKind::And => self.binary(&lhs, "&&", &rhs),
+                    BinOpKind::Or => self.binary(&lhs, "||", &rhs),
+                    BinOpKind::Implication => self.binary(&lhs, "->", &rhs),
+                    // Pipes are calls, with the arguments collected like those
+                    BinOpKind::PipeRight => self.apply(rhs, lhs),
+                    BinOpKind::PipeLeft => self.apply(lhs, rhs),
+                    BinOpKind::Equal => self.binary(&lhs, "==", &rhs),
+                    BinOpKind::NotEqual => self.binary(&lhs, "!=", &rhs),
+                    BinOpKind::Less => self.call("__lessThan", &[&lhs, &rhs]),
+                    BinOpKind::More => self.call("__lessThan", &[&rhs, &lhs]),
+                    BinOpKind::LessOrEq => {
+                        self.push("(! ");
+                        self.call("__lessThan", &[&rhs, &lhs])?;
+                        self.push(")");
+                        Some(())
+                    }
+                    BinOpKind::MoreOrEq => {
+                        self.push("(! ");
+                        self.call("__lessThan", &[&lhs, &rhs])?;
+                        self.push(")");
+                        Some(())
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::ParserOptions, Root};
+
+    fn print(src: &str) -> String {
+        nix(&Root::parse(src).syntax(), "/src/dir", "/home/user").unwrap()
+    }
+
+    #[test]
+    fn canonical_form() {
+        assert_eq!(print("(1 + 2) * -x"), "(__mul (1 + 2) (__sub 0 x))");
+        assert_eq!(print("a <= b && !(c > d)"), "((! (__lessThan b a)) && (! (__lessThan d c)))");
+        assert_eq!(print("(f a) b c"), "(f a b c)");
+        assert_eq!(print("{ a.b.c = 1; a.b.d = 2; }"), "{ a = { b = { c = 1; d = 2; }; }; }");
+        assert_eq!(nix(&Root::parse("{ a.b.c = 1; a.b.c = 2; }").syntax(), "/", "/"), None);
+        let options = ParserOptions { experimental_pipe: true, ..ParserOptions::default() };
+        let root = Root::parse_with_options("[ (a |> f b |> g) (f <| g <| a) ]", options).syntax();
+        assert_eq!(nix(&root, "/", "/").unwrap(), "[ (g (f b a)) (f (g a)) ]");
+        assert_eq!(print("[ 1.5 0.1 1.0e300 3.14159265 ]"), "[ 1.5 0.1 1e+300 3.14159 ]");
+        assert_eq!(
+            print(
+                "{ b.c = 1; a = 2; b = { d = \"x${y}\"; }; inherit e; inherit (s) g f; ${h} = 3; }"
+            ),
+            r#"{ inherit e; inherit (s) f g; a = 2; b = { c = 1; d = ("x" + y); }; "${h}" = 3; }"#
+        );
+        assert_eq!(
+            print("{ b ? 1, a, ... } @ args: let x = a.b or c; in x ? y"),
+            "({ a, b ? 1, ... } @ args: (let x = (a).b or (c); in ((x) ? y)))"
+        );
+        assert_eq!(
+            print("[ ./a/../b ~/c /d <nixpkgs/lib> http://x.org ]"),
+            r#"[ /src/dir/b /home/user/c /d (__findFile __nixPath "nixpkgs/lib") "http://x.org" ]"#
+        );
+        assert_eq!(print("''\n  a\n  ${b}\n''"), r#"("a\n" + b + "\n")"#);
+        assert_eq!(print("let { body = 1; }"), "(rec { body = 1; }).body");
+        assert_eq!(nix(&Root::parse("{ a = ; }").syntax(), "/", "/"), None);
+    }
+
+    #[test]
+    fn associativity() {
+        // As printed by `nix-instantiate --parse`
+        for (src, parsed) in [
+            ("a -> b -> c", "(a -> (b -> c))"),
+            ("a // b // c", "(a // (b // c))"),
+            ("a ++ b ++ c", "(a ++ (b ++ c))"),
+            ("a - b - c", "(__sub (__sub a b) c)"),
+            ("a && b && c", "((a && b) && c)"),
+            ("a ++ b // c ++ d", "((a ++ b) // (c ++ d))"),
+        ] {
+            assert_eq!(print(src), parsed, "{}", src);
+        }
+    }
+
+    #[test]
+    fn negation() {
+        // A `-` after a function is a subtraction, and a negation applies to
+        // a whole application but not to `?`
+        for (src, parsed) in [
+            ("f -1", "(__sub f 1)"),
+            ("f (-1)", "(f (__sub 0 1))"),
+            ("[ (-1) 2 ]", "[ (__sub 0 1) 2 ]"),
+            ("-f x", "(__sub 0 (f x))"),
+            ("-a.b", "(__sub 0 (a).b)"),
+            ("-x ? y", "(((__sub 0 x)) ? y)"),
+            ("- -1", "(__sub 0 (__sub 0 1))"),
+        ] {
+            assert_eq!(print(src), parsed, "{}", src);
+        }
+        // List items can't start with `-`, as in Nix
+        assert_eq!(nix(&Root::parse("[ -1 2 ]").syntax(), "/", "/"), None);
+    }
+
+    #[test]
+    fn division() {
+        // Without whitespace after it, a `/` starts or continues a path
+        for (src, parsed) in [
+            ("a / b", "(__div a b)"),
+            ("a/ b", "(__div a b)"),
+            ("a /b", "(a /b)"),
+            ("a/b", "/src/dir/a/b"),
+            ("6/3", "/src/dir/6/3"),
+            ("6 /3", "(6 /3)"),
+        ] {
+            assert_eq!(print(src), parsed, "{}", src);
+        }
+    }
+
+    #[test]
+    fn or_default() {
+        // The default is a select, which can have a default of its own
+        for (src, parsed) in [
+            ("a.b or c.d.e", "(a).b or ((c).d.e)"),
+            ("x.y or z.w or q", "(x).y or ((z).w or (q))"),
+            ("a.b or c d", "((a).b or (c) d)"),
+            ("(a.b or c).d", "((a).b or (c)).d"),
+            ("a.b or c ? d", "(((a).b or (c)) ? d)"),
+        ] {
+            assert_eq!(print(src), parsed, "{}", src);
+        }
+    }
+}
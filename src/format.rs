@@ -0,0 +1,160 @@
+//! A conservative formatter, which only changes the indentation of lines
+//!
+//! Everything except whitespace at the start and end of lines is kept as it
+//! is, including the contents of strings and comments, so formatting never
+//! changes the meaning of an expression.
+
+use std::collections::BTreeSet;
+
+use rowan::{ast::AstNode, TextRange, TextSize};
+
+use crate::{
+    line_index::LineIndex,
+    parser::ParseError,
+    rewrite::{apply_edits, TextEdit},
+    NodeOrToken, Root, SyntaxKind,
+    SyntaxKind::*,
+    SyntaxNode, SyntaxToken,
+};
+
+/// How to format
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FormatOptions {
+    /// The number of spaces per indentation level
+    pub indent_width: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self { indent_width: 2 }
+    }
+}
+
+/// Format a source text. Text which doesn't parse isn't formatted, and the
+/// first error is returned instead.
+pub fn format(source: &str, options: &FormatOptions) -> Result<String, ParseError> {
+    let root = Root::parse(source).ok()?;
+    Ok(apply_edits(source, &edits(root.syntax(), options)))
+}
+
+/// Compute the edits formatting a tree. Trailing whitespace is removed, each
+/// line is indented by the number of lines opening a construct which is still
+/// open on it, and the text ends with a single newline.
+pub fn edits(root: &SyntaxNode, options: &FormatOptions) -> Vec<TextEdit> {
+    let source = root.text().to_string();
+    let index = LineIndex::new(&source);
+    let mut edits = Vec::new();
+
+    for token in root.descendants_with_tokens().filter_map(NodeOrToken::into_token) {
+        if token.kind() != TOKEN_WHITESPACE {
+            continue;
+        }
+        let replacement = match token.next_token() {
+            None => "\n".to_string(),
+            Some(_) if token.prev_token().is_none() => String::new(),
+            Some(next) if token.text().contains('\n') => {
+                let newlines = "\n".repeat(token.text().matches('\n').count());
+                let level = indent_level(&index, &next);
+                format!("{}{}", newlines, " ".repeat(level * options.indent_width))
+            }
+            Some(_) => continue,
+        };
+        if replacement != token.text() {
+            edits.push(TextEdit::replace(token.text_range(), replacement));
+        }
+    }
+    if root.last_token().is_some_and(|token| token.kind() != TOKEN_WHITESPACE) {
+        edits.push(TextEdit::insert(TextSize::of(source.as_str()), "\n"));
+    }
+    edits
+}
+
+/// Nodes whose contents are indented when they span multiple lines
+const BLOCKS: &[SyntaxKind] = &[
+    NODE_APPLY,
+    NODE_ATTRPATH_VALUE,
+    NODE_ATTR_SET,
+    NODE_INHERIT,
+    NODE_INTERPOL,
+    NODE_LEGACY_LET,
+    NODE_LET_IN,
+    NODE_LIST,
+    NODE_PAREN,
+    NODE_PATTERN,
+];
+
+/// The token ending the indented part of a block
+fn closing_token(node: &SyntaxNode) -> Option<SyntaxToken> {
+    node.children_with_tokens().filter_map(NodeOrToken::into_token).find(|token| {
+        matches!(
+            (node.kind(), token.kind()),
+            (NODE_LET_IN, TOKEN_IN)
+                | (_, TOKEN_R_BRACE | TOKEN_R_BRACK | TOKEN_R_PAREN | TOKEN_INTERPOL_END)
+                | (NODE_ATTRPATH_VALUE | NODE_INHERIT, TOKEN_SEMICOLON)
+        )
+    })
+}
+
+fn start_line(index: &LineIndex, node: &SyntaxNode) -> u32 {
+    let start = node
+        .descendants_with_tokens()
+        .filter_map(NodeOrToken::into_token)
+        .find(|token| !token.kind().is_trivia())
+        .map_or(node.text_range().start(), |token| token.text_range().start());
+    index.line(start)
+}
+
+/// Count the lines opening a block which contains `token`. Blocks opened on
+/// the same line only add one level, and a line closing a block is indented
+/// like the line which opened it.
+fn indent_level(index: &LineIndex, token: &SyntaxToken) -> usize {
+    let line = index.line(token.text_range().start());
+    let mut open = BTreeSet::new();
+    let mut closed = BTreeSet::new();
+    for node in token.parent_ancestors().filter(|node| BLOCKS.contains(&node.kind())) {
+        let start = start_line(index, &node);
+        if start >= line {
+            continue;
+        }
+        let closed_at = closing_token(&node).map(|token| token.text_range());
+        if closed_at.is_some_and(|range: TextRange| range.start() <= token.text_range().start()) {
+            closed.insert(start);
+        } else {
+            open.insert(start);
+        }
+    }
+    open.difference(&closed).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fmt(source: &str) -> String {
+        format(source, &FormatOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn indentation() {
+        assert_eq!(
+            fmt("  {\nfoo = {\n        a = [\n1\n    2 ];   \n};\n\n\n  bar =\nx;\n    }"),
+            "{\n  foo = {\n    a = [\n      1\n      2 ];\n  };\n\n\n  bar =\n    x;\n}\n"
+        );
+        assert_eq!(
+            fmt("let\na = f {\nx = 1;\n};\n    in\n  a"),
+            "let\n  a = f {\n    x = 1;\n  };\nin\na\n"
+        );
+        assert_eq!(fmt("{ lib\n, stdenv\n    }: null"), "{ lib\n  , stdenv\n}: null\n");
+    }
+
+    #[test]
+    fn strings_are_kept() {
+        let source = "{\n    a = ''\n      keep   \n   this\n'';\n  b = \"x\n     y\";\n}\n";
+        assert_eq!(
+            fmt(source),
+            "{\n  a = ''\n      keep   \n   this\n'';\n  b = \"x\n     y\";\n}\n"
+        );
+        assert_eq!(fmt("1"), "1\n");
+        assert!(format("{", &FormatOptions::default()).is_err());
+    }
+}
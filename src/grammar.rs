@@ -0,0 +1,896 @@
+//! The grammar implemented by the parser, as data: keywords and symbols,
+//! operator precedence, and the children of each kind of node, in the order
+//! they appear. The names of nodes and children are those of the types and
+//! accessors in [`ast`](crate::ast).
+//!
+//! [`json`] prints all of it, for generating documentation or grammars for
+//! editors.
+
+use std::fmt::Write;
+
+use crate::{dump::json_string, SyntaxKind, SyntaxKind::*};
+
+/// Keywords and the tokens they are parsed as
+pub const KEYWORDS: &[(&str, SyntaxKind)] = &[
+    ("assert", TOKEN_ASSERT),
+    ("else", TOKEN_ELSE),
+    ("if", TOKEN_IF),
+    ("in", TOKEN_IN),
+    ("inherit", TOKEN_INHERIT),
+    ("let", TOKEN_LET),
+    ("or", TOKEN_OR),
+    ("rec", TOKEN_REC),
+    ("then", TOKEN_THEN),
+    ("with", TOKEN_WITH),
+];
+
+/// Operators and punctuation and the tokens they are parsed as
+pub const SYMBOLS: &[(&str, SyntaxKind)] = &[
+    ("{", TOKEN_L_BRACE),
+    ("}", TOKEN_R_BRACE),
+    ("[", TOKEN_L_BRACK),
+    ("]", TOKEN_R_BRACK),
+    ("(", TOKEN_L_PAREN),
+    (")", TOKEN_R_PAREN),
+    ("=", TOKEN_ASSIGN),
+    ("@", TOKEN_AT),
+    (":", TOKEN_COLON),
+    (",", TOKEN_COMMA),
+    (".", TOKEN_DOT),
+    ("...", TOKEN_ELLIPSIS),
+    ("?", TOKEN_QUESTION),
+    (";", TOKEN_SEMICOLON),
+    ("${", TOKEN_INTERPOL_START),
+    ("++", TOKEN_CONCAT),
+    ("!", TOKEN_INVERT),
+    ("//", TOKEN_UPDATE),
+    ("+", TOKEN_ADD),
+    ("-", TOKEN_SUB),
+    ("*", TOKEN_MUL),
+    ("/", TOKEN_DIV),
+    ("&&", TOKEN_AND_AND),
+    ("==", TOKEN_EQUAL),
+    ("->", TOKEN_IMPLICATION),
+    ("<", TOKEN_LESS),
+    ("<=", TOKEN_LESS_OR_EQ),
+    (">", TOKEN_MORE),
+    (">=", TOKEN_MORE_OR_EQ),
+    ("!=", TOKEN_NOT_EQUAL),
+    ("||", TOKEN_OR_OR),
+    ("|>", TOKEN_PIPE_RIGHT),
+    ("<|", TOKEN_PIPE_LEFT),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Associativity {
+    Left,
+    Right,
+    /// Chaining the operators is a syntax error
+    None,
+    /// A prefix operator, which can be repeated
+    Prefix,
+}
+
+/// A precedence level: the operators which bind equally tight and the node
+/// they create
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Level {
+    pub node: SyntaxKind,
+    /// Empty for function application, which has no operator
+    pub operators: &'static [SyntaxKind],
+    pub associativity: Associativity,
+}
+
+/// The precedence levels of operators, from the tightest to the loosest
+/// binding. Everything else, like `if` and functions, extends as far to the
+/// right as possible.
+pub const PRECEDENCE: &[Level] = &[
+    Level { node: NODE_SELECT, operators: &[TOKEN_DOT], associativity: Associativity::Left },
+    Level { node: NODE_APPLY, operators: &[], associativity: Associativity::Left },
+    Level { node: NODE_UNARY_OP, operators: &[TOKEN_SUB], associativity: Associativity::Prefix },
+    Level { node: NODE_HAS_ATTR, operators: &[TOKEN_QUESTION], associativity: Associativity::Left },
+    Level { node: NODE_BIN_OP, operators: &[TOKEN_CONCAT], associativity: Associativity::Right },
+    Level {
+        node: NODE_BIN_OP,
+        operators: &[TOKEN_MUL, TOKEN_DIV],
+        associativity: Associativity::Left,
+    },
+    Level {
+        node: NODE_BIN_OP,
+        operators: &[TOKEN_ADD, TOKEN_SUB],
+        associativity: Associativity::Left,
+    },
+    Level { node: NODE_UNARY_OP, operators: &[TOKEN_INVERT], associativity: Associativity::Prefix },
+    Level { node: NODE_BIN_OP, operators: &[TOKEN_UPDATE], associativity: Associativity::Right },
+    Level {
+        node: NODE_BIN_OP,
+        operators: &[TOKEN_LESS, TOKEN_LESS_OR_EQ, TOKEN_MORE, TOKEN_MORE_OR_EQ],
+        associativity: Associativity::None,
+    },
+    Level {
+        node: NODE_BIN_OP,
+        operators: &[TOKEN_EQUAL, TOKEN_NOT_EQUAL],
+        associativity: Associativity::None,
+    },
+    Level { node: NODE_BIN_OP, operators: &[TOKEN_AND_AND], associativity: Associativity::Left },
+    Level { node: NODE_BIN_OP, operators: &[TOKEN_OR_OR], associativity: Associativity::Left },
+    Level {
+        node: NODE_BIN_OP,
+        operators: &[TOKEN_IMPLICATION],
+        associativity: Associativity::Right,
+    },
+];
+
+/// The levels of the pipe operators, which are only parsed with
+/// [`ParserOptions::experimental_pipe`](crate::parser::ParserOptions). Both
+/// bind equally tight and looser than every level in [`PRECEDENCE`], and
+/// mixing them without parentheses is a syntax error.
+pub const PIPES: &[Level] = &[
+    Level { node: NODE_BIN_OP, operators: &[TOKEN_PIPE_RIGHT], associativity: Associativity::Left },
+    Level { node: NODE_BIN_OP, operators: &[TOKEN_PIPE_LEFT], associativity: Associativity::Right },
+];
+
+/// The level of an operator token in a node, from [`PRECEDENCE`] or
+/// [`PIPES`]. The node is needed because `-` is both a unary and a binary
+/// operator.
+pub fn level(node: SyntaxKind, operator: SyntaxKind) -> Option<&'static Level> {
+    PRECEDENCE
+        .iter()
+        .chain(PIPES)
+        .find(|level| level.node == node && level.operators.contains(&operator))
+}
+
+/// The precedence of an operator token in a node: the position of its level
+/// in [`PRECEDENCE`], so smaller numbers bind tighter. Both pipes bind
+/// equally tight, after every other level, at `PRECEDENCE.len()`.
+///
+/// ```
+/// use rnix::{ast::BinOpKind, grammar, SyntaxKind::*};
+///
+/// assert!(BinOpKind::Mul.precedence() < BinOpKind::Add.precedence());
+/// assert_eq!(grammar::precedence(NODE_BIN_OP, TOKEN_UPDATE), Some(BinOpKind::Update.precedence()));
+/// assert_eq!(grammar::precedence(NODE_BIN_OP, TOKEN_PIPE_LEFT), Some(grammar::PRECEDENCE.len()));
+/// ```
+pub fn precedence(node: SyntaxKind, operator: SyntaxKind) -> Option<usize> {
+    level(node, operator).map(position)
+}
+
+fn position(level: &Level) -> usize {
+    PRECEDENCE.iter().position(|other| other == level).unwrap_or(PRECEDENCE.len())
+}
+
+/// The side of an operator an operand is on. The operand of a prefix
+/// operator is on its right.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// Whether an operand with the level `child` needs parentheses on the `side`
+/// of an operator with the level `parent`. This is the case if the child
+/// binds looser, or equally tight without associating on that side. The
+/// pipes bind equally tight but don't associate with each other, so
+/// `f <| (a |> g)` keeps its parentheses.
+///
+/// Prefix operators binding looser than the parent are always parenthesized,
+/// although `a * !b` parses on its own: followed by `+ c` it doesn't.
+///
+/// ```
+/// use rnix::{grammar::{self, Side}, SyntaxKind::*};
+///
+/// let add = grammar::level(NODE_BIN_OP, TOKEN_ADD).unwrap();
+/// let mul = grammar::level(NODE_BIN_OP, TOKEN_MUL).unwrap();
+/// assert!(!grammar::needs_parens(add, mul, Side::Right));
+/// assert!(grammar::needs_parens(add, add, Side::Right));
+/// assert!(!grammar::needs_parens(add, add, Side::Left));
+/// ```
+pub fn needs_parens(parent: &Level, child: &Level, side: Side) -> bool {
+    let (parent_position, child_position) = (position(parent), position(child));
+    if parent_position != child_position {
+        return child_position > parent_position;
+    }
+    if parent != child {
+        return true;
+    }
+    !matches!(
+        (child.associativity, side),
+        (Associativity::Left, Side::Left)
+            | (Associativity::Right, Side::Right)
+            | (Associativity::Prefix, Side::Right)
+    )
+}
+
+/// What can appear as a child: a token, or a node or union by name
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shape {
+    Token(SyntaxKind),
+    Node(&'static str),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cardinality {
+    One,
+    Optional,
+    Many,
+    /// Any number, separated by a token
+    Separated(SyntaxKind),
+}
+
+/// A child of a node, which can have one of several shapes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Child {
+    pub name: &'static str,
+    pub shapes: &'static [Shape],
+    pub cardinality: Cardinality,
+}
+
+/// A kind of node, with its children in the order they appear. Whitespace and
+/// comments can appear between any of them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Node {
+    pub name: &'static str,
+    pub kind: SyntaxKind,
+    pub children: &'static [Child],
+}
+
+/// A name for a choice between several kinds of nodes, like `Expr`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Union {
+    pub name: &'static str,
+    pub variants: &'static [&'static str],
+}
+
+pub const UNIONS: &[Union] = &[
+    Union {
+        name: "Expr",
+        variants: &[
+            "Apply",
+            "Assert",
+            "Error",
+            "IfElse",
+            "Select",
+            "Str",
+            "Path",
+            "Literal",
+            "Lambda",
+            "LegacyLet",
+            "LetIn",
+            "List",
+            "BinOp",
+            "Paren",
+            "Root",
+            "AttrSet",
+            "UnaryOp",
+            "Ident",
+            "With",
+            "HasAttr",
+        ],
+    },
+    Union { name: "Attr", variants: &["Ident", "Dynamic", "Str"] },
+    Union { name: "Param", variants: &["Pattern", "IdentParam"] },
+    Union { name: "Entry", variants: &["Inherit", "AttrpathValue"] },
+];
+
+const fn child(name: &'static str, shapes: &'static [Shape]) -> Child {
+    Child { name, shapes, cardinality: Cardinality::One }
+}
+
+const fn optional(name: &'static str, shapes: &'static [Shape]) -> Child {
+    Child { name, shapes, cardinality: Cardinality::Optional }
+}
+
+const fn many(name: &'static str, shapes: &'static [Shape]) -> Child {
+    Child { name, shapes, cardinality: Cardinality::Many }
+}
+
+const fn separated(name: &'static str, shapes: &'static [Shape], by: SyntaxKind) -> Child {
+    Child { name, shapes, cardinality: Cardinality::Separated(by) }
+}
+
+const EXPR: &[Shape] = &[Shape::Node("Expr")];
+const IDENT: &[Shape] = &[Shape::Node("Ident")];
+const ATTR: &[Shape] = &[Shape::Node("Attr")];
+const ATTRPATH: &[Shape] = &[Shape::Node("Attrpath")];
+const ENTRY: &[Shape] = &[Shape::Node("Entry")];
+
+macro_rules! token {
+    ($name:literal, $kind:ident) => {
+        child($name, &[Shape::Token($kind)])
+    };
+}
+
+pub const NODES: &[Node] = &[
+    Node { name: "Root", kind: NODE_ROOT, children: &[child("expr", EXPR)] },
+    Node {
+        name: "Apply",
+        kind: NODE_APPLY,
+        children: &[child("lambda", EXPR), child("argument", EXPR)],
+    },
+    Node {
+        name: "Assert",
+        kind: NODE_ASSERT,
+        children: &[
+            token!("assert_token", TOKEN_ASSERT),
+            child("condition", EXPR),
+            token!("semicolon_token", TOKEN_SEMICOLON),
+            child("body", EXPR),
+        ],
+    },
+    Node {
+        name: "Attrpath",
+        kind: NODE_ATTRPATH,
+        children: &[separated("attrs", ATTR, TOKEN_DOT)],
+    },
+    Node {
+        name: "AttrpathValue",
+        kind: NODE_ATTRPATH_VALUE,
+        children: &[
+            child("attrpath", ATTRPATH),
+            token!("assign_token", TOKEN_ASSIGN),
+            child("value", EXPR),
+            token!("semicolon_token", TOKEN_SEMICOLON),
+        ],
+    },
+    Node {
+        name: "AttrSet",
+        kind: NODE_ATTR_SET,
+        children: &[
+            optional("rec_token", &[Shape::Token(TOKEN_REC)]),
+            token!("l_curly_token", TOKEN_L_BRACE),
+            many("entries", ENTRY),
+            token!("r_curly_token", TOKEN_R_BRACE),
+        ],
+    },
+    Node {
+        name: "BinOp",
+        kind: NODE_BIN_OP,
+        children: &[
+            child("lhs", EXPR),
+            child(
+                "operator",
+                &[
+                    Shape::Token(TOKEN_CONCAT),
+                    Shape::Token(TOKEN_UPDATE),
+                    Shape::Token(TOKEN_ADD),
+                    Shape::Token(TOKEN_SUB),
+                    Shape::Token(TOKEN_MUL),
+                    Shape::Token(TOKEN_DIV),
+                    Shape::Token(TOKEN_AND_AND),
+                    Shape::Token(TOKEN_EQUAL),
+                    Shape::Token(TOKEN_IMPLICATION),
+                    Shape::Token(TOKEN_LESS),
+                    Shape::Token(TOKEN_LESS_OR_EQ),
+                    Shape::Token(TOKEN_MORE),
+                    Shape::Token(TOKEN_MORE_OR_EQ),
+                    Shape::Token(TOKEN_NOT_EQUAL),
+                    Shape::Token(TOKEN_OR_OR),
+                    Shape::Token(TOKEN_PIPE_RIGHT),
+                    Shape::Token(TOKEN_PIPE_LEFT),
+                ],
+            ),
+            child("rhs", EXPR),
+        ],
+    },
+    Node {
+        name: "Dynamic",
+        kind: NODE_DYNAMIC,
+        children: &[
+            token!("interpol_start_token", TOKEN_INTERPOL_START),
+            child("expr", EXPR),
+            token!("interpol_end_token", TOKEN_INTERPOL_END),
+        ],
+    },
+    // Contains whatever couldn't be parsed
+    Node { name: "Error", kind: NODE_ERROR, children: &[] },
+    Node {
+        name: "HasAttr",
+        kind: NODE_HAS_ATTR,
+        children: &[
+            child("expr", EXPR),
+            token!("question_token", TOKEN_QUESTION),
+            child("attrpath", ATTRPATH),
+        ],
+    },
+    Node { name: "Ident", kind: NODE_IDENT, children: &[token!("ident_token", TOKEN_IDENT)] },
+    Node { name: "IdentParam", kind: NODE_IDENT_PARAM, children: &[child("ident", IDENT)] },
+    Node {
+        name: "IfElse",
+        kind: NODE_IF_ELSE,
+        children: &[
+            token!("if_token", TOKEN_IF),
+            child("condition", EXPR),
+            token!("then_token", TOKEN_THEN),
+            child("body", EXPR),
+            token!("else_token", TOKEN_ELSE),
+            child("else_body", EXPR),
+        ],
+    },
+    Node {
+        name: "Inherit",
+        kind: NODE_INHERIT,
+        children: &[
+            token!("inherit_token", TOKEN_INHERIT),
+            optional("from", &[Shape::Node("InheritFrom")]),
+            many("attrs", ATTR),
+            token!("semicolon_token", TOKEN_SEMICOLON),
+        ],
+    },
+    Node {
+        name: "InheritFrom",
+        kind: NODE_INHERIT_FROM,
+        children: &[
+            token!("l_paren_token", TOKEN_L_PAREN),
+            child("expr", EXPR),
+            token!("r_paren_token", TOKEN_R_PAREN),
+        ],
+    },
+    Node {
+        name: "Interpol",
+        kind: NODE_INTERPOL,
+        children: &[
+            token!("interpol_start_token", TOKEN_INTERPOL_START),
+            child("expr", EXPR),
+            token!("interpol_end_token", TOKEN_INTERPOL_END),
+        ],
+    },
+    Node {
+        name: "Lambda",
+        kind: NODE_LAMBDA,
+        children: &[
+            child("param", &[Shape::Node("Param")]),
+            token!("token_colon", TOKEN_COLON),
+            child("body", EXPR),
+        ],
+    },
+    Node {
+        name: "LegacyLet",
+        kind: NODE_LEGACY_LET,
+        children: &[
+            token!("let_token", TOKEN_LET),
+            token!("curly_open_token", TOKEN_L_BRACE),
+            many("entries", ENTRY),
+            token!("curly_close_token", TOKEN_R_BRACE),
+        ],
+    },
+    Node {
+        name: "LetIn",
+        kind: NODE_LET_IN,
+        children: &[
+            token!("let_token", TOKEN_LET),
+            many("entries", ENTRY),
+            token!("in_token", TOKEN_IN),
+            child("body", EXPR),
+        ],
+    },
+    Node {
+        name: "List",
+        kind: NODE_LIST,
+        children: &[
+            token!("l_brack_token", TOKEN_L_BRACK),
+            many("items", EXPR),
+            token!("r_brack_token", TOKEN_R_BRACK),
+        ],
+    },
+    Node {
+        name: "Literal",
+        kind: NODE_LITERAL,
+        children: &[child(
+            "token",
+            &[Shape::Token(TOKEN_FLOAT), Shape::Token(TOKEN_INTEGER), Shape::Token(TOKEN_URI)],
+        )],
+    },
+    Node {
+        name: "Paren",
+        kind: NODE_PAREN,
+        children: &[
+            token!("l_paren_token", TOKEN_L_PAREN),
+            child("expr", EXPR),
+            token!("r_paren_token", TOKEN_R_PAREN),
+        ],
+    },
+    Node {
+        name: "Path",
+        kind: NODE_PATH,
+        children: &[many("parts", &[Shape::Token(TOKEN_PATH), Shape::Node("Interpol")])],
+    },
+    // The `@` comes after the name when the binding is before the braces
+    Node {
+        name: "PatBind",
+        kind: NODE_PAT_BIND,
+        children: &[
+            optional("at_token", &[Shape::Token(TOKEN_AT)]),
+            child("ident", IDENT),
+            optional("at_token", &[Shape::Token(TOKEN_AT)]),
+        ],
+    },
+    Node {
+        name: "PatEntry",
+        kind: NODE_PAT_ENTRY,
+        children: &[
+            child("ident", IDENT),
+            optional("question_token", &[Shape::Token(TOKEN_QUESTION)]),
+            optional("default", EXPR),
+        ],
+    },
+    // The binding is either before or after the braces
+    Node {
+        name: "Pattern",
+        kind: NODE_PATTERN,
+        children: &[
+            optional("pat_bind", &[Shape::Node("PatBind")]),
+            token!("l_curly_token", TOKEN_L_BRACE),
+            separated("pat_entries", &[Shape::Node("PatEntry")], TOKEN_COMMA),
+            optional("ellipsis_token", &[Shape::Token(TOKEN_ELLIPSIS)]),
+            token!("r_curly_token", TOKEN_R_BRACE),
+            optional("pat_bind", &[Shape::Node("PatBind")]),
+        ],
+    },
+    Node {
+        name: "Select",
+        kind: NODE_SELECT,
+        children: &[
+            child("expr", EXPR),
+            token!("dot_token", TOKEN_DOT),
+            child("attrpath", ATTRPATH),
+            optional("or_token", &[Shape::Token(TOKEN_OR)]),
+            optional("default_expr", EXPR),
+        ],
+    },
+    Node {
+        name: "Str",
+        kind: NODE_STRING,
+        children: &[
+            token!("string_start_token", TOKEN_STRING_START),
+            many("parts", &[Shape::Token(TOKEN_STRING_CONTENT), Shape::Node("Interpol")]),
+            token!("string_end_token", TOKEN_STRING_END),
+        ],
+    },
+    Node {
+        name: "UnaryOp",
+        kind: NODE_UNARY_OP,
+        children: &[
+            child("operator", &[Shape::Token(TOKEN_INVERT), Shape::Token(TOKEN_SUB)]),
+            child("expr", EXPR),
+        ],
+    },
+    Node {
+        name: "With",
+        kind: NODE_WITH,
+        children: &[
+            token!("with_token", TOKEN_WITH),
+            child("namespace", EXPR),
+            token!("semicolon_token", TOKEN_SEMICOLON),
+            child("body", EXPR),
+        ],
+    },
+];
+
+/// Find the description of a kind of node
+pub fn node(kind: SyntaxKind) -> Option<&'static Node> {
+    NODES.iter().find(|node| node.kind == kind)
+}
+
+/// Print the whole grammar as JSON, as an object with the keys `keywords`,
+/// `symbols`, `precedence`, `pipes`, `unions` and `nodes`
+pub fn json() -> String {
+    let mut out = String::from("{");
+    let tokens = |out: &mut String, list: &[(&str, SyntaxKind)]| {
+        list_json(out, list, |out, (text, kind)| {
+            out.push_str(r#"{"text":"#);
+            json_string(out, text);
+            write!(out, r#","kind":"{:?}"}}"#, kind).unwrap();
+        })
+    };
+    out.push_str(r#""keywords":"#);
+    tokens(&mut out, KEYWORDS);
+    out.push_str(r#","symbols":"#);
+    tokens(&mut out, SYMBOLS);
+
+    let levels = |out: &mut String, levels: &[Level]| {
+        list_json(out, levels, |out, level| {
+            write!(out, r#"{{"node":"{:?}","operators":"#, level.node).unwrap();
+            list_json(out, level.operators, |out, kind| write!(out, r#""{:?}""#, kind).unwrap());
+            let associativity = match level.associativity {
+                Associativity::Left => "left",
+                Associativity::Right => "right",
+                Associativity::None => "none",
+                Associativity::Prefix => "prefix",
+            };
+            write!(out, r#","associativity":"{}"}}"#, associativity).unwrap();
+        })
+    };
+    out.push_str(r#","precedence":"#);
+    levels(&mut out, PRECEDENCE);
+    out.push_str(r#","pipes":"#);
+    levels(&mut out, PIPES);
+
+    out.push_str(r#","unions":"#);
+    list_json(&mut out, UNIONS, |out, union| {
+        write!(out, r#"{{"name":"{}","variants":"#, union.name).unwrap();
+        list_json(out, union.variants, |out, variant| write!(out, r#""{}""#, variant).unwrap());
+        out.push('}');
+    });
+
+    out.push_str(r#","nodes":"#);
+    list_json(&mut out, NODES, |out, node| {
+        write!(out, r#"{{"name":"{}","kind":"{:?}","children":"#, node.name, node.kind).unwrap();
+        list_json(out, node.children, |out, child| {
+            write!(out, r#"{{"name":"{}","shapes":"#, child.name).unwrap();
+            list_json(out, child.shapes, |out, shape| match shape {
+                Shape::Token(kind) => write!(out, r#""{:?}""#, kind).unwrap(),
+                Shape::Node(name) => write!(out, r#""{}""#, name).unwrap(),
+            });
+            match child.cardinality {
+                Cardinality::One => out.push_str(r#","cardinality":"one""#),
+                Cardinality::Optional => out.push_str(r#","cardinality":"optional""#),
+                Cardinality::Many => out.push_str(r#","cardinality":"many""#),
+                Cardinality::Separated(by) => {
+                    write!(out, r#","cardinality":"many","separator":"{:?}""#, by).unwrap()
+                }
+            }
+            out.push('}');
+        });
+        out.push('}');
+    });
+    out.push('}');
+    out
+}
+
+fn list_json<T>(out: &mut String, items: &[T], mut write_item: impl FnMut(&mut String, &T)) {
+    out.push('[');
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_item(out, item);
+    }
+    out.push(']');
+}
+
+#[cfg(test)]
+mod tests {
+    use rowan::{ast::AstNode, Language};
+
+    use super::*;
+    use crate::{ast, parser::ParserOptions, tokenize, NixLanguage, Root, SyntaxNode};
+
+    fn level(node: &SyntaxNode) -> Option<usize> {
+        PRECEDENCE.iter().position(|level| {
+            level.node == node.kind()
+                && (level.operators.is_empty()
+                    || node.children_with_tokens().any(|child| {
+                        child
+                            .as_token()
+                            .is_some_and(|token| level.operators.contains(&token.kind()))
+                    }))
+        })
+    }
+
+    fn text(kind: SyntaxKind) -> &'static str {
+        SYMBOLS.iter().find(|(_, symbol)| *symbol == kind).map_or("", |(text, _)| text)
+    }
+
+    fn root(source: &str) -> Option<SyntaxNode> {
+        let root = Root::parse(source).ok().ok()?;
+        Some(root.expr()?.syntax().clone())
+    }
+
+    #[test]
+    fn tokens() {
+        for (text, kind) in KEYWORDS.iter().chain(SYMBOLS) {
+            assert_eq!(tokenize(text).first().map(|token| token.0), Some(*kind), "{}", text);
+        }
+    }
+
+    #[test]
+    fn nodes() {
+        for kind in (NODE_APPLY as u16)..(SyntaxKind::__LAST as u16) {
+            let kind = NixLanguage::kind_from_raw(rowan::SyntaxKind(kind));
+            assert!(node(kind).is_some(), "{:?} is missing", kind);
+        }
+        let names: Vec<&str> = NODES.iter().map(|node| node.name).collect();
+        for union in UNIONS {
+            assert!(union.variants.iter().all(|variant| names.contains(variant)));
+        }
+        let expr = &UNIONS[0];
+        for node in NODES {
+            assert_eq!(expr.variants.contains(&node.name), ast::Expr::can_cast(node.kind));
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        for (i, tighter) in PRECEDENCE.iter().enumerate() {
+            for (j, looser) in PRECEDENCE.iter().enumerate().skip(i + 1) {
+                let (x, y) = (tighter.operators.first(), looser.operators.first());
+                let (x, y) = (x.map_or(" ", |x| text(*x)), y.map_or(" ", |y| text(*y)));
+                if looser.associativity == Associativity::Prefix {
+                    continue;
+                }
+                if tighter.associativity == Associativity::Prefix {
+                    let node = root(&format!("{} a {} b", x, y)).unwrap();
+                    assert_eq!(level(&node), Some(j), "{} a {} b", x, y);
+                    continue;
+                }
+                let mut sources = vec![format!("a {} b {} c", x, y)];
+                // The right hand side of `?` is an attribute path, not an
+                // expression
+                if looser.node != NODE_HAS_ATTR {
+                    sources.push(format!("a {} b {} c", y, x));
+                }
+                for source in sources {
+                    let node = root(&source).unwrap_or_else(|| panic!("{}", source));
+                    assert_eq!(level(&node), Some(j), "{}", source);
+                }
+            }
+        }
+        for (i, level_) in PRECEDENCE.iter().enumerate() {
+            let op = level_.operators.first().map_or(" ", |op| text(*op));
+            // Attribute paths take up all following selections
+            if level_.node == NODE_SELECT || level_.associativity == Associativity::Prefix {
+                continue;
+            }
+            let node = root(&format!("a {} b {} c", op, op));
+            let lhs = node.as_ref().and_then(|node| node.first_child()).and_then(|n| level(&n));
+            let rhs = node.as_ref().and_then(|node| node.last_child()).and_then(|n| level(&n));
+            match level_.associativity {
+                Associativity::Left => assert_eq!(lhs, Some(i), "{}", op),
+                Associativity::Right => assert_eq!(rhs, Some(i), "{}", op),
+                _ => assert!(node.is_none(), "{}", op),
+            }
+        }
+    }
+
+    #[test]
+    fn operators() {
+        use ast::{BinOpKind::*, UnaryOpKind::*};
+
+        let binary = [
+            Concat,
+            Update,
+            Add,
+            Sub,
+            Mul,
+            Div,
+            And,
+            Equal,
+            Implication,
+            Less,
+            LessOrEq,
+            More,
+            MoreOrEq,
+            NotEqual,
+            Or,
+            PipeRight,
+            PipeLeft,
+        ];
+        for op in binary {
+            assert_eq!(ast::BinOpKind::from_kind(op.to_kind()), Some(op));
+            let level = super::level(NODE_BIN_OP, op.to_kind()).unwrap();
+            assert_eq!(op.associativity(), level.associativity);
+        }
+        for op in [Invert, Negate] {
+            assert_eq!(ast::UnaryOpKind::from_kind(op.to_kind()), Some(op));
+            assert_eq!(op.associativity(), Associativity::Prefix);
+        }
+        // Every operator token of a level is an operator of its node
+        for level in PRECEDENCE.iter().chain(PIPES) {
+            for &operator in level.operators {
+                let known = match level.node {
+                    NODE_BIN_OP => ast::BinOpKind::from_kind(operator).is_some(),
+                    NODE_UNARY_OP => ast::UnaryOpKind::from_kind(operator).is_some(),
+                    _ => true,
+                };
+                assert!(known, "{:?}", operator);
+            }
+        }
+        assert!(Negate.precedence() < Mul.precedence());
+        assert!(Sub.precedence() < Invert.precedence());
+        assert!(Or.precedence() < Implication.precedence());
+        assert_eq!(PipeRight.precedence(), PipeLeft.precedence());
+        assert!(Implication.precedence() < PipeRight.precedence());
+    }
+
+    #[test]
+    fn pipes() {
+        let options = ParserOptions { experimental_pipe: true, ..ParserOptions::default() };
+        let parse = |source: &str| {
+            let root = Root::parse_with_options(source, options).ok().ok()?;
+            Some(root.expr()?.syntax().to_string())
+        };
+        let operator = |source: &str| {
+            let root = Root::parse_with_options(source, options).ok().ok()?;
+            ast::BinOp::cast(root.expr()?.syntax().clone())?.operator()
+        };
+        // Every other operator binds tighter
+        for level in PRECEDENCE.iter().filter(|level| level.associativity != Associativity::Prefix)
+        {
+            let op = level.operators.first().map_or(" ", |op| text(*op));
+            if level.node == NODE_HAS_ATTR {
+                continue;
+            }
+            assert_eq!(operator(&format!("a {} b |> c", op)), Some(ast::BinOpKind::PipeRight));
+            assert_eq!(operator(&format!("a <| b {} c", op)), Some(ast::BinOpKind::PipeLeft));
+        }
+        let lhs = |source: &str| {
+            let root = Root::parse_with_options(source, options).ok().ok()?;
+            let op = ast::BinOp::cast(root.expr()?.syntax().clone())?;
+            Some((op.lhs()?.syntax().to_string(), op.rhs()?.syntax().to_string()))
+        };
+        assert_eq!(lhs("a |> f |> g"), Some(("a |> f".into(), "g".into())));
+        assert_eq!(lhs("f <| g <| a"), Some(("f".into(), "g <| a".into())));
+        assert_eq!(parse("x: x |> f"), Some("x: x |> f".into()));
+        assert_eq!(parse("a |> (f <| b)"), Some("a |> (f <| b)".into()));
+        // Mixing them is an error, as is using them without the option
+        assert_eq!(parse("a |> f <| b"), None);
+        assert_eq!(parse("f <| a |> g"), None);
+        assert!(Root::parse("a |> f").ok().is_err());
+        assert!(Root::parse("[ (f <| a) ]").ok().is_err());
+    }
+
+    #[test]
+    fn needs_parens() {
+        let options = ParserOptions { experimental_pipe: true, ..ParserOptions::default() };
+        let levels: Vec<&Level> = PRECEDENCE
+            .iter()
+            .chain(PIPES)
+            .filter(|level| level.node != NODE_SELECT)
+            .filter(|level| level.associativity != Associativity::Prefix)
+            .collect();
+        let level = |node: &SyntaxNode| {
+            levels.iter().copied().find(|level| {
+                level.node == node.kind()
+                    && (level.operators.is_empty()
+                        || node.children_with_tokens().any(|child| {
+                            child
+                                .as_token()
+                                .is_some_and(|token| level.operators.contains(&token.kind()))
+                        }))
+            })
+        };
+        // An operand needs parentheses exactly if the source without them
+        // doesn't parse into the same tree
+        for &parent in &levels {
+            for &child in &levels {
+                let x = parent.operators.first().map_or(" ", |op| text(*op));
+                let y = child.operators.first().map_or(" ", |op| text(*op));
+                let mut cases = vec![(Side::Left, format!("a {} b {} c", y, x))];
+                // The right hand side of `?` is an attribute path
+                if parent.node != NODE_HAS_ATTR {
+                    cases.push((Side::Right, format!("a {} b {} c", x, y)));
+                }
+                for (side, source) in cases {
+                    let node = Root::parse_with_options(&source, options)
+                        .ok()
+                        .ok()
+                        .and_then(|root| root.expr())
+                        .map(|expr| expr.syntax().clone());
+                    let parses = node.is_some_and(|node| {
+                        let operand = match side {
+                            Side::Left => node.first_child(),
+                            Side::Right => node.last_child(),
+                        };
+                        level(&node) == Some(parent)
+                            && operand.and_then(|n| level(&n)) == Some(child)
+                    });
+                    assert_eq!(super::needs_parens(parent, child, side), !parses, "{}", source);
+                }
+            }
+        }
+
+        let pipe_left = super::level(NODE_BIN_OP, TOKEN_PIPE_LEFT).unwrap();
+        let pipe_right = super::level(NODE_BIN_OP, TOKEN_PIPE_RIGHT).unwrap();
+        assert!(super::needs_parens(pipe_left, pipe_right, Side::Right));
+        assert!(super::needs_parens(pipe_right, pipe_left, Side::Left));
+        assert!(!super::needs_parens(pipe_left, pipe_left, Side::Right));
+
+        let negate = super::level(NODE_UNARY_OP, TOKEN_SUB).unwrap();
+        let invert = super::level(NODE_UNARY_OP, TOKEN_INVERT).unwrap();
+        let mul = super::level(NODE_BIN_OP, TOKEN_MUL).unwrap();
+        assert!(!super::needs_parens(negate, negate, Side::Right));
+        assert!(!super::needs_parens(mul, negate, Side::Right));
+        assert!(super::needs_parens(mul, invert, Side::Right));
+        assert!(!super::needs_parens(invert, mul, Side::Right));
+    }
+}
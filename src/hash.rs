@@ -0,0 +1,101 @@
+//! Stable hashes of sources and syntax trees, for keying caches of parses and
+//! analyses across runs of a tool
+//!
+//! The hashes are computed with BLAKE3. They stay the same between runs and
+//! platforms, but may change with new versions of this crate.
+
+use std::fmt;
+
+use rowan::ast::AstNode;
+
+use crate::{Root, SyntaxKind::*, SyntaxNode, WalkEvent};
+
+/// A 256-bit hash, printed as hexadecimal
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ContentHash([u8; 32]);
+
+impl ContentHash {
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl fmt::Display for ContentHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Hash the text of a source, for caching anything depending on its exact
+/// text, like a parse or the positions of diagnostics
+pub fn source_hash(source: &str) -> ContentHash {
+    ContentHash(*blake3::hash(source.as_bytes()).as_bytes())
+}
+
+/// Hash the structure of a tree and the text of its tokens, leaving out
+/// whitespace and comments. Trees differing only in formatting have the same
+/// hash, which makes it suitable for caching anything not depending on
+/// positions, like the result of an evaluation.
+pub fn content_hash(node: &SyntaxNode) -> ContentHash {
+    let mut hasher = blake3::Hasher::new();
+    for event in node.preorder_with_tokens() {
+        match event {
+            WalkEvent::Enter(element) => match element.into_token() {
+                Some(token) if matches!(token.kind(), TOKEN_WHITESPACE | TOKEN_COMMENT) => (),
+                // The length separates the text from the next token
+                Some(token) => {
+                    hasher.update(b"t");
+                    hasher.update(&(token.kind() as u16).to_le_bytes());
+                    hasher.update(&(token.text().len() as u64).to_le_bytes());
+                    hasher.update(token.text().as_bytes());
+                }
+                None => {
+                    hasher.update(b"(");
+                }
+            },
+            WalkEvent::Leave(element) => {
+                if let Some(node) = element.into_node() {
+                    hasher.update(&(node.kind() as u16).to_le_bytes());
+                    hasher.update(b")");
+                }
+            }
+        }
+    }
+    ContentHash(*hasher.finalize().as_bytes())
+}
+
+impl Root {
+    /// Hash the tree ignoring formatting, see [`content_hash`]
+    pub fn content_hash(&self) -> ContentHash {
+        content_hash(self.syntax())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(src: &str) -> ContentHash {
+        Root::parse(src).tree().content_hash()
+    }
+
+    #[test]
+    fn formatting() {
+        assert_eq!(hash("{ a = 1; }"), hash("{\n  # a comment\n  a = 1;\n}"));
+        assert_ne!(hash("{ a = 1; }"), hash("{ a = 2; }"));
+        assert_ne!(hash("[ a b ]"), hash("[ ab ]"));
+        assert_ne!(hash("a b c"), hash("a (b c)"));
+        assert_ne!(source_hash("{ a = 1; }"), source_hash("{ a = 1;  }"));
+    }
+
+    #[test]
+    fn stable() {
+        assert_eq!(
+            source_hash("").to_string(),
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+        );
+    }
+}
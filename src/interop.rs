@@ -0,0 +1,79 @@
+//! Conversion between constant Nix expressions and other data formats
+//!
+//! Each format is behind a feature of the same name.
+
+use std::fmt;
+
+use rowan::TextRange;
+
+#[cfg(feature = "json")]
+mod json;
+#[cfg(feature = "toml")]
+mod toml;
+#[cfg(feature = "yaml")]
+mod yaml;
+
+#[cfg(feature = "toml")]
+pub use self::toml::{from_toml, to_toml, ToTomlError};
+#[cfg(feature = "json")]
+pub use json::{from_json, to_json_value};
+#[cfg(feature = "yaml")]
+pub use yaml::{from_yaml, to_yaml, AliasPolicy, TagPolicy, YamlError, YamlOptions};
+
+/// How expressions converted from other formats are printed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrintOptions {
+    /// The number of spaces per indentation level
+    pub indent_width: usize,
+    /// Write nested attribute sets with a single attribute as an attribute
+    /// path, like `a.b = 1;` instead of `a = { b = 1; };`
+    pub collapse_attrpaths: bool,
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        Self { indent_width: 2, collapse_attrpaths: false }
+    }
+}
+
+/// The reason an expression can't be converted
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum NotConstant {
+    /// A function, which can't be represented as data
+    Function(TextRange),
+    /// An attribute whose name is only known after evaluation
+    DynamicAttr(TextRange),
+    /// An attribute which is defined twice
+    DuplicateAttr(TextRange),
+    /// Any other expression whose value is only known after evaluation, like
+    /// variables or interpolated strings
+    Dynamic(TextRange),
+}
+
+impl NotConstant {
+    /// The range of the expression which can't be converted
+    pub fn range(&self) -> TextRange {
+        match self {
+            NotConstant::Function(range)
+            | NotConstant::DynamicAttr(range)
+            | NotConstant::DuplicateAttr(range)
+            | NotConstant::Dynamic(range) => *range,
+        }
+    }
+}
+
+impl fmt::Display for NotConstant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let range = self.range();
+        let what = match self {
+            NotConstant::Function(_) => "function can't be converted",
+            NotConstant::DynamicAttr(_) => "attribute name isn't constant",
+            NotConstant::DuplicateAttr(_) => "attribute is defined twice",
+            NotConstant::Dynamic(_) => "expression isn't constant",
+        };
+        write!(f, "{} at {}..{}", what, usize::from(range.start()), usize::from(range.end()))
+    }
+}
+
+impl std::error::Error for NotConstant {}
@@ -0,0 +1,366 @@
+use rowan::{ast::AstNode, TextRange};
+use serde_json::{Map, Number, Value};
+
+use crate::{
+    analysis::{attr_name, eval_const},
+    ast::{self, BinOpKind, HasEntry},
+    tokenize, value,
+    value::{escape_indented, escape_string},
+    Root, SyntaxKind,
+};
+
+use super::{NotConstant, PrintOptions};
+
+/// Convert an expression consisting only of literals, lists and attribute sets
+/// to JSON. Arithmetic on literals, `++` and `//` are evaluated, and `true`,
+/// `false` and `null` are taken to be the builtin values. Paths are converted
+/// to strings as written.
+pub fn to_json_value(expr: &ast::Expr) -> Result<Value, NotConstant> {
+    let range = expr.syntax().text_range();
+    match expr {
+        ast::Expr::Paren(paren) => match paren.expr() {
+            Some(inner) => to_json_value(&inner),
+            None => Err(NotConstant::Dynamic(range)),
+        },
+        ast::Expr::Ident(ident) => {
+            match ident.ident_token().and_then(|token| value::Value::from_keyword(token.text())) {
+                Some(constant) => json_scalar(constant, range),
+                None => Err(NotConstant::Dynamic(range)),
+            }
+        }
+        ast::Expr::List(list) => list.items().map(|item| to_json_value(&item)).collect(),
+        ast::Expr::AttrSet(set) => {
+            let mut map = Map::new();
+            for entry in set.entries() {
+                let entry = match entry {
+                    ast::Entry::AttrpathValue(entry) => entry,
+                    ast::Entry::Inherit(inherit) => {
+                        return Err(NotConstant::Dynamic(inherit.syntax().text_range()))
+                    }
+                };
+                let attrpath = entry.attrpath().ok_or(NotConstant::Dynamic(range))?;
+                let mut path = Vec::new();
+                for attr in attrpath.attrs() {
+                    path.push(
+                        attr_name(&attr)
+                            .ok_or(NotConstant::DynamicAttr(attr.syntax().text_range()))?,
+                    );
+                }
+                let value = to_json_value(&entry.value().ok_or(NotConstant::Dynamic(range))?)?;
+                insert(&mut map, &path, value, attrpath.syntax().text_range())?;
+            }
+            Ok(Value::Object(map))
+        }
+        ast::Expr::Lambda(_) => Err(NotConstant::Function(range)),
+        ast::Expr::BinOp(op) => {
+            let operands = op.lhs().zip(op.rhs());
+            match (op.operator(), operands) {
+                (Some(BinOpKind::Concat), Some((lhs, rhs))) => {
+                    match (to_json_value(&lhs)?, to_json_value(&rhs)?) {
+                        (Value::Array(mut lhs), Value::Array(rhs)) => {
+                            lhs.extend(rhs);
+                            Ok(Value::Array(lhs))
+                        }
+                        _ => Err(NotConstant::Dynamic(range)),
+                    }
+                }
+                (Some(BinOpKind::Update), Some((lhs, rhs))) => {
+                    match (to_json_value(&lhs)?, to_json_value(&rhs)?) {
+                        (Value::Object(mut lhs), Value::Object(rhs)) => {
+                            lhs.extend(rhs);
+                            Ok(Value::Object(lhs))
+                        }
+                        _ => Err(NotConstant::Dynamic(range)),
+                    }
+                }
+                _ => scalar(expr),
+            }
+        }
+        _ => scalar(expr),
+    }
+}
+
+fn scalar(expr: &ast::Expr) -> Result<Value, NotConstant> {
+    let range = expr.syntax().text_range();
+    json_scalar(eval_const(expr).ok_or(NotConstant::Dynamic(range))?, range)
+}
+
+fn json_scalar(value: value::Value, range: TextRange) -> Result<Value, NotConstant> {
+    match value {
+        value::Value::Integer(integer, _) => Ok(Value::Number(integer.into())),
+        value::Value::Float(float, _) => {
+            Number::from_f64(float).map(Value::Number).ok_or(NotConstant::Dynamic(range))
+        }
+        value::Value::String(string, _) => Ok(Value::String(string)),
+        value::Value::Uri(uri) => Ok(Value::String(uri)),
+        path @ value::Value::Path(..) => Ok(Value::String(path.to_string())),
+        value::Value::Bool(value) => Ok(Value::Bool(value)),
+        value::Value::Null => Ok(Value::Null),
+    }
+}
+
+/// Insert a value at a path of attribute names, merging attribute sets like
+/// Nix does for `a.b = 1; a.c = 2;`
+fn insert(
+    map: &mut Map<String, Value>,
+    path: &[String],
+    value: Value,
+    range: TextRange,
+) -> Result<(), NotConstant> {
+    let (name, rest) = path.split_first().ok_or(NotConstant::Dynamic(range))?;
+    if rest.is_empty() {
+        return match (map.get_mut(name), value) {
+            (None, value) => {
+                map.insert(name.clone(), value);
+                Ok(())
+            }
+            (Some(Value::Object(existing)), Value::Object(value)) => {
+                for (key, value) in value {
+                    insert(existing, &[key], value, range)?;
+                }
+                Ok(())
+            }
+            _ => Err(NotConstant::DuplicateAttr(range)),
+        };
+    }
+    match map.entry(name.clone()).or_insert_with(|| Value::Object(Map::new())) {
+        Value::Object(inner) => insert(inner, rest, value, range),
+        _ => Err(NotConstant::DuplicateAttr(range)),
+    }
+}
+
+/// Convert JSON to an idiomatic Nix expression, with one attribute or list
+/// item per line. Strings with multiple lines are written as `''` strings when
+/// that doesn't change their value.
+pub fn from_json(value: &Value, options: &PrintOptions) -> Root {
+    let mut out = String::new();
+    print(&mut out, value, options, 0);
+    out.push('\n');
+    // Every value is printed as a literal, an escaped string, or a list or
+    // attrset of those with keys quoted unless they are identifiers, see the
+    // `edge_cases` test
+    Root::parse(&out).ok().expect("JSON is always printed as valid Nix")
+}
+
+fn print(out: &mut String, value: &Value, options: &PrintOptions, depth: usize) {
+    let indent = " ".repeat(depth * options.indent_width);
+    let inner = " ".repeat((depth + 1) * options.indent_width);
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(value) => out.push_str(if *value { "true" } else { "false" }),
+        Value::Number(number) => match number.as_i64() {
+            // The literal of its absolute value would overflow
+            Some(i64::MIN) => out.push_str("-9223372036854775807 - 1"),
+            Some(integer) => out.push_str(&integer.to_string()),
+            None => print_float(out, number.as_f64().unwrap_or_default()),
+        },
+        Value::String(string) => print_string(out, string, &indent, &inner),
+        Value::Array(items) if items.is_empty() => out.push_str("[ ]"),
+        Value::Array(items) => {
+            out.push_str("[\n");
+            for item in items {
+                out.push_str(&inner);
+                // Negative numbers would be parsed as a subtraction, and
+                // `-0.0` has to keep its sign
+                let negative = item.as_f64().is_some_and(f64::is_sign_negative);
+                if negative {
+                    out.push('(');
+                }
+                print(out, item, options, depth + 1);
+                if negative {
+                    out.push(')');
+                }
+                out.push('\n');
+            }
+            out.push_str(&indent);
+            out.push(']');
+        }
+        Value::Object(map) if map.is_empty() => out.push_str("{ }"),
+        Value::Object(map) => {
+            out.push_str("{\n");
+            for (key, mut value) in map {
+                out.push_str(&inner);
+                print_key(out, key);
+                while let Value::Object(map) = value {
+                    match map.iter().next() {
+                        Some((key, inner)) if options.collapse_attrpaths && map.len() == 1 => {
+                            out.push('.');
+                            print_key(out, key);
+                            value = inner;
+                        }
+                        _ => break,
+                    }
+                }
+                out.push_str(" = ");
+                print(out, value, options, depth + 1);
+                out.push_str(";\n");
+            }
+            out.push_str(&indent);
+            out.push('}');
+        }
+    }
+}
+
+/// Print a float, which needs a dot in Nix. `{:?}` prints one unless there is
+/// an exponent.
+fn print_float(out: &mut String, float: f64) {
+    let float = format!("{:?}", float);
+    match float.split_once('e') {
+        Some((mantissa, exp)) if !mantissa.contains('.') => {
+            out.push_str(&format!("{}.0e{}", mantissa, exp))
+        }
+        _ => out.push_str(&float),
+    }
+}
+
+/// Print an attribute name, quoting it unless it's a valid identifier
+fn print_key(out: &mut String, key: &str) {
+    if matches!(tokenize(key)[..], [(SyntaxKind::TOKEN_IDENT, name)] if name == key) {
+        out.push_str(key);
+    } else {
+        out.push('"');
+        out.push_str(&escape_string(key));
+        out.push('"');
+    }
+}
+
+/// Print a string, using a `''` string if it has multiple lines and ends with
+/// a newline, unless indentation or special whitespace in it would be stripped
+/// or be hard to read. `indent` is the indentation of the line the string is
+/// on, and `inner` the one of its contents.
+fn print_string(out: &mut String, string: &str, indent: &str, inner: &str) {
+    let lines: Vec<&str> = string.lines().collect();
+    let indented = string.ends_with('\n')
+        && lines.len() > 1
+        && !string.contains(['\r', '\t'])
+        && lines.iter().all(|line| line.is_empty() || !line.trim().is_empty())
+        && lines.iter().any(|line| !line.is_empty() && !line.starts_with(' '));
+    if !indented {
+        out.push('"');
+        out.push_str(&escape_string(string));
+        out.push('"');
+        return;
+    }
+    out.push_str("''\n");
+    for line in lines {
+        if !line.is_empty() {
+            out.push_str(inner);
+            out.push_str(&escape_indented(line));
+        }
+        out.push('\n');
+    }
+    out.push_str(indent);
+    out.push_str("''");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Root;
+    use serde_json::json;
+
+    fn convert(src: &str) -> Result<Value, NotConstant> {
+        to_json_value(&Root::parse(src).ok().unwrap().expr().unwrap())
+    }
+
+    #[test]
+    fn constant() {
+        assert_eq!(
+            convert(
+                r#"{
+                  name = "foo";
+                  meta.license = null;
+                  meta = { broken = false; };
+                  "with space" = [ 1 (2 * 3) 0.5 ./src ] ++ [ true ];
+                  nested = { a = 1; } // { b = "x" + "y"; };
+                }"#
+            ),
+            Ok(json!({
+                "name": "foo",
+                "meta": { "license": null, "broken": false },
+                "with space": [1, 6, 0.5, "./src", true],
+                "nested": { "a": 1, "b": "xy" },
+            }))
+        );
+    }
+
+    #[test]
+    fn not_constant() {
+        let range = |start: u32, end: u32| TextRange::new(start.into(), end.into());
+        assert_eq!(convert("{ a = x: x; }"), Err(NotConstant::Function(range(6, 10))));
+        assert_eq!(convert("{ ${a} = 1; }"), Err(NotConstant::DynamicAttr(range(2, 6))));
+        assert_eq!(convert("{ a = 1; a = 2; }"), Err(NotConstant::DuplicateAttr(range(9, 10))));
+        assert_eq!(convert("[ \"${x}\" ]"), Err(NotConstant::Dynamic(range(2, 8))));
+        assert_eq!(convert("{ inherit a; }"), Err(NotConstant::Dynamic(range(2, 12))));
+    }
+
+    #[test]
+    fn from_json_roundtrip() {
+        let value = json!({
+            "name": "foo",
+            "with space": [1, -2, 0.5, 1e300, null, [], {}],
+            "script": "echo ${x}\n  ''\n",
+            "indented": "  a\n  b\n",
+            "or": { "a-b'": true },
+        });
+        let root = from_json(&value, &PrintOptions::default());
+        assert_eq!(
+            root.to_string(),
+            r#"{
+  indented = "  a\n  b\n";
+  name = "foo";
+  "or" = {
+    a-b' = true;
+  };
+  script = ''
+    echo ''${x}
+      '''
+  '';
+  "with space" = [
+    1
+    (-2)
+    0.5
+    1.0e300
+    null
+    [ ]
+    { }
+  ];
+}
+"#
+        );
+        assert_eq!(to_json_value(&root.expr().unwrap()), Ok(value));
+
+        let options = PrintOptions { indent_width: 4, collapse_attrpaths: true };
+        let value = json!({ "a": { "b": { "c": [1] } }, "d": {} });
+        assert_eq!(
+            from_json(&value, &options).to_string(),
+            "{\n    a.b.c = [\n        1\n    ];\n    d = { };\n}\n"
+        );
+    }
+
+    #[test]
+    fn edge_cases() {
+        let keys = ["or", "if", "true", "a-b", "a'b", "a.b", "", "1", "${x}", "\"", "\u{0}", "é"];
+        for key in keys {
+            let value = json!({
+                key: [
+                    -1, -0.0, 1e-7, 1e300, u64::MAX, i64::MIN,
+                    "''${x}\n\\\n", "a\n  ''\n$\n", "\r\t\u{1}", { key: { key: i64::MIN } },
+                ],
+            });
+            let options = PrintOptions { collapse_attrpaths: true, ..PrintOptions::default() };
+            from_json(&value, &options);
+            from_json(&value, &PrintOptions::default());
+        }
+        let root = from_json(&json!(i64::MIN), &PrintOptions::default());
+        assert_eq!(root.to_string(), "-9223372036854775807 - 1\n");
+    }
+
+    #[test]
+    fn negative_zero() {
+        let root = from_json(&json!([-0.0, 0.0]), &PrintOptions::default());
+        assert_eq!(root.to_string(), "[\n  (-0.0)\n  0.0\n]\n");
+        assert_eq!(Root::parse(&root.to_string()).errors(), []);
+        assert_eq!(from_json(&json!(-0.0), &PrintOptions::default()).to_string(), "-0.0\n");
+    }
+}
@@ -0,0 +1,193 @@
+use std::fmt;
+
+use rowan::{ast::AstNode, TextRange};
+use serde_json::{Map, Number, Value};
+
+use crate::{ast, Root};
+
+use super::{from_json, to_json_value, NotConstant, PrintOptions};
+
+/// The reason an expression can't be converted to TOML
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ToTomlError {
+    /// The expression isn't constant, see [`to_json_value`]
+    NotConstant(NotConstant),
+    /// The expression isn't an attribute set, which a TOML document has to be
+    NotAttrSet(TextRange),
+    /// A `null`, which TOML doesn't have, at a path like `a.b[0]`
+    Null(String),
+}
+
+impl fmt::Display for ToTomlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ToTomlError::NotConstant(err) => err.fmt(f),
+            ToTomlError::NotAttrSet(range) => write!(
+                f,
+                "expected an attribute set at {}..{}",
+                usize::from(range.start()),
+                usize::from(range.end())
+            ),
+            ToTomlError::Null(path) => write!(f, "`{}` is null, which TOML can't represent", path),
+        }
+    }
+}
+
+impl std::error::Error for ToTomlError {}
+
+impl From<NotConstant> for ToTomlError {
+    fn from(err: NotConstant) -> Self {
+        ToTomlError::NotConstant(err)
+    }
+}
+
+/// Convert a TOML document to a Nix attribute set. Dates and times, as well as
+/// infinite and NaN floats, are converted to strings as written in TOML.
+pub fn from_toml(source: &str, options: &PrintOptions) -> Result<Root, toml::de::Error> {
+    let table: toml::Table = source.parse()?;
+    Ok(from_json(&to_json(toml::Value::Table(table)), options))
+}
+
+fn to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(string) => Value::String(string),
+        toml::Value::Integer(integer) => Value::Number(integer.into()),
+        toml::Value::Float(float) => match Number::from_f64(float) {
+            Some(number) => Value::Number(number),
+            None => Value::String(toml::Value::Float(float).to_string()),
+        },
+        toml::Value::Boolean(boolean) => Value::Bool(boolean),
+        toml::Value::Datetime(datetime) => Value::String(datetime.to_string()),
+        toml::Value::Array(items) => Value::Array(items.into_iter().map(to_json).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table.into_iter().map(|(key, value)| (key, to_json(value))).collect::<Map<_, _>>(),
+        ),
+    }
+}
+
+/// Convert a constant attribute set to a TOML document, see [`to_json_value`]
+/// for which expressions are constant
+pub fn to_toml(expr: &ast::Expr) -> Result<String, ToTomlError> {
+    let range = expr.syntax().text_range();
+    match to_toml_value(to_json_value(expr)?, &mut String::new())? {
+        toml::Value::Table(table) => Ok(table.to_string()),
+        _ => Err(ToTomlError::NotAttrSet(range)),
+    }
+}
+
+/// Convert JSON to TOML, keeping track of the path to the current value for
+/// errors
+fn to_toml_value(value: Value, path: &mut String) -> Result<toml::Value, ToTomlError> {
+    Ok(match value {
+        Value::Null => return Err(ToTomlError::Null(path.clone())),
+        Value::Bool(boolean) => toml::Value::Boolean(boolean),
+        Value::Number(number) => match number.as_i64() {
+            Some(integer) => toml::Value::Integer(integer),
+            None => toml::Value::Float(number.as_f64().unwrap_or_default()),
+        },
+        Value::String(string) => toml::Value::String(string),
+        Value::Array(items) => {
+            let mut array = toml::value::Array::new();
+            for (i, item) in items.into_iter().enumerate() {
+                let len = path.len();
+                path.push_str(&format!("[{}]", i));
+                array.push(to_toml_value(item, path)?);
+                path.truncate(len);
+            }
+            toml::Value::Array(array)
+        }
+        Value::Object(map) => {
+            let mut table = toml::Table::new();
+            for (key, value) in map {
+                let len = path.len();
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(&key);
+                table.insert(key, to_toml_value(value, path)?);
+                path.truncate(len);
+            }
+            toml::Value::Table(table)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cargo_toml() {
+        let source = r#"
+[package]
+name = "rnix"
+edition = "2021"
+
+[dependencies]
+rowan = "0.15.0"
+toml = { version = "0.8.0", optional = true }
+
+[[bench]]
+name = "all-packages"
+harness = false
+
+[release]
+date = 2024-01-01
+ratio = nan
+"#;
+        let options = PrintOptions { collapse_attrpaths: true, ..PrintOptions::default() };
+        assert_eq!(
+            from_toml(source, &options).unwrap().to_string(),
+            r#"{
+  bench = [
+    {
+      harness = false;
+      name = "all-packages";
+    }
+  ];
+  dependencies = {
+    rowan = "0.15.0";
+    toml = {
+      optional = true;
+      version = "0.8.0";
+    };
+  };
+  package = {
+    edition = "2021";
+    name = "rnix";
+  };
+  release = {
+    date = "2024-01-01";
+    ratio = "nan";
+  };
+}
+"#
+        );
+        assert!(from_toml("a = ", &options).is_err());
+        assert_eq!(
+            from_toml("a = [-0.0]", &options).unwrap().to_string(),
+            "{\n  a = [\n    (-0.0)\n  ];\n}\n"
+        );
+    }
+
+    #[test]
+    fn export() {
+        let convert = |src: &str| to_toml(&Root::parse(src).ok().unwrap().expr().unwrap());
+        assert_eq!(
+            convert(
+                r#"{ package = { name = "rnix"; edition = 2021; }; bench = [ { harness = false; } ]; }"#
+            ),
+            Ok("[[bench]]\nharness = false\n\n[package]\nedition = 2021\nname = \"rnix\"\n".into())
+        );
+        assert_eq!(convert("{ a.b = [ 1 null ]; }"), Err(ToTomlError::Null("a.b[1]".into())));
+        assert_eq!(
+            convert("[ 1 ]"),
+            Err(ToTomlError::NotAttrSet(TextRange::new(0.into(), 5.into())))
+        );
+        assert!(matches!(
+            convert("{ f = x: x; }"),
+            Err(ToTomlError::NotConstant(NotConstant::Function(_)))
+        ));
+    }
+}
@@ -0,0 +1,372 @@
+use std::{collections::HashMap, fmt};
+
+use serde_json::{Map, Number, Value};
+use yaml_rust2::{
+    parser::{MarkedEventReceiver, Parser, Tag},
+    scanner::{Marker, TScalarStyle},
+    yaml::Hash,
+    Event, ScanError, Yaml, YamlEmitter,
+};
+
+use crate::{ast, Root};
+
+use super::{from_json, to_json_value, NotConstant, PrintOptions};
+
+/// What to do with aliases (`*name`) referring to an anchored node
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AliasPolicy {
+    /// Copy the anchored value to every place it is referred to
+    Expand,
+    /// Fail with [`YamlError::Alias`]
+    Reject,
+}
+
+/// What to do with tagged values (`!tag value`). The standard tags like
+/// `!!str` are always understood.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TagPolicy {
+    /// Convert the value as if it had no tag
+    Ignore,
+    /// Fail with [`YamlError::Tag`]
+    Reject,
+}
+
+/// How YAML documents are converted
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct YamlOptions {
+    pub aliases: AliasPolicy,
+    pub tags: TagPolicy,
+    pub print: PrintOptions,
+}
+
+impl Default for YamlOptions {
+    fn default() -> Self {
+        Self {
+            aliases: AliasPolicy::Expand,
+            tags: TagPolicy::Reject,
+            print: PrintOptions::default(),
+        }
+    }
+}
+
+/// The reason a YAML document can't be converted
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum YamlError {
+    /// The document isn't valid YAML
+    Syntax(ScanError),
+    /// An alias, with [`AliasPolicy::Reject`]
+    Alias(Marker),
+    /// A tag other than the standard ones, with [`TagPolicy::Reject`]
+    Tag(String, Marker),
+    /// A sequence or mapping used as a key, which can't be an attribute name
+    ComplexKey(Marker),
+    /// A second document in the same stream
+    MultipleDocuments(Marker),
+}
+
+impl fmt::Display for YamlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (mark, what) = match self {
+            YamlError::Syntax(error) => return error.fmt(f),
+            YamlError::Alias(mark) => (mark, "aliases aren't allowed".to_string()),
+            YamlError::Tag(tag, mark) => (mark, format!("unsupported tag `{}`", tag)),
+            YamlError::ComplexKey(mark) => (mark, "key isn't a scalar".to_string()),
+            YamlError::MultipleDocuments(mark) => (mark, "expected a single document".to_string()),
+        };
+        write!(f, "{} at line {} column {}", what, mark.line(), mark.col() + 1)
+    }
+}
+
+impl std::error::Error for YamlError {}
+
+/// Convert a YAML document to a Nix expression. Plain scalars are resolved
+/// like YAML 1.2 does, so `yes` stays a string; infinite and NaN floats are
+/// converted to strings as written. Keys are always converted to strings, as
+/// written, and merge keys (`<<`) are kept like any other key.
+pub fn from_yaml(source: &str, options: &YamlOptions) -> Result<Root, YamlError> {
+    let mut builder = Builder {
+        options,
+        stack: Vec::new(),
+        anchors: HashMap::new(),
+        document: None,
+        error: None,
+    };
+    Parser::new_from_str(source).load(&mut builder, true).map_err(YamlError::Syntax)?;
+    if let Some(error) = builder.error {
+        return Err(error);
+    }
+    Ok(from_json(&builder.document.unwrap_or(Value::Null), &options.print))
+}
+
+/// A sequence or mapping whose end hasn't been reached yet, with its anchor id
+enum Frame {
+    Sequence(usize, Vec<Value>),
+    /// Also holds the key whose value is expected next
+    Mapping(usize, Map<String, Value>, Option<String>),
+}
+
+struct Builder<'a> {
+    options: &'a YamlOptions,
+    stack: Vec<Frame>,
+    anchors: HashMap<usize, Value>,
+    document: Option<Value>,
+    error: Option<YamlError>,
+}
+
+impl MarkedEventReceiver for Builder<'_> {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        if self.error.is_none() {
+            if let Err(error) = self.event(event, mark) {
+                self.error = Some(error);
+            }
+        }
+    }
+}
+
+impl Builder<'_> {
+    fn event(&mut self, event: Event, mark: Marker) -> Result<(), YamlError> {
+        match event {
+            Event::DocumentStart if self.document.is_some() => {
+                return Err(YamlError::MultipleDocuments(mark))
+            }
+            Event::Scalar(text, style, anchor, tag) => {
+                let string = self.check_tag(tag.as_ref(), mark)?;
+                if let Some(Frame::Mapping(_, _, key @ None)) = self.stack.last_mut() {
+                    if anchor > 0 {
+                        self.anchors.insert(anchor, Value::String(text.clone()));
+                    }
+                    *key = Some(text);
+                    return Ok(());
+                }
+                let value = if string || style != TScalarStyle::Plain {
+                    Value::String(text)
+                } else {
+                    resolve(text)
+                };
+                self.push(value, anchor);
+            }
+            Event::SequenceStart(anchor, tag) => {
+                self.check_key(mark)?;
+                self.check_tag(tag.as_ref(), mark)?;
+                self.stack.push(Frame::Sequence(anchor, Vec::new()));
+            }
+            Event::MappingStart(anchor, tag) => {
+                self.check_key(mark)?;
+                self.check_tag(tag.as_ref(), mark)?;
+                self.stack.push(Frame::Mapping(anchor, Map::new(), None));
+            }
+            Event::SequenceEnd | Event::MappingEnd => match self.stack.pop() {
+                Some(Frame::Sequence(anchor, items)) => self.push(Value::Array(items), anchor),
+                Some(Frame::Mapping(anchor, map, _)) => self.push(Value::Object(map), anchor),
+                None => (),
+            },
+            Event::Alias(anchor) => {
+                if self.options.aliases == AliasPolicy::Reject {
+                    return Err(YamlError::Alias(mark));
+                }
+                let value = self.anchors.get(&anchor).cloned().ok_or_else(|| {
+                    YamlError::Syntax(ScanError::new(mark, "alias refers to an unknown anchor"))
+                })?;
+                match (self.stack.last_mut(), value) {
+                    (Some(Frame::Mapping(_, _, key @ None)), Value::String(string)) => {
+                        *key = Some(string)
+                    }
+                    (Some(Frame::Mapping(_, _, key @ None)), Value::Number(number)) => {
+                        *key = Some(number.to_string())
+                    }
+                    (Some(Frame::Mapping(_, _, None)), _) => {
+                        return Err(YamlError::ComplexKey(mark))
+                    }
+                    (_, value) => self.push(value, 0),
+                }
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Fail if a sequence or mapping starts where a key is expected
+    fn check_key(&self, mark: Marker) -> Result<(), YamlError> {
+        match self.stack.last() {
+            Some(Frame::Mapping(_, _, None)) => Err(YamlError::ComplexKey(mark)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check a tag against the policy, returning whether it is `!!str`
+    fn check_tag(&self, tag: Option<&Tag>, mark: Marker) -> Result<bool, YamlError> {
+        match tag {
+            None => Ok(false),
+            Some(tag) if tag.handle == "tag:yaml.org,2002:" => Ok(tag.suffix == "str"),
+            Some(_) if self.options.tags == TagPolicy::Ignore => Ok(false),
+            Some(tag) => Err(YamlError::Tag(format!("{}{}", tag.handle, tag.suffix), mark)),
+        }
+    }
+
+    /// Add a finished value to the enclosing sequence or mapping, or make it
+    /// the document
+    fn push(&mut self, value: Value, anchor: usize) {
+        if anchor > 0 {
+            self.anchors.insert(anchor, value.clone());
+        }
+        match self.stack.last_mut() {
+            Some(Frame::Sequence(_, items)) => items.push(value),
+            Some(Frame::Mapping(_, map, key)) => {
+                map.insert(key.take().unwrap_or_default(), value);
+            }
+            None => self.document = Some(value),
+        }
+    }
+}
+
+/// Convert a constant expression to a YAML document, see [`to_json_value`] for
+/// which expressions are constant. Strings with multiple lines are written as
+/// literal blocks.
+pub fn to_yaml(expr: &ast::Expr) -> Result<String, NotConstant> {
+    let mut out = String::new();
+    let mut emitter = YamlEmitter::new(&mut out);
+    emitter.multiline_strings(true);
+    emitter.dump(&to_yaml_value(to_json_value(expr)?)).expect("writing to a string can't fail");
+    // Leave out the document start marker
+    let mut out =
+        out.strip_prefix("---").unwrap_or(&out).trim_start_matches([' ', '\n']).to_string();
+    out.push('\n');
+    Ok(out)
+}
+
+fn to_yaml_value(value: Value) -> Yaml {
+    match value {
+        Value::Null => Yaml::Null,
+        Value::Bool(boolean) => Yaml::Boolean(boolean),
+        Value::Number(number) => match number.as_i64() {
+            Some(integer) => Yaml::Integer(integer),
+            None => Yaml::Real(format!("{:?}", number.as_f64().unwrap_or_default())),
+        },
+        Value::String(string) => Yaml::String(string),
+        Value::Array(items) => Yaml::Array(items.into_iter().map(to_yaml_value).collect()),
+        Value::Object(map) => Yaml::Hash(
+            map.into_iter()
+                .map(|(key, value)| (Yaml::String(key), to_yaml_value(value)))
+                .collect::<Hash>(),
+        ),
+    }
+}
+
+/// Resolve the type of a plain scalar
+fn resolve(text: String) -> Value {
+    match Yaml::from_str(&text) {
+        Yaml::Null => Value::Null,
+        Yaml::Boolean(boolean) => Value::Bool(boolean),
+        Yaml::Integer(integer) => Value::Number(integer.into()),
+        Yaml::Real(real) => match real.parse().ok().and_then(Number::from_f64) {
+            Some(number) => Value::Number(number),
+            None => Value::String(text),
+        },
+        _ => Value::String(text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn workflow() {
+        let source = r#"
+defaults: &defaults
+  runs-on: ubuntu-latest
+  timeout: 10
+jobs:
+  build:
+    <<: *defaults
+    steps:
+      - run: |
+          cargo build
+          cargo test
+      - version: "1.0"
+        enabled: yes
+        ratio: .nan
+        count: 0x10
+        name: !!str 12
+        when: ~
+"#;
+        assert_eq!(
+            from_yaml(source, &YamlOptions::default()).unwrap().to_string(),
+            r#"{
+  defaults = {
+    runs-on = "ubuntu-latest";
+    timeout = 10;
+  };
+  jobs = {
+    build = {
+      "<<" = {
+        runs-on = "ubuntu-latest";
+        timeout = 10;
+      };
+      steps = [
+        {
+          run = ''
+            cargo build
+            cargo test
+          '';
+        }
+        {
+          count = 16;
+          enabled = "yes";
+          name = "12";
+          ratio = ".nan";
+          version = "1.0";
+          when = null;
+        }
+      ];
+    };
+  };
+}
+"#
+        );
+    }
+
+    #[test]
+    fn policies() {
+        let reject = YamlOptions { aliases: AliasPolicy::Reject, ..YamlOptions::default() };
+        assert!(matches!(from_yaml("a: &x 1\nb: *x\n", &reject), Err(YamlError::Alias(_))));
+
+        let source = "a: !secret token\n";
+        assert!(matches!(
+            from_yaml(source, &YamlOptions::default()),
+            Err(YamlError::Tag(tag, _)) if tag == "!secret"
+        ));
+        let ignore = YamlOptions { tags: TagPolicy::Ignore, ..YamlOptions::default() };
+        assert_eq!(from_yaml(source, &ignore).unwrap().to_string(), "{\n  a = \"token\";\n}\n");
+
+        let options = YamlOptions::default();
+        assert!(matches!(from_yaml("? [a]\n: 1\n", &options), Err(YamlError::ComplexKey(_))));
+        assert!(matches!(
+            from_yaml("a: 1\n---\nb: 2\n", &options),
+            Err(YamlError::MultipleDocuments(_))
+        ));
+        assert!(matches!(from_yaml("a: [", &options), Err(YamlError::Syntax(_))));
+        assert_eq!(from_yaml("", &options).unwrap().to_string(), "null\n");
+    }
+
+    #[test]
+    fn export() {
+        let src =
+            r#"{ name = "rnix"; version = "1.0"; steps = [ { run = "a\nb\n"; } 0.5 null ]; }"#;
+        let yaml = to_yaml(&Root::parse(src).ok().unwrap().expr().unwrap()).unwrap();
+        assert_eq!(
+            yaml,
+            "name: rnix\nsteps:\n  - run: |\n      a\n      b\n  - 0.5\n  - ~\nversion: \"1.0\"\n"
+        );
+        let root = from_yaml(&yaml, &YamlOptions::default()).unwrap();
+        assert_eq!(
+            to_json_value(&root.expr().unwrap()),
+            to_json_value(&Root::parse(src).ok().unwrap().expr().unwrap())
+        );
+        assert!(matches!(
+            to_yaml(&Root::parse("[ x ]").ok().unwrap().expr().unwrap()),
+            Err(NotConstant::Dynamic(_))
+        ));
+    }
+}
@@ -1,21 +1,50 @@
 #[macro_use]
 mod macros;
+pub mod analysis;
 pub mod ast;
+#[cfg(feature = "cache")]
+pub mod cache;
+pub mod dump;
+pub mod format;
+pub mod grammar;
+#[cfg(feature = "hash")]
+pub mod hash;
+pub mod interop;
 mod kinds;
+mod line_index;
+pub mod lint;
 pub mod parser;
+#[cfg(feature = "python")]
+mod python;
+pub mod refactor;
+pub mod rewrite;
+pub mod search;
+#[cfg(feature = "proptest")]
+pub mod strategy;
+pub mod template;
+#[cfg(feature = "test-support")]
+pub mod testing;
 #[cfg(test)]
 mod tests;
 mod token_set;
 pub mod tokenizer;
+pub mod value;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod workspace;
 
 use std::marker::PhantomData;
 
-pub use self::{kinds::SyntaxKind, tokenizer::tokenize};
+pub use self::{
+    kinds::SyntaxKind,
+    line_index::{LineCol, LineIndex},
+    tokenizer::tokenize,
+};
 
 use ast::AstNode;
 use parser::ParseError;
 use rowan::GreenNode;
-pub use rowan::{NodeOrToken, TextRange, TextSize, TokenAtOffset, WalkEvent};
+pub use rowan::{NodeCache, NodeOrToken, TextRange, TextSize, TokenAtOffset, WalkEvent};
 pub(crate) use token_set::TokenSet;
 
 use self::tokenizer::Tokenizer;
@@ -48,6 +77,20 @@ impl Root {
         let (green, errors) = parser::parse(Tokenizer::new(s));
         Parse { green, errors, _ty: PhantomData }
     }
+
+    /// Parse like [`Root::parse`], sharing identical tokens and small nodes
+    /// with every other tree parsed with the same cache, see
+    /// [`parser::parse_with_cache`]
+    pub fn parse_with_cache(s: &str, cache: &mut NodeCache) -> Parse<Root> {
+        let (green, errors) = parser::parse_with_cache(Tokenizer::new(s), cache);
+        Parse { green, errors, _ty: PhantomData }
+    }
+
+    /// Parse like [`Root::parse`], accepting the syntax enabled in `options`
+    pub fn parse_with_options(s: &str, options: parser::ParserOptions) -> Parse<Root> {
+        let (green, errors) = parser::parse_with_options(Tokenizer::new(s), options);
+        Parse { green, errors, _ty: PhantomData }
+    }
 }
 
 /// The result of a parse
@@ -71,7 +114,20 @@ impl<T: AstNode> Parse<T> {
 
     /// Return all errors in the tree, if any
     pub fn errors(&self) -> &[ParseError] {
-        &*self.errors
+        &self.errors
+    }
+
+    /// Print the errors and the tree for reading them, like in a bug report,
+    /// see [`dump::tree`]
+    pub fn debug_tree(&self) -> String {
+        let errors: String =
+            self.errors.iter().map(|error| format!("error: {}\n", error)).collect();
+        errors + &dump::tree(&self.syntax())
+    }
+
+    /// Map the offsets in the tree and the errors to lines and columns
+    pub fn line_index(&self) -> LineIndex {
+        LineIndex::new(&self.syntax().text().to_string())
     }
 
     /// Either return the first error in the tree, or if there are none return self
@@ -0,0 +1,157 @@
+use rowan::{TextRange, TextSize};
+
+/// A zero-based line and column, counting columns in characters
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LineCol {
+    pub line: u32,
+    pub col: u32,
+}
+
+/// Maps offsets to line and column numbers and back.
+///
+/// ```
+/// use rnix::{LineCol, LineIndex, TextSize};
+///
+/// let index = LineIndex::new("{\n  a = \"é\";\n}");
+/// assert_eq!(index.line_col(TextSize::from(11)), LineCol { line: 1, col: 8 });
+/// assert_eq!(index.offset(LineCol { line: 1, col: 8 }), Some(TextSize::from(11)));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LineIndex {
+    text: String,
+    line_starts: Vec<TextSize>,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let line_starts = std::iter::once(TextSize::from(0))
+            .chain(text.match_indices('\n').map(|(i, _)| TextSize::from(i as u32 + 1)))
+            .collect();
+        Self { text: text.to_string(), line_starts }
+    }
+
+    /// The zero-based line an offset is on
+    pub fn line(&self, offset: TextSize) -> u32 {
+        (self.line_starts.partition_point(|&start| start <= offset) - 1) as u32
+    }
+
+    /// The line and column of an offset
+    pub fn line_col(&self, offset: TextSize) -> LineCol {
+        let line = self.line(offset);
+        let start = self.line_starts[line as usize];
+        let col = self.text[TextRange::new(start, offset)].chars().count();
+        LineCol { line, col: col as u32 }
+    }
+
+    /// The offset of a line and column, or `None` if the line doesn't exist
+    /// or is shorter. The end of a line, before its line break, is the column
+    /// after its last character.
+    pub fn offset(&self, line_col: LineCol) -> Option<TextSize> {
+        let (start, line) = self.line_text(line_col.line)?;
+        let (offset, _) = line
+            .char_indices()
+            .chain(std::iter::once((line.len(), '\n')))
+            .nth(line_col.col as usize)?;
+        Some(start + TextSize::from(offset as u32))
+    }
+
+    /// The lines and columns of the start and end of a range
+    pub fn line_col_range(&self, range: TextRange) -> (LineCol, LineCol) {
+        (self.line_col(range.start()), self.line_col(range.end()))
+    }
+
+    /// The range between two lines and columns, see [`LineIndex::offset`]
+    pub fn text_range(&self, start: LineCol, end: LineCol) -> Option<TextRange> {
+        let (start, end) = (self.offset(start)?, self.offset(end)?);
+        (start <= end).then(|| TextRange::new(start, end))
+    }
+
+    /// The zero-based line and UTF-16 column of an offset, as used by LSP
+    pub fn line_col_utf16(&self, offset: TextSize) -> (u32, u32) {
+        let line = self.line(offset);
+        let start = self.line_starts[line as usize];
+        let col = self.text[TextRange::new(start, offset)].encode_utf16().count();
+        (line, col as u32)
+    }
+
+    /// The offset of a zero-based line and UTF-16 column, as used by LSP, or
+    /// `None` if the line doesn't exist, is shorter, or the column is within a
+    /// character
+    pub fn offset_utf16(&self, line: u32, col: u32) -> Option<TextSize> {
+        let (start, line) = self.line_text(line)?;
+        let mut units = 0;
+        for (offset, c) in line.char_indices().chain(std::iter::once((line.len(), '\n'))) {
+            if units >= col {
+                return (units == col).then(|| start + TextSize::from(offset as u32));
+            }
+            units += c.len_utf16() as u32;
+        }
+        None
+    }
+
+    /// The start and text of a line, without its line break
+    fn line_text(&self, line: u32) -> Option<(TextSize, &str)> {
+        let start = *self.line_starts.get(line as usize)?;
+        let end = match self.line_starts.get(line as usize + 1) {
+            Some(&next) => next - TextSize::from(1),
+            None => TextSize::of(self.text.as_str()),
+        };
+        Some((start, &self.text[TextRange::new(start, end)]))
+    }
+
+    /// Split a range at line breaks, without including them
+    pub(crate) fn lines(&self, range: TextRange) -> impl Iterator<Item = TextRange> + '_ {
+        let first = self.line(range.start()) as usize;
+        let last = self.line(range.end()) as usize;
+        (first..=last).filter_map(move |line| {
+            let start = self.line_starts[line].max(range.start());
+            let end = match self.line_starts.get(line + 1) {
+                Some(&next) => (next - TextSize::from(1)).min(range.end()),
+                None => range.end(),
+            };
+            let end = if self.text[TextRange::new(start, end)].ends_with('\r') {
+                end - TextSize::from(1)
+            } else {
+                end
+            };
+            (start < end).then(|| TextRange::new(start, end))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let text = "let\r\n  ä = \"😀\";\nin\n\nä\n";
+        let index = LineIndex::new(text);
+        for (offset, _) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+            let offset = TextSize::from(offset as u32);
+            assert_eq!(index.offset(index.line_col(offset)), Some(offset));
+        }
+        assert_eq!(index.line_col(TextSize::from(12)), LineCol { line: 1, col: 6 });
+        assert_eq!(index.line_col_utf16(TextSize::from(12)), (1, 6));
+        assert_eq!(index.line_col_utf16(TextSize::from(17)), (1, 9));
+        assert_eq!(index.offset_utf16(1, 6), Some(TextSize::from(12)));
+        assert_eq!(index.offset_utf16(1, 9), Some(TextSize::from(17)));
+        // Within the surrogate pair of the emoji
+        assert_eq!(index.offset_utf16(1, 8), None);
+        assert_eq!(index.offset_utf16(1, 11), Some(TextSize::from(19)));
+        assert_eq!(index.offset_utf16(1, 12), None);
+        assert_eq!(index.offset_utf16(6, 0), None);
+        assert_eq!(index.line_col(TextSize::from(17)), LineCol { line: 1, col: 8 });
+        // The `\r` of the first line counts as a character
+        assert_eq!(index.offset(LineCol { line: 0, col: 4 }), Some(TextSize::from(4)));
+        assert_eq!(index.offset(LineCol { line: 0, col: 5 }), None);
+        assert_eq!(index.offset(LineCol { line: 4, col: 0 }), Some(TextSize::from(24)));
+        assert_eq!(index.offset(LineCol { line: 5, col: 0 }), Some(TextSize::from(27)));
+        assert_eq!(index.offset(LineCol { line: 6, col: 0 }), None);
+        let range = TextRange::new(TextSize::from(7), TextSize::from(22));
+        let (start, end) = index.line_col_range(range);
+        assert_eq!((start, end), (LineCol { line: 1, col: 2 }, LineCol { line: 2, col: 2 }));
+        assert_eq!(index.text_range(start, end), Some(range));
+        assert_eq!(index.text_range(end, start), None);
+    }
+}
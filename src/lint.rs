@@ -0,0 +1,446 @@
+//! A pluggable lint framework, checking the AST for common mistakes.
+//!
+//! Lints implement the [`Rule`] trait and are collected in a [`Registry`],
+//! which runs them with a [`LintConfig`]. Diagnostics can be suppressed with a
+//! comment like `# rnix-lint: disable rule-id`, which applies to the line it is
+//! on when it trails code, or to the next line otherwise. Leaving out the rule
+//! ids suppresses all diagnostics.
+
+mod deprecated;
+mod interpolation;
+mod paths;
+mod purity;
+mod rules;
+
+use std::{collections::HashMap, fmt};
+
+use rowan::TextRange;
+
+use crate::{
+    analysis::{GlobalScope, Resolver},
+    line_index::LineIndex,
+    rewrite::TextEdit,
+    NodeOrToken,
+    SyntaxKind::*,
+    SyntaxNode, SyntaxToken,
+};
+
+pub use deprecated::{LegacyLet, UriLiteral};
+pub use interpolation::{ConstantDynamicAttr, LiteralInterpolation, RedundantInterpolation};
+pub use paths::{PathConcatenation, PathInterpolation};
+pub use purity::{ImportFromDerivation, ImpureBuiltin, UnpinnedFetch};
+pub use rules::{
+    DeadBinding, DuplicateAttr, DuplicateFormal, UndefinedVariable, UnexpectedCallPackageArg,
+    UnusedLetBinding,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Hint,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Hint => "hint",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// A suggested change resolving a diagnostic
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fix {
+    pub message: String,
+    pub edits: Vec<TextEdit>,
+}
+
+/// A problem found by a rule
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// The id of the rule which reported this
+    pub rule: &'static str,
+    pub severity: Severity,
+    pub range: TextRange,
+    pub message: String,
+    pub fix: Option<Fix>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at {}..{}: {} [{}]",
+            self.severity,
+            usize::from(self.range.start()),
+            usize::from(self.range.end()),
+            self.message,
+            self.rule
+        )
+    }
+}
+
+/// The state passed to [`Rule::check`]
+pub struct Context<'a> {
+    root: &'a SyntaxNode,
+    resolver: &'a Resolver,
+    rule: &'static str,
+    severity: Severity,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Context<'a> {
+    /// The node being checked, usually the root of a file
+    pub fn root(&self) -> &'a SyntaxNode {
+        self.root
+    }
+
+    /// The resolver for all identifiers in [`Context::root`]
+    pub fn resolver(&self) -> &'a Resolver {
+        self.resolver
+    }
+
+    pub fn report(&mut self, range: TextRange, message: impl Into<String>) {
+        self.push(range, message.into(), None);
+    }
+
+    pub fn report_with_fix(&mut self, range: TextRange, message: impl Into<String>, fix: Fix) {
+        self.push(range, message.into(), Some(fix));
+    }
+
+    fn push(&mut self, range: TextRange, message: String, fix: Option<Fix>) {
+        self.diagnostics.push(Diagnostic {
+            rule: self.rule,
+            severity: self.severity,
+            range,
+            message,
+            fix,
+        });
+    }
+}
+
+/// A single lint
+pub trait Rule {
+    /// A unique, kebab-case identifier, used in configuration and suppressions
+    fn id(&self) -> &'static str;
+
+    /// A short description of what the rule checks
+    fn description(&self) -> &'static str;
+
+    fn default_severity(&self) -> Severity;
+
+    /// Check the tree and report diagnostics with [`Context::report`]
+    fn check(&self, ctx: &mut Context);
+}
+
+/// Per-rule configuration. Rules not mentioned use their default severity.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LintConfig {
+    levels: HashMap<String, Option<Severity>>,
+    globals: GlobalScope,
+}
+
+impl LintConfig {
+    /// A configuration for enforcing modern Nix, which turns the rules for
+    /// deprecated syntax and duplicate attributes and arguments into errors
+    pub fn strict() -> Self {
+        let mut config = Self::default();
+        for rule in ["duplicate-attr", "duplicate-formal", "legacy-let", "uri-literal"] {
+            config.set_severity(rule, Severity::Error);
+        }
+        config
+    }
+
+    pub fn disable(&mut self, rule: &str) -> &mut Self {
+        self.levels.insert(rule.to_string(), None);
+        self
+    }
+
+    pub fn set_severity(&mut self, rule: &str, severity: Severity) -> &mut Self {
+        self.levels.insert(rule.to_string(), Some(severity));
+        self
+    }
+
+    /// Set the names defined in the global scope, which the rules use to tell
+    /// undefined variables and builtins apart
+    pub fn set_globals(&mut self, globals: GlobalScope) -> &mut Self {
+        self.globals = globals;
+        self
+    }
+
+    pub fn globals(&self) -> &GlobalScope {
+        &self.globals
+    }
+
+    /// The severity a rule runs with, or `None` if it is disabled
+    pub fn severity(&self, rule: &dyn Rule) -> Option<Severity> {
+        match self.levels.get(rule.id()) {
+            Some(level) => *level,
+            None => Some(rule.default_severity()),
+        }
+    }
+}
+
+/// A collection of rules
+#[derive(Default)]
+pub struct Registry {
+    rules: Vec<Box<dyn Rule>>,
+}
+
+impl Registry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a registry with all built-in rules
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        registry.register(ConstantDynamicAttr);
+        registry.register(DeadBinding);
+        registry.register(DuplicateAttr);
+        registry.register(DuplicateFormal);
+        registry.register(ImportFromDerivation);
+        registry.register(ImpureBuiltin);
+        registry.register(LegacyLet);
+        registry.register(LiteralInterpolation);
+        registry.register(PathConcatenation);
+        registry.register(PathInterpolation);
+        registry.register(RedundantInterpolation);
+        registry.register(UndefinedVariable);
+        registry.register(UnexpectedCallPackageArg);
+        registry.register(UnpinnedFetch);
+        registry.register(UnusedLetBinding);
+        registry.register(UriLiteral);
+        registry
+    }
+
+    /// Add a rule.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a rule with the same id is already registered.
+    pub fn register(&mut self, rule: impl Rule + 'static) -> &mut Self {
+        assert!(self.get(rule.id()).is_none(), "rule {} is registered twice", rule.id());
+        self.rules.push(Box::new(rule));
+        self
+    }
+
+    pub fn get(&self, id: &str) -> Option<&dyn Rule> {
+        self.rules.iter().find(|rule| rule.id() == id).map(|rule| &**rule)
+    }
+
+    pub fn rules(&self) -> impl Iterator<Item = &dyn Rule> {
+        self.rules.iter().map(|rule| &**rule)
+    }
+
+    /// Run all enabled rules on a tree, returning the diagnostics which aren't
+    /// suppressed, sorted by position.
+    pub fn check(&self, root: &SyntaxNode, config: &LintConfig) -> Vec<Diagnostic> {
+        let resolver = Resolver::with_globals(root, &config.globals);
+        let suppressions = Suppressions::new(root);
+
+        let mut diagnostics = Vec::new();
+        for rule in self.rules() {
+            let severity = match config.severity(rule) {
+                Some(severity) => severity,
+                None => continue,
+            };
+            let mut ctx = Context {
+                root,
+                resolver: &resolver,
+                rule: rule.id(),
+                severity,
+                diagnostics: Vec::new(),
+            };
+            rule.check(&mut ctx);
+            diagnostics.extend(ctx.diagnostics.into_iter().filter(|d| !suppressions.matches(d)));
+        }
+        diagnostics.sort_by_key(|d| (d.range.start(), d.range.end()));
+        diagnostics
+    }
+}
+
+/// Lines on which diagnostics are suppressed with `rnix-lint: disable`
+struct Suppressions {
+    index: LineIndex,
+    /// The line and the disabled rules, an empty list means all rules
+    lines: Vec<(usize, Vec<String>)>,
+}
+
+impl Suppressions {
+    fn new(root: &SyntaxNode) -> Self {
+        // Offsets are absolute, so lines are counted in the whole file even
+        // when checking a node inside it
+        let file = root.ancestors().last().unwrap_or_else(|| root.clone());
+        let index = LineIndex::new(&file.text().to_string());
+        let mut suppressions = Self { index, lines: Vec::new() };
+
+        for token in root.descendants_with_tokens().filter_map(NodeOrToken::into_token) {
+            if token.kind() != TOKEN_COMMENT {
+                continue;
+            }
+            let rules = match parse_suppression(token.text()) {
+                Some(rules) => rules,
+                None => continue,
+            };
+            let line = suppressions.index.line(token.text_range().start()) as usize;
+            let line = if trails_code(&token) { line } else { line + 1 };
+            suppressions.lines.push((line, rules));
+        }
+        suppressions
+    }
+
+    fn matches(&self, diagnostic: &Diagnostic) -> bool {
+        let line = self.index.line(diagnostic.range.start()) as usize;
+        self.lines.iter().any(|(l, rules)| {
+            *l == line && (rules.is_empty() || rules.iter().any(|rule| rule == diagnostic.rule))
+        })
+    }
+}
+
+fn parse_suppression(comment: &str) -> Option<Vec<String>> {
+    let text = match comment.strip_prefix('#') {
+        Some(text) => text,
+        None => comment.strip_prefix("/*")?.strip_suffix("*/")?,
+    };
+    let rest = text.trim().strip_prefix("rnix-lint:")?.trim_start().strip_prefix("disable")?;
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some(
+        rest.split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|rule| !rule.is_empty())
+            .map(String::from)
+            .collect(),
+    )
+}
+
+/// Whether a token is preceded by anything but whitespace on its line
+fn trails_code(token: &SyntaxToken) -> bool {
+    let mut prev = token.prev_token();
+    while let Some(token) = prev {
+        match token.kind() {
+            TOKEN_WHITESPACE if token.text().contains('\n') => return false,
+            TOKEN_WHITESPACE => prev = token.prev_token(),
+            _ => return true,
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Root;
+
+    struct NoFoo;
+
+    impl Rule for NoFoo {
+        fn id(&self) -> &'static str {
+            "no-foo"
+        }
+        fn description(&self) -> &'static str {
+            "forbids identifiers named foo"
+        }
+        fn default_severity(&self) -> Severity {
+            Severity::Warning
+        }
+        fn check(&self, ctx: &mut Context) {
+            for (ident, _) in ctx.resolver().resolutions() {
+                if ident.to_string() == "foo" {
+                    ctx.report(rowan::ast::AstNode::syntax(&ident).text_range(), "foo!");
+                }
+            }
+        }
+    }
+
+    fn check(src: &str, config: &LintConfig) -> Vec<(&'static str, Severity, String)> {
+        let root = Root::parse(src).ok().unwrap();
+        let mut registry = Registry::new();
+        registry.register(NoFoo);
+        registry
+            .check(rowan::ast::AstNode::syntax(&root), config)
+            .into_iter()
+            .map(|d| (d.rule, d.severity, src[d.range].to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn config() {
+        let src = "{ foo }: foo";
+        assert_eq!(
+            check(src, &LintConfig::default()),
+            [("no-foo", Severity::Warning, "foo".into())]
+        );
+        let mut config = LintConfig::default();
+        config.set_severity("no-foo", Severity::Error);
+        assert_eq!(check(src, &config), [("no-foo", Severity::Error, "foo".into())]);
+        config.disable("no-foo");
+        assert_eq!(check(src, &config), []);
+    }
+
+    #[test]
+    fn strict() {
+        let registry = Registry::builtin();
+        let root = Root::parse("let { a = http://x; body = a; }").ok().unwrap();
+        let severities = |config: &LintConfig| -> Vec<(&'static str, Severity)> {
+            registry
+                .check(rowan::ast::AstNode::syntax(&root), config)
+                .into_iter()
+                .map(|d| (d.rule, d.severity))
+                .collect()
+        };
+        assert_eq!(
+            severities(&LintConfig::default()),
+            [("legacy-let", Severity::Warning), ("uri-literal", Severity::Warning)]
+        );
+        assert_eq!(
+            severities(&LintConfig::strict()),
+            [("legacy-let", Severity::Error), ("uri-literal", Severity::Error)]
+        );
+    }
+
+    #[test]
+    fn suppressions() {
+        let src =
+            "# a list of foos, each of them on a line of its own, some with a comment disabling
+# the rule no-foo on their line or the line after, or a comment disabling another rule
+{ foo }: [
+  # rnix-lint: disable no-foo
+  foo
+  foo # rnix-lint: disable
+  foo /* rnix-lint: disable other-rule */
+  foo
+]";
+        let config = LintConfig::default();
+        let root = Root::parse(src).ok().unwrap();
+        let mut registry = Registry::new();
+        registry.register(NoFoo);
+        let lines = |node: &SyntaxNode| -> Vec<usize> {
+            registry
+                .check(node, &config)
+                .iter()
+                .map(|d| src[..usize::from(d.range.start())].matches('\n').count())
+                .collect()
+        };
+        assert_eq!(lines(rowan::ast::AstNode::syntax(&root)), [6, 7]);
+        // A node which doesn't start the file
+        let Some(crate::ast::Expr::Lambda(lambda)) = root.expr() else { panic!("not a lambda") };
+        assert_eq!(lines(rowan::ast::AstNode::syntax(&lambda.body().unwrap())), [6, 7]);
+        assert_eq!(parse_suppression("# rnix-lint: disabled"), None);
+        assert_eq!(
+            parse_suppression("# rnix-lint: disable a,b c"),
+            Some(vec!["a".into(), "b".into(), "c".into()])
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn duplicate_rule() {
+        Registry::builtin().register(DuplicateAttr);
+    }
+}
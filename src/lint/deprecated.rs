@@ -0,0 +1,107 @@
+//! Rules for legacy syntax which Nix still accepts but discourages
+
+use rowan::ast::AstNode;
+
+use crate::{
+    ast,
+    rewrite::{convert_legacy_let, TextEdit},
+    NodeOrToken,
+    SyntaxKind::*,
+};
+
+use super::{Context, Fix, Rule, Severity};
+
+/// Reports `let { a = 1; body = a; }`, which is the same as `let a = 1; in a`
+pub struct LegacyLet;
+
+impl Rule for LegacyLet {
+    fn id(&self) -> &'static str {
+        "legacy-let"
+    }
+    fn description(&self) -> &'static str {
+        "`let { ... body = ...; }` is deprecated in favor of `let ... in`"
+    }
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+    fn check(&self, ctx: &mut Context) {
+        for node in ctx.root().descendants().filter_map(ast::LegacyLet::cast) {
+            let range = match node.let_token() {
+                Some(token) => token.text_range(),
+                None => continue,
+            };
+            let message = "legacy let expression";
+            match convert_legacy_let(&node) {
+                Ok(edits) => {
+                    let fix = Fix { message: "convert to `let ... in`".into(), edits };
+                    ctx.report_with_fix(range, message, fix)
+                }
+                Err(_) => ctx.report(range, message),
+            }
+        }
+    }
+}
+
+/// Reports unquoted URLs like `https://nixos.org`, which are disabled by the
+/// `no-url-literals` experimental feature of Nix
+pub struct UriLiteral;
+
+impl Rule for UriLiteral {
+    fn id(&self) -> &'static str {
+        "uri-literal"
+    }
+    fn description(&self) -> &'static str {
+        "URLs should be written as strings"
+    }
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+    fn check(&self, ctx: &mut Context) {
+        let tokens = ctx.root().descendants_with_tokens().filter_map(NodeOrToken::into_token);
+        for token in tokens.filter(|token| token.kind() == TOKEN_URI) {
+            // URIs can't contain `"`, `\` or `${`, so they don't need escaping
+            let fix = Fix {
+                message: "quote the URL".into(),
+                edits: vec![TextEdit::replace(token.text_range(), format!("\"{}\"", token.text()))],
+            };
+            ctx.report_with_fix(token.text_range(), "unquoted URL", fix);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rowan::ast::AstNode;
+
+    use crate::{
+        lint::{LintConfig, Registry},
+        rewrite::apply_edits,
+        Root,
+    };
+
+    fn fix(src: &str) -> Option<String> {
+        let root = Root::parse(src).ok().unwrap();
+        let diagnostics = Registry::builtin().check(root.syntax(), &LintConfig::default());
+        let diagnostic = diagnostics.iter().find(|d| d.rule != "unused-let-binding")?;
+        Some(apply_edits(src, &diagnostic.fix.as_ref()?.edits))
+    }
+
+    #[test]
+    fn legacy_let() {
+        assert_eq!(fix("let { a = 1; body = a; }").as_deref(), Some("let a = 1; in a"));
+        assert_eq!(
+            fix("let {\n  a = 1;\n  body = a;\n  b = 2;\n}").as_deref(),
+            Some("let\n  a = 1;\n  b = 2;\nin\na")
+        );
+        // `body` can't be removed when it is used
+        assert_eq!(fix("let { body = { a = body; }; }"), None);
+    }
+
+    #[test]
+    fn uri_literal() {
+        assert_eq!(
+            fix("{ url = https://example.org/a?b=c; }").as_deref(),
+            Some(r#"{ url = "https://example.org/a?b=c"; }"#)
+        );
+    }
+}
@@ -0,0 +1,351 @@
+//! Rules for interpolations which can be written more simply
+
+use rowan::{ast::AstNode, TextRange};
+
+use crate::{
+    analysis::{const_attr_name, static_str, strip_parens, BindingKind},
+    ast::{self, InterpolPart},
+    rewrite::{attr_key, TextEdit},
+    value::{escape_indented, escape_string},
+    NodeOrToken,
+    SyntaxKind::*,
+    SyntaxToken,
+};
+
+use super::{Context, Fix, Rule, Severity};
+
+/// Reports strings consisting of a single interpolation like `"${x}"`. They
+/// are the same as `x` if it is a string, so a fix is only offered if it is
+/// known to be one. Otherwise the interpolation may be deliberate: it copies
+/// paths to the store and takes the `outPath` of derivations, which
+/// `toString` doesn't do for paths, so no `toString` is suggested.
+pub struct RedundantInterpolation;
+
+impl Rule for RedundantInterpolation {
+    fn id(&self) -> &'static str {
+        "redundant-interpolation"
+    }
+    fn description(&self) -> &'static str {
+        "a string containing only an interpolation is the same as the interpolated string"
+    }
+    fn default_severity(&self) -> Severity {
+        Severity::Hint
+    }
+    fn check(&self, ctx: &mut Context) {
+        for s in ctx.root().descendants().filter_map(ast::Str::cast) {
+            let mut parts = s.normalized_parts().into_iter().filter(
+                |part| !matches!(part, InterpolPart::Literal(literal) if literal.is_empty()),
+            );
+            let (Some(InterpolPart::Interpolation(interpol)), None) = (parts.next(), parts.next())
+            else {
+                continue;
+            };
+            let Some(expr) = interpol.expr() else { continue };
+            // `"${"a"}"` and `"${./a}"` are reported by `literal-interpolation`
+            // and `path-interpolation`
+            let is_literal = |expr| match expr {
+                ast::Expr::Str(s) => static_str(&s).is_some(),
+                ast::Expr::Path(_) => true,
+                _ => false,
+            };
+            if is_literal(strip_parens(expr.clone())) {
+                continue;
+            }
+            let range = s.syntax().text_range();
+            if !is_string(ctx, &expr) {
+                ctx.report(
+                    range,
+                    "string with a single interpolation, which coerces its value to a string",
+                );
+                continue;
+            }
+            let text = match strip_parens(expr) {
+                expr @ (ast::Expr::Ident(_) | ast::Expr::Str(_)) => expr.syntax().to_string(),
+                expr if s.syntax().parent().is_some_and(|parent| {
+                    matches!(parent.kind(), NODE_ROOT | NODE_PAREN | NODE_ATTRPATH_VALUE)
+                }) =>
+                {
+                    expr.syntax().to_string()
+                }
+                expr => format!("({})", expr.syntax()),
+            };
+            let fix = Fix {
+                message: "remove the interpolation".into(),
+                edits: vec![TextEdit::replace(range, text)],
+            };
+            ctx.report_with_fix(range, "interpolation of a value which is already a string", fix);
+        }
+    }
+}
+
+/// Whether an expression is known to evaluate to a string: a string literal,
+/// a call of `toString`, or a name bound to one of those
+fn is_string(ctx: &Context, expr: &ast::Expr) -> bool {
+    match strip_parens(expr.clone()) {
+        ast::Expr::Str(_) => true,
+        ast::Expr::Apply(apply) => apply.lambda().is_some_and(|lambda| {
+            let name = match strip_parens(lambda) {
+                ast::Expr::Ident(ident) if ctx.resolver().definition(&ident).is_none() => {
+                    ident.to_string()
+                }
+                ast::Expr::Select(select) => select.syntax().to_string(),
+                _ => return false,
+            };
+            matches!(name.as_str(), "toString" | "builtins.toString" | "__toString")
+        }),
+        ast::Expr::Ident(ident) => {
+            let Some(binding) = ctx.resolver().definition(&ident) else { return false };
+            if binding.inherited
+                || !matches!(
+                    binding.kind,
+                    BindingKind::LetIn | BindingKind::LegacyLet | BindingKind::RecAttrSet
+                )
+            {
+                return false;
+            }
+            let entry = binding.node.parent().and_then(ast::Attrpath::cast).and_then(|attrpath| {
+                (attrpath.attrs().count() == 1).then_some(attrpath.syntax().parent()?)
+            });
+            let value = entry.and_then(ast::AttrpathValue::cast).and_then(|entry| entry.value());
+            // Don't follow bindings to themselves, like `a = "${a}";`
+            value.is_some_and(|value| {
+                !value.syntax().text_range().contains_range(ident.syntax().text_range())
+                    && matches!(strip_parens(value), ast::Expr::Str(_))
+            })
+        }
+        _ => false,
+    }
+}
+
+/// Reports interpolations of string literals like `"a${"b"}"`, which can be
+/// folded into the surrounding string
+pub struct LiteralInterpolation;
+
+impl Rule for LiteralInterpolation {
+    fn id(&self) -> &'static str {
+        "literal-interpolation"
+    }
+    fn description(&self) -> &'static str {
+        "interpolated string literals can be part of the surrounding string"
+    }
+    fn default_severity(&self) -> Severity {
+        Severity::Hint
+    }
+    fn check(&self, ctx: &mut Context) {
+        for interpol in ctx.root().descendants().filter_map(ast::Interpol::cast) {
+            let Some(ast::Expr::Str(inner)) = interpol.expr().map(strip_parens) else { continue };
+            let Some(value) = static_str(&inner) else { continue };
+            let Some(outer) = interpol.syntax().parent().and_then(ast::Str::cast) else {
+                continue;
+            };
+            let range = interpol.syntax().text_range();
+            let message = "interpolation of a string literal";
+            match fold(&outer, range, &value) {
+                Some(text) => {
+                    let fix = Fix {
+                        message: "move the string into the surrounding one".into(),
+                        edits: vec![TextEdit::replace(range, text)],
+                    };
+                    ctx.report_with_fix(range, message, fix)
+                }
+                None => ctx.report(range, message),
+            }
+        }
+    }
+}
+
+/// The escaped text to put in the place of an interpolation, or `None` if it
+/// would change the meaning of the text around it
+fn fold(outer: &ast::Str, range: TextRange, value: &str) -> Option<String> {
+    let tokens = || outer.syntax().children_with_tokens().filter_map(NodeOrToken::into_token);
+    let before = tokens()
+        .filter(|token| token.kind() == TOKEN_STRING_CONTENT)
+        .find(|token| token.text_range().end() == range.start());
+    let after = tokens()
+        .filter(|token| token.kind() == TOKEN_STRING_CONTENT)
+        .find(|token| token.text_range().start() == range.end());
+    // Characters at the edges could form escapes or interpolations with the
+    // text around them, like `$` followed by `{`
+    let special = ['$', '{', '\''];
+    let joins = |left: Option<char>, right: Option<char>| {
+        left.is_some_and(|c| special.contains(&c)) && right.is_some_and(|c| special.contains(&c))
+    };
+    let last = |token: &Option<SyntaxToken>| token.as_ref()?.text().chars().next_back();
+    let first = |token: &Option<SyntaxToken>| token.as_ref()?.text().chars().next();
+    if joins(last(&before), value.chars().next()) || joins(value.chars().next_back(), first(&after))
+    {
+        return None;
+    }
+    let multiline = tokens().next().is_some_and(|token| token.text() == "''");
+    if !multiline {
+        return Some(escape_string(value));
+    }
+    // Text in `''` strings takes part in the stripping of indentation
+    let starts_line = match &before {
+        Some(token) => token.text().rsplit('\n').next().is_some_and(|line| line.trim().is_empty()),
+        None => tokens().next().is_some_and(|token| token.text_range().end() == range.start()),
+    };
+    if value.contains('\n') || (starts_line && !value.starts_with(|c: char| !c.is_whitespace())) {
+        return None;
+    }
+    Some(escape_indented(value))
+}
+
+/// Reports dynamic attributes whose name is a constant string, like
+/// `${"foo"}` or `${"pre" + "fix"}`, which can be written as a plain name.
+/// No fix is offered in `rec` sets that use the name, since a static name
+/// would capture those references.
+pub struct ConstantDynamicAttr;
+
+impl Rule for ConstantDynamicAttr {
+    fn id(&self) -> &'static str {
+        "constant-dynamic-attr"
+    }
+    fn description(&self) -> &'static str {
+        "dynamic attributes with a constant name can be written as the name"
+    }
+    fn default_severity(&self) -> Severity {
+        Severity::Hint
+    }
+    fn check(&self, ctx: &mut Context) {
+        for attr in ctx.root().descendants().filter_map(ast::Attr::cast) {
+            let ast::Attr::Dynamic(dynamic) = &attr else { continue };
+            let Some(name) = const_attr_name(&attr) else { continue };
+            let range = dynamic.syntax().text_range();
+            let key = attr_key(&name);
+            let message = format!("this is the attribute `{}`", key);
+            if captures_references(ctx, &attr, &name) {
+                ctx.report(range, message);
+                continue;
+            }
+            let fix = Fix {
+                message: format!("use `{}`", key),
+                edits: vec![TextEdit::replace(range, key.clone())],
+            };
+            ctx.report_with_fix(range, message, fix);
+        }
+    }
+}
+
+/// Whether writing a dynamic attribute as its name would bring it into the
+/// scope of references to the name. Dynamic attributes aren't in the scope of
+/// a `rec` set, so in `rec { ${"a"} = 1; b = a; }` the `a` refers to another
+/// variable.
+fn captures_references(ctx: &Context, attr: &ast::Attr, name: &str) -> bool {
+    let Some(attrpath) = attr.syntax().parent().and_then(ast::Attrpath::cast) else {
+        return false;
+    };
+    if attrpath.attrs().next().as_ref() != Some(attr) {
+        return false;
+    }
+    let set = attrpath.syntax().parent().and_then(|entry| entry.parent());
+    let Some(set) = set.and_then(ast::AttrSet::cast) else { return false };
+    set.rec_token().is_some()
+        && set.syntax().descendants().filter_map(ast::Ident::cast).any(|ident| {
+            ident.ident_token().is_some_and(|token| token.text() == name)
+                && ctx.resolver().resolve(&ident).is_some()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use rowan::ast::AstNode;
+
+    use crate::{
+        lint::{LintConfig, Registry},
+        rewrite::apply_edits,
+        Root,
+    };
+
+    fn check(rule: &str, src: &str) -> Vec<(String, Option<String>)> {
+        let root = Root::parse(src).ok().unwrap();
+        Registry::builtin()
+            .check(root.syntax(), &LintConfig::default())
+            .into_iter()
+            .filter(|d| d.rule == rule)
+            .map(|d| {
+                let fixed = d.fix.map(|fix| apply_edits(src, &fix.edits));
+                (src[d.range].to_string(), fixed)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn redundant_interpolation() {
+        let src =
+            r#"let name = "foo"; in { pname, src }: [ "${name}" "${pname}" "${toString src}" ]"#;
+        let fixed = |list: &str| format!("let name = \"foo\"; in {{ pname, src }}: [ {} ]", list);
+        assert_eq!(
+            check("redundant-interpolation", src),
+            [
+                ("\"${name}\"".into(), Some(fixed(r#"name "${pname}" "${toString src}""#))),
+                ("\"${pname}\"".into(), None),
+                (
+                    "\"${toString src}\"".into(),
+                    Some(fixed(r#""${name}" "${pname}" (toString src)"#))
+                ),
+            ]
+        );
+        assert_eq!(
+            check("redundant-interpolation", "{ a = ''${\"a${b}\"}''; }"),
+            [("''${\"a${b}\"}''".into(), Some("{ a = \"a${b}\"; }".into()))]
+        );
+        // Paths are copied to the store, which `toString` doesn't do
+        assert_eq!(check("redundant-interpolation", "\"${src}\""), [("\"${src}\"".into(), None)]);
+        assert_eq!(check("redundant-interpolation", "\"${./.}\""), []);
+    }
+
+    #[test]
+    fn literal_interpolation() {
+        assert_eq!(
+            check("literal-interpolation", r#""a${"\"b"}c""#),
+            [("${\"\\\"b\"}".into(), Some(r#""a\"bc""#.into()))]
+        );
+        assert_eq!(
+            check("literal-interpolation", "''a ${\"''\"} b''"),
+            [("${\"''\"}".into(), Some("''a ''' b''".into()))]
+        );
+        // `$` followed by `{` would start an interpolation
+        assert_eq!(check("literal-interpolation", r#""${"$"}{a}""#), [("${\"$\"}".into(), None)]);
+        // Leading whitespace would change the indentation
+        assert_eq!(
+            check("literal-interpolation", "''\n  ${\"  a\"}\n''"),
+            [("${\"  a\"}".into(), None)]
+        );
+    }
+
+    #[test]
+    fn constant_dynamic_attr() {
+        assert_eq!(
+            check("constant-dynamic-attr", r#"{ ${"foo"} = 1; a.${"b" + " c"} = 2; ${x} = 3; }"#),
+            [
+                (
+                    "${\"foo\"}".into(),
+                    Some(r#"{ foo = 1; a.${"b" + " c"} = 2; ${x} = 3; }"#.into())
+                ),
+                (
+                    "${\"b\" + \" c\"}".into(),
+                    Some(r#"{ ${"foo"} = 1; a."b c" = 2; ${x} = 3; }"#.into())
+                ),
+            ]
+        );
+        assert_eq!(
+            check("constant-dynamic-attr", "x.${\"y\"}"),
+            [("${\"y\"}".into(), Some("x.y".into()))]
+        );
+        // In a `rec` set the name would refer to the attribute
+        let src = r#"let a = 5; in rec { ${"a"} = 1; b = a; }"#;
+        assert_eq!(check("constant-dynamic-attr", src), [("${\"a\"}".into(), None)]);
+        assert_eq!(
+            check("constant-dynamic-attr", r#"rec { ${"a"}.c = 1; b.${"a"} = a; }"#),
+            [
+                ("${\"a\"}".into(), None),
+                ("${\"a\"}".into(), Some(r#"rec { ${"a"}.c = 1; b.a = a; }"#.into())),
+            ]
+        );
+        assert_eq!(
+            check("constant-dynamic-attr", r#"rec { ${"a"} = 1; b = c; }"#),
+            [("${\"a\"}".into(), Some("rec { a = 1; b = c; }".into()))]
+        );
+    }
+}
@@ -0,0 +1,238 @@
+//! Rules for idioms mixing paths and strings, whose differences in copying
+//! files to the store are a common source of mistakes
+
+use rowan::ast::AstNode;
+
+use crate::{
+    analysis::{eval_const, reference_path, strip_parens},
+    ast::{self, BinOpKind, InterpolPart},
+    rewrite::TextEdit,
+    tokenizer::tokenize,
+    value::Value,
+    SyntaxKind::*,
+};
+
+use super::{Context, Fix, Rule, Severity};
+
+/// The text of a path value as a literal, if it can be written as one
+fn path_literal(value: &Value) -> Option<String> {
+    let Value::Path(..) = value else { return None };
+    let text = value.to_string();
+    matches!(tokenize(&text)[..], [(TOKEN_PATH, token)] if token == text).then_some(text)
+}
+
+/// The value of a path literal without interpolations
+fn static_path(expr: &ast::Expr) -> Option<Value> {
+    match strip_parens(expr.clone()) {
+        expr @ ast::Expr::Path(_) => eval_const(&expr),
+        _ => None,
+    }
+}
+
+/// Whether an expression is passed where a path works like its store path,
+/// as the file of `import` or the function of `callPackage`
+fn accepts_path(expr: &ast::Expr) -> bool {
+    let mut node = expr.syntax().clone();
+    while let Some(parent) = node.parent().filter(|parent| parent.kind() == NODE_PAREN) {
+        node = parent;
+    }
+    let Some(apply) = node.parent().and_then(ast::Apply::cast) else { return false };
+    if apply.argument().map(|argument| argument.syntax().clone()) != Some(node) {
+        return false;
+    }
+    let name = apply.lambda().as_ref().and_then(reference_path).and_then(|mut path| path.pop());
+    matches!(name.as_deref(), Some("import" | "callPackage" | "callPackages"))
+}
+
+/// Reports strings interpolating a path literal, like `"${./foo}"` and
+/// `"${./.}/foo"`. Interpolating a path copies it to the store, which is
+/// wasteful when only a file in a directory is needed, and often not what
+/// was meant when the location of the file was. The path itself is only
+/// offered as a fix where it is used like the string, as for `import`, since
+/// a string and a path behave differently elsewhere, like in `+`.
+pub struct PathInterpolation;
+
+impl Rule for PathInterpolation {
+    fn id(&self) -> &'static str {
+        "path-interpolation"
+    }
+    fn description(&self) -> &'static str {
+        "interpolating a path copies it to the store"
+    }
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+    fn check(&self, ctx: &mut Context) {
+        for s in ctx.root().descendants().filter_map(ast::Str::cast) {
+            let parts = s.normalized_parts();
+            let (path, rest) = match &parts[..] {
+                [InterpolPart::Interpolation(interpol)] => (interpol, ""),
+                [InterpolPart::Interpolation(interpol), InterpolPart::Literal(rest)]
+                    if rest.starts_with('/') =>
+                {
+                    (interpol, rest.as_str())
+                }
+                _ => continue,
+            };
+            let Some(expr) = path.expr() else { continue };
+            if static_path(&expr).is_none() {
+                continue;
+            }
+            let path = expr.syntax().to_string();
+            let range = s.syntax().text_range();
+            if !rest.is_empty() {
+                // Copying only the file would break references to the files
+                // next to it, like imports in it
+                let message = format!(
+                    "all of `{}` is copied to the store to refer to `{}` in it",
+                    path,
+                    rest.trim_start_matches('/')
+                );
+                ctx.report(range, message);
+                continue;
+            }
+            let message = format!(
+                "`{}` is copied to the store and this is its store path, use `{}` to refer \
+                 to the file or `builtins.toString {}` for its location without copying it",
+                path, path, path
+            );
+            if accepts_path(&ast::Expr::Str(s.clone())) {
+                let fix = Fix {
+                    message: "use the path".into(),
+                    edits: vec![TextEdit::replace(range, path.clone())],
+                };
+                ctx.report_with_fix(range, message, fix);
+            } else {
+                ctx.report(range, message);
+            }
+        }
+    }
+}
+
+/// Reports appending a string to a path literal, like `./. + "/foo"`, which
+/// is the same as the path literal `./foo`. Strings not starting with a slash
+/// are appended to the last component of the path, so `./. + "foo"` is the
+/// sibling `../<dir>foo` of the directory, which is reported as a mistake.
+pub struct PathConcatenation;
+
+impl Rule for PathConcatenation {
+    fn id(&self) -> &'static str {
+        "path-concatenation"
+    }
+    fn description(&self) -> &'static str {
+        "appending a constant string to a path literal can be written as a path literal"
+    }
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+    fn check(&self, ctx: &mut Context) {
+        for op in ctx.root().descendants().filter_map(ast::BinOp::cast) {
+            let (Some(BinOpKind::Add), Some(lhs), Some(ast::Expr::Str(rhs))) =
+                (op.operator(), op.lhs(), op.rhs().map(strip_parens))
+            else {
+                continue;
+            };
+            let Some(value) = static_path(&lhs) else { continue };
+            let Some(Value::String(text, _)) = eval_const(&ast::Expr::Str(rhs)) else {
+                continue;
+            };
+            let range = op.syntax().text_range();
+            if !text.starts_with('/') {
+                ctx.report(
+                    range,
+                    format!(
+                        "`{}` is appended to the last component of `{}`, not to the path in it",
+                        text,
+                        lhs.syntax()
+                    ),
+                );
+                continue;
+            }
+            let Some(joined) = value.join(&text).as_ref().and_then(path_literal) else { continue };
+            let fix = Fix {
+                message: format!("use `{}`", joined),
+                edits: vec![TextEdit::replace(range, joined.clone())],
+            };
+            let message = format!(
+                "this is the path `{}`, which unlike `\"${{{}}}{}\"` doesn't copy `{}` to the store",
+                joined,
+                lhs.syntax(),
+                text,
+                lhs.syntax()
+            );
+            ctx.report_with_fix(range, message, fix);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rowan::ast::AstNode;
+
+    use crate::{
+        lint::{LintConfig, Registry},
+        rewrite::apply_edits,
+        Root,
+    };
+
+    fn check(rule: &str, src: &str) -> Vec<(String, Option<String>)> {
+        let root = Root::parse(src).ok().unwrap();
+        Registry::builtin()
+            .check(root.syntax(), &LintConfig::default())
+            .into_iter()
+            .filter(|d| d.rule == rule)
+            .map(|d| (d.message, d.fix.map(|fix| apply_edits(src, &fix.edits))))
+            .collect()
+    }
+
+    #[test]
+    fn path_interpolation() {
+        let message = "`./src` is copied to the store and this is its store path, use `./src` to \
+                       refer to the file or `builtins.toString ./src` for its location without \
+                       copying it";
+        assert_eq!(
+            check("path-interpolation", r#"{ src = "${./src}"; }"#),
+            [(message.into(), None)]
+        );
+        assert_eq!(
+            check("path-interpolation", r#"import ("${./src}") { }"#),
+            [(message.into(), Some("import (./src) { }".into()))]
+        );
+        assert_eq!(
+            check("path-interpolation", r#"pkgs.callPackage "${./src}" { }"#),
+            [(message.into(), Some("pkgs.callPackage ./src { }".into()))]
+        );
+        // Only the file would be copied, without the ones it imports
+        assert_eq!(
+            check("path-interpolation", r#"import "${./.}/lib/default.nix""#),
+            [(
+                "all of `./.` is copied to the store to refer to `lib/default.nix` in it".into(),
+                None
+            )]
+        );
+        // Other text around the path is usually deliberate
+        assert_eq!(check("path-interpolation", r#""-I${./include}""#), []);
+    }
+
+    #[test]
+    fn path_concatenation() {
+        assert_eq!(
+            check("path-concatenation", r#"import (./. + "/lib/../default.nix")"#),
+            [(
+                "this is the path `./default.nix`, which unlike `\"${./.}/lib/../default.nix\"` \
+                 doesn't copy `./.` to the store"
+                    .into(),
+                Some("import (./default.nix)".into())
+            )]
+        );
+        assert_eq!(
+            check("path-concatenation", r#"./src + "foo""#),
+            [(
+                "`foo` is appended to the last component of `./src`, not to the path in it".into(),
+                None
+            )]
+        );
+        // Only constant strings can be folded
+        assert_eq!(check("path-concatenation", r#"./. + "/${name}""#), []);
+    }
+}
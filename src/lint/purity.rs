@@ -0,0 +1,253 @@
+//! Rules for expressions which can't be evaluated in pure evaluation mode or
+//! which need to build something during evaluation, which CI setups and
+//! flakes often forbid
+
+use rowan::ast::AstNode;
+
+use crate::{
+    analysis::{attrpath_names, callee_name, strip_parens, Resolution, GLOBALS},
+    ast::{self, HasEntry},
+};
+
+use super::{Context, Rule, Severity};
+
+/// The name of the builtin an expression refers to, like `getEnv` for
+/// `builtins.getEnv` or `__getEnv`. Builtins available without `builtins.`,
+/// like `import`, are recognized too, as are names in `with builtins;`. Names
+/// bound in the tree shadow the builtins, but `with` doesn't.
+fn builtin_name(ctx: &Context, expr: &ast::Expr) -> Option<String> {
+    let is_global = |ident: &ast::Ident| {
+        matches!(ctx.resolver().resolve(ident), Some(Resolution::Global | Resolution::With(_)))
+    };
+    let is_builtins = |expr: Option<ast::Expr>| match expr.map(strip_parens) {
+        Some(ast::Expr::Ident(ident)) => {
+            is_global(&ident) && ident.ident_token().is_some_and(|token| token.text() == "builtins")
+        }
+        _ => false,
+    };
+    match strip_parens(expr.clone()) {
+        ast::Expr::Ident(ident) => {
+            let name = ident.ident_token()?.text().to_string();
+            match ctx.resolver().resolve(&ident)? {
+                Resolution::Global | Resolution::With(_) if name.starts_with("__") => {
+                    Some(name[2..].to_string())
+                }
+                Resolution::Global | Resolution::With(_) if GLOBALS.contains(&name.as_str()) => {
+                    Some(name)
+                }
+                Resolution::With(withs)
+                    if withs.iter().any(|with| is_builtins(with.namespace())) =>
+                {
+                    Some(name)
+                }
+                _ => None,
+            }
+        }
+        ast::Expr::Select(select) if select.or_token().is_none() => {
+            if !is_builtins(select.expr()) {
+                return None;
+            }
+            let [name] = <[String; 1]>::try_from(attrpath_names(&select.attrpath()?)?).ok()?;
+            Some(name)
+        }
+        _ => None,
+    }
+}
+
+/// The builtin called by an application, like `fetchTarball` for
+/// `builtins.fetchTarball { ... }`
+fn called_builtin(ctx: &Context, apply: &ast::Apply) -> Option<String> {
+    builtin_name(ctx, &apply.lambda()?)
+}
+
+/// Reports `import` of a file fetched by a derivation, like
+/// `import (pkgs.fetchFromGitHub { ... })`, which builds the derivation during
+/// evaluation
+pub struct ImportFromDerivation;
+
+impl Rule for ImportFromDerivation {
+    fn id(&self) -> &'static str {
+        "import-from-derivation"
+    }
+    fn description(&self) -> &'static str {
+        "importing the output of a derivation builds it during evaluation"
+    }
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+    fn check(&self, ctx: &mut Context) {
+        for apply in ctx.root().descendants().filter_map(ast::Apply::cast) {
+            if !matches!(called_builtin(ctx, &apply).as_deref(), Some("import" | "scopedImport")) {
+                continue;
+            }
+            let Some(argument) = apply.argument() else { continue };
+            // Also find the fetcher in `"${fetchurl { ... }}/default.nix"`
+            let fetcher =
+                argument.syntax().descendants().filter_map(ast::Apply::cast).find(|call| {
+                    callee_name(call).is_some_and(|name| name.starts_with("fetch"))
+                        && called_builtin(ctx, call).is_none()
+                });
+            if let Some(fetcher) = fetcher {
+                let name = callee_name(&fetcher).unwrap_or_default();
+                ctx.report(
+                    apply.syntax().text_range(),
+                    format!(
+                        "import from derivation: the result of `{}` is built during evaluation",
+                        name
+                    ),
+                );
+            }
+        }
+    }
+}
+
+/// Reports `builtins.fetchTarball` and `builtins.fetchurl` without a hash,
+/// and `builtins.fetchGit` without a revision, whose results can change
+/// between evaluations
+pub struct UnpinnedFetch;
+
+impl Rule for UnpinnedFetch {
+    fn id(&self) -> &'static str {
+        "unpinned-fetch"
+    }
+    fn description(&self) -> &'static str {
+        "builtin fetchers need a hash or revision to be reproducible"
+    }
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+    fn check(&self, ctx: &mut Context) {
+        for apply in ctx.root().descendants().filter_map(ast::Apply::cast) {
+            let Some(name) = called_builtin(ctx, &apply) else { continue };
+            let pins: &[&str] = match name.as_str() {
+                "fetchTarball" | "fetchurl" => &["sha256", "hash"],
+                "fetchGit" => &["rev"],
+                _ => continue,
+            };
+            let pinned = match apply.argument().map(strip_parens) {
+                Some(ast::Expr::AttrSet(set)) => set.attrpath_values().any(|entry| {
+                    entry
+                        .attrpath()
+                        .and_then(|attrpath| attrpath_names(&attrpath))
+                        .is_some_and(|names| names.len() == 1 && pins.contains(&names[0].as_str()))
+                }),
+                // A plain URL, or arguments which can't be known
+                Some(ast::Expr::Str(_)) | Some(ast::Expr::Path(_)) => false,
+                _ => continue,
+            };
+            if !pinned {
+                ctx.report(
+                    apply.syntax().text_range(),
+                    format!("`builtins.{}` without `{}`", name, pins[0]),
+                );
+            }
+        }
+    }
+}
+
+/// Reports `builtins.currentSystem`, `builtins.currentTime`,
+/// `builtins.getEnv` and `builtins.nixPath`, which depend on the machine
+/// evaluating the expression and aren't available in pure evaluation mode
+pub struct ImpureBuiltin;
+
+impl Rule for ImpureBuiltin {
+    fn id(&self) -> &'static str {
+        "impure-builtin"
+    }
+    fn description(&self) -> &'static str {
+        "builtins depending on the environment aren't available in pure evaluation"
+    }
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+    fn check(&self, ctx: &mut Context) {
+        for node in ctx.root().descendants() {
+            let Some(expr) = ast::Expr::cast(node) else { continue };
+            if !matches!(expr, ast::Expr::Ident(_) | ast::Expr::Select(_)) {
+                continue;
+            }
+            let Some(name) = builtin_name(ctx, &expr) else { continue };
+            if matches!(name.as_str(), "currentSystem" | "currentTime" | "getEnv" | "nixPath") {
+                ctx.report(expr.syntax().text_range(), format!("`builtins.{}` is impure", name));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rowan::ast::AstNode;
+
+    use crate::{
+        lint::{LintConfig, Registry},
+        Root,
+    };
+
+    fn check(rule: &str, src: &str) -> Vec<String> {
+        let root = Root::parse(src).ok().unwrap();
+        Registry::builtin()
+            .check(root.syntax(), &LintConfig::default())
+            .into_iter()
+            .filter(|d| d.rule == rule)
+            .map(|d| format!("{}: {}", &src[d.range], d.message))
+            .collect()
+    }
+
+    #[test]
+    fn import_from_derivation() {
+        let src = r#"{ pkgs }: [
+  (import (pkgs.fetchFromGitHub { owner = "a"; repo = "b"; }) { })
+  (import "${pkgs.fetchurl { url = "x"; }}/default.nix")
+  (import (builtins.fetchTarball { url = "x"; sha256 = "y"; }))
+  (import ./local.nix)
+]"#;
+        assert_eq!(
+            check("import-from-derivation", src),
+            [
+                "import (pkgs.fetchFromGitHub { owner = \"a\"; repo = \"b\"; }): import from \
+                 derivation: the result of `fetchFromGitHub` is built during evaluation",
+                "import \"${pkgs.fetchurl { url = \"x\"; }}/default.nix\": import from \
+                 derivation: the result of `fetchurl` is built during evaluation",
+            ]
+        );
+        // A function named `import` in the tree isn't the builtin
+        assert_eq!(
+            check("import-from-derivation", "import: import (fetchurl { })"),
+            [] as [String; 0]
+        );
+    }
+
+    #[test]
+    fn unpinned_fetch() {
+        let src = r#"[
+  (builtins.fetchTarball "https://example.org/a.tar.gz")
+  (fetchTarball { url = "https://example.org/a.tar.gz"; sha256 = "0abc"; })
+  (builtins.fetchGit { url = "https://example.org/a.git"; })
+  (builtins.fetchGit { url = "https://example.org/a.git"; rev = "abc"; })
+  (pkgs.fetchurl { url = "https://example.org/a"; })
+]"#;
+        assert_eq!(
+            check("unpinned-fetch", src),
+            [
+                "builtins.fetchTarball \"https://example.org/a.tar.gz\": \
+                 `builtins.fetchTarball` without `sha256`",
+                "builtins.fetchGit { url = \"https://example.org/a.git\"; }: \
+                 `builtins.fetchGit` without `rev`",
+            ]
+        );
+    }
+
+    #[test]
+    fn impure_builtin() {
+        let src = "with builtins; [ builtins.currentSystem (getEnv \"HOME\") __currentTime \
+                   (let builtins = { }; in builtins.currentSystem) ]";
+        assert_eq!(
+            check("impure-builtin", src),
+            [
+                "builtins.currentSystem: `builtins.currentSystem` is impure",
+                "getEnv: `builtins.getEnv` is impure",
+                "__currentTime: `builtins.currentTime` is impure",
+            ]
+        );
+    }
+}
@@ -0,0 +1,367 @@
+use std::collections::{HashMap, HashSet};
+
+use rowan::{ast::AstNode, TextRange};
+
+use crate::{
+    analysis::{self, attr_name, duplicate_args, duplicate_attrs, BindingKind, Resolution},
+    ast,
+    rewrite::TextEdit,
+    SyntaxKind::*,
+    SyntaxNode,
+};
+
+use super::{Context, Fix, Rule, Severity};
+
+/// Reports attributes which are defined more than once in the same attrset or
+/// `let`, taking into account that Nix merges nested attrset literals, as in
+/// `a.b = 1; a = { c = 2; };`, and that `${"a"}` defines `a`.
+pub struct DuplicateAttr;
+
+impl Rule for DuplicateAttr {
+    fn id(&self) -> &'static str {
+        "duplicate-attr"
+    }
+    fn description(&self) -> &'static str {
+        "attributes must not be defined twice"
+    }
+    fn default_severity(&self) -> Severity {
+        Severity::Error
+    }
+    fn check(&self, ctx: &mut Context) {
+        for (range, path) in duplicate_attrs(ctx.root()) {
+            ctx.report(range, format!("attribute `{}` is already defined", path.join(".")));
+        }
+    }
+}
+
+/// Reports lambda patterns with the same argument twice, like `{ a, a }: a`
+pub struct DuplicateFormal;
+
+impl Rule for DuplicateFormal {
+    fn id(&self) -> &'static str {
+        "duplicate-formal"
+    }
+    fn description(&self) -> &'static str {
+        "function arguments must not be duplicated"
+    }
+    fn default_severity(&self) -> Severity {
+        Severity::Error
+    }
+    fn check(&self, ctx: &mut Context) {
+        for (range, name) in duplicate_args(ctx.root()) {
+            ctx.report(range, format!("argument `{}` is duplicated", name));
+        }
+    }
+}
+
+/// Reports variables which aren't defined anywhere. Names which may come from
+/// a `with` are never reported.
+pub struct UndefinedVariable;
+
+impl Rule for UndefinedVariable {
+    fn id(&self) -> &'static str {
+        "undefined-variable"
+    }
+    fn description(&self) -> &'static str {
+        "variables must be defined"
+    }
+    fn default_severity(&self) -> Severity {
+        Severity::Error
+    }
+    fn check(&self, ctx: &mut Context) {
+        let resolver = ctx.resolver();
+        for (ident, resolution) in resolver.resolutions() {
+            if *resolution == Resolution::Undefined {
+                ctx.report(ident.syntax().text_range(), format!("undefined variable `{}`", ident));
+            }
+        }
+    }
+}
+
+/// Reports `let` bindings which are never used
+pub struct UnusedLetBinding;
+
+impl Rule for UnusedLetBinding {
+    fn id(&self) -> &'static str {
+        "unused-let-binding"
+    }
+    fn description(&self) -> &'static str {
+        "bindings in `let` should be used"
+    }
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+    fn check(&self, ctx: &mut Context) {
+        let resolver = ctx.resolver();
+        for binding in resolver.bindings() {
+            if binding.kind != BindingKind::LetIn || !resolver.references(binding.id).is_empty() {
+                continue;
+            }
+            let message = format!("binding `{}` is unused", binding.name);
+            match removal(&binding.node) {
+                Some(edit) => ctx.report_with_fix(
+                    binding.node.text_range(),
+                    message,
+                    Fix { message: format!("remove `{}`", binding.name), edits: vec![edit] },
+                ),
+                None => ctx.report(binding.node.text_range(), message),
+            }
+        }
+    }
+}
+
+/// Reports `let` bindings which are used, but only by bindings which don't
+/// contribute to the result themselves, like `a` in
+/// `let a = 1; b = a; in 2`. Attributes of a `rec` set bound in a `let` and
+/// only used as a namespace, like `ns` in `let ns = rec { ... }; in ns.a`, are
+/// reported if they aren't selected and not used by a selected attribute.
+pub struct DeadBinding;
+
+/// The names selected from a binding if all its references select from it,
+/// like `a` in `ns.a` and `inherit (ns) a;`
+fn selected_names(ctx: &Context, id: analysis::BindingId) -> Option<HashSet<String>> {
+    let mut names = HashSet::new();
+    for reference in ctx.resolver().references(id) {
+        let parent = reference.syntax().parent()?;
+        if let Some(select) = ast::Select::cast(parent.clone()) {
+            let attr = select.attrpath()?.attrs().next()?;
+            names.insert(attr_name(&attr)?);
+        } else if let Some(from) = ast::InheritFrom::cast(parent) {
+            let inherit = from.syntax().parent().and_then(ast::Inherit::cast)?;
+            for attr in inherit.attrs() {
+                names.insert(attr_name(&attr)?);
+            }
+        } else {
+            return None;
+        }
+    }
+    Some(names)
+}
+
+impl Rule for DeadBinding {
+    fn id(&self) -> &'static str {
+        "dead-binding"
+    }
+    fn description(&self) -> &'static str {
+        "bindings should contribute to the result"
+    }
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+    fn check(&self, ctx: &mut Context) {
+        let graph = analysis::binding_graph(ctx.root());
+        let resolver = graph.resolver();
+
+        // `rec` sets used as namespaces, with the names selected from them
+        let mut namespaces = HashMap::new();
+        for binding in resolver.bindings().filter(|binding| binding.kind == BindingKind::LetIn) {
+            let Some(entry) = binding.node.ancestors().find_map(ast::AttrpathValue::cast) else {
+                continue;
+            };
+            let Some(ast::Expr::AttrSet(set)) = entry.value().map(analysis::strip_parens) else {
+                continue;
+            };
+            if set.rec_token().is_none()
+                || entry.attrpath().map(|path| path.attrs().count()) != Some(1)
+            {
+                continue;
+            }
+            if let Some(names) = selected_names(ctx, binding.id) {
+                namespaces.insert(set.syntax().clone(), (binding.name.clone(), names));
+            }
+        }
+
+        let mut stack: Vec<_> = graph.roots().to_vec();
+        for binding in resolver.bindings() {
+            let live = match binding.kind {
+                BindingKind::LetIn => false,
+                BindingKind::LegacyLet => binding.name == "body",
+                BindingKind::RecAttrSet => match namespaces.get(&binding.scope) {
+                    Some((_, names)) => names.contains(&binding.name),
+                    None => true,
+                },
+                BindingKind::Lambda | BindingKind::PatEntry | BindingKind::PatBind => true,
+            };
+            if live {
+                stack.push(binding.id);
+            }
+        }
+        let mut live = HashSet::new();
+        while let Some(id) = stack.pop() {
+            if live.insert(id) {
+                stack.extend(graph.uses(id));
+            }
+        }
+
+        for binding in resolver.bindings().filter(|binding| !live.contains(&binding.id)) {
+            let message = match (binding.kind, namespaces.get(&binding.scope)) {
+                (BindingKind::RecAttrSet, Some((namespace, _))) => {
+                    format!("attribute `{}` of `{}` is never used", binding.name, namespace)
+                }
+                // Bindings without any references are reported by `unused-let-binding`
+                _ if resolver.references(binding.id).is_empty() => continue,
+                _ => format!("binding `{}` is only used by unused bindings", binding.name),
+            };
+            ctx.report(binding.node.text_range(), message);
+        }
+    }
+}
+
+/// An edit removing the binding of `attr` together with its leading
+/// whitespace, if the binding consists of nothing but `attr`.
+fn removal(attr: &SyntaxNode) -> Option<TextEdit> {
+    let entry = attr.ancestors().find_map(ast::AttrpathValue::cast)?;
+    if entry.attrpath()?.attrs().count() != 1 || entry.attrpath()?.syntax() != &attr.parent()? {
+        return None;
+    }
+    let mut start = entry.syntax().text_range().start();
+    if let Some(prev) = entry.syntax().first_token().and_then(|t| t.prev_token()) {
+        if prev.kind() == TOKEN_WHITESPACE {
+            start = prev.text_range().start();
+        }
+    }
+    Some(TextEdit::delete(TextRange::new(start, entry.syntax().text_range().end())))
+}
+
+/// Reports arguments passed to an inline function with `callPackage` which
+/// it doesn't accept
+pub struct UnexpectedCallPackageArg;
+
+impl Rule for UnexpectedCallPackageArg {
+    fn id(&self) -> &'static str {
+        "unexpected-callpackage-arg"
+    }
+    fn description(&self) -> &'static str {
+        "arguments passed with `callPackage` must be accepted by the function"
+    }
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+    fn check(&self, ctx: &mut Context) {
+        for call in analysis::callpackage_calls(ctx.root()) {
+            for arg in call.unexpected_args() {
+                ctx.report(arg.range, format!("function doesn't accept argument `{}`", arg.name));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        analysis::GlobalScope,
+        lint::{LintConfig, Registry},
+        rewrite::apply_edits,
+        Root,
+    };
+
+    fn check(src: &str) -> Vec<String> {
+        let root = Root::parse(src).ok().unwrap();
+        Registry::builtin()
+            .check(rowan::ast::AstNode::syntax(&root), &LintConfig::default())
+            .into_iter()
+            .map(|d| format!("{}: {} ({})", d.rule, d.message, &src[d.range]))
+            .collect()
+    }
+
+    #[test]
+    fn duplicate_attr() {
+        assert_eq!(
+            check("x: { a.b = 1; a = { c = 2; }; a.c = 3; d = 1; d.e = 2; inherit (x) d; }"),
+            [
+                "duplicate-attr: attribute `a.c` is already defined (a.c)",
+                "duplicate-attr: attribute `d.e` is already defined (d.e)",
+                "duplicate-attr: attribute `d` is already defined (d)",
+            ]
+        );
+        assert!(check("let a = { b = 1; }; a.c = 2; in a").is_empty());
+        assert_eq!(
+            check("{ a = ({ b = 1; b = 2; }); }"),
+            ["duplicate-attr: attribute `a.b` is already defined (b)"]
+        );
+        assert_eq!(
+            check("{ a = 1; a = { b = 1; b = 2; }; }"),
+            [
+                "duplicate-attr: attribute `a` is already defined (a)",
+                "duplicate-attr: attribute `a.b` is already defined (b)",
+            ]
+        );
+        assert_eq!(
+            check(r#"{ a.b = 1; a.${"b"} = 2; }"#),
+            [
+                "duplicate-attr: attribute `a.b` is already defined (a.${\"b\"})",
+                "constant-dynamic-attr: this is the attribute `b` (${\"b\"})",
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicate_formal() {
+        assert_eq!(
+            check("{ a, b, a }@b: a"),
+            [
+                "duplicate-formal: argument `b` is duplicated (b)",
+                "duplicate-formal: argument `a` is duplicated (a)",
+            ]
+        );
+    }
+
+    #[test]
+    fn undefined_and_unused() {
+        assert_eq!(
+            check("let a = 1;\n  b = c; in b + (with x; d)"),
+            [
+                "unused-let-binding: binding `a` is unused (a)",
+                "undefined-variable: undefined variable `c` (c)",
+                "undefined-variable: undefined variable `x` (x)",
+            ]
+        );
+
+        let src = "[ pkgs (fetchTree { }) ]";
+        let root = Root::parse(src).ok().unwrap();
+        let mut globals = GlobalScope::nix_2_3();
+        globals.define("pkgs");
+        let diagnostics = Registry::builtin()
+            .check(rowan::ast::AstNode::syntax(&root), LintConfig::default().set_globals(globals));
+        let messages: Vec<_> = diagnostics.into_iter().map(|d| d.message).collect();
+        assert_eq!(messages, ["undefined variable `fetchTree`"]);
+    }
+
+    #[test]
+    fn dead_binding() {
+        assert_eq!(
+            check("let a = 1; b = a; c = 2; in c"),
+            [
+                "dead-binding: binding `a` is only used by unused bindings (a)",
+                "unused-let-binding: binding `b` is unused (b)",
+            ]
+        );
+        assert_eq!(
+            check("let ns = rec { a = 1; b = a; c = d; d = 2; }; in ns.b + (let inherit (ns) e; in e)"),
+            [
+                "dead-binding: attribute `c` of `ns` is never used (c)",
+                "dead-binding: attribute `d` of `ns` is never used (d)",
+            ]
+        );
+        // Namespaces which are used as a whole are left alone
+        assert!(check("let ns = rec { a = 1; b = a; }; in ns // { }").is_empty());
+    }
+
+    #[test]
+    fn unused_fix() {
+        let src = "let\n  a = 1;\n  b = 2;\nin b";
+        let root = Root::parse(src).ok().unwrap();
+        let diagnostics =
+            Registry::builtin().check(rowan::ast::AstNode::syntax(&root), &LintConfig::default());
+        let fix = diagnostics[0].fix.as_ref().unwrap();
+        assert_eq!(apply_edits(src, &fix.edits), "let\n  b = 2;\nin b");
+    }
+
+    #[test]
+    fn callpackage_args() {
+        assert_eq!(
+            check("callPackage: callPackage ({ a }: a) { b = 1; }"),
+            ["unexpected-callpackage-arg: function doesn't accept argument `b` (b)"]
+        );
+    }
+}
@@ -2,13 +2,14 @@
 
 use std::{collections::VecDeque, fmt};
 
-use rowan::{Checkpoint, GreenNode, GreenNodeBuilder, Language, TextRange, TextSize};
+use rowan::{Checkpoint, GreenNode, GreenNodeBuilder, Language, NodeCache, TextRange, TextSize};
 
 use crate::{
+    analysis::{duplicate_args, duplicate_attrs},
     tokenizer::Token,
-    NixLanguage,
+    NixLanguage, NodeOrToken,
     SyntaxKind::{self, *},
-    TokenSet,
+    SyntaxNode, TokenSet,
 };
 
 /// An error that occurred during parsing
@@ -21,17 +22,32 @@ pub enum ParseError {
     UnexpectedExtra(TextRange),
     /// UnexpectedWanted is used when specific tokens are expected, but different one is found
     UnexpectedWanted(SyntaxKind, TextRange, Box<[SyntaxKind]>),
-    /// UnexpectedDoubleBind is used when a pattern is bound twice
-    UnexpectedDoubleBind(TextRange),
+    /// UnexpectedDoubleBind is used when a pattern is bound twice, like `a@{ }@b`. It holds the
+    /// range of the second bind, and the one of the first
+    UnexpectedDoubleBind(TextRange, TextRange),
     /// UnexpectedEOF is used when the end of file is reached, while tokens are still expected
     UnexpectedEOF,
     /// UnexpectedEOFWanted is used when specific tokens are expected, but the end of file is reached
     UnexpectedEOFWanted(Box<[SyntaxKind]>),
-    /// DuplicatedArgs is used when formal arguments are duplicated, e.g. `{ a, a }`
+    /// DuplicatedArgs is used when formal arguments are duplicated, e.g. `{ a, a }`, with
+    /// [`ParserOptions::reject_duplicates`]
     DuplicatedArgs(TextRange, String),
     /// RecursionLimitExceeded is used when we're unable to parse further due to likely being close to
     /// a stack overflow.
     RecursionLimitExceeded,
+    /// IntegerOverflow is used when an integer literal doesn't fit into 64 bits, which Nix rejects
+    IntegerOverflow(TextRange),
+    /// InvalidToken is used for text the tokenizer doesn't recognize, like `1.0e` or a stray `\``
+    InvalidToken(TextRange),
+    /// UriLiteral is used for unquoted URIs like `https://nixos.org` with
+    /// [`ParserOptions::reject_deprecated`]
+    UriLiteral(TextRange),
+    /// LegacyLet is used for the `let` of `let { body = ...; }` with
+    /// [`ParserOptions::reject_deprecated`]
+    LegacyLet(TextRange),
+    /// DuplicatedAttr is used when an attribute is defined twice, e.g. `{ a.b = 1; a.b = 2; }`,
+    /// with [`ParserOptions::reject_duplicates`]. It holds the full path of the attribute.
+    DuplicatedAttr(TextRange, String),
 }
 
 impl fmt::Display for ParseError {
@@ -61,12 +77,14 @@ impl fmt::Display for ParseError {
                 usize::from(range.end()),
                 kinds
             ),
-            ParseError::UnexpectedDoubleBind(range) => {
+            ParseError::UnexpectedDoubleBind(range, first) => {
                 write!(
                     f,
-                    "unexpected double bind at {}..{}",
+                    "unexpected double bind at {}..{}, already bound at {}..{}",
                     usize::from(range.start()),
-                    usize::from(range.end())
+                    usize::from(range.end()),
+                    usize::from(first.start()),
+                    usize::from(first.end())
                 )
             }
             ParseError::UnexpectedEOF => write!(f, "unexpected end of file"),
@@ -83,18 +101,118 @@ impl fmt::Display for ParseError {
                 )
             }
             ParseError::RecursionLimitExceeded => write!(f, "recursion limit exceeded"),
+            ParseError::IntegerOverflow(range) => {
+                write!(
+                    f,
+                    "integer at {}..{} is larger than the maximum of {}",
+                    usize::from(range.start()),
+                    usize::from(range.end()),
+                    i64::MAX
+                )
+            }
+            ParseError::InvalidToken(range) => {
+                write!(
+                    f,
+                    "invalid token at {}..{}",
+                    usize::from(range.start()),
+                    usize::from(range.end())
+                )
+            }
+            ParseError::UriLiteral(range) => {
+                write!(
+                    f,
+                    "deprecated URI literal at {}..{}",
+                    usize::from(range.start()),
+                    usize::from(range.end())
+                )
+            }
+            ParseError::LegacyLet(range) => {
+                write!(
+                    f,
+                    "deprecated legacy let at {}..{}",
+                    usize::from(range.start()),
+                    usize::from(range.end())
+                )
+            }
+            ParseError::DuplicatedAttr(range, path) => {
+                write!(
+                    f,
+                    "attribute `{}` is duplicated in {}..{}",
+                    path,
+                    usize::from(range.start()),
+                    usize::from(range.end())
+                )
+            }
         }
     }
 }
 
 impl std::error::Error for ParseError {}
 
-struct Parser<'a, I>
+impl ParseError {
+    /// The range the error refers to, if it isn't at the end of the file or
+    /// without a location
+    pub fn range(&self) -> Option<TextRange> {
+        match self {
+            ParseError::Unexpected(range)
+            | ParseError::UnexpectedExtra(range)
+            | ParseError::UnexpectedWanted(_, range, _)
+            | ParseError::UnexpectedDoubleBind(range, _)
+            | ParseError::DuplicatedArgs(range, _)
+            | ParseError::IntegerOverflow(range)
+            | ParseError::InvalidToken(range)
+            | ParseError::UriLiteral(range)
+            | ParseError::LegacyLet(range)
+            | ParseError::DuplicatedAttr(range, _) => Some(*range),
+            ParseError::UnexpectedEOF
+            | ParseError::UnexpectedEOFWanted(_)
+            | ParseError::RecursionLimitExceeded => None,
+        }
+    }
+
+    /// Another range the error refers to, like the first bind of a pattern
+    /// which is bound twice
+    pub fn related_range(&self) -> Option<TextRange> {
+        match self {
+            ParseError::UnexpectedDoubleBind(_, first) => Some(*first),
+            _ => None,
+        }
+    }
+}
+
+/// Which syntax beyond the one of stable Nix to accept, and which of the
+/// syntax Nix accepts to reject
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ParserOptions {
+    /// Parse the pipe operators `x |> f` and `f <| x` of the experimental
+    /// `pipe-operators` feature of Nix. They bind looser than every other
+    /// operator, `|>` is left and `<|` right associative, and mixing them
+    /// without parentheses is an error. Without this option they are
+    /// unexpected tokens.
+    pub experimental_pipe: bool,
+    /// Report the deprecated URI literals and legacy `let { }`, which Nix
+    /// only warns about, as errors
+    pub reject_deprecated: bool,
+    /// Report attributes defined twice in the same attrset or `let`, and
+    /// function arguments listed twice, as errors, like Nix does
+    pub reject_duplicates: bool,
+}
+
+impl ParserOptions {
+    /// Options for enforcing modern Nix, like in CI: deprecated syntax and
+    /// duplicated attributes are errors
+    pub fn strict() -> Self {
+        Self { reject_deprecated: true, reject_duplicates: true, ..Self::default() }
+    }
+}
+
+struct Parser<'a, 'c, I>
 where
     I: Iterator<Item = Token<'a>>,
 {
-    builder: GreenNodeBuilder<'static>,
+    builder: GreenNodeBuilder<'c>,
     errors: Vec<ParseError>,
+    options: ParserOptions,
 
     trivia_buffer: Vec<Token<'a>>,
     buffer: VecDeque<Token<'a>>,
@@ -105,14 +223,15 @@ where
     // by any method as long as it is decremented when that method returns.
     depth: u32,
 }
-impl<'a, I> Parser<'a, I>
+impl<'a, 'c, I> Parser<'a, 'c, I>
 where
     I: Iterator<Item = Token<'a>>,
 {
-    fn new(iter: I) -> Self {
+    fn new(iter: I, builder: GreenNodeBuilder<'c>, options: ParserOptions) -> Self {
         Self {
-            builder: GreenNodeBuilder::new(),
+            builder,
             errors: Vec::new(),
+            options,
 
             trivia_buffer: Vec::with_capacity(1),
             buffer: VecDeque::with_capacity(1),
@@ -137,6 +256,10 @@ where
     }
     fn drain_trivia_buffer(&mut self) {
         for (t, s) in self.trivia_buffer.drain(..) {
+            if t == TOKEN_ERROR {
+                let range = TextRange::at(self.consumed, TextSize::of(s));
+                self.errors.push(ParseError::InvalidToken(range));
+            }
             self.consumed += TextSize::of(s);
             self.builder.token(NixLanguage::kind_to_raw(t), s);
         }
@@ -303,7 +426,9 @@ where
         }
         self.finish_node();
     }
-    fn parse_pattern(&mut self, bound: bool) {
+    /// Parse the rest of a pattern after the `{`. `bound` is the range of
+    /// `name@` if the pattern was bound before it.
+    fn parse_pattern(&mut self, bound: Option<TextRange>) {
         if self.peek().map(|t| t == T!['}']).unwrap_or(true) {
             self.bump();
         } else {
@@ -326,12 +451,15 @@ where
                             self.parse_expr();
                         }
                         self.finish_node();
-                        match self.peek() {
+                        // After a typo, skip to the next entry so the rest of
+                        // the pattern and the body are still parsed
+                        match self.expect_peek_any(&[T![,], T!['}']]) {
                             Some(T![,]) => self.bump(),
-                            _ => {
-                                self.expect(T!['}']);
+                            Some(_) => {
+                                self.bump();
                                 break;
                             }
+                            None => break,
                         }
                     }
                     // handled by expect_peek_any
@@ -341,14 +469,15 @@ where
         }
 
         if self.peek() == Some(T![@]) {
-            let kind = if bound { NODE_ERROR } else { NODE_PAT_BIND };
+            let kind = if bound.is_some() { NODE_ERROR } else { NODE_PAT_BIND };
             self.start_node(kind);
             let start = self.get_text_position();
             self.bump();
             self.expect_ident();
             let end = self.finish_error_node();
-            if bound {
-                self.errors.push(ParseError::UnexpectedDoubleBind(TextRange::new(start, end)));
+            if let Some(first) = bound {
+                let range = TextRange::new(start, end);
+                self.errors.push(ParseError::UnexpectedDoubleBind(range, first));
             }
         }
     }
@@ -414,6 +543,7 @@ where
             }
         };
         let checkpoint = self.checkpoint();
+        let start = self.get_text_position();
         match peek {
             T!['('] => {
                 self.start_node(NODE_PAREN);
@@ -458,7 +588,7 @@ where
 
                         self.start_node(NODE_PATTERN);
                         self.bump();
-                        self.parse_pattern(false);
+                        self.parse_pattern(None);
                         self.finish_node();
 
                         self.expect(T![:]);
@@ -488,7 +618,9 @@ where
             TOKEN_PATH => {
                 self.start_node(NODE_PATH);
                 self.bump();
-                let is_complex_path = self.peek().map_or(false, |t| t == TOKEN_INTERPOL_START);
+                // Trivia, including error tokens, ends the path
+                let is_complex_path =
+                    self.peek_raw().is_some_and(|&(t, _)| t == TOKEN_INTERPOL_START);
                 if is_complex_path {
                     loop {
                         match self.peek_raw().map(|(t, _)| t) {
@@ -508,6 +640,12 @@ where
             }
             t if t.is_literal() => {
                 self.start_node(NODE_LITERAL);
+                if let Some(&(TOKEN_INTEGER, text)) = self.peek_data() {
+                    if text.parse::<i64>().is_err() {
+                        let range = TextRange::at(self.get_text_position(), TextSize::of(text));
+                        self.errors.push(ParseError::IntegerOverflow(range));
+                    }
+                }
                 self.bump();
                 self.finish_node();
             }
@@ -529,9 +667,10 @@ where
                         self.start_node_at(checkpoint, NODE_PAT_BIND);
                         self.bump();
                         self.finish_node(); // PatBind
+                        let bind = TextRange::new(start, self.get_text_position());
 
                         self.expect(T!['{']);
-                        self.parse_pattern(true);
+                        self.parse_pattern(Some(bind));
                         self.finish_node(); // Pattern
 
                         self.expect(T![:]);
@@ -605,6 +744,11 @@ where
             self.parse_fn()
         }
     }
+    // The operator levels below go from the tightest to the loosest binding.
+    // They are described along with their associativity in
+    // `grammar::PRECEDENCE`, which is tested against this parser.
+
+    /// `a < b < c` is an error, used for comparisons
     fn parse_non_assoc(&mut self, next: fn(&mut Self) -> Checkpoint, ops: TokenSet) -> Checkpoint {
         let checkpoint = next(self);
         if self.peek().map(|t| ops.contains(t)).unwrap_or(false) {
@@ -615,6 +759,7 @@ where
         }
         checkpoint
     }
+    /// `a - b - c` is `(a - b) - c`
     fn parse_left_assoc(&mut self, next: fn(&mut Self) -> Checkpoint, ops: TokenSet) -> Checkpoint {
         let checkpoint = next(self);
         while self.peek().map(|t| ops.contains(t)).unwrap_or(false) {
@@ -625,6 +770,8 @@ where
         }
         checkpoint
     }
+    /// `a -> b -> c` is `a -> (b -> c)`, used for `->`, `//` and `++` like in
+    /// Nix
     fn parse_right_assoc(
         &mut self,
         next: fn(&mut Self) -> Checkpoint,
@@ -688,10 +835,36 @@ where
     fn parse_implication(&mut self) -> Checkpoint {
         self.parse_right_assoc(Self::parse_or, T![->] | ())
     }
+    /// `a |> f |> g` is `(a |> f) |> g` and `f <| g <| a` is `f <| (g <| a)`,
+    /// the other operator is left for the caller to report
+    fn parse_pipe(&mut self) -> Checkpoint {
+        let checkpoint = self.parse_implication();
+        if !self.options.experimental_pipe {
+            return checkpoint;
+        }
+        match self.peek() {
+            Some(T![|>]) => {
+                while self.peek() == Some(T![|>]) {
+                    self.start_node_at(checkpoint, NODE_BIN_OP);
+                    self.bump();
+                    self.parse_implication();
+                    self.finish_node();
+                }
+            }
+            Some(T![<|]) => {
+                self.start_node_at(checkpoint, NODE_BIN_OP);
+                self.bump();
+                self.parse_right_assoc(Self::parse_implication, T![<|] | ());
+                self.finish_node();
+            }
+            _ => (),
+        }
+        checkpoint
+    }
     #[inline(always)]
     fn parse_math(&mut self) -> Checkpoint {
         // Always point this to the lowest-level math function there is
-        self.parse_implication()
+        self.parse_pipe()
     }
     /// Parse Nix code into an AST
     pub fn parse_expr(&mut self) -> Checkpoint {
@@ -770,7 +943,34 @@ pub fn parse<'s, I>(iter: I) -> (GreenNode, Vec<ParseError>)
 where
     I: Iterator<Item = Token<'s>>,
 {
-    let mut parser = Parser::new(iter);
+    finish(Parser::new(iter, GreenNodeBuilder::new(), ParserOptions::default()))
+}
+
+/// Parse tokens into an AST, accepting the syntax enabled in `options`
+pub fn parse_with_options<'s, I>(iter: I, options: ParserOptions) -> (GreenNode, Vec<ParseError>)
+where
+    I: Iterator<Item = Token<'s>>,
+{
+    finish(Parser::new(iter, GreenNodeBuilder::new(), options))
+}
+
+/// Parse tokens into an AST, reusing the tokens and small nodes in `cache`.
+///
+/// Every parse deduplicates identical tokens, like the many `0`, `true` and
+/// `"x86_64-linux"` in a file. Sharing a cache between the parses of many
+/// files does so across files as well, at the cost of keeping everything in
+/// the cache alive for as long as the cache.
+pub fn parse_with_cache<'s, I>(iter: I, cache: &mut NodeCache) -> (GreenNode, Vec<ParseError>)
+where
+    I: Iterator<Item = Token<'s>>,
+{
+    finish(Parser::new(iter, GreenNodeBuilder::with_cache(cache), ParserOptions::default()))
+}
+
+fn finish<'s, I>(mut parser: Parser<'s, '_, I>) -> (GreenNode, Vec<ParseError>)
+where
+    I: Iterator<Item = Token<'s>>,
+{
     parser.builder.start_node(NixLanguage::kind_to_raw(NODE_ROOT));
     parser.parse_expr();
     parser.eat_trivia();
@@ -784,5 +984,45 @@ where
         parser.eat_trivia();
     }
     parser.builder.finish_node();
-    (parser.builder.finish(), parser.errors)
+    let green = parser.builder.finish();
+    let mut errors = parser.errors;
+    let options = parser.options;
+    if options.reject_deprecated || options.reject_duplicates {
+        let root = SyntaxNode::new_root(green.clone());
+        let mut rejected = Vec::new();
+        if options.reject_deprecated {
+            rejected.extend(deprecated(&root));
+        }
+        if options.reject_duplicates {
+            rejected.extend(
+                duplicate_args(&root)
+                    .into_iter()
+                    .map(|(range, name)| ParseError::DuplicatedArgs(range, name)),
+            );
+            rejected.extend(
+                duplicate_attrs(&root)
+                    .into_iter()
+                    .map(|(range, path)| ParseError::DuplicatedAttr(range, path.join("."))),
+            );
+        }
+        rejected.sort_by_key(|error| error.range().map(TextRange::start));
+        errors.extend(rejected);
+    }
+    (green, errors)
+}
+
+/// The deprecated syntax in a tree, in order
+fn deprecated(root: &SyntaxNode) -> impl Iterator<Item = ParseError> {
+    root.descendants_with_tokens().filter_map(|element| match element {
+        NodeOrToken::Token(token) if token.kind() == TOKEN_URI => {
+            Some(ParseError::UriLiteral(token.text_range()))
+        }
+        NodeOrToken::Token(token)
+            if token.kind() == TOKEN_LET
+                && token.parent().is_some_and(|parent| parent.kind() == NODE_LEGACY_LET) =>
+        {
+            Some(ParseError::LegacyLet(token.text_range()))
+        }
+        _ => None,
+    })
 }
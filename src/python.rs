@@ -0,0 +1,42 @@
+//! Bindings for using the parser from Python, built with
+//! `maturin build --features python,pyo3/extension-module`
+
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+use crate::{dump, format::FormatOptions, Root};
+
+/// Convert JSON to the equivalent Python objects by means of the `json` module
+fn to_python<'py>(py: Python<'py>, value: &str) -> PyResult<Bound<'py, PyAny>> {
+    py.import("json")?.call_method1("loads", (value,))
+}
+
+/// Parse a source text into a tree as printed by [`dump::json`], as nested
+/// dictionaries and lists
+#[pyfunction]
+fn parse<'py>(py: Python<'py>, source: &str) -> PyResult<Bound<'py, PyAny>> {
+    to_python(py, &dump::json(&Root::parse(source).syntax()))
+}
+
+/// Collect the parse errors and the findings of the builtin lints, as a list
+/// of `{ span, severity, source, message }` dictionaries
+#[pyfunction]
+fn diagnostics<'py>(py: Python<'py>, source: &str) -> PyResult<Bound<'py, PyAny>> {
+    to_python(py, &dump::diagnostics_json(source))
+}
+
+/// Format a source text, see [`format::format`](crate::format::format).
+/// Raises `ValueError` if the text doesn't parse.
+#[pyfunction]
+#[pyo3(signature = (source, indent_width = 2))]
+fn format(source: &str, indent_width: usize) -> PyResult<String> {
+    crate::format::format(source, &FormatOptions { indent_width })
+        .map_err(|err| PyValueError::new_err(err.to_string()))
+}
+
+#[pymodule]
+fn rnix(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(parse, module)?)?;
+    module.add_function(wrap_pyfunction!(diagnostics, module)?)?;
+    module.add_function(wrap_pyfunction!(format, module)?)?;
+    Ok(())
+}
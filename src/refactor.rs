@@ -0,0 +1,230 @@
+//! Refactorings which produce text edits, like renaming a variable
+
+use std::fmt;
+
+use rowan::{ast::AstNode, TextRange};
+
+use crate::{
+    analysis::{attr_name, strip_parens, Binding, BindingKind, Resolution, Resolver},
+    ast,
+    rewrite::TextEdit,
+    tokenizer::tokenize,
+    SyntaxKind::*,
+    SyntaxNode,
+};
+
+mod inline;
+mod pattern;
+
+pub use inline::{inline, InlineConflict};
+pub use pattern::{add_formal, ident_to_pattern, pattern_to_ident, remove_formal, PatternConflict};
+
+/// The reason a rename was refused
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RenameConflict {
+    /// The node is neither a binding nor a reference to one
+    NotABinding,
+    /// The new name isn't a valid identifier, e.g. because it is a keyword
+    InvalidName,
+    /// The new name is already defined in the same scope, at the given range
+    AlreadyDefined(TextRange),
+    /// The reference at the given range would resolve to another binding of
+    /// the new name, which is defined between it and the renamed binding
+    Shadowed(TextRange),
+    /// The identifier at the given range, which is called like the new name,
+    /// would resolve to the renamed binding instead of its current one. This
+    /// includes names which come from a `with`.
+    Captured(TextRange),
+    /// The binding is used with the `inherit` shorthand at the given range,
+    /// which would need to be expanded into `new = old;`
+    Inherited(TextRange),
+}
+
+impl fmt::Display for RenameConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (message, range) = match self {
+            RenameConflict::NotABinding => return f.write_str("not a binding"),
+            RenameConflict::InvalidName => return f.write_str("invalid identifier"),
+            RenameConflict::AlreadyDefined(range) => ("name is already defined", range),
+            RenameConflict::Shadowed(range) => ("reference would be shadowed", range),
+            RenameConflict::Captured(range) => ("identifier would be captured", range),
+            RenameConflict::Inherited(range) => ("binding is inherited", range),
+        };
+        write!(f, "{} at {}..{}", message, usize::from(range.start()), usize::from(range.end()))
+    }
+}
+
+impl std::error::Error for RenameConflict {}
+
+/// Rename a binding and all references to it.
+///
+/// `binder` is either the node defining the binding (see
+/// [`Binding::node`](crate::analysis::Binding::node)) or an identifier
+/// referring to it, and `root` is usually the root of the file. The rename is
+/// refused if it would change what any identifier refers to.
+pub fn rename(
+    root: &SyntaxNode,
+    binder: &SyntaxNode,
+    new_name: &str,
+) -> Result<Vec<TextEdit>, RenameConflict> {
+    if !matches!(tokenize(new_name)[..], [(TOKEN_IDENT, name)] if name == new_name) {
+        return Err(RenameConflict::InvalidName);
+    }
+    let resolver = Resolver::new(root);
+    let binding = resolver
+        .binding_at(binder)
+        .or_else(|| resolver.definition(&ast::Ident::cast(binder.clone())?))
+        .ok_or(RenameConflict::NotABinding)?;
+    if binding.name == new_name {
+        return Ok(Vec::new());
+    }
+
+    check_conflicts(&resolver, binding, new_name)?;
+
+    let mut edits = Vec::new();
+    for node in definitions(binding) {
+        if node.parent().is_some_and(|parent| parent.kind() == NODE_INHERIT) {
+            return Err(RenameConflict::Inherited(node.text_range()));
+        }
+        edits.push(TextEdit::replace(node.text_range(), new_name));
+    }
+    for reference in resolver.references(binding.id) {
+        let node = reference.syntax();
+        if node.parent().is_some_and(|parent| parent.kind() == NODE_INHERIT) {
+            return Err(RenameConflict::Inherited(node.text_range()));
+        }
+        edits.push(TextEdit::replace(node.text_range(), new_name));
+    }
+    Ok(edits)
+}
+
+fn check_conflicts(
+    resolver: &Resolver,
+    binding: &Binding,
+    new_name: &str,
+) -> Result<(), RenameConflict> {
+    let is_inside = |node: &SyntaxNode, scope: &SyntaxNode| {
+        node != scope && node.ancestors().any(|ancestor| &ancestor == scope)
+    };
+
+    for other in resolver.bindings().filter(|other| other.name == new_name) {
+        if other.scope == binding.scope {
+            return Err(RenameConflict::AlreadyDefined(other.node.text_range()));
+        }
+        if !is_inside(&other.scope, &binding.scope) {
+            continue;
+        }
+        let shadowed = resolver
+            .references(binding.id)
+            .iter()
+            .find(|reference| is_inside(reference.syntax(), &other.scope));
+        if let Some(reference) = shadowed {
+            return Err(RenameConflict::Shadowed(reference.syntax().text_range()));
+        }
+    }
+
+    for (ident, resolution) in resolver.resolutions() {
+        if ident.to_string() != new_name || !is_inside(ident.syntax(), &binding.scope) {
+            continue;
+        }
+        let captured = match resolution {
+            // Bindings between the renamed one and the identifier still win
+            Resolution::Binding(id) => !is_inside(&resolver.binding(*id).scope, &binding.scope),
+            Resolution::With(_) | Resolution::Global | Resolution::Undefined => true,
+        };
+        if captured {
+            return Err(RenameConflict::Captured(ident.syntax().text_range()));
+        }
+    }
+    Ok(())
+}
+
+/// All nodes defining a binding. An attribute can be defined multiple times,
+/// like `a` in `let a.b = 1; a.c = 2; in a`.
+fn definitions(binding: &Binding) -> Vec<SyntaxNode> {
+    let is_attr = matches!(
+        binding.kind,
+        BindingKind::LetIn | BindingKind::LegacyLet | BindingKind::RecAttrSet
+    );
+    if binding.inherited || !is_attr {
+        return vec![binding.node.clone()];
+    }
+    binding
+        .scope
+        .children()
+        .filter_map(ast::AttrpathValue::cast)
+        .filter_map(|entry| entry.attrpath()?.attrs().next())
+        .filter(|attr| attr_name(attr).as_deref() == Some(binding.name.as_str()))
+        .map(|attr| attr.syntax().clone())
+        .collect()
+}
+
+/// The text of an expression to put in the place of an identifier or an
+/// argument, in parentheses unless it is atomic, like `(g 2)` for `g 2`
+fn argument_text(expr: ast::Expr) -> String {
+    match strip_parens(expr) {
+        expr @ (ast::Expr::Ident(_)
+        | ast::Expr::Literal(_)
+        | ast::Expr::Str(_)
+        | ast::Expr::Path(_)
+        | ast::Expr::List(_)
+        | ast::Expr::AttrSet(_)
+        | ast::Expr::Select(_)) => expr.syntax().to_string(),
+        expr => format!("({})", expr.syntax()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rewrite::apply_edits, Root};
+
+    fn rename_at(src: &str, offset: u32, new_name: &str) -> Result<String, RenameConflict> {
+        let root = Root::parse(src).ok().unwrap();
+        let token = root.syntax().token_at_offset(offset.into()).right_biased().unwrap();
+        let edits = rename(root.syntax(), &token.parent().unwrap(), new_name)?;
+        Ok(apply_edits(src, &edits))
+    }
+
+    #[test]
+    fn renames() {
+        let src = "let a.b = 1; a.c = x: a; in a";
+        assert_eq!(rename_at(src, 4, "y").unwrap(), "let y.b = 1; y.c = x: y; in y");
+        assert_eq!(rename_at(src, 28, "y").unwrap(), "let y.b = 1; y.c = x: y; in y");
+        assert_eq!(rename_at("{ a ? 1 }@args: a", 2, "b").unwrap(), "{ b ? 1 }@args: b");
+        assert_eq!(
+            rename_at("rec { \"a\" = 1; b = a; }", 6, "c").unwrap(),
+            "rec { c = 1; b = c; }"
+        );
+    }
+
+    #[test]
+    fn conflicts() {
+        assert_eq!(rename_at("a: a", 0, "let"), Err(RenameConflict::InvalidName));
+        assert_eq!(rename_at("a: a", 2, "b c"), Err(RenameConflict::InvalidName));
+        assert_eq!(rename_at("1", 0, "b"), Err(RenameConflict::NotABinding));
+        assert_eq!(
+            rename_at("let a = 1; b = 2; in a", 4, "b"),
+            Err(RenameConflict::AlreadyDefined(TextRange::new(11.into(), 12.into())))
+        );
+        assert_eq!(
+            rename_at("a: b: a", 0, "b"),
+            Err(RenameConflict::Shadowed(TextRange::new(6.into(), 7.into())))
+        );
+        assert_eq!(
+            rename_at("x: a: x", 3, "x"),
+            Err(RenameConflict::Captured(TextRange::new(6.into(), 7.into())))
+        );
+        assert_eq!(
+            rename_at("with pkgs; a: hello", 11, "hello"),
+            Err(RenameConflict::Captured(TextRange::new(14.into(), 19.into())))
+        );
+        assert_eq!(
+            rename_at("a: { inherit a; }", 0, "b"),
+            Err(RenameConflict::Inherited(TextRange::new(13.into(), 14.into())))
+        );
+        // shadowing bindings between the two are fine
+        assert_eq!(rename_at("a: c: c", 0, "c").unwrap(), "c: c: c");
+    }
+}
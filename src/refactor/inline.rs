@@ -0,0 +1,236 @@
+use std::fmt;
+
+use rowan::{ast::AstNode, TextRange};
+
+use crate::{
+    analysis::{attr_name, static_str, strip_parens, BindingKind, Resolution, Resolver},
+    ast::{self, HasEntry},
+    rewrite::{layout::SourceText, remove_leading_comment, TextEdit},
+    SyntaxNode,
+};
+
+use super::argument_text;
+
+/// The reason inlining a binding was refused
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InlineConflict {
+    /// The node is neither a `let` binding nor a reference to one
+    NotABinding,
+    /// The binding is defined in a way which can't be inlined, at the given
+    /// range, like with `inherit` or a dotted attrpath like `a.b = 1;`
+    Unsupported(TextRange),
+    /// The value of the binding refers to the binding itself at the given
+    /// range
+    Recursive(TextRange),
+    /// The binding is used with the `inherit` shorthand at the given range,
+    /// which would need to be expanded into `a = ...;`
+    Inherited(TextRange),
+    /// The identifier in the value at the given range would refer to
+    /// something else at one of the references, because a binding or a `with`
+    /// in between shadows it
+    Captured(TextRange),
+    /// The value isn't trivial and would be copied into this many places,
+    /// which needs to be allowed explicitly
+    Duplicated(usize),
+}
+
+impl fmt::Display for InlineConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (message, range) = match self {
+            InlineConflict::NotABinding => return f.write_str("not a let binding"),
+            InlineConflict::Duplicated(count) => {
+                return write!(f, "value would be duplicated into {} places", count)
+            }
+            InlineConflict::Unsupported(range) => ("binding can't be inlined", range),
+            InlineConflict::Recursive(range) => ("binding is recursive", range),
+            InlineConflict::Inherited(range) => ("binding is inherited", range),
+            InlineConflict::Captured(range) => ("identifier would be captured", range),
+        };
+        write!(f, "{} at {}..{}", message, usize::from(range.start()), usize::from(range.end()))
+    }
+}
+
+impl std::error::Error for InlineConflict {}
+
+/// Replace all references to a `let` binding with its value and remove the
+/// binding, removing the whole `let` if it was the only binding.
+///
+/// `binder` is either the node defining the binding or an identifier
+/// referring to it, and `root` is usually the root of the file. Values are put
+/// in parentheses unless they are atomic. The change is refused if any
+/// identifier in the value would refer to something else where it is
+/// inlined. Values other than identifiers, literals, paths, plain strings and
+/// selections from them are only copied into several places if `duplicate`
+/// is set.
+pub fn inline(
+    root: &SyntaxNode,
+    binder: &SyntaxNode,
+    duplicate: bool,
+) -> Result<Vec<TextEdit>, InlineConflict> {
+    let resolver = Resolver::new(root);
+    let binding = resolver
+        .binding_at(binder)
+        .or_else(|| resolver.definition(&ast::Ident::cast(binder.clone())?))
+        .filter(|binding| binding.kind == BindingKind::LetIn)
+        .ok_or(InlineConflict::NotABinding)?;
+    if binding.inherited {
+        return Err(InlineConflict::Unsupported(binding.node.text_range()));
+    }
+    let let_in = ast::LetIn::cast(binding.scope.clone()).ok_or(InlineConflict::NotABinding)?;
+    let mut definitions = let_in.attrpath_values().filter(|entry| {
+        let first = entry.attrpath().and_then(|attrpath| attrpath.attrs().next());
+        first.is_some_and(|attr| attr_name(&attr).as_deref() == Some(binding.name.as_str()))
+    });
+    let entry = definitions.next().ok_or(InlineConflict::NotABinding)?;
+    let attrpath = entry.attrpath().ok_or(InlineConflict::NotABinding)?;
+    if let Some(other) = definitions.next() {
+        return Err(InlineConflict::Unsupported(other.syntax().text_range()));
+    }
+    if attrpath.attrs().count() != 1 {
+        return Err(InlineConflict::Unsupported(attrpath.syntax().text_range()));
+    }
+    let value = entry.value().ok_or(InlineConflict::NotABinding)?;
+
+    let references = resolver.references(binding.id);
+    for reference in references {
+        let range = reference.syntax().text_range();
+        if is_inside(reference.syntax(), value.syntax()) {
+            return Err(InlineConflict::Recursive(range));
+        }
+        if reference.syntax().parent().is_some_and(|parent| ast::Inherit::can_cast(parent.kind())) {
+            return Err(InlineConflict::Inherited(range));
+        }
+    }
+    if references.len() > 1 && !duplicate && !is_trivial(&value) {
+        return Err(InlineConflict::Duplicated(references.len()));
+    }
+    check_captures(&resolver, &value, references)?;
+
+    let text = argument_text(value);
+    let mut edits: Vec<TextEdit> = references
+        .iter()
+        .map(|reference| TextEdit::replace(reference.syntax().text_range(), text.clone()))
+        .collect();
+    let source = SourceText::new(root);
+    match (let_in.entries().count(), let_in.let_token(), let_in.body()) {
+        (1, Some(let_token), Some(body)) => edits.push(TextEdit::delete(TextRange::new(
+            let_token.text_range().start(),
+            body.syntax().text_range().start(),
+        ))),
+        _ => {
+            if source.starts_line(entry.syntax().text_range().start()) {
+                edits.extend(remove_leading_comment(entry.syntax()));
+            }
+            edits.push(source.remove_node(entry.syntax()));
+        }
+    }
+    Ok(edits)
+}
+
+fn is_inside(node: &SyntaxNode, scope: &SyntaxNode) -> bool {
+    node.ancestors().any(|ancestor| &ancestor == scope)
+}
+
+/// Whether a value is cheap and short enough to be copied
+fn is_trivial(value: &ast::Expr) -> bool {
+    match strip_parens(value.clone()) {
+        ast::Expr::Ident(_) | ast::Expr::Literal(_) | ast::Expr::Path(_) => true,
+        ast::Expr::Str(s) => static_str(&s).is_some(),
+        ast::Expr::Select(select) => {
+            select.or_token().is_none() && select.expr().is_some_and(|expr| is_trivial(&expr))
+        }
+        _ => false,
+    }
+}
+
+/// Check that every identifier in the value refers to the same thing at every
+/// reference
+fn check_captures(
+    resolver: &Resolver,
+    value: &ast::Expr,
+    references: &[ast::Ident],
+) -> Result<(), InlineConflict> {
+    for ident in value.syntax().descendants().filter_map(ast::Ident::cast) {
+        let Some(resolution) = resolver.resolve(&ident) else { continue };
+        let name = ident.to_string();
+        for reference in references {
+            let shadowed = resolver.bindings().any(|other| {
+                other.name == name
+                    && is_inside(reference.syntax(), &other.scope)
+                    && !is_inside(ident.syntax(), &other.scope)
+            });
+            // Names which aren't bound lexically may come from a `with`
+            let in_with = !matches!(resolution, Resolution::Binding(_))
+                && reference.syntax().ancestors().filter_map(ast::With::cast).any(|with| {
+                    with.body().is_some_and(|body| is_inside(reference.syntax(), body.syntax()))
+                        && !is_inside(ident.syntax(), with.syntax())
+                });
+            if shadowed || in_with {
+                return Err(InlineConflict::Captured(ident.syntax().text_range()));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rewrite::apply_edits, Root};
+
+    fn inline_at(src: &str, offset: u32, duplicate: bool) -> Result<String, InlineConflict> {
+        let root = Root::parse(src).ok().unwrap();
+        let token = root.syntax().token_at_offset(offset.into()).right_biased().unwrap();
+        let edits = inline(root.syntax(), &token.parent().unwrap(), duplicate)?;
+        Ok(apply_edits(src, &edits))
+    }
+
+    #[test]
+    fn inlines() {
+        assert_eq!(
+            inline_at("let a = 1; b = 2; in a + b", 4, false).unwrap(),
+            "let b = 2; in 1 + b"
+        );
+        assert_eq!(inline_at("let a = f 1; in a", 16, false).unwrap(), "(f 1)");
+        assert_eq!(inline_at("let a = x + 1; in a * a", 4, true).unwrap(), "(x + 1) * (x + 1)");
+        assert_eq!(inline_at("let a = x.y; in [ a a ]", 4, false).unwrap(), "[ x.y x.y ]");
+
+        let src = "let\n  # the name\n  name = \"foo\";\n  version = 1;\nin\n{ inherit version; pname = name; }";
+        assert_eq!(
+            inline_at(src, 19, false).unwrap(),
+            "let\n  version = 1;\nin\n{ inherit version; pname = \"foo\"; }"
+        );
+    }
+
+    #[test]
+    fn conflicts() {
+        assert_eq!(inline_at("a: a", 0, false), Err(InlineConflict::NotABinding));
+        assert_eq!(
+            inline_at("let a = x + 1; in a * a", 4, false),
+            Err(InlineConflict::Duplicated(2))
+        );
+        assert_eq!(
+            inline_at("let a.b = 1; in a", 4, false),
+            Err(InlineConflict::Unsupported(TextRange::new(4.into(), 7.into())))
+        );
+        assert_eq!(
+            inline_at("let a = [ a ]; in a", 4, false),
+            Err(InlineConflict::Recursive(TextRange::new(10.into(), 11.into())))
+        );
+        assert_eq!(
+            inline_at("let a = 1; in { inherit a; }", 4, false),
+            Err(InlineConflict::Inherited(TextRange::new(24.into(), 25.into())))
+        );
+        assert_eq!(
+            inline_at("x: let a = x; in x: a", 7, false),
+            Err(InlineConflict::Captured(TextRange::new(11.into(), 12.into())))
+        );
+        assert_eq!(
+            inline_at("let a = hello; in with pkgs; a", 4, false),
+            Err(InlineConflict::Captured(TextRange::new(8.into(), 13.into())))
+        );
+        // Bindings of the same `let` stay visible
+        assert_eq!(inline_at("let a = b; b = 1; in a", 4, false).unwrap(), "let b = 1; in b");
+    }
+}
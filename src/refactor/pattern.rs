@@ -0,0 +1,517 @@
+use std::fmt;
+
+use rowan::{ast::AstNode, TextRange, TextSize};
+
+use crate::{
+    analysis::{attr_name, attrpath_names, strip_parens, Resolution, Resolver},
+    ast::{self, HasEntry},
+    rewrite::{layout::SourceText, TextEdit},
+    template::is_ident,
+    SyntaxKind::*,
+    SyntaxNode, SyntaxToken,
+};
+
+use super::argument_text;
+
+/// The reason a change of a lambda's parameter was refused
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PatternConflict {
+    /// The lambda doesn't have the kind of parameter the change applies to,
+    /// like a pattern for adding a formal
+    WrongParam,
+    /// The new name isn't a valid identifier
+    InvalidName,
+    /// The formal to remove isn't in the pattern
+    NotFound,
+    /// The name is already bound by the pattern, at the given range
+    AlreadyDefined(TextRange),
+    /// The identifier at the given range, which is called like the new
+    /// formal, would resolve to it instead of its current binding
+    Captured(TextRange),
+    /// The formal to remove is still used at the given range
+    Used(TextRange),
+    /// The call at the given range passes the formal to remove, which the
+    /// function would no longer accept
+    Passed(TextRange),
+    /// The call at the given range doesn't pass the new formal, which has no
+    /// default
+    NotPassed(TextRange),
+    /// The function is used at the given range without being called, or isn't
+    /// bound to a name in the file, so not all of its calls can be changed
+    Escapes(TextRange),
+    /// The argument of the call at the given range can't be converted
+    UnsupportedCall(TextRange),
+}
+
+impl fmt::Display for PatternConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (message, range) = match self {
+            PatternConflict::WrongParam => return f.write_str("unsupported kind of parameter"),
+            PatternConflict::InvalidName => return f.write_str("invalid identifier"),
+            PatternConflict::NotFound => return f.write_str("no such formal"),
+            PatternConflict::AlreadyDefined(range) => ("name is already defined", range),
+            PatternConflict::Captured(range) => ("identifier would be captured", range),
+            PatternConflict::Used(range) => ("formal is still used", range),
+            PatternConflict::Passed(range) => ("formal is passed", range),
+            PatternConflict::NotPassed(range) => ("formal isn't passed", range),
+            PatternConflict::Escapes(range) => ("function is used without calling it", range),
+            PatternConflict::UnsupportedCall(range) => ("argument can't be converted", range),
+        };
+        write!(f, "{} at {}..{}", message, usize::from(range.start()), usize::from(range.end()))
+    }
+}
+
+impl std::error::Error for PatternConflict {}
+
+/// Add a formal to the pattern of a lambda, like `c ? null` to `{ a, b }:`,
+/// following the layout of the pattern. `default` is the Nix source text of
+/// the default, if any.
+///
+/// `root` is usually the root of the file. The change is refused if it would
+/// change what an identifier in the lambda refers to, or if a call in the file
+/// doesn't pass a formal without a default.
+pub fn add_formal(
+    root: &SyntaxNode,
+    lambda: &ast::Lambda,
+    name: &str,
+    default: Option<&str>,
+) -> Result<Vec<TextEdit>, PatternConflict> {
+    let Some(ast::Param::Pattern(pattern)) = lambda.param() else {
+        return Err(PatternConflict::WrongParam);
+    };
+    if !is_ident(name) {
+        return Err(PatternConflict::InvalidName);
+    }
+    let bind = pattern.pat_bind().and_then(|bind| bind.ident());
+    let mut defined = pattern.pat_entries().filter_map(|entry| entry.ident()).chain(bind);
+    if let Some(ident) = defined.find(|ident| ident.to_string() == name) {
+        return Err(PatternConflict::AlreadyDefined(ident.syntax().text_range()));
+    }
+
+    let resolver = Resolver::new(root);
+    for (ident, resolution) in resolver.resolutions() {
+        if ident.to_string() != name || !is_inside(ident.syntax(), lambda.syntax()) {
+            continue;
+        }
+        let captured = match resolution {
+            Resolution::Binding(id) => !is_inside(&resolver.binding(*id).scope, lambda.syntax()),
+            Resolution::With(_) | Resolution::Global | Resolution::Undefined => true,
+        };
+        if captured {
+            return Err(PatternConflict::Captured(ident.syntax().text_range()));
+        }
+    }
+    if default.is_none() {
+        for call in known_calls(&resolver, lambda) {
+            if !passes(&call, name) {
+                return Err(PatternConflict::NotPassed(call.syntax().text_range()));
+            }
+        }
+    }
+
+    let formal = match default {
+        Some(default) => format!("{} ? {}", name, default),
+        None => name.to_string(),
+    };
+    let text = SourceText::new(root);
+    let Some(last) = pattern.pat_entries().last() else {
+        if let Some(ellipsis) = pattern.ellipsis_token() {
+            let edit = TextEdit::insert(ellipsis.text_range().start(), format!("{}, ", formal));
+            return Ok(vec![edit]);
+        }
+        let open = token(pattern.syntax(), TOKEN_L_BRACE);
+        let close = token(pattern.syntax(), TOKEN_R_BRACE);
+        let (Some(open), Some(close)) = (open, close) else { return Ok(Vec::new()) };
+        let inner = TextRange::new(open.text_range().end(), close.text_range().start());
+        return Ok(vec![TextEdit::replace(inner, format!(" {} ", formal))]);
+    };
+
+    let start = last.syntax().text_range().start();
+    let comma_before = significant(last.syntax().first_token(), SyntaxToken::prev_token)
+        .filter(|token| token.kind() == TOKEN_COMMA);
+    let comma_after = significant(last.syntax().last_token(), SyntaxToken::next_token)
+        .filter(|token| token.kind() == TOKEN_COMMA);
+    let edit = match comma_before {
+        // Leading commas, like `{ lib\n, stdenv\n}`
+        Some(comma) if !text.starts_line(start) && text.starts_line(comma.text_range().start()) => {
+            let indent = text.indent(comma.text_range().start());
+            let end = text.end_of_line_comment(last.syntax());
+            TextEdit::insert(end, format!("\n{}, {}", indent, formal))
+        }
+        _ if text.starts_line(start) => {
+            let indent = text.indent(start);
+            match comma_after {
+                Some(comma) => TextEdit::insert(
+                    text.end_of_line_comment_after(&comma),
+                    format!("\n{}{},", indent, formal),
+                ),
+                None => TextEdit::insert(
+                    last.syntax().text_range().end(),
+                    format!(",\n{}{}", indent, formal),
+                ),
+            }
+        }
+        _ => TextEdit::insert(last.syntax().text_range().end(), format!(", {}", formal)),
+    };
+    Ok(vec![edit])
+}
+
+/// Remove a formal from the pattern of a lambda, together with its comma.
+///
+/// The change is refused if the formal is still used, or if a call in the
+/// file passes it to a pattern without `...`.
+pub fn remove_formal(
+    root: &SyntaxNode,
+    lambda: &ast::Lambda,
+    name: &str,
+) -> Result<Vec<TextEdit>, PatternConflict> {
+    let Some(ast::Param::Pattern(pattern)) = lambda.param() else {
+        return Err(PatternConflict::WrongParam);
+    };
+    let entry = pattern
+        .pat_entries()
+        .find(|entry| entry.ident().is_some_and(|ident| ident.to_string() == name))
+        .ok_or(PatternConflict::NotFound)?;
+
+    let resolver = Resolver::new(root);
+    let ident = entry.ident().ok_or(PatternConflict::NotFound)?;
+    if let Some(binding) = resolver.binding_at(ident.syntax()) {
+        if let Some(reference) = resolver.references(binding.id).first() {
+            return Err(PatternConflict::Used(reference.syntax().text_range()));
+        }
+    }
+    if pattern.ellipsis_token().is_none() {
+        if let Some(call) =
+            known_calls(&resolver, lambda).into_iter().find(|call| passes(call, name))
+        {
+            return Err(PatternConflict::Passed(call.syntax().text_range()));
+        }
+    }
+
+    let text = SourceText::new(root);
+    let range = entry.syntax().text_range();
+    let comma_before = significant(entry.syntax().first_token(), SyntaxToken::prev_token)
+        .filter(|token| token.kind() == TOKEN_COMMA);
+    let comma_after = significant(entry.syntax().last_token(), SyntaxToken::next_token)
+        .filter(|token| token.kind() == TOKEN_COMMA);
+
+    // A formal on a line of its own goes with its line and its comma, like
+    // `  b, # about b\n` or `, b\n`
+    let first = match &comma_before {
+        Some(comma) if text.starts_line(comma.text_range().start()) => comma.text_range().start(),
+        _ => range.start(),
+    };
+    let line_end = match &comma_after {
+        Some(comma) if !text.starts_line(comma.text_range().start()) => {
+            text.end_of_line_comment_after(comma)
+        }
+        _ => text.end_of_line_comment(entry.syntax()),
+    };
+    let rest = &text[usize::from(line_end)..];
+    if text.starts_line(first) && rest.trim_start_matches([' ', '\t']).starts_with('\n') {
+        let newline = rest.find('\n').unwrap_or_default() + 1;
+        let end = line_end + TextSize::from(newline as u32);
+        return Ok(vec![TextEdit::delete(TextRange::new(text.line_start(first), end))]);
+    }
+
+    let removed = match (comma_before, comma_after) {
+        (_, Some(comma)) => {
+            let mut end = comma.text_range().end();
+            if let Some(next) = comma.next_token() {
+                if next.kind() == TOKEN_WHITESPACE && !next.text().contains('\n') {
+                    end = next.text_range().end();
+                }
+            }
+            TextRange::new(range.start(), end)
+        }
+        (Some(comma), None) => TextRange::new(comma.text_range().start(), range.end()),
+        (None, None) => return Ok(vec![text.remove_node(entry.syntax())]),
+    };
+    Ok(vec![TextEdit::delete(removed)])
+}
+
+/// Turn a parameter like `x:` into a pattern like `{ x }:`, changing all calls
+/// like `f 1` in the file into `f { x = 1; }`.
+///
+/// The change is refused unless the function is bound to a name in the file
+/// and only ever called.
+pub fn ident_to_pattern(
+    root: &SyntaxNode,
+    lambda: &ast::Lambda,
+) -> Result<Vec<TextEdit>, PatternConflict> {
+    let Some(ast::Param::IdentParam(param)) = lambda.param() else {
+        return Err(PatternConflict::WrongParam);
+    };
+    let name = param.ident().ok_or(PatternConflict::WrongParam)?.to_string();
+    let resolver = Resolver::new(root);
+    let mut edits = vec![TextEdit::replace(param.syntax().text_range(), format!("{{ {} }}", name))];
+    for call in calls(&resolver, lambda)? {
+        let argument =
+            call.argument().ok_or(PatternConflict::UnsupportedCall(call.syntax().text_range()))?;
+        let value = strip_parens(argument.clone());
+        edits.push(TextEdit::replace(
+            argument.syntax().text_range(),
+            format!("{{ {} = {}; }}", name, value.syntax()),
+        ));
+    }
+    Ok(edits)
+}
+
+/// Turn a pattern with a single formal like `{ x }:` into a parameter like
+/// `x:`, changing all calls like `f { x = 1; }` in the file into `f 1`.
+///
+/// The change is refused unless the pattern has no `...` and no `@`, and the
+/// function is bound to a name in the file and only ever called with an
+/// attribute set defining nothing but the formal.
+pub fn pattern_to_ident(
+    root: &SyntaxNode,
+    lambda: &ast::Lambda,
+) -> Result<Vec<TextEdit>, PatternConflict> {
+    let Some(ast::Param::Pattern(pattern)) = lambda.param() else {
+        return Err(PatternConflict::WrongParam);
+    };
+    let entries: Vec<_> = pattern.pat_entries().collect();
+    let ([entry], None, None) = (&entries[..], pattern.ellipsis_token(), pattern.pat_bind()) else {
+        return Err(PatternConflict::WrongParam);
+    };
+    let name = entry.ident().ok_or(PatternConflict::WrongParam)?.to_string();
+    let resolver = Resolver::new(root);
+    let mut edits = vec![TextEdit::replace(pattern.syntax().text_range(), name.clone())];
+    for call in calls(&resolver, lambda)? {
+        let unsupported = PatternConflict::UnsupportedCall(call.syntax().text_range());
+        let argument = call.argument().ok_or(unsupported.clone())?;
+        let value = match strip_parens(argument.clone()) {
+            ast::Expr::AttrSet(set) if set.rec_token().is_none() => {
+                single_value(&set, &name).ok_or(unsupported)?
+            }
+            _ => return Err(unsupported),
+        };
+        let value = argument_text(value);
+        edits.push(TextEdit::replace(argument.syntax().text_range(), value));
+    }
+    Ok(edits)
+}
+
+/// The value of the only attribute of a set if it is called `name`, like `1`
+/// in `{ x = 1; }`. `{ inherit x; }` gives the identifier `x`.
+fn single_value(set: &ast::AttrSet, name: &str) -> Option<ast::Expr> {
+    let entries: Vec<_> = set.entries().collect();
+    match &entries[..] {
+        [ast::Entry::AttrpathValue(entry)] => {
+            (attrpath_names(&entry.attrpath()?)? == [name]).then(|| entry.value())?
+        }
+        [ast::Entry::Inherit(inherit)] if inherit.from().is_none() => {
+            let attrs: Vec<_> = inherit.attrs().collect();
+            let [ast::Attr::Ident(ident)] = &attrs[..] else { return None };
+            (ident.to_string() == name).then(|| ast::Expr::Ident(ident.clone()))
+        }
+        _ => None,
+    }
+}
+
+/// Whether a call passes an attribute set defining `name`. Calls with
+/// anything else are assumed to pass it.
+fn passes(call: &ast::Apply, name: &str) -> bool {
+    let Some(ast::Expr::AttrSet(set)) = call.argument().map(strip_parens) else { return true };
+    let defined = set
+        .attrpath_values()
+        .filter_map(|entry| entry.attrpath()?.attrs().next())
+        .chain(set.inherits().flat_map(|inherit| inherit.attrs()));
+    defined.filter_map(|attr| attr_name(&attr)).any(|attr| attr == name)
+}
+
+/// All calls of a lambda in the file, or the range of a use of it which isn't
+/// a call
+fn calls(resolver: &Resolver, lambda: &ast::Lambda) -> Result<Vec<ast::Apply>, PatternConflict> {
+    let node = outer_parens(lambda.syntax());
+    if let Some(call) = called_by(&node) {
+        return Ok(vec![call]);
+    }
+    let escapes = PatternConflict::Escapes(lambda.syntax().text_range());
+    let entry = node.parent().and_then(ast::AttrpathValue::cast).ok_or(escapes.clone())?;
+    let attrs: Vec<_> =
+        entry.attrpath().into_iter().flat_map(|attrpath| attrpath.attrs()).collect();
+    let [attr] = &attrs[..] else { return Err(escapes) };
+    let binding = resolver.binding_at(attr.syntax()).ok_or(escapes)?;
+    resolver
+        .references(binding.id)
+        .iter()
+        .map(|reference| {
+            called_by(&outer_parens(reference.syntax()))
+                .ok_or(PatternConflict::Escapes(reference.syntax().text_range()))
+        })
+        .collect()
+}
+
+/// The calls of a lambda in the file which can be found, ignoring any other
+/// uses
+fn known_calls(resolver: &Resolver, lambda: &ast::Lambda) -> Vec<ast::Apply> {
+    let node = outer_parens(lambda.syntax());
+    if let Some(call) = called_by(&node) {
+        return vec![call];
+    }
+    let Some(entry) = node.parent().and_then(ast::AttrpathValue::cast) else { return Vec::new() };
+    let attrs: Vec<_> =
+        entry.attrpath().into_iter().flat_map(|attrpath| attrpath.attrs()).collect();
+    let [attr] = &attrs[..] else { return Vec::new() };
+    let Some(binding) = resolver.binding_at(attr.syntax()) else { return Vec::new() };
+    resolver
+        .references(binding.id)
+        .iter()
+        .filter_map(|reference| called_by(&outer_parens(reference.syntax())))
+        .collect()
+}
+
+fn outer_parens(node: &SyntaxNode) -> SyntaxNode {
+    let mut node = node.clone();
+    while let Some(parent) = node.parent().filter(|parent| parent.kind() == NODE_PAREN) {
+        node = parent;
+    }
+    node
+}
+
+/// The call with a node as the function, like `f 1` for `f`
+fn called_by(node: &SyntaxNode) -> Option<ast::Apply> {
+    let apply = node.parent().and_then(ast::Apply::cast)?;
+    (apply.lambda()?.syntax() == node).then_some(apply)
+}
+
+fn is_inside(node: &SyntaxNode, scope: &SyntaxNode) -> bool {
+    node.ancestors().any(|ancestor| &ancestor == scope)
+}
+
+fn token(node: &SyntaxNode, kind: crate::SyntaxKind) -> Option<SyntaxToken> {
+    node.children_with_tokens()
+        .filter_map(|element| element.into_token())
+        .find(|token| token.kind() == kind)
+}
+
+/// The next token in a direction which isn't whitespace or a comment
+fn significant(
+    token: Option<SyntaxToken>,
+    step: fn(&SyntaxToken) -> Option<SyntaxToken>,
+) -> Option<SyntaxToken> {
+    let mut token = step(&token?);
+    while let Some(current) = token {
+        if !matches!(current.kind(), TOKEN_WHITESPACE | TOKEN_COMMENT) {
+            return Some(current);
+        }
+        token = step(&current);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rewrite::apply_edits, Root};
+
+    fn refactor(
+        src: &str,
+        f: impl FnOnce(&SyntaxNode, &ast::Lambda) -> Result<Vec<TextEdit>, PatternConflict>,
+    ) -> Result<String, PatternConflict> {
+        let root = Root::parse(src).ok().unwrap();
+        let lambda = root.syntax().descendants().find_map(ast::Lambda::cast).unwrap();
+        f(root.syntax(), &lambda).map(|edits| apply_edits(src, &edits))
+    }
+
+    #[test]
+    fn add() {
+        let add = |src, name, default| refactor(src, |root, l| add_formal(root, l, name, default));
+        assert_eq!(add("{ a, b }: a", "c", None).unwrap(), "{ a, b, c }: a");
+        assert_eq!(add("{ a, ... }: a", "c", Some("null")).unwrap(), "{ a, c ? null, ... }: a");
+        assert_eq!(add("{ ... }: 1", "c", None).unwrap(), "{ c, ... }: 1");
+        assert_eq!(add("{ }: 1", "c", None).unwrap(), "{ c }: 1");
+        assert_eq!(
+            add("{ lib\n, stdenv # the stdenv\n}: 1", "zlib", None).unwrap(),
+            "{ lib\n, stdenv # the stdenv\n, zlib\n}: 1"
+        );
+        assert_eq!(
+            add("{\n  lib,\n  stdenv, # the stdenv\n  ...\n}: 1", "zlib", None).unwrap(),
+            "{\n  lib,\n  stdenv, # the stdenv\n  zlib,\n  ...\n}: 1"
+        );
+        assert_eq!(add("{\n  lib\n}: 1", "zlib", None).unwrap(), "{\n  lib,\n  zlib\n}: 1");
+
+        assert_eq!(add("x: x", "c", None), Err(PatternConflict::WrongParam));
+        assert_eq!(add("{ a }: a", "let", None), Err(PatternConflict::InvalidName));
+        assert_eq!(
+            add("{ a }@args: a", "args", None),
+            Err(PatternConflict::AlreadyDefined(TextRange::new(6.into(), 10.into())))
+        );
+        assert_eq!(
+            add("{ a }: a + c", "c", None),
+            Err(PatternConflict::Captured(TextRange::new(11.into(), 12.into())))
+        );
+        assert_eq!(
+            add("{ a }: let c = 1; in a + c", "c", None).unwrap(),
+            "{ a, c }: let c = 1; in a + c"
+        );
+        assert_eq!(
+            add("let f = { a }: a; in f { a = 1; }", "b", None),
+            Err(PatternConflict::NotPassed(TextRange::new(21.into(), 33.into())))
+        );
+    }
+
+    #[test]
+    fn remove() {
+        let remove = |src, name| refactor(src, |root, l| remove_formal(root, l, name));
+        assert_eq!(remove("{ a, b, c }: a", "b").unwrap(), "{ a, c }: a");
+        assert_eq!(remove("{ a, b, c }: a", "c").unwrap(), "{ a, b }: a");
+        assert_eq!(remove("{ b, ... }: 1", "b").unwrap(), "{ ... }: 1");
+        assert_eq!(remove("{ b }: 1", "b").unwrap(), "{ }: 1");
+        assert_eq!(
+            remove("{ lib\n, stdenv # the stdenv\n, zlib\n}: lib", "stdenv").unwrap(),
+            "{ lib\n, zlib\n}: lib"
+        );
+        assert_eq!(
+            remove("{\n  lib,\n  stdenv, # the stdenv\n  zlib,\n}: lib", "stdenv").unwrap(),
+            "{\n  lib,\n  zlib,\n}: lib"
+        );
+
+        assert_eq!(remove("{ a }: a", "b"), Err(PatternConflict::NotFound));
+        assert_eq!(
+            remove("{ a }: a", "a"),
+            Err(PatternConflict::Used(TextRange::new(7.into(), 8.into())))
+        );
+        assert_eq!(
+            remove("let f = { a, b }: a; in f { a = 1; b = 2; }", "b"),
+            Err(PatternConflict::Passed(TextRange::new(24.into(), 43.into())))
+        );
+        assert_eq!(
+            remove("let f = { a, b, ... }: a; in f { a = 1; b = 2; }", "b").unwrap(),
+            "let f = { a, ... }: a; in f { a = 1; b = 2; }"
+        );
+    }
+
+    #[test]
+    fn convert() {
+        assert_eq!(
+            refactor("let f = x: x + 1; in [ (f 1) (f (g 2)) ]", ident_to_pattern).unwrap(),
+            "let f = { x }: x + 1; in [ (f { x = 1; }) (f { x = g 2; }) ]"
+        );
+        assert_eq!(
+            refactor(
+                "let f = { x }: x + 1; in [ (f { x = 1; }) (f { x = g 2; }) (f { inherit x; }) ]",
+                pattern_to_ident
+            )
+            .unwrap(),
+            "let f = x: x + 1; in [ (f 1) (f (g 2)) (f x) ]"
+        );
+        assert_eq!(refactor("({ x }: x) { x = 1; }", pattern_to_ident).unwrap(), "(x: x) 1");
+
+        assert_eq!(
+            refactor("x: x", ident_to_pattern),
+            Err(PatternConflict::Escapes(TextRange::new(0.into(), 4.into())))
+        );
+        assert_eq!(
+            refactor("let f = x: x; in map f [ ]", ident_to_pattern),
+            Err(PatternConflict::Escapes(TextRange::new(21.into(), 22.into())))
+        );
+        assert_eq!(
+            refactor("let f = { x }: x; in f { x = 1; y = 2; }", pattern_to_ident),
+            Err(PatternConflict::UnsupportedCall(TextRange::new(21.into(), 40.into())))
+        );
+        assert_eq!(refactor("{ x, ... }: x", pattern_to_ident), Err(PatternConflict::WrongParam));
+    }
+}
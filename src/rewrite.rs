@@ -0,0 +1,157 @@
+//! Format preserving rewriting of source code by means of text edits
+
+mod attrpath;
+mod attrset;
+mod comment;
+pub(crate) mod import;
+pub(crate) mod layout;
+mod legacy_let;
+mod list;
+mod merge;
+mod with;
+
+use rowan::{ast::AstNode, TextRange, TextSize};
+
+use crate::{
+    analysis::{self, str_content_range},
+    ast,
+    value::{escape_indented, escape_string},
+    SyntaxNode,
+};
+
+pub use attrpath::{collapse_attrpaths, expand_attrpaths};
+pub(crate) use attrset::attr_key;
+pub use attrset::{attrset, AttrSetEditError, AttrSetEditor};
+pub use comment::{leading_comments, remove_leading_comment, set_leading_comment};
+pub use import::{import_target, splice_import, SpliceError};
+pub use legacy_let::{convert_legacy_let, convert_legacy_lets, LegacyLetError};
+pub use list::{list, ListEditor};
+pub use merge::{merge3, MergeConflict};
+pub use with::{eliminate_with, eliminate_withs, WithError};
+
+/// A replacement of a range in the source text. Insertions are represented by
+/// an empty range, deletions by an empty replacement.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TextEdit {
+    pub range: TextRange,
+    pub replacement: String,
+}
+
+impl TextEdit {
+    pub fn replace(range: TextRange, replacement: impl Into<String>) -> Self {
+        Self { range, replacement: replacement.into() }
+    }
+    pub fn insert(offset: TextSize, text: impl Into<String>) -> Self {
+        Self::replace(TextRange::empty(offset), text)
+    }
+    pub fn delete(range: TextRange) -> Self {
+        Self::replace(range, String::new())
+    }
+}
+
+/// Apply a set of edits to the source text they were computed for.
+///
+/// The edits may be given in any order, but must not overlap. Insertions at
+/// the same offset are applied in the order they are given.
+///
+/// # Panics
+///
+/// Panics if two edits overlap or an edit is out of bounds.
+pub fn apply_edits(source: &str, edits: &[TextEdit]) -> String {
+    let mut sorted: Vec<&TextEdit> = edits.iter().collect();
+    sorted.sort_by_key(|edit| (edit.range.start(), edit.range.end()));
+
+    let mut output = String::with_capacity(source.len());
+    let mut last = 0;
+    for edit in sorted {
+        let start = usize::from(edit.range.start());
+        assert!(start >= last, "overlapping text edits at {:?}", edit.range);
+        output.push_str(&source[last..start]);
+        output.push_str(&edit.replacement);
+        last = usize::from(edit.range.end());
+    }
+    output.push_str(&source[last..]);
+    output
+}
+
+/// Replace the contents of the string assigned to `attr_path` with `new_value`,
+/// keeping the quotes and all surrounding formatting and comments intact.
+///
+/// The path is matched against the full path of each binding, including the
+/// bindings it is nested in, looking through function calls. This means
+/// `["src", "hash"]` matches the `hash` of `src = fetchurl { hash = ...; };`.
+/// Only strings without interpolations are rewritten, `new_value` is escaped
+/// according to the kind of string it is inserted into.
+pub fn update_attr(node: &SyntaxNode, attr_path: &[&str], new_value: &str) -> Vec<TextEdit> {
+    node.descendants()
+        .filter_map(ast::AttrpathValue::cast)
+        .filter(|entry| analysis::qualified_attrpath(entry).is_some_and(|path| path == attr_path))
+        .filter_map(|entry| match analysis::strip_parens(entry.value()?) {
+            ast::Expr::Str(s) if analysis::static_str(&s).is_some() => {
+                let multiline = s.syntax().first_token()?.text() == "''";
+                let escaped =
+                    if multiline { escape_indented(new_value) } else { escape_string(new_value) };
+                Some(TextEdit::replace(str_content_range(&s)?, escaped))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Root;
+
+    #[test]
+    fn apply_in_any_order() {
+        let edits = [
+            TextEdit::replace(TextRange::new(4.into(), 5.into()), "y"),
+            TextEdit::insert(0.into(), "# hi\n"),
+            TextEdit::delete(TextRange::new(5.into(), 9.into())),
+        ];
+        assert_eq!(apply_edits("a = x + 1;", &edits), "# hi\na = y;");
+    }
+
+    #[test]
+    fn update_fetcher_hash() {
+        let src = r#"{
+  version = "1.0"; # keep me
+  src = fetchurl {
+    url = "https://example.org/foo-${version}.tar.gz";
+    hash = "sha256-old"; # and me
+  };
+  other.hash = "sha256-old";
+}"#;
+        let root = Root::parse(src).ok().unwrap();
+        let edits = update_attr(root.syntax(), &["src", "hash"], "sha256-new");
+        assert_eq!(edits.len(), 1);
+        let edits = [edits, update_attr(root.syntax(), &["version"], "2.0")].concat();
+        assert_eq!(
+            apply_edits(src, &edits),
+            r#"{
+  version = "2.0"; # keep me
+  src = fetchurl {
+    url = "https://example.org/foo-${version}.tar.gz";
+    hash = "sha256-new"; # and me
+  };
+  other.hash = "sha256-old";
+}"#
+        );
+
+        // strings with interpolations aren't touched
+        assert!(update_attr(root.syntax(), &["src", "url"], "x").is_empty());
+    }
+
+    #[test]
+    fn escapes() {
+        let src = "{ a = \"\"; b = ''''; }";
+        let root = Root::parse(src).ok().unwrap();
+        let edits = [
+            update_attr(root.syntax(), &["a"], "\"${x}\""),
+            update_attr(root.syntax(), &["b"], "''${x}"),
+        ]
+        .concat();
+        assert_eq!(apply_edits(src, &edits), r#"{ a = "\"\${x}\""; b = '''''''${x}''; }"#);
+    }
+}
@@ -0,0 +1,402 @@
+use rowan::ast::AstNode;
+
+use crate::{
+    analysis::{attr_name, strip_parens},
+    ast::{self, HasEntry},
+    SyntaxKind::*,
+    SyntaxNode,
+};
+
+use super::{
+    layout::{dedent, reindent, rewritten, SourceText},
+    leading_comments, remove_leading_comment, TextEdit,
+};
+
+/// Expand dotted attrpaths into nested attribute sets, like `a.b.c = 1;` into
+/// `a = { b = { c = 1; }; };`, in all attribute sets and `let` blocks in a
+/// tree.
+///
+/// `levels` is the number of leading attributes to split off, so with 1 the
+/// result is `a = { b.c = 1; };`. Bindings sharing their first attribute are
+/// combined, so `a.b = 1; a.c = 2;` becomes `a = { b = 1; c = 2; };`, unless
+/// one of them defines the attribute directly, like `a = { }; a.b = 1;`. The
+/// new sets span several lines if the bindings are on lines of their own, and
+/// the comments in front of the bindings move with them.
+pub fn expand_attrpaths(node: &SyntaxNode, levels: usize) -> Vec<TextEdit> {
+    let mut expander = Expander { text: SourceText::new(node), levels, edits: Vec::new() };
+    expander.walk(node);
+    expander.edits
+}
+
+/// Collapse attribute sets with a single binding into dotted attrpaths, like
+/// `a = { b = { c = 1; }; };` into `a.b.c = 1;`, in all attribute sets and
+/// `let` blocks in a tree.
+///
+/// Attrpaths are only made as long as `max_len` attributes, so with 2 the
+/// result is `a.b = { c = 1; };`. `rec` sets, sets with an `inherit` and sets
+/// with comments aren't collapsed.
+pub fn collapse_attrpaths(node: &SyntaxNode, max_len: usize) -> Vec<TextEdit> {
+    let mut edits = Vec::new();
+    collapse(&SourceText::new(node), node, max_len, &mut edits);
+    edits
+}
+
+/// A binding to put into the expanded sets
+struct Item {
+    attrs: Vec<String>,
+    value: String,
+    comments: Vec<String>,
+}
+
+struct Expander {
+    text: SourceText,
+    levels: usize,
+    edits: Vec<TextEdit>,
+}
+
+impl Expander {
+    fn walk(&mut self, node: &SyntaxNode) {
+        match node.kind() {
+            NODE_ATTR_SET | NODE_LET_IN => self.expand_set(node),
+            _ => node.children().for_each(|child| self.walk(&child)),
+        }
+    }
+
+    /// The text of a node with the attrpaths inside it expanded
+    fn expanded(&self, node: &SyntaxNode) -> String {
+        let mut inner =
+            Expander { text: self.text.clone(), levels: self.levels, edits: Vec::new() };
+        inner.walk(node);
+        rewritten(node, &inner.edits)
+    }
+
+    fn expand_set(&mut self, set: &SyntaxNode) {
+        let mut groups: Vec<(String, Vec<ast::Entry>)> = Vec::new();
+        for child in set.children() {
+            let Some(entry) = ast::Entry::cast(child.clone()) else {
+                self.walk(&child);
+                continue;
+            };
+            let keys: Vec<String> = match &entry {
+                ast::Entry::AttrpathValue(entry) => entry
+                    .attrpath()
+                    .and_then(|path| path.attrs().next())
+                    .map(key)
+                    .into_iter()
+                    .collect(),
+                ast::Entry::Inherit(inherit) => inherit.attrs().map(key).collect(),
+            };
+            for key in keys {
+                match groups.iter_mut().find(|(other, _)| *other == key) {
+                    Some((_, entries)) => entries.push(entry.clone()),
+                    None => groups.push((key, vec![entry.clone()])),
+                }
+            }
+        }
+
+        for (_, group) in &groups {
+            let entries: Vec<&ast::AttrpathValue> = group
+                .iter()
+                .filter_map(|entry| match entry {
+                    ast::Entry::AttrpathValue(entry) => Some(entry),
+                    ast::Entry::Inherit(_) => None,
+                })
+                .collect();
+            let dotted = |entry: &&ast::AttrpathValue| {
+                entry.attrpath().is_some_and(|path| path.attrs().count() > 1)
+            };
+            if self.levels > 0 && entries.len() == group.len() && entries.iter().all(dotted) {
+                self.expand_group(&entries);
+            } else {
+                for entry in entries {
+                    if let Some(value) = entry.value() {
+                        self.walk(value.syntax());
+                    }
+                }
+            }
+        }
+    }
+
+    fn expand_group(&mut self, entries: &[&ast::AttrpathValue]) {
+        let first = entries[0].syntax();
+        let start = first.text_range().start();
+        let multiline = self.text.starts_line(start);
+        let mut items = Vec::new();
+        for (i, entry) in entries.iter().enumerate() {
+            let (Some(attrpath), Some(value)) = (entry.attrpath(), entry.value()) else { return };
+            // The comments of the first binding stay in front of the new set
+            let comments = if multiline && i > 0 {
+                leading_comments(entry.syntax()).iter().map(|c| c.to_string()).collect()
+            } else {
+                Vec::new()
+            };
+            items.push(Item {
+                attrs: attrpath.attrs().map(|attr| attr.syntax().to_string()).collect(),
+                value: self.expanded(value.syntax()),
+                comments,
+            });
+        }
+
+        let indent = multiline.then(|| self.text.indent(start).to_string());
+        let rendered = render(items, self.levels, indent.as_deref());
+        let separator = match &indent {
+            Some(indent) => format!("\n{}", indent),
+            None => " ".to_string(),
+        };
+        self.edits.push(TextEdit::replace(first.text_range(), rendered.join(&separator)));
+        for entry in &entries[1..] {
+            if multiline && self.text.starts_line(entry.syntax().text_range().start()) {
+                self.edits.extend(remove_leading_comment(entry.syntax()));
+            }
+            self.edits.push(self.text.remove_node(entry.syntax()));
+        }
+    }
+}
+
+fn key(attr: ast::Attr) -> String {
+    attr_name(&attr).unwrap_or_else(|| attr.syntax().to_string())
+}
+
+/// The bindings for items, grouped into sets by their first attribute up to
+/// `levels` deep. With an indentation the sets span several lines.
+fn render(items: Vec<Item>, levels: usize, indent: Option<&str>) -> Vec<String> {
+    let mut groups: Vec<(String, Vec<Item>)> = Vec::new();
+    for item in items {
+        let key = item.attrs[0].clone();
+        match groups.iter_mut().find(|(other, _)| *other == key) {
+            Some((_, items)) => items.push(item),
+            None => groups.push((key, vec![item])),
+        }
+    }
+
+    let mut out = Vec::new();
+    for (key, group) in groups {
+        if levels == 0 || group.iter().any(|item| item.attrs.len() < 2) {
+            out.extend(group.into_iter().map(|item| {
+                let entry = format!("{} = {};", item.attrs.join("."), item.value);
+                match indent {
+                    Some(indent) if !item.comments.is_empty() => {
+                        let separator = format!("\n{}", indent);
+                        format!("{}{}{}", item.comments.join(&separator), separator, entry)
+                    }
+                    _ => entry,
+                }
+            }));
+            continue;
+        }
+        let comments: Vec<String> = match indent {
+            // Comments of the first binding go in front of the set
+            Some(_) if group.len() == 1 => group[0].comments.clone(),
+            _ => Vec::new(),
+        };
+        let inner_indent = indent.map(|indent| format!("{}  ", indent));
+        let inner: Vec<Item> = group
+            .into_iter()
+            .map(|item| Item {
+                attrs: item.attrs[1..].to_vec(),
+                value: match indent {
+                    Some(_) => reindent(&item.value, "  "),
+                    None => item.value,
+                },
+                comments: if comments.is_empty() { item.comments } else { Vec::new() },
+            })
+            .collect();
+        let entries = render(inner, levels - 1, inner_indent.as_deref());
+        let set = match (indent, &inner_indent) {
+            (Some(indent), Some(inner_indent)) => {
+                let separator = format!("\n{}", inner_indent);
+                format!("{} = {{{}{}\n{}}};", key, separator, entries.join(&separator), indent)
+            }
+            _ => format!("{} = {{ {} }};", key, entries.join(" ")),
+        };
+        match indent {
+            Some(indent) if !comments.is_empty() => {
+                let separator = format!("\n{}", indent);
+                out.push(format!("{}{}{}", comments.join(&separator), separator, set));
+            }
+            _ => out.push(set),
+        }
+    }
+    out
+}
+
+fn collapse(text: &SourceText, node: &SyntaxNode, max_len: usize, edits: &mut Vec<TextEdit>) {
+    let Some(entry) = ast::AttrpathValue::cast(node.clone()) else {
+        node.children().for_each(|child| collapse(text, &child, max_len, edits));
+        return;
+    };
+    let (Some(attrpath), Some(mut value)) = (entry.attrpath(), entry.value()) else { return };
+    let mut attrs: Vec<String> = attrpath.attrs().map(|attr| attr.syntax().to_string()).collect();
+    let mut innermost = entry.clone();
+    while let Some(inner) = single_binding(&value) {
+        let (Some(inner_attrpath), Some(inner_value)) = (inner.attrpath(), inner.value()) else {
+            break;
+        };
+        if attrs.len() + inner_attrpath.attrs().count() > max_len {
+            break;
+        }
+        attrs.extend(inner_attrpath.attrs().map(|attr| attr.syntax().to_string()));
+        value = inner_value;
+        innermost = inner;
+    }
+    if innermost == entry {
+        node.children().for_each(|child| collapse(text, &child, max_len, edits));
+        return;
+    }
+
+    let mut inner = Vec::new();
+    collapse(text, value.syntax(), max_len, &mut inner);
+    let mut value = rewritten(value.syntax(), &inner);
+    // Lines of the value move out as far as the binding it moves into
+    let (outer_start, inner_start) =
+        (entry.syntax().text_range().start(), innermost.syntax().text_range().start());
+    if text.starts_line(outer_start) && text.starts_line(inner_start) {
+        let (outer, inner) = (text.indent(outer_start), text.indent(inner_start));
+        if let Some(extra) = inner.strip_prefix(outer) {
+            value = dedent(&value, extra);
+        }
+    }
+    edits.push(TextEdit::replace(
+        entry.syntax().text_range(),
+        format!("{} = {};", attrs.join("."), value),
+    ));
+}
+
+/// The only binding of an attribute set without comments, like `b = 1;` in
+/// `{ b = 1; }`
+fn single_binding(value: &ast::Expr) -> Option<ast::AttrpathValue> {
+    let ast::Expr::AttrSet(set) = strip_parens(value.clone()) else { return None };
+    if set.rec_token().is_some() {
+        return None;
+    }
+    let entries: Vec<ast::Entry> = set.entries().collect();
+    let [ast::Entry::AttrpathValue(entry)] = &entries[..] else { return None };
+    let has_comment = set
+        .syntax()
+        .children_with_tokens()
+        .filter_map(|element| element.into_token())
+        .any(|token| token.kind() == TOKEN_COMMENT);
+    (!has_comment).then(|| entry.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rewrite::apply_edits, Root};
+
+    fn expand(src: &str, levels: usize) -> String {
+        let root = Root::parse(src).ok().unwrap();
+        apply_edits(src, &expand_attrpaths(root.syntax(), levels))
+    }
+
+    fn collapse(src: &str, max_len: usize) -> String {
+        let root = Root::parse(src).ok().unwrap();
+        apply_edits(src, &collapse_attrpaths(root.syntax(), max_len))
+    }
+
+    #[test]
+    fn expands() {
+        assert_eq!(expand("{ a.b.c = 1; }", usize::MAX), "{ a = { b = { c = 1; }; }; }");
+        assert_eq!(expand("{ a.b.c = 1; }", 1), "{ a = { b.c = 1; }; }");
+        assert_eq!(
+            expand("{ a.b = 1; x = 2; a.c = { d.e = 3; }; }", usize::MAX),
+            "{ a = { b = 1; c = { d = { e = 3; }; }; }; x = 2; }"
+        );
+        // Attributes also defined directly are left alone
+        assert_eq!(expand("let a = { }; a.b = 1; in a", 1), "let a = { }; a.b = 1; in a");
+
+        let src = "{
+  # SSH
+  services.openssh.enable = true;
+  networking.hostName = \"a\";
+  # with keys
+  services.openssh.keys = [
+    \"ssh-ed25519 ...\"
+  ];
+}";
+        assert_eq!(
+            expand(src, usize::MAX),
+            "{
+  # SSH
+  services = {
+    openssh = {
+      enable = true;
+      # with keys
+      keys = [
+        \"ssh-ed25519 ...\"
+      ];
+    };
+  };
+  networking = {
+    hostName = \"a\";
+  };
+}"
+        );
+        assert_eq!(
+            expand(src, 1),
+            "{
+  # SSH
+  services = {
+    openssh.enable = true;
+    # with keys
+    openssh.keys = [
+      \"ssh-ed25519 ...\"
+    ];
+  };
+  networking = {
+    hostName = \"a\";
+  };
+}"
+        );
+    }
+
+    #[test]
+    fn strings() {
+        // Lines in strings are part of their values
+        assert_eq!(
+            expand("{\n  a.b = \"x\ny\";\n}", usize::MAX),
+            "{\n  a = {\n    b = \"x\ny\";\n  };\n}"
+        );
+        assert_eq!(
+            collapse("{\n  a = {\n    b = \"x\n    y\";\n  };\n}", usize::MAX),
+            "{\n  a.b = \"x\n    y\";\n}"
+        );
+        // The lines of `''` strings move together, as long as their
+        // indentation is stripped the same way
+        assert_eq!(
+            expand("{\n  a.b = ''\n    x\n\n      ${y}\n  '';\n}", usize::MAX),
+            "{\n  a = {\n    b = ''\n      x\n\n        ${y}\n    '';\n  };\n}"
+        );
+        assert_eq!(
+            expand("{\n  a.b = ''x\n    y'';\n}", usize::MAX),
+            "{\n  a = {\n    b = ''x\n    y'';\n  };\n}"
+        );
+        assert_eq!(
+            collapse("{\n  a = {\n    b = ''\n      x\n    '';\n  };\n}", usize::MAX),
+            "{\n  a.b = ''\n    x\n  '';\n}"
+        );
+        assert_eq!(
+            collapse("{\n  a = {\n    b = ''\n     x\n y\n    '';\n  };\n}", usize::MAX),
+            "{\n  a.b = ''\n     x\n y\n    '';\n}"
+        );
+    }
+
+    #[test]
+    fn collapses() {
+        assert_eq!(collapse("{ a = { b = { c = 1; }; }; }", usize::MAX), "{ a.b.c = 1; }");
+        assert_eq!(collapse("{ a = { b = { c = 1; }; }; }", 2), "{ a.b = { c = 1; }; }");
+        assert_eq!(
+            collapse("{ a = { b = 1; c = { d = 2; }; }; }", usize::MAX),
+            "{ a = { b = 1; c.d = 2; }; }"
+        );
+        assert_eq!(
+            collapse("{\n  a = {\n    b.c = [\n      1\n    ];\n  };\n}", usize::MAX),
+            "{\n  a.b.c = [\n    1\n  ];\n}"
+        );
+        let unchanged = "{ a = rec { b = 1; }; c = { inherit d; }; e = { /* f */ f = 1; }; }";
+        assert_eq!(collapse(unchanged, usize::MAX), unchanged);
+
+        let src = "{ a = { b = 1; }; c.d.e = 2; }";
+        assert_eq!(collapse(&expand(src, usize::MAX), usize::MAX), "{ a.b = 1; c.d.e = 2; }");
+    }
+}
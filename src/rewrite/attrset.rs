@@ -0,0 +1,444 @@
+use std::fmt;
+
+use rowan::{ast::AstNode, TextRange};
+
+use crate::{
+    analysis::{attr_name, attrpath_names, strip_parens},
+    ast::{self, HasEntry},
+    template::is_ident,
+    value::escape_string,
+    SyntaxNode,
+};
+
+use super::{layout::SourceText, TextEdit};
+
+/// The reason an edit of an attribute set was refused
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AttrSetEditError {
+    /// The attribute to update or remove, or the one to insert after, isn't
+    /// defined
+    NotFound,
+    /// The attribute to insert is already defined, at the given range
+    AlreadyDefined(TextRange),
+    /// The attribute is defined in a way which can't be edited, at the given
+    /// range. This is the case for inherited attributes which are updated,
+    /// attributes defined partly by a dotted attrpath like `a.b = 1;` when
+    /// editing `a`, and attributes inside values which aren't attribute sets.
+    Unsupported(TextRange),
+    /// The path to edit is empty
+    EmptyPath,
+}
+
+impl fmt::Display for AttrSetEditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (message, range) = match self {
+            AttrSetEditError::NotFound => return f.write_str("attribute not found"),
+            AttrSetEditError::EmptyPath => return f.write_str("attribute path is empty"),
+            AttrSetEditError::AlreadyDefined(range) => ("attribute is already defined", range),
+            AttrSetEditError::Unsupported(range) => ("attribute can't be edited", range),
+        };
+        write!(f, "{} at {}..{}", message, usize::from(range.start()), usize::from(range.end()))
+    }
+}
+
+impl std::error::Error for AttrSetEditError {}
+
+/// Edits an attribute set while keeping its formatting, see [`attrset`]
+#[derive(Clone, Debug)]
+pub struct AttrSetEditor {
+    set: ast::AttrSet,
+    text: SourceText,
+}
+
+/// Edit an attribute set, producing text edits which keep the indentation,
+/// alignment and comments around them.
+///
+/// Paths are relative to the set and reach into nested sets, looking through
+/// function calls, so `["src", "hash"]` refers to the `hash` of
+/// `src = fetchurl { ... };`. Values are given as Nix source text, like
+/// `"\"sha256-...\""`.
+pub fn attrset(set: &ast::AttrSet) -> AttrSetEditor {
+    AttrSetEditor { set: set.clone(), text: SourceText::new(set.syntax()) }
+}
+
+/// A definition of an attribute
+enum Def {
+    Value(ast::AttrpathValue),
+    Inherit(ast::Inherit, ast::Attr),
+}
+
+impl Def {
+    fn range(&self) -> TextRange {
+        match self {
+            Def::Value(entry) => entry
+                .attrpath()
+                .map_or(entry.syntax().text_range(), |attrpath| attrpath.syntax().text_range()),
+            Def::Inherit(_, attr) => attr.syntax().text_range(),
+        }
+    }
+}
+
+enum Lookup {
+    /// All definitions of exactly the path
+    Defined(Vec<Def>),
+    /// The path is defined partly, by a longer attrpath at the range
+    Partial(TextRange),
+    /// The path reaches into a value which isn't an attribute set, at the
+    /// range
+    Opaque(TextRange),
+    /// The path isn't defined, so it can be inserted as the remaining path into
+    /// the set
+    Missing(ast::AttrSet, Vec<String>),
+}
+
+/// The attribute set in a value, looking through function calls like
+/// `fetchurl { ... }` and functions like `finalAttrs: { ... }`
+fn inner_set(expr: ast::Expr) -> Option<ast::AttrSet> {
+    match strip_parens(expr) {
+        ast::Expr::AttrSet(set) => Some(set),
+        ast::Expr::Apply(apply) => inner_set(apply.argument()?),
+        ast::Expr::Lambda(lambda) => inner_set(lambda.body()?),
+        _ => None,
+    }
+}
+
+fn lookup(set: &ast::AttrSet, path: &[String]) -> Lookup {
+    let mut defs = Vec::new();
+    let mut partial = None;
+    let mut nested = Vec::new();
+    for entry in set.entries() {
+        match entry {
+            ast::Entry::Inherit(inherit) => {
+                for attr in inherit.attrs() {
+                    if path.len() == 1 && attr_name(&attr).as_deref() == Some(&path[0]) {
+                        defs.push(Def::Inherit(inherit.clone(), attr));
+                    }
+                }
+            }
+            ast::Entry::AttrpathValue(entry) => {
+                let Some(attrpath) = entry.attrpath() else { continue };
+                let Some(names) = attrpath_names(&attrpath) else { continue };
+                if names == path {
+                    defs.push(Def::Value(entry));
+                } else if names.starts_with(path) {
+                    partial.get_or_insert(attrpath.syntax().text_range());
+                } else if path.starts_with(&names) {
+                    nested.push((entry, names.len()));
+                }
+            }
+        }
+    }
+    if !defs.is_empty() {
+        return Lookup::Defined(defs);
+    }
+    if let Some(range) = partial {
+        return Lookup::Partial(range);
+    }
+
+    let mut missing = None;
+    for (entry, len) in nested {
+        let Some(value) = entry.value() else { continue };
+        let Some(inner) = inner_set(value.clone()) else {
+            return Lookup::Opaque(value.syntax().text_range());
+        };
+        match lookup(&inner, &path[len..]) {
+            Lookup::Missing(set, rest) => {
+                missing.get_or_insert(Lookup::Missing(set, rest));
+            }
+            found => return found,
+        }
+    }
+    missing.unwrap_or_else(|| Lookup::Missing(set.clone(), path.to_vec()))
+}
+
+impl AttrSetEditor {
+    /// Replace the values of all definitions of an attribute
+    pub fn update(&self, path: &[&str], value: &str) -> Result<Vec<TextEdit>, AttrSetEditError> {
+        let defs = self.defined(path)?;
+        defs.iter()
+            .map(|def| match def {
+                Def::Value(entry) => match entry.value() {
+                    Some(old) => Ok(TextEdit::replace(old.syntax().text_range(), value)),
+                    None => Err(AttrSetEditError::Unsupported(def.range())),
+                },
+                Def::Inherit(..) => Err(AttrSetEditError::Unsupported(def.range())),
+            })
+            .collect()
+    }
+
+    /// Remove all definitions of an attribute. Definitions on a line of their
+    /// own are removed with their line, including a comment trailing them.
+    pub fn remove(&self, path: &[&str]) -> Result<Vec<TextEdit>, AttrSetEditError> {
+        let defs = self.defined(path)?;
+        Ok(defs
+            .iter()
+            .map(|def| match def {
+                Def::Inherit(inherit, attr) if inherit.attrs().count() > 1 => {
+                    let start = self.text.skip_back_spaces(attr.syntax().text_range().start());
+                    TextEdit::delete(TextRange::new(start, attr.syntax().text_range().end()))
+                }
+                Def::Inherit(inherit, _) => self.text.remove_node(inherit.syntax()),
+                Def::Value(entry) => self.text.remove_node(entry.syntax()),
+            })
+            .collect())
+    }
+
+    /// Insert an attribute after the last one of the set it goes into. The set
+    /// is the innermost one along the path which already exists, so inserting
+    /// `["src", "hash"]` into `{ src = fetchurl { url = ...; }; }` adds
+    /// `hash = ...;` after the `url`, and inserting `["a", "b"]` into `{ }`
+    /// adds `a.b = ...;`.
+    pub fn insert(&self, path: &[&str], value: &str) -> Result<Vec<TextEdit>, AttrSetEditError> {
+        let (set, rest) = self.missing(path)?;
+        let anchor = set.entries().last().map(|entry| entry.syntax().clone());
+        Ok(vec![self.insert_entry(&set, anchor.as_ref(), &rest, value)])
+    }
+
+    /// Insert an attribute after another one in the set it goes into, like
+    /// `hash` after `url`. `after` is the name of the other attribute, which
+    /// may also be the first name of a dotted attrpath or an inherited
+    /// attribute.
+    pub fn insert_after(
+        &self,
+        path: &[&str],
+        value: &str,
+        after: &str,
+    ) -> Result<Vec<TextEdit>, AttrSetEditError> {
+        let (set, rest) = self.missing(path)?;
+        let anchor = set
+            .entries()
+            .filter(|entry| match entry {
+                ast::Entry::AttrpathValue(entry) => entry
+                    .attrpath()
+                    .and_then(|attrpath| attrpath.attrs().next())
+                    .is_some_and(|attr| attr_name(&attr).as_deref() == Some(after)),
+                ast::Entry::Inherit(inherit) => {
+                    inherit.attrs().any(|attr| attr_name(&attr).as_deref() == Some(after))
+                }
+            })
+            .last()
+            .ok_or(AttrSetEditError::NotFound)?;
+        Ok(vec![self.insert_entry(&set, Some(anchor.syntax()), &rest, value)])
+    }
+
+    fn lookup(&self, path: &[&str]) -> Lookup {
+        let path: Vec<String> = path.iter().map(|name| name.to_string()).collect();
+        lookup(&self.set, &path)
+    }
+
+    fn defined(&self, path: &[&str]) -> Result<Vec<Def>, AttrSetEditError> {
+        if path.is_empty() {
+            return Err(AttrSetEditError::EmptyPath);
+        }
+        match self.lookup(path) {
+            Lookup::Defined(defs) => Ok(defs),
+            Lookup::Partial(range) | Lookup::Opaque(range) => {
+                Err(AttrSetEditError::Unsupported(range))
+            }
+            Lookup::Missing(..) => Err(AttrSetEditError::NotFound),
+        }
+    }
+
+    fn missing(&self, path: &[&str]) -> Result<(ast::AttrSet, Vec<String>), AttrSetEditError> {
+        if path.is_empty() {
+            return Err(AttrSetEditError::EmptyPath);
+        }
+        match self.lookup(path) {
+            Lookup::Defined(defs) => Err(AttrSetEditError::AlreadyDefined(defs[0].range())),
+            Lookup::Partial(range) => Err(AttrSetEditError::AlreadyDefined(range)),
+            Lookup::Opaque(range) => Err(AttrSetEditError::Unsupported(range)),
+            Lookup::Missing(set, rest) => Ok((set, rest)),
+        }
+    }
+
+    fn insert_entry(
+        &self,
+        set: &ast::AttrSet,
+        anchor: Option<&SyntaxNode>,
+        path: &[String],
+        value: &str,
+    ) -> TextEdit {
+        let attrpath = path.iter().map(|name| attr_key(name)).collect::<Vec<_>>().join(".");
+
+        let Some(anchor) = anchor else {
+            let (Some(open), Some(close)) = (set.l_curly_token(), set.r_curly_token()) else {
+                return TextEdit::insert(set.syntax().text_range().end(), "");
+            };
+            let inner = TextRange::new(open.text_range().end(), close.text_range().start());
+            let entry = format!("{} = {};", attrpath, value);
+            if self.text[inner].contains('\n') {
+                let indent = format!("{}  ", self.text.indent(open.text_range().start()));
+                return TextEdit::insert(inner.start(), format!("\n{}{}", indent, entry));
+            }
+            return TextEdit::replace(inner, format!(" {} ", entry));
+        };
+
+        let start = anchor.text_range().start();
+        if !self.text.starts_line(start) {
+            // All on one line, like `{ a = 1; }`
+            let entry = format!("{} = {};", attrpath, value);
+            return TextEdit::insert(anchor.text_range().end(), format!(" {}", entry));
+        }
+        let entry = match self.alignment(set) {
+            Some(width) if attrpath.len() < width => {
+                format!("{:width$}= {};", attrpath, value, width = width)
+            }
+            _ => format!("{} = {};", attrpath, value),
+        };
+        let end = self.text.end_of_line_comment(anchor);
+        TextEdit::insert(end, format!("\n{}{}", self.text.indent(start), entry))
+    }
+
+    /// If the `=` of all entries on lines of their own are aligned with
+    /// padding, the width of the attrpaths up to the `=`
+    fn alignment(&self, set: &ast::AttrSet) -> Option<usize> {
+        let mut widths = Vec::new();
+        let mut padded = false;
+        for entry in set.attrpath_values() {
+            let start = entry.syntax().text_range().start();
+            if !self.text.starts_line(start) {
+                continue;
+            }
+            let assign = entry.assign_token()?.text_range().start();
+            let before = &self.text[TextRange::new(start, assign)];
+            padded |= before.ends_with("  ");
+            widths.push(before.chars().count());
+        }
+        let first = *widths.first()?;
+        (padded && widths.len() > 1 && widths.iter().all(|&width| width == first)).then_some(first)
+    }
+}
+
+/// A name as written in an attrpath, quoted if it isn't an identifier
+pub(crate) fn attr_key(name: &str) -> String {
+    if is_ident(name) {
+        name.to_string()
+    } else {
+        format!("\"{}\"", escape_string(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rewrite::apply_edits, Root};
+
+    fn edit(
+        src: &str,
+        f: impl FnOnce(&AttrSetEditor) -> Result<Vec<TextEdit>, AttrSetEditError>,
+    ) -> Result<String, AttrSetEditError> {
+        let root = Root::parse(src).ok().unwrap();
+        let Some(ast::Expr::AttrSet(set)) = root.expr() else { panic!("not an attrset") };
+        f(&attrset(&set)).map(|edits| apply_edits(src, &edits))
+    }
+
+    const SRC: &str = r#"{
+  pname = "foo"; # the name
+  src = fetchurl {
+    url = "https://example.org/foo.tar.gz";
+    # a comment
+    patches = [ ];
+  };
+  meta.license = lib.licenses.mit;
+  inherit version stdenv;
+}"#;
+
+    #[test]
+    fn insert() {
+        assert_eq!(
+            edit(SRC, |e| e.insert_after(&["src", "sha256"], "\"0abc\"", "url")).unwrap(),
+            SRC.replace("foo.tar.gz\";\n", "foo.tar.gz\";\n    sha256 = \"0abc\";\n")
+        );
+        assert_eq!(
+            edit(SRC, |e| e.insert(&["version2"], "\"1\"")).unwrap(),
+            SRC.replace("stdenv;\n", "stdenv;\n  version2 = \"1\";\n")
+        );
+        assert_eq!(
+            edit(SRC, |e| e.insert_after(&["meta", "a b"], "1", "pname")).unwrap(),
+            SRC.replace("# the name\n", "# the name\n  meta.\"a b\" = 1;\n")
+        );
+        assert_eq!(edit("{ }", |e| e.insert(&["a"], "1")).unwrap(), "{ a = 1; }");
+        assert_eq!(
+            edit("{ b = { }; }", |e| e.insert(&["b", "c"], "1")).unwrap(),
+            "{ b = { c = 1; }; }"
+        );
+        assert_eq!(edit("{\n}", |e| e.insert(&["a"], "1")).unwrap(), "{\n  a = 1;\n}");
+        assert_eq!(edit("{ a = 1; }", |e| e.insert(&["b"], "2")).unwrap(), "{ a = 1; b = 2; }");
+
+        let aligned = "{\n  a   = 1;\n  bcd = 2;\n}";
+        assert_eq!(
+            edit(aligned, |e| e.insert(&["ef"], "3")).unwrap(),
+            "{\n  a   = 1;\n  bcd = 2;\n  ef  = 3;\n}"
+        );
+
+        assert_eq!(
+            edit(SRC, |e| e.insert(&["pname"], "1")),
+            Err(AttrSetEditError::AlreadyDefined(TextRange::new(4.into(), 9.into())))
+        );
+        assert!(matches!(
+            edit(SRC, |e| e.insert(&["meta"], "1")),
+            Err(AttrSetEditError::AlreadyDefined(_))
+        ));
+        assert!(matches!(
+            edit(SRC, |e| e.insert(&["pname", "a"], "1")),
+            Err(AttrSetEditError::Unsupported(_))
+        ));
+        assert_eq!(
+            edit(SRC, |e| e.insert_after(&["b"], "1", "nope")),
+            Err(AttrSetEditError::NotFound)
+        );
+    }
+
+    #[test]
+    fn empty_path() {
+        assert_eq!(edit("{ }", |e| e.insert(&[], "1")), Err(AttrSetEditError::EmptyPath));
+        assert_eq!(
+            edit("{ a = 1; }", |e| e.insert_after(&[], "1", "a")),
+            Err(AttrSetEditError::EmptyPath)
+        );
+        assert_eq!(edit("{ a = 1; }", |e| e.update(&[], "1")), Err(AttrSetEditError::EmptyPath));
+        assert_eq!(edit("{ a = 1; }", |e| e.remove(&[])), Err(AttrSetEditError::EmptyPath));
+    }
+
+    #[test]
+    fn update() {
+        assert_eq!(
+            edit(SRC, |e| e.update(&["src", "url"], "\"https://example.org/bar.tar.gz\"")).unwrap(),
+            SRC.replace("foo.tar.gz", "bar.tar.gz")
+        );
+        assert_eq!(
+            edit(SRC, |e| e.update(&["meta", "license"], "lib.licenses.gpl3")).unwrap(),
+            SRC.replace("licenses.mit", "licenses.gpl3")
+        );
+        assert_eq!(edit(SRC, |e| e.update(&["nope"], "1")), Err(AttrSetEditError::NotFound));
+        assert!(matches!(
+            edit(SRC, |e| e.update(&["version"], "1")),
+            Err(AttrSetEditError::Unsupported(_))
+        ));
+        assert!(matches!(
+            edit(SRC, |e| e.update(&["meta"], "1")),
+            Err(AttrSetEditError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn remove() {
+        assert_eq!(
+            edit(SRC, |e| e.remove(&["pname"])).unwrap(),
+            SRC.replace("  pname = \"foo\"; # the name\n", "")
+        );
+        assert_eq!(
+            edit(SRC, |e| e.remove(&["src", "patches"])).unwrap(),
+            SRC.replace("    patches = [ ];\n", "")
+        );
+        assert_eq!(
+            edit(SRC, |e| e.remove(&["version"])).unwrap(),
+            SRC.replace("inherit version stdenv;", "inherit stdenv;")
+        );
+        assert_eq!(edit("{ a = 1; b = 2; }", |e| e.remove(&["a"])).unwrap(), "{ b = 2; }");
+        assert_eq!(edit("{ a = 1; b = 2; }", |e| e.remove(&["b"])).unwrap(), "{ a = 1; }");
+        assert_eq!(edit("{ inherit a; }", |e| e.remove(&["a"])).unwrap(), "{ }");
+        assert_eq!(edit("{ a = 1; a = 2; }", |e| e.remove(&["a"])).unwrap(), "{ }");
+    }
+}
@@ -0,0 +1,155 @@
+use rowan::{TextRange, TextSize};
+
+use crate::{SyntaxKind::*, SyntaxNode, SyntaxToken};
+
+use super::{layout::SourceText, TextEdit};
+
+/// The node owning the leading comments of a binding: the whole binding for
+/// any node inside it, like the attribute of `a = 1;`
+fn owner(node: &SyntaxNode) -> SyntaxNode {
+    node.ancestors()
+        .find(|node| {
+            matches!(node.kind(), NODE_ATTRPATH_VALUE | NODE_INHERIT | NODE_PAT_ENTRY | NODE_LAMBDA)
+        })
+        .unwrap_or_else(|| node.clone())
+}
+
+/// The comments in front of a binding, in order. Comments separated from the
+/// binding by a blank line and comments trailing code on their line, like the
+/// one in `a = 1; # about a` in front of the next binding, aren't included.
+pub fn leading_comments(binding: &SyntaxNode) -> Vec<SyntaxToken> {
+    let owner = owner(binding);
+    let mut comments = Vec::new();
+    let mut on_binding_line = true;
+    let mut token = owner.first_token().and_then(|token| token.prev_token());
+    while let Some(current) = token {
+        match current.kind() {
+            TOKEN_WHITESPACE if current.text().matches('\n').count() > 1 => break,
+            TOKEN_WHITESPACE => on_binding_line &= !current.text().contains('\n'),
+            TOKEN_COMMENT if on_binding_line || starts_line(&current) => {
+                comments.push(current.clone())
+            }
+            _ => break,
+        }
+        token = current.prev_token();
+    }
+    comments.reverse();
+    comments
+}
+
+fn starts_line(token: &SyntaxToken) -> bool {
+    match token.prev_token() {
+        Some(prev) => prev.kind() == TOKEN_WHITESPACE && prev.text().contains('\n'),
+        None => true,
+    }
+}
+
+/// Set the comment in front of a binding, replacing its leading comments.
+/// The text is written as `#` comments on lines of their own with the
+/// indentation of the binding, or as a `/* */` comment if the binding shares
+/// its line with code before it. Blank lines around the binding are kept.
+pub fn set_leading_comment(binding: &SyntaxNode, text: &str) -> TextEdit {
+    let owner = owner(binding);
+    let source = SourceText::new(&owner);
+    let start = owner.text_range().start();
+    let range = comments_range(&source, &owner);
+    if !source.starts_line(start) {
+        return TextEdit::replace(range, format!("/* {} */ ", text.replace("*/", "* /")));
+    }
+    let indent = source.indent(start);
+    let comment: String = text
+        .lines()
+        .map(|line| match line {
+            "" => format!("{}#\n", indent),
+            line => format!("{}# {}\n", indent, line),
+        })
+        .collect();
+    TextEdit::replace(range, comment)
+}
+
+/// Remove the leading comments of a binding, see [`leading_comments`],
+/// together with their lines
+pub fn remove_leading_comment(binding: &SyntaxNode) -> Option<TextEdit> {
+    let owner = owner(binding);
+    let source = SourceText::new(&owner);
+    let range = comments_range(&source, &owner);
+    (!range.is_empty()).then(|| TextEdit::delete(range))
+}
+
+/// The range from the start of the line of the first leading comment up to
+/// the start of the line of the binding, or up to the binding itself if it
+/// shares its line with anything before it. It is empty at the point to
+/// insert a comment if there are none.
+pub(crate) fn comments_range(source: &SourceText, owner: &SyntaxNode) -> TextRange {
+    let start = owner.text_range().start();
+    let end = if source.starts_line(start) { source.line_start(start) } else { start };
+    let first: Option<TextSize> =
+        leading_comments(owner).first().map(|comment| comment.text_range().start());
+    match first {
+        Some(first) if source.starts_line(first) => TextRange::new(source.line_start(first), end),
+        Some(first) => TextRange::new(first, end),
+        None => TextRange::empty(end),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rowan::ast::AstNode;
+
+    use super::*;
+    use crate::{ast, rewrite::apply_edits, Root};
+
+    const SRC: &str = "{
+  a = 1; # trailing a
+
+  # The version
+  # of the package
+  version = \"1.0\";
+  b = 2; c = /* inline */ 3;
+}";
+
+    fn binding(src: &str, name: &str) -> SyntaxNode {
+        let root = Root::parse(src).ok().unwrap();
+        root.syntax()
+            .descendants()
+            .filter_map(ast::AttrpathValue::cast)
+            .find(|entry| entry.attrpath().unwrap().to_string() == name)
+            .unwrap()
+            .syntax()
+            .clone()
+    }
+
+    #[test]
+    fn leading() {
+        let comments = |name| -> Vec<String> {
+            leading_comments(&binding(SRC, name)).iter().map(|c| c.text().to_string()).collect()
+        };
+        assert_eq!(comments("version"), ["# The version", "# of the package"]);
+        assert_eq!(comments("a"), [] as [&str; 0]);
+        assert_eq!(comments("b"), [] as [&str; 0]);
+    }
+
+    #[test]
+    fn set_and_remove() {
+        let set = |name, text| apply_edits(SRC, &[set_leading_comment(&binding(SRC, name), text)]);
+        assert_eq!(
+            set("version", "Pinned\n\nfor now"),
+            SRC.replace("  # The version\n  # of the package\n", "  # Pinned\n  #\n  # for now\n")
+        );
+        assert_eq!(set("b", "B"), SRC.replace("  b = 2;", "  # B\n  b = 2;"));
+        assert_eq!(set("c", "C"), SRC.replace("c = ", "/* C */ c = "));
+
+        let remove = |name| {
+            let edits: Vec<_> = remove_leading_comment(&binding(SRC, name)).into_iter().collect();
+            apply_edits(SRC, &edits)
+        };
+        assert_eq!(remove("version"), SRC.replace("  # The version\n  # of the package\n", ""));
+        assert_eq!(remove("a"), SRC);
+
+        let src = "{ /* A */ a = 1; }";
+        assert_eq!(
+            apply_edits(src, &[remove_leading_comment(&binding(src, "a")).unwrap()]),
+            "{ a = 1; }"
+        );
+    }
+}
@@ -0,0 +1,250 @@
+use std::{
+    fmt, fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+use rowan::ast::AstNode;
+
+use crate::{
+    analysis::{eval_const, strip_parens, Resolution, Resolver},
+    ast::{self, AstToken, InterpolPart},
+    value::{normalize, Anchor, PathRoots, Raw, Value},
+    Root, SyntaxNode,
+};
+
+use super::{
+    layout::{reindent, rewritten, SourceText},
+    TextEdit,
+};
+
+/// The reason an import can't be spliced
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SpliceError {
+    /// The node isn't a call of the builtin `import` with a path literal
+    NotAnImport,
+    /// The imported path can't be resolved, like `<nixpkgs>` without a
+    /// search directory or a path with interpolations
+    Unresolved,
+    /// The imported file can't be read
+    Read(PathBuf, io::ErrorKind),
+    /// The imported file has syntax errors
+    Parse(PathBuf),
+    /// The imported file uses the global name, which is shadowed where the
+    /// import is
+    Captured(String),
+}
+
+impl fmt::Display for SpliceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpliceError::NotAnImport => f.write_str("not an import of a path"),
+            SpliceError::Unresolved => f.write_str("imported path can't be resolved"),
+            SpliceError::Read(path, kind) => {
+                write!(f, "can't read {}: {}", path.display(), io::Error::from(*kind))
+            }
+            SpliceError::Parse(path) => write!(f, "{} has syntax errors", path.display()),
+            SpliceError::Captured(name) => write!(f, "`{}` is shadowed at the import", name),
+        }
+    }
+}
+
+impl std::error::Error for SpliceError {}
+
+/// The file imported by `import ./file.nix`, resolved against `roots`. The
+/// `default.nix` of imported directories is looked up on the file system.
+pub fn import_target(node: &ast::Apply, roots: &PathRoots) -> Result<PathBuf, SpliceError> {
+    let root = node.syntax().ancestors().last().unwrap_or_else(|| node.syntax().clone());
+    let file = imported_path(&Resolver::new(&root), node, roots)?;
+    Ok(if file.is_dir() { file.join("default.nix") } else { file })
+}
+
+/// Replace `import ./file.nix` by the expression in `file.nix`, in
+/// parentheses, so the result doesn't depend on the imported file anymore.
+///
+/// `roots` are those of the file containing the import. Relative paths in
+/// the imported file are rebased onto its [`base_dir`](PathRoots::base_dir),
+/// and the lines of the expression are indented like the import. Imports in
+/// the imported file are kept, splicing can be repeated to inline them too.
+/// The change is refused if a global name used in the imported file is
+/// shadowed where the import is, like `map` in `let map = ...; in import ./x.nix`.
+pub fn splice_import(node: &ast::Apply, roots: &PathRoots) -> Result<Vec<TextEdit>, SpliceError> {
+    let root = node.syntax().ancestors().last().unwrap_or_else(|| node.syntax().clone());
+    let resolver = Resolver::new(&root);
+    let file = imported_path(&resolver, node, roots)?;
+    let file = if file.is_dir() { file.join("default.nix") } else { file };
+    let source =
+        fs::read_to_string(&file).map_err(|err| SpliceError::Read(file.clone(), err.kind()))?;
+    let parse = Root::parse(&source);
+    let expr = match (parse.errors().is_empty(), parse.tree().expr()) {
+        (true, Some(expr)) => expr,
+        _ => return Err(SpliceError::Parse(file)),
+    };
+    check_captures(&resolver, node.syntax(), &parse.syntax())?;
+
+    let target_dir = file.parent().unwrap_or(Path::new("/"));
+    let edits: Vec<TextEdit> = expr
+        .syntax()
+        .descendants()
+        .filter_map(ast::Path::cast)
+        .filter_map(|path| rebase(&path, target_dir, roots.base_dir))
+        .collect();
+    let text = SourceText::new(node.syntax());
+    let indent = text.indent(node.syntax().text_range().start());
+    let spliced = reindent(&rewritten(expr.syntax(), &edits), indent);
+    Ok(vec![TextEdit::replace(node.syntax().text_range(), format!("({})", spliced))])
+}
+
+/// The path imported by `import ./file.nix`, without looking at the file
+/// system, so directories are returned as they are
+pub(crate) fn imported_path(
+    resolver: &Resolver,
+    node: &ast::Apply,
+    roots: &PathRoots,
+) -> Result<PathBuf, SpliceError> {
+    // Names bound in the tree shadow the builtins, but `with` doesn't
+    let is_builtin = |expr: Option<ast::Expr>, name: &str| match expr.map(strip_parens) {
+        Some(ast::Expr::Ident(ident)) => {
+            ident.to_string() == name
+                && matches!(
+                    resolver.resolve(&ident),
+                    Some(Resolution::Global | Resolution::With(_))
+                )
+        }
+        _ => false,
+    };
+    let is_import = match node.lambda().map(strip_parens) {
+        Some(ast::Expr::Select(select)) => {
+            select.or_token().is_none()
+                && select.attrpath().is_some_and(|attrpath| attrpath.to_string() == "import")
+                && is_builtin(select.expr(), "builtins")
+        }
+        lambda => is_builtin(lambda, "import"),
+    };
+    let path = match node.argument().map(strip_parens) {
+        Some(path @ ast::Expr::Path(_)) if is_import => path,
+        _ => return Err(SpliceError::NotAnImport),
+    };
+    eval_const(&path).and_then(|value| value.resolve_path(roots)).ok_or(SpliceError::Unresolved)
+}
+
+/// Check that the global names used in the imported file aren't bound or
+/// brought into scope by a `with` where the import is
+fn check_captures(
+    resolver: &Resolver,
+    node: &SyntaxNode,
+    imported: &SyntaxNode,
+) -> Result<(), SpliceError> {
+    let imported = Resolver::new(imported);
+    for (ident, resolution) in imported.resolutions() {
+        let name = ident.to_string();
+        let shadowed = resolver.bindings().any(|binding| {
+            binding.name == name && node.ancestors().any(|ancestor| ancestor == binding.scope)
+        });
+        // Names which aren't global would come from a `with` around the import
+        let in_with = *resolution == Resolution::Undefined
+            && node.ancestors().filter_map(ast::With::cast).any(|with| {
+                with.body()
+                    .is_some_and(|body| node.ancestors().any(|ancestor| &ancestor == body.syntax()))
+            });
+        if shadowed || in_with {
+            return Err(SpliceError::Captured(name));
+        }
+    }
+    Ok(())
+}
+
+/// An edit making a relative path in a file in `from` relative to `to`
+/// instead. Only the part in front of the first interpolation is changed.
+fn rebase(path: &ast::Path, from: &Path, to: &Path) -> Option<TextEdit> {
+    let InterpolPart::Literal(first) = path.parts().next()? else { return None };
+    let text = first.to_string();
+    let (prefix, trailing) = match text.strip_suffix('/') {
+        Some(prefix) => (prefix, "/"),
+        None => (text.as_str(), ""),
+    };
+    let Value::Path(Anchor::Relative, relative, _) = Value::from_path(prefix) else {
+        return None;
+    };
+    let rebased = relative_to(&normalize(&from.join(relative)), &normalize(to))?;
+    let value = Value::Path(Anchor::Relative, rebased.to_str()?.to_string(), Raw::default());
+    let range = first.syntax().text_range();
+    Some(TextEdit::replace(range, format!("{}{}", value.normalize()?, trailing)))
+}
+
+/// The path of `file` relative to `dir`, going up with `..` where needed
+fn relative_to(file: &Path, dir: &Path) -> Option<PathBuf> {
+    let file: Vec<Component> = file.components().collect();
+    let dir: Vec<Component> = dir.components().collect();
+    let common = file.iter().zip(&dir).take_while(|(a, b)| a == b).count();
+    if common == 0 {
+        return None;
+    }
+    let mut relative: PathBuf = dir[common..].iter().map(|_| Component::ParentDir).collect();
+    relative.extend(&file[common..]);
+    Some(relative)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::rewrite::apply_edits;
+
+    fn splice(src: &str, files: &[(&str, &str)]) -> Result<String, SpliceError> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let count = COUNT.fetch_add(1, Ordering::Relaxed);
+        let dir =
+            std::env::temp_dir().join(format!("rnix-splice-{}-{}", std::process::id(), count));
+        let _ = fs::remove_dir_all(&dir);
+        for (name, text) in files {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+        let root = Root::parse(src).ok().unwrap();
+        let node = root.syntax().descendants().filter_map(ast::Apply::cast).last().unwrap();
+        let base_dir = dir.join("main");
+        let roots = PathRoots { base_dir: &base_dir, home_dir: &dir, search_dir: None };
+        let result = splice_import(&node, &roots).map(|edits| apply_edits(src, &edits));
+        let _ = fs::remove_dir_all(&dir);
+        result
+    }
+
+    #[test]
+    fn splices() {
+        let lib = "# the library\n{ lib }:\n{\n  src = ./src;\n  other = import ../other/${lib}.nix;\n}\n";
+        assert_eq!(
+            splice("{\n  lib = import ../lib { };\n}", &[("lib/default.nix", lib)]).unwrap(),
+            "{\n  lib = ({ lib }:\n  {\n    src = ../lib/src;\n    other = import ../other/${lib}.nix;\n  }) { };\n}"
+        );
+        assert_eq!(
+            splice("builtins.import ./a.nix", &[("main/a.nix", "[ ./. <nixpkgs> ]")]).unwrap(),
+            "([ ./. <nixpkgs> ])"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(splice("import x", &[]), Err(SpliceError::NotAnImport));
+        assert_eq!(splice("import: import ./a.nix", &[]), Err(SpliceError::NotAnImport));
+        assert_eq!(splice("import <nixpkgs>", &[]), Err(SpliceError::Unresolved));
+        assert!(matches!(
+            splice("import ./a.nix", &[]),
+            Err(SpliceError::Read(_, io::ErrorKind::NotFound))
+        ));
+        assert!(matches!(
+            splice("import ./a.nix", &[("main/a.nix", "{")]),
+            Err(SpliceError::Parse(_))
+        ));
+        assert_eq!(
+            splice("let map = 1; in import ./a.nix", &[("main/a.nix", "map f [ ]")]),
+            Err(SpliceError::Captured("map".into()))
+        );
+        assert_eq!(
+            splice("with pkgs; import ./a.nix", &[("main/a.nix", "hello")]),
+            Err(SpliceError::Captured("hello".into()))
+        );
+    }
+}
@@ -0,0 +1,173 @@
+use std::ops::Deref;
+
+use rowan::{TextRange, TextSize};
+
+use crate::{NodeOrToken, Root, SyntaxKind::*, SyntaxNode, SyntaxToken};
+
+use super::{apply_edits, TextEdit};
+
+/// The text of a whole tree, for looking at the layout around its nodes
+#[derive(Clone, Debug)]
+pub(crate) struct SourceText(String);
+
+impl SourceText {
+    pub(crate) fn new(node: &SyntaxNode) -> Self {
+        let root = node.ancestors().last().unwrap_or_else(|| node.clone());
+        Self(root.to_string())
+    }
+
+    /// Whether there is only whitespace in front of an offset on its line
+    pub(crate) fn starts_line(&self, offset: TextSize) -> bool {
+        self[TextRange::new(self.line_start(offset), offset)].trim().is_empty()
+    }
+
+    pub(crate) fn line_start(&self, offset: TextSize) -> TextSize {
+        let before = &self.0[..usize::from(offset)];
+        TextSize::from(before.rfind('\n').map_or(0, |i| i + 1) as u32)
+    }
+
+    /// The whitespace at the start of the line containing an offset
+    pub(crate) fn indent(&self, offset: TextSize) -> &str {
+        let line = &self.0[usize::from(self.line_start(offset))..];
+        &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+    }
+
+    /// The offset of the end of a node, or of a comment after it on the same
+    /// line
+    pub(crate) fn end_of_line_comment(&self, node: &SyntaxNode) -> TextSize {
+        match node.last_token() {
+            Some(token) => self.end_of_line_comment_after(&token),
+            None => node.text_range().end(),
+        }
+    }
+
+    /// The offset of the end of a token, or of a comment after it on the same
+    /// line
+    pub(crate) fn end_of_line_comment_after(&self, token: &SyntaxToken) -> TextSize {
+        let mut end = token.text_range().end();
+        let mut next = token.next_token();
+        while let Some(token) = next {
+            match token.kind() {
+                TOKEN_WHITESPACE if !token.text().contains('\n') => (),
+                TOKEN_COMMENT if !token.text().contains('\n') => end = token.text_range().end(),
+                _ => break,
+            }
+            next = token.next_token();
+        }
+        end
+    }
+
+    /// The offset before the spaces and tabs in front of an offset
+    pub(crate) fn skip_back_spaces(&self, offset: TextSize) -> TextSize {
+        let before = &self.0[..usize::from(offset)];
+        TextSize::from(before.trim_end_matches([' ', '\t']).len() as u32)
+    }
+
+    /// Remove a node, with its line if nothing else is on it
+    pub(crate) fn remove_node(&self, node: &SyntaxNode) -> TextEdit {
+        let start = node.text_range().start();
+        let end = self.end_of_line_comment(node);
+        let line_start = self.line_start(start);
+        let rest = &self.0[usize::from(end)..];
+        let rest_of_line = rest.find('\n').map_or(rest, |i| &rest[..i]);
+        let own_line =
+            self.starts_line(start) && rest_of_line.trim().is_empty() && rest.contains('\n');
+        if own_line {
+            let line_end = end + TextSize::from(rest_of_line.len() as u32 + 1);
+            TextEdit::delete(TextRange::new(line_start, line_end))
+        } else {
+            TextEdit::delete(TextRange::new(self.skip_back_spaces(start), node.text_range().end()))
+        }
+    }
+}
+
+impl Deref for SourceText {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// The text of a node with edits of its descendants applied
+pub(crate) fn rewritten(node: &SyntaxNode, edits: &[TextEdit]) -> String {
+    let start = node.text_range().start();
+    let edits: Vec<TextEdit> = edits
+        .iter()
+        .map(|edit| TextEdit::replace(edit.range - start, edit.replacement.clone()))
+        .collect();
+    apply_edits(&node.to_string(), &edits)
+}
+
+/// Add indentation to all lines but the first one, except empty ones and the
+/// ones inside strings, see [`movable_lines`]
+pub(crate) fn reindent(text: &str, extra: &str) -> String {
+    let movable = movable_lines(text, |_| true);
+    let mut lines = text.split('\n');
+    let mut out = lines.next().unwrap_or_default().to_string();
+    for (line, movable) in lines.zip(movable) {
+        out.push('\n');
+        if movable && !line.is_empty() {
+            out.push_str(extra);
+        }
+        out.push_str(line);
+    }
+    out
+}
+
+/// Remove indentation from all lines but the first one, where they have it,
+/// except inside strings, see [`movable_lines`]
+pub(crate) fn dedent(text: &str, extra: &str) -> String {
+    let movable = movable_lines(text, |lines| {
+        lines.iter().all(|line| line.is_empty() || line.starts_with(extra))
+    });
+    let mut lines = text.split('\n');
+    let mut out = lines.next().unwrap_or_default().to_string();
+    for (line, movable) in lines.zip(movable) {
+        out.push('\n');
+        match line.strip_prefix(extra) {
+            Some(rest) if movable => out.push_str(rest),
+            _ => out.push_str(line),
+        }
+    }
+    out
+}
+
+/// Whether each line of an expression but the first one may be indented
+/// differently. Lines starting inside a `"` string are part of its value, and
+/// so are the ones of a `''` string, but moving all of them together keeps the
+/// value the same if there is nothing on the line of the opening quotes. They
+/// are moved if `shift` allows it for the lines of the string.
+fn movable_lines(text: &str, shift: impl Fn(&[&str]) -> bool) -> Vec<bool> {
+    let lines: Vec<&str> = text.split('\n').skip(1).collect();
+    let mut movable = vec![true; lines.len()];
+    let root = Root::parse(text).syntax();
+    let mut strings: Vec<(SyntaxNode, Vec<usize>)> = Vec::new();
+    for (i, (newline, _)) in text.match_indices('\n').enumerate() {
+        let range = TextRange::at(TextSize::from(newline as u32), TextSize::from(1));
+        let string = match root.covering_element(range) {
+            NodeOrToken::Token(token) if token.kind() == TOKEN_STRING_CONTENT => token.parent(),
+            _ => None,
+        };
+        let Some(string) = string else { continue };
+        match strings.iter_mut().find(|(other, _)| *other == string) {
+            Some((_, indices)) => indices.push(i),
+            None => strings.push((string, vec![i])),
+        }
+    }
+    for (string, indices) in strings {
+        let text = string.to_string();
+        let keeps_value = match text.strip_prefix("''") {
+            Some(body) => {
+                let first_line = body.split('\n').next().unwrap_or_default();
+                let string_lines: Vec<&str> = indices.iter().map(|&i| lines[i]).collect();
+                first_line.trim().is_empty() && shift(&string_lines)
+            }
+            None => false,
+        };
+        if !keeps_value {
+            indices.iter().for_each(|&i| movable[i] = false);
+        }
+    }
+    movable
+}
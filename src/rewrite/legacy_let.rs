@@ -0,0 +1,239 @@
+use std::fmt;
+
+use rowan::{ast::AstNode, TextRange};
+
+use crate::{
+    analysis::{attrpath_names, Resolver},
+    ast::{self, HasEntry},
+    SyntaxNode,
+};
+
+use super::{
+    layout::{dedent, rewritten, SourceText},
+    leading_comments, remove_leading_comment, TextEdit,
+};
+
+/// The reason a legacy `let { }` can't be converted
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LegacyLetError {
+    /// The legacy let at the given range has no `body`
+    NoBody(TextRange),
+    /// The `body` is used by a binding at the given range, so it can't become
+    /// the body of a `let ... in`
+    BodyUsed(TextRange),
+    /// The binding at the given range is allowed in a legacy let but not in a
+    /// `let ... in`, like a dynamic attribute or a `body` which isn't a single
+    /// plain binding
+    Unsupported(TextRange),
+}
+
+impl fmt::Display for LegacyLetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (message, range) = match self {
+            LegacyLetError::NoBody(range) => ("legacy let has no body", range),
+            LegacyLetError::BodyUsed(range) => ("body is used", range),
+            LegacyLetError::Unsupported(range) => ("binding can't be converted", range),
+        };
+        write!(f, "{} at {}..{}", message, usize::from(range.start()), usize::from(range.end()))
+    }
+}
+
+impl std::error::Error for LegacyLetError {}
+
+/// Convert a legacy `let { a = 1; body = a; }` into `let a = 1; in a`.
+///
+/// The `body` binding becomes the body of the `let`, together with the
+/// comments in front of it and trailing it. Layouts with one binding per line
+/// get the body on a line of its own after `in`.
+pub fn convert_legacy_let(node: &ast::LegacyLet) -> Result<Vec<TextEdit>, LegacyLetError> {
+    let root = node.syntax().ancestors().last().unwrap_or_else(|| node.syntax().clone());
+    let resolver = Resolver::new(&root);
+    convert(&resolver, &SourceText::new(&root), node, |value| value.syntax().to_string())
+}
+
+/// Convert all legacy lets in a tree, see [`convert_legacy_let`], returning
+/// the edits for those which can be converted and the reasons why the others
+/// can't
+pub fn convert_legacy_lets(node: &SyntaxNode) -> (Vec<TextEdit>, Vec<LegacyLetError>) {
+    let (resolver, text) = (Resolver::new(node), SourceText::new(node));
+    let mut converter =
+        Converter { resolver: &resolver, text: &text, edits: Vec::new(), errors: Vec::new() };
+    converter.walk(node);
+    (converter.edits, converter.errors)
+}
+
+struct Converter<'a> {
+    resolver: &'a Resolver,
+    text: &'a SourceText,
+    edits: Vec<TextEdit>,
+    errors: Vec<LegacyLetError>,
+}
+
+impl Converter<'_> {
+    fn walk(&mut self, node: &SyntaxNode) {
+        let Some(legacy) = ast::LegacyLet::cast(node.clone()) else {
+            node.children().for_each(|child| self.walk(&child));
+            return;
+        };
+        // Legacy lets in the body are converted as part of its new text
+        let mut body = None;
+        let mut body_errors = Vec::new();
+        let result = convert(self.resolver, self.text, &legacy, |value| {
+            body = Some(value.syntax().clone());
+            let mut inner = Converter {
+                resolver: self.resolver,
+                text: self.text,
+                edits: Vec::new(),
+                errors: Vec::new(),
+            };
+            inner.walk(value.syntax());
+            body_errors = inner.errors;
+            rewritten(value.syntax(), &inner.edits)
+        });
+        match result {
+            Ok(edits) => {
+                self.edits.extend(edits);
+                self.errors.extend(body_errors);
+            }
+            Err(error) => {
+                self.errors.push(error);
+                body = None;
+            }
+        }
+        for child in node.children() {
+            if !body.as_ref().is_some_and(|body| body.ancestors().any(|node| node == child)) {
+                self.walk(&child);
+            }
+        }
+    }
+}
+
+fn convert(
+    resolver: &Resolver,
+    text: &SourceText,
+    node: &ast::LegacyLet,
+    body_text: impl FnOnce(&ast::Expr) -> String,
+) -> Result<Vec<TextEdit>, LegacyLetError> {
+    let unsupported = |node: &SyntaxNode| LegacyLetError::Unsupported(node.text_range());
+    let mut body = None;
+    for entry in node.entries() {
+        let ast::Entry::AttrpathValue(entry) = entry else {
+            let inherit = entry.syntax();
+            if inherit.children().filter_map(ast::Attr::cast).any(|attr| is_body(&attr)) {
+                return Err(unsupported(inherit));
+            }
+            continue;
+        };
+        let attrpath = entry.attrpath().ok_or_else(|| unsupported(entry.syntax()))?;
+        let names = attrpath_names(&attrpath).ok_or_else(|| unsupported(attrpath.syntax()))?;
+        if names[0] != "body" {
+            continue;
+        }
+        if names.len() > 1 || body.is_some() {
+            return Err(unsupported(entry.syntax()));
+        }
+        body = Some(entry);
+    }
+    let let_token = node.let_token().ok_or_else(|| unsupported(node.syntax()))?;
+    let body = body.ok_or(LegacyLetError::NoBody(let_token.text_range()))?;
+    let attr = body.attrpath().and_then(|attrpath| attrpath.attrs().next());
+    if let Some(binding) = attr.and_then(|attr| resolver.binding_at(attr.syntax())) {
+        if let Some(reference) = resolver.references(binding.id).first() {
+            return Err(LegacyLetError::BodyUsed(reference.syntax().text_range()));
+        }
+    }
+    let (Some(open), Some(close), Some(value)) =
+        (node.curly_open_token(), node.curly_close_token(), body.value())
+    else {
+        return Err(unsupported(node.syntax()));
+    };
+
+    let body_start = body.syntax().text_range().start();
+    let multiline = text.starts_line(body_start) && text.starts_line(close.text_range().start());
+    let mut edits = vec![TextEdit::delete(TextRange::new(
+        let_token.text_range().end(),
+        open.text_range().end(),
+    ))];
+    let mut value_text = body_text(&value);
+    let replacement = if multiline {
+        let indent = text.indent(let_token.text_range().start());
+        if let Some(extra) = text.indent(body_start).strip_prefix(indent) {
+            value_text = dedent(&value_text, extra);
+        }
+        let mut lines: Vec<String> =
+            leading_comments(body.syntax()).iter().map(|comment| comment.to_string()).collect();
+        let end = text.end_of_line_comment(body.syntax());
+        let trailing = text[TextRange::new(body.syntax().text_range().end(), end)].trim();
+        match trailing {
+            "" => lines.push(value_text),
+            comment => lines.push(format!("{} {}", value_text, comment)),
+        }
+        edits.extend(remove_leading_comment(body.syntax()));
+        format!("in\n{}{}", indent, lines.join(&format!("\n{}", indent)))
+    } else {
+        format!("in {}", value_text)
+    };
+    edits.push(text.remove_node(body.syntax()));
+    edits.push(TextEdit::replace(close.text_range(), replacement));
+    Ok(edits)
+}
+
+fn is_body(attr: &ast::Attr) -> bool {
+    attr.static_name().as_deref() == Some("body")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rewrite::apply_edits, Root};
+
+    fn convert(src: &str) -> Result<String, LegacyLetError> {
+        let root = Root::parse(src).ok().unwrap();
+        let node = root.syntax().descendants().find_map(ast::LegacyLet::cast).unwrap();
+        convert_legacy_let(&node).map(|edits| apply_edits(src, &edits))
+    }
+
+    #[test]
+    fn converts() {
+        assert_eq!(convert("let { a = 1; body = a; }").unwrap(), "let a = 1; in a");
+        assert_eq!(
+            convert("let {\n  a = 1;\n  # the result\n  body = {\n    inherit a;\n  }; # set\n  b = 2;\n}")
+                .unwrap(),
+            "let\n  a = 1;\n  b = 2;\nin\n# the result\n{\n  inherit a;\n} # set"
+        );
+        assert_eq!(
+            convert("{\n  x = let {\n    body = a;\n    a = 1;\n  };\n}").unwrap(),
+            "{\n  x = let\n    a = 1;\n  in\n  a;\n}"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            convert("let { a = 1; }"),
+            Err(LegacyLetError::NoBody(TextRange::new(0.into(), 3.into())))
+        );
+        assert_eq!(
+            convert("let { body = { a = body; }; }"),
+            Err(LegacyLetError::BodyUsed(TextRange::new(19.into(), 23.into())))
+        );
+        assert_eq!(
+            convert("let { ${a} = 1; body = 1; }"),
+            Err(LegacyLetError::Unsupported(TextRange::new(6.into(), 10.into())))
+        );
+        assert_eq!(
+            convert("let { body.a = 1; }"),
+            Err(LegacyLetError::Unsupported(TextRange::new(6.into(), 17.into())))
+        );
+    }
+
+    #[test]
+    fn converts_all() {
+        let src = "[ (let { a = let { body = 1; }; body = let { body = a; }; }) (let { }) ]";
+        let root = Root::parse(src).ok().unwrap();
+        let (edits, errors) = convert_legacy_lets(root.syntax());
+        assert_eq!(apply_edits(src, &edits), "[ (let a = let in 1; in let in a) (let { }) ]");
+        assert_eq!(errors, [LegacyLetError::NoBody(TextRange::new(62.into(), 65.into()))]);
+    }
+}
@@ -0,0 +1,140 @@
+use rowan::{ast::AstNode, TextRange};
+
+use crate::ast;
+
+use super::{layout::SourceText, TextEdit};
+
+/// Edits a list while keeping its layout, see [`list`]
+#[derive(Clone, Debug)]
+pub struct ListEditor {
+    list: ast::List,
+    text: SourceText,
+}
+
+/// Edit a list, producing text edits which follow its layout. In a list with
+/// one element per line, elements are added on lines of their own with the
+/// same indentation, otherwise they are added on the same line. Elements are
+/// given as Nix source text, which is put in parentheses by the caller if
+/// needed, like `(callPackage ./foo.nix { })`.
+pub fn list(list: &ast::List) -> ListEditor {
+    ListEditor { list: list.clone(), text: SourceText::new(list.syntax()) }
+}
+
+impl ListEditor {
+    /// Whether the elements are on lines of their own. An empty list counts as
+    /// one if its brackets are on different lines.
+    pub fn is_multiline(&self) -> bool {
+        match self.list.items().next() {
+            Some(first) => self.text.starts_line(first.syntax().text_range().start()),
+            None => self.text[self.list.syntax().text_range()].contains('\n'),
+        }
+    }
+
+    /// Add an element at the end
+    pub fn append(&self, item: &str) -> Vec<TextEdit> {
+        let Some(last) = self.list.items().last() else {
+            return self.insert_into_empty(item);
+        };
+        if self.is_multiline() {
+            let indent = self.text.indent(last.syntax().text_range().start());
+            let end = self.text.end_of_line_comment(last.syntax());
+            vec![TextEdit::insert(end, format!("\n{}{}", indent, item))]
+        } else {
+            vec![TextEdit::insert(last.syntax().text_range().end(), format!(" {}", item))]
+        }
+    }
+
+    /// Add an element before the first one whose text sorts after it, keeping
+    /// a sorted list sorted. Nothing is added if an element with the same text
+    /// exists.
+    pub fn insert_sorted(&self, item: &str) -> Vec<TextEdit> {
+        let mut items = self.list.items();
+        let Some(next) = items.find(|existing| existing.syntax().to_string().as_str() >= item)
+        else {
+            return self.append(item);
+        };
+        if next.syntax().to_string() == item {
+            return Vec::new();
+        }
+        let start = next.syntax().text_range().start();
+        if self.is_multiline() {
+            let line_start = self.text.line_start(start);
+            let indent = self.text.indent(start);
+            vec![TextEdit::insert(line_start, format!("{}{}\n", indent, item))]
+        } else {
+            vec![TextEdit::insert(start, format!("{} ", item))]
+        }
+    }
+
+    /// Remove all elements matching a predicate. Elements on lines of their
+    /// own are removed with their line, including a comment trailing them.
+    pub fn remove(&self, predicate: impl Fn(&ast::Expr) -> bool) -> Vec<TextEdit> {
+        self.list
+            .items()
+            .filter(|item| predicate(item))
+            .map(|item| self.text.remove_node(item.syntax()))
+            .collect()
+    }
+
+    fn insert_into_empty(&self, item: &str) -> Vec<TextEdit> {
+        let (Some(open), Some(close)) = (self.list.l_brack_token(), self.list.r_brack_token())
+        else {
+            return Vec::new();
+        };
+        let inner = TextRange::new(open.text_range().end(), close.text_range().start());
+        if self.is_multiline() {
+            let indent = format!("{}  ", self.text.indent(open.text_range().start()));
+            vec![TextEdit::insert(inner.start(), format!("\n{}{}", indent, item))]
+        } else {
+            vec![TextEdit::replace(inner, format!(" {} ", item))]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rewrite::apply_edits, Root};
+
+    fn edit(src: &str, f: impl FnOnce(&ListEditor) -> Vec<TextEdit>) -> String {
+        let root = Root::parse(src).ok().unwrap();
+        let list = root.syntax().descendants().find_map(ast::List::cast).unwrap();
+        apply_edits(src, &f(&super::list(&list)))
+    }
+
+    const SRC: &str = "{
+  environment.systemPackages = with pkgs; [
+    git # for everything
+    htop
+    vim
+  ];
+}";
+
+    #[test]
+    fn multiline() {
+        assert_eq!(
+            edit(SRC, |e| e.append("ripgrep")),
+            SRC.replace("    vim\n", "    vim\n    ripgrep\n")
+        );
+        assert_eq!(
+            edit(SRC, |e| e.insert_sorted("jq")),
+            SRC.replace("    vim\n", "    jq\n    vim\n")
+        );
+        assert_eq!(edit(SRC, |e| e.insert_sorted("htop")), SRC);
+        assert_eq!(
+            edit(SRC, |e| e.remove(|item| item.syntax().to_string() == "git")),
+            SRC.replace("    git # for everything\n", "")
+        );
+        assert_eq!(edit("[\n]", |e| e.append("a")), "[\n  a\n]");
+    }
+
+    #[test]
+    fn inline() {
+        assert_eq!(edit("[ a c ]", |e| e.append("d")), "[ a c d ]");
+        assert_eq!(edit("[ a c ]", |e| e.insert_sorted("b")), "[ a b c ]");
+        assert_eq!(edit("[ a c ]", |e| e.insert_sorted("0")), "[ 0 a c ]");
+        assert_eq!(edit("[ ]", |e| e.append("a")), "[ a ]");
+        assert_eq!(edit("[]", |e| e.insert_sorted("a")), "[ a ]");
+        assert_eq!(edit("[ a b a ]", |e| e.remove(|item| item.to_string() == "a")), "[ b ]");
+    }
+}
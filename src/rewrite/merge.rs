@@ -0,0 +1,603 @@
+use rowan::{ast::AstNode, TextRange};
+
+use crate::{
+    analysis::attrpath_names,
+    ast::{self, HasEntry},
+    Root, SyntaxNode, SyntaxToken,
+};
+
+use super::{
+    apply_edits, comment::comments_range, layout::SourceText, leading_comments,
+    remove_leading_comment, TextEdit,
+};
+
+/// A part of a file which both sides of a merge changed in different ways
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MergeConflict {
+    /// The attrpath of the conflicting binding, relative to the attribute
+    /// sets and `let` blocks it is nested in. It is empty for conflicts
+    /// outside of any binding, like in the body of a function.
+    pub path: Vec<String>,
+    /// The source text of the part in the base, or `None` if it was added
+    pub base: Option<String>,
+    /// The source text of the part in our version, or `None` if we removed it
+    pub ours: Option<String>,
+    /// The source text of the part in their version, or `None` if they
+    /// removed it
+    pub theirs: Option<String>,
+}
+
+/// Merge two versions of a file changed from a common base, like
+/// `git merge-file` but following the structure of the code instead of its
+/// lines.
+///
+/// Bindings in attribute sets and `let` blocks are matched by their
+/// attrpaths, so changes to different bindings merge cleanly even if they are
+/// on adjacent lines, and so do changes inside different parts of a function,
+/// a `with` or a call. Their changes are applied to our version, keeping its
+/// formatting. Comments are merged with the bindings they lead and the code
+/// around them. Parts changed by both sides in different ways are returned as
+/// conflicts, as are files which don't parse.
+pub fn merge3(base: &str, ours: &str, theirs: &str) -> Result<String, Vec<MergeConflict>> {
+    if ours == theirs || theirs == base {
+        return Ok(ours.to_string());
+    }
+    if ours == base {
+        return Ok(theirs.to_string());
+    }
+    let parse = |text| Root::parse(text).ok().ok();
+    let (Some(base_root), Some(ours_root), Some(theirs_root)) =
+        (parse(base), parse(ours), parse(theirs))
+    else {
+        return Err(vec![MergeConflict {
+            path: Vec::new(),
+            base: Some(base.to_string()),
+            ours: Some(ours.to_string()),
+            theirs: Some(theirs.to_string()),
+        }]);
+    };
+
+    let mut merger = Merger {
+        base: SourceText::new(base_root.syntax()),
+        ours: SourceText::new(ours_root.syntax()),
+        theirs: SourceText::new(theirs_root.syntax()),
+        edits: Vec::new(),
+        conflicts: Vec::new(),
+    };
+    merger.merge(&[], base_root.syntax(), ours_root.syntax(), theirs_root.syntax());
+    if merger.conflicts.is_empty() {
+        Ok(apply_edits(ours, &merger.edits))
+    } else {
+        Err(merger.conflicts)
+    }
+}
+
+/// Collects the edits which apply their changes to our version
+struct Merger {
+    base: SourceText,
+    ours: SourceText,
+    theirs: SourceText,
+    edits: Vec<TextEdit>,
+    conflicts: Vec<MergeConflict>,
+}
+
+/// What an entry of an attribute set or a `let` block is matched by
+#[derive(PartialEq, Eq)]
+enum Key {
+    /// The names of a static attrpath, like `["a", "b"]` for `a.b = 1;`
+    Attr(Vec<String>),
+    /// The whole text of other entries, like `inherit (pkgs) hello;`
+    Other(String),
+}
+
+fn keyed(entries: impl Iterator<Item = ast::Entry>) -> Vec<(Key, ast::Entry)> {
+    entries.map(|entry| (key(&entry), entry)).collect()
+}
+
+fn key(entry: &ast::Entry) -> Key {
+    let names = match entry {
+        ast::Entry::AttrpathValue(entry) => entry.attrpath().and_then(|path| attrpath_names(&path)),
+        ast::Entry::Inherit(_) => None,
+    };
+    names.map_or_else(|| Key::Other(entry.syntax().to_string()), Key::Attr)
+}
+
+/// The path of an entry in a set at a path
+fn entry_path(path: &[String], key: &Key) -> Vec<String> {
+    match key {
+        Key::Attr(names) => [path, names].concat(),
+        Key::Other(_) => path.to_vec(),
+    }
+}
+
+/// The ranges of the text of a node around some of its children, in order
+fn gaps(node: &SyntaxNode, parts: &[SyntaxNode]) -> Vec<TextRange> {
+    let mut start = node.text_range().start();
+    let mut gaps = Vec::new();
+    for part in parts {
+        gaps.push(TextRange::new(start, part.text_range().start()));
+        start = part.text_range().end();
+    }
+    gaps.push(TextRange::new(start, node.text_range().end()));
+    gaps
+}
+
+/// The children of a node which are merged one by one if both sides changed
+/// it, or `None` if it can't be merged that way
+fn children(node: &SyntaxNode) -> Option<Vec<SyntaxNode>> {
+    let children: Vec<Option<SyntaxNode>> = match ast::Expr::cast(node.clone())? {
+        ast::Expr::Lambda(lambda) => vec![
+            lambda.param().map(|param| param.syntax().clone()),
+            lambda.body().map(|body| body.syntax().clone()),
+        ],
+        ast::Expr::With(with) => vec![
+            with.namespace().map(|namespace| namespace.syntax().clone()),
+            with.body().map(|body| body.syntax().clone()),
+        ],
+        ast::Expr::Apply(apply) => vec![
+            apply.lambda().map(|lambda| lambda.syntax().clone()),
+            apply.argument().map(|argument| argument.syntax().clone()),
+        ],
+        ast::Expr::Paren(paren) => vec![paren.expr().map(|expr| expr.syntax().clone())],
+        _ => return None,
+    };
+    children.into_iter().collect()
+}
+
+impl Merger {
+    fn merge(
+        &mut self,
+        path: &[String],
+        base: &SyntaxNode,
+        ours: &SyntaxNode,
+        theirs: &SyntaxNode,
+    ) {
+        let (base_text, ours_text, theirs_text) =
+            (base.to_string(), ours.to_string(), theirs.to_string());
+        if ours_text == theirs_text || theirs_text == base_text {
+            return;
+        }
+        if ours_text == base_text {
+            self.edits.push(TextEdit::replace(ours.text_range(), theirs_text));
+            return;
+        }
+
+        if let (Some(base), Some(ours), Some(theirs)) = (
+            ast::Root::cast(base.clone()),
+            ast::Root::cast(ours.clone()),
+            ast::Root::cast(theirs.clone()),
+        ) {
+            if let (Some(base_expr), Some(ours_expr), Some(theirs_expr)) =
+                (base.expr(), ours.expr(), theirs.expr())
+            {
+                let (base_expr, ours_expr, theirs_expr) =
+                    (base_expr.syntax(), ours_expr.syntax(), theirs_expr.syntax());
+                let merged = self.merge_trivia(
+                    path,
+                    &gaps(base.syntax(), std::slice::from_ref(base_expr)),
+                    &gaps(ours.syntax(), std::slice::from_ref(ours_expr)),
+                    &gaps(theirs.syntax(), std::slice::from_ref(theirs_expr)),
+                );
+                if merged {
+                    self.merge(path, base_expr, ours_expr, theirs_expr);
+                }
+                return;
+            }
+        }
+        let cast = |node: &SyntaxNode| ast::Expr::cast(node.clone());
+        match (cast(base), cast(ours), cast(theirs)) {
+            (
+                Some(ast::Expr::AttrSet(base)),
+                Some(ast::Expr::AttrSet(ours)),
+                Some(ast::Expr::AttrSet(theirs)),
+            ) if base.rec_token().is_some() == ours.rec_token().is_some()
+                && ours.rec_token().is_some() == theirs.rec_token().is_some() =>
+            {
+                let (open, close) = (ours.l_curly_token(), ours.r_curly_token());
+                return self.merge_entries(path, &base, &ours, &theirs, open, close);
+            }
+            (
+                Some(ast::Expr::LetIn(base)),
+                Some(ast::Expr::LetIn(ours)),
+                Some(ast::Expr::LetIn(theirs)),
+            ) => {
+                let (open, close) = (ours.let_token(), ours.in_token());
+                self.merge_entries(path, &base, &ours, &theirs, open, close);
+                if let (Some(base), Some(ours), Some(theirs)) =
+                    (base.body(), ours.body(), theirs.body())
+                {
+                    self.merge(path, base.syntax(), ours.syntax(), theirs.syntax());
+                }
+                return;
+            }
+            _ => (),
+        }
+        if base.kind() == ours.kind() && ours.kind() == theirs.kind() {
+            if let (Some(base_children), Some(ours_children), Some(theirs_children)) =
+                (children(base), children(ours), children(theirs))
+            {
+                if base_children.len() == ours_children.len()
+                    && ours_children.len() == theirs_children.len()
+                {
+                    let merged = self.merge_trivia(
+                        path,
+                        &gaps(base, &base_children),
+                        &gaps(ours, &ours_children),
+                        &gaps(theirs, &theirs_children),
+                    );
+                    if merged {
+                        for ((base, ours), theirs) in
+                            base_children.iter().zip(&ours_children).zip(&theirs_children)
+                        {
+                            self.merge(path, base, ours, theirs);
+                        }
+                    }
+                    return;
+                }
+            }
+        }
+        self.conflict(path, Some(base_text), Some(ours_text), Some(theirs_text));
+    }
+
+    fn merge_entries(
+        &mut self,
+        path: &[String],
+        base: &impl HasEntry,
+        ours: &impl HasEntry,
+        theirs: &impl HasEntry,
+        open: Option<SyntaxToken>,
+        close: Option<SyntaxToken>,
+    ) {
+        let trailing = [
+            trailing_trivia(&self.base, base.syntax()),
+            trailing_trivia(&self.ours, ours.syntax()),
+            trailing_trivia(&self.theirs, theirs.syntax()),
+        ];
+        if let [Some(base), Some(ours), Some(theirs)] = trailing {
+            self.merge_trivia(path, &[base], &[ours], &[theirs]);
+        }
+
+        let base = keyed(base.entries());
+        let ours = keyed(ours.entries());
+        let theirs = keyed(theirs.entries());
+        let find = |entries: &[(Key, ast::Entry)], key: &Key| {
+            let mut matching = entries.iter().filter(|(other, _)| other == key);
+            matching.next().map(|(_, entry)| (entry.clone(), matching.next().is_none()))
+        };
+
+        // Their changes and removals of bindings we have
+        for (key, ours_entry) in &ours {
+            let full_path = entry_path(path, key);
+            let base_entry = find(&base, key);
+            let theirs_entry = find(&theirs, key);
+            match (base_entry, theirs_entry) {
+                (Some((_, false)), _) | (_, Some((_, false))) => {
+                    self.conflict(&full_path, None, Some(ours_entry.syntax().to_string()), None)
+                }
+                (Some((base, _)), Some((theirs, _))) => {
+                    let merged = self.merge_trivia(
+                        &full_path,
+                        &entry_trivia(&self.base, &base),
+                        &entry_trivia(&self.ours, ours_entry),
+                        &entry_trivia(&self.theirs, &theirs),
+                    );
+                    if merged {
+                        self.merge_entry(&full_path, &base, ours_entry, &theirs)
+                    }
+                }
+                (Some((base, _)), None)
+                    if base.syntax().to_string() == ours_entry.syntax().to_string() =>
+                {
+                    self.remove(ours_entry)
+                }
+                (Some((base, _)), None) => self.conflict(
+                    &full_path,
+                    Some(base.syntax().to_string()),
+                    Some(ours_entry.syntax().to_string()),
+                    None,
+                ),
+                (None, Some((theirs, _)))
+                    if theirs.syntax().to_string() != ours_entry.syntax().to_string() =>
+                {
+                    self.conflict(
+                        &full_path,
+                        None,
+                        Some(ours_entry.syntax().to_string()),
+                        Some(theirs.syntax().to_string()),
+                    )
+                }
+                (None, _) => (),
+            }
+        }
+
+        // Bindings they added, or which they changed and we removed. Added
+        // bindings go after the binding in front of them in their version, and
+        // after the bindings we added there.
+        let mut anchor = None;
+        for (key, theirs_entry) in &theirs {
+            if let Some(index) = ours.iter().position(|(other, _)| other == key) {
+                anchor = Some(index);
+                continue;
+            }
+            match find(&base, key) {
+                None => {
+                    let mut after = anchor;
+                    for (index, (key, _)) in
+                        ours.iter().enumerate().skip(anchor.map_or(0, |i| i + 1))
+                    {
+                        if find(&base, key).is_some() || find(&theirs, key).is_some() {
+                            break;
+                        }
+                        after = Some(index);
+                    }
+                    let after = after.map(|index| &ours[index].1);
+                    let edit = self.insert(after, theirs_entry, open.as_ref(), close.as_ref());
+                    self.edits.extend(edit);
+                }
+                Some((base, _))
+                    if base.syntax().to_string() == theirs_entry.syntax().to_string() => {}
+                Some((base, _)) => self.conflict(
+                    &entry_path(path, key),
+                    Some(base.syntax().to_string()),
+                    None,
+                    Some(theirs_entry.syntax().to_string()),
+                ),
+            }
+        }
+    }
+
+    fn merge_entry(
+        &mut self,
+        path: &[String],
+        base: &ast::Entry,
+        ours: &ast::Entry,
+        theirs: &ast::Entry,
+    ) {
+        let value = |entry: &ast::Entry| match entry {
+            ast::Entry::AttrpathValue(entry) => entry.value().map(|value| value.syntax().clone()),
+            ast::Entry::Inherit(_) => None,
+        };
+        // Inherits are matched by their whole text, so they are the same
+        if let (Some(base), Some(ours), Some(theirs)) = (value(base), value(ours), value(theirs)) {
+            self.merge(path, &base, &ours, &theirs);
+        }
+    }
+
+    /// Apply their changes to the text around the parts of a node which are
+    /// merged on their own, like comments, or report a conflict if we changed
+    /// it too. The ranges of each side correspond to each other. Returns
+    /// whether there was no conflict.
+    fn merge_trivia(
+        &mut self,
+        path: &[String],
+        base: &[TextRange],
+        ours: &[TextRange],
+        theirs: &[TextRange],
+    ) -> bool {
+        let text = |source: &SourceText, ranges: &[TextRange]| -> Vec<String> {
+            ranges.iter().map(|range| source[*range].to_string()).collect()
+        };
+        let base_text = text(&self.base, base);
+        let ours_text = text(&self.ours, ours);
+        let theirs_text = text(&self.theirs, theirs);
+        if theirs_text == base_text || ours_text == theirs_text {
+            return true;
+        }
+        if ours_text != base_text {
+            self.conflict(
+                path,
+                Some(base_text.concat()),
+                Some(ours_text.concat()),
+                Some(theirs_text.concat()),
+            );
+            return false;
+        }
+        for ((range, ours), theirs) in ours.iter().zip(ours_text).zip(theirs_text) {
+            if ours != theirs {
+                self.edits.push(TextEdit::replace(*range, theirs));
+            }
+        }
+        true
+    }
+
+    /// Remove a binding with its leading comments
+    fn remove(&mut self, entry: &ast::Entry) {
+        let node = entry.syntax();
+        // A comment in front of a binding sharing its line is removed with it
+        if self.ours.starts_line(node.text_range().start()) {
+            self.edits.extend(remove_leading_comment(node));
+        }
+        self.edits.push(self.ours.remove_node(node));
+    }
+
+    /// Insert a binding of theirs with its leading comments after a binding of
+    /// ours, or at the start of the set
+    fn insert(
+        &self,
+        anchor: Option<&ast::Entry>,
+        entry: &ast::Entry,
+        open: Option<&SyntaxToken>,
+        close: Option<&SyntaxToken>,
+    ) -> Option<TextEdit> {
+        let root = entry.syntax().ancestors().last()?;
+        let start = leading_comments(entry.syntax())
+            .first()
+            .map_or(entry.syntax().text_range().start(), |comment| comment.text_range().start());
+        let text =
+            root.text().slice(TextRange::new(start, entry.syntax().text_range().end())).to_string();
+
+        if let Some(anchor) = anchor {
+            let anchor = anchor.syntax();
+            let anchor_start = anchor.text_range().start();
+            if !self.ours.starts_line(anchor_start) {
+                return Some(TextEdit::insert(anchor.text_range().end(), format!(" {}", text)));
+            }
+            let indent = self.ours.indent(anchor_start);
+            let end = self.ours.end_of_line_comment(anchor);
+            return Some(TextEdit::insert(end, format!("\n{}{}", indent, text)));
+        }
+
+        let (open, close) = (open?, close?);
+        let inner = TextRange::new(open.text_range().end(), close.text_range().start());
+        let first = open.parent()?.children().find_map(ast::Entry::cast);
+        match first {
+            Some(first) if self.ours.starts_line(first.syntax().text_range().start()) => {
+                let indent = self.ours.indent(first.syntax().text_range().start());
+                let end = self.ours.end_of_line_comment_after(open);
+                Some(TextEdit::insert(end, format!("\n{}{}", indent, text)))
+            }
+            Some(_) => Some(TextEdit::insert(open.text_range().end(), format!(" {}", text))),
+            None if self.ours[inner].contains('\n') => {
+                let indent = format!("{}  ", self.ours.indent(open.text_range().start()));
+                Some(TextEdit::insert(inner.start(), format!("\n{}{}", indent, text)))
+            }
+            None => Some(TextEdit::replace(inner, format!(" {} ", text))),
+        }
+    }
+
+    fn conflict(
+        &mut self,
+        path: &[String],
+        base: Option<String>,
+        ours: Option<String>,
+        theirs: Option<String>,
+    ) {
+        self.conflicts.push(MergeConflict { path: path.to_vec(), base, ours, theirs });
+    }
+}
+
+/// The range between the last entry of a set or `let` and its closing token,
+/// or `None` if it is missing a token
+fn trailing_trivia(source: &SourceText, node: &SyntaxNode) -> Option<TextRange> {
+    let mut tokens = node.children_with_tokens().filter_map(|element| element.into_token());
+    let open = tokens.clone().find(|token| matches!(token.kind(), T!['{'] | T![let]))?;
+    let close = tokens.find(|token| matches!(token.kind(), T!['}'] | T![in]))?;
+    let start = match node.children().filter_map(ast::Entry::cast).last() {
+        Some(entry) => source.end_of_line_comment(entry.syntax()),
+        None => source.end_of_line_comment_after(&open),
+    };
+    Some(TextRange::new(start, close.text_range().start()))
+}
+
+/// The ranges of the leading comments of an entry, and of a comment after it
+/// on its line
+fn entry_trivia(source: &SourceText, entry: &ast::Entry) -> [TextRange; 2] {
+    let node = entry.syntax();
+    let end = node.text_range().end();
+    [comments_range(source, node), TextRange::new(end, source.end_of_line_comment(node))]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "{ pkgs, ... }:
+{
+  imports = [ ./hardware.nix ];
+
+  networking.hostName = \"base\";
+  services.openssh.enable = true;
+  environment.systemPackages = with pkgs; [ git ];
+}";
+
+    #[test]
+    fn independent_changes() {
+        let ours = BASE.replace("\"base\"", "\"laptop\"");
+        let theirs = BASE.replace("openssh.enable = true", "openssh.enable = false");
+        assert_eq!(
+            merge3(BASE, &ours, &theirs).unwrap(),
+            BASE.replace("\"base\"", "\"laptop\"").replace("enable = true", "enable = false")
+        );
+
+        // Additions next to each other, and a removal
+        let ours = BASE.replace("  services", "  # Time\n  time.timeZone = \"UTC\";\n  services");
+        let theirs = BASE
+            .replace("  services", "  boot.loader.grub.enable = true;\n  services")
+            .replace("  imports = [ ./hardware.nix ];\n", "");
+        assert_eq!(
+            merge3(BASE, &ours, &theirs).unwrap(),
+            BASE.replace(
+                "  services",
+                "  # Time\n  time.timeZone = \"UTC\";\n  boot.loader.grub.enable = true;\n  services"
+            )
+            .replace("  imports = [ ./hardware.nix ];\n", "")
+        );
+
+        // Changes to the arguments and inside a nested value
+        let ours = BASE.replace("{ pkgs, ... }", "{ pkgs, lib, ... }");
+        let theirs = BASE.replace("[ git ]", "[ git vim ]");
+        assert_eq!(
+            merge3(BASE, &ours, &theirs).unwrap(),
+            BASE.replace("{ pkgs, ... }", "{ pkgs, lib, ... }").replace("[ git ]", "[ git vim ]")
+        );
+
+        assert_eq!(merge3("{ }", "{ a = 1; }", "{ b = 2; }").unwrap(), "{ a = 1; b = 2; }");
+        assert_eq!(
+            merge3("let a = 1; in a", "let a = 2; in a", "let a = 1; in a + 1").unwrap(),
+            "let a = 2; in a + 1"
+        );
+    }
+
+    #[test]
+    fn comments() {
+        // Their comment in front of a binding, while we changed another one
+        let base = "{\n  a = 1;\n  b = 2;\n}";
+        let ours = base.replace("a = 1", "a = 10");
+        let theirs = base.replace("  b = 2", "  # explain b\n  b = 2");
+        assert_eq!(
+            merge3(base, &ours, &theirs).unwrap(),
+            "{\n  a = 10;\n  # explain b\n  b = 2;\n}"
+        );
+        let theirs = base.replace("b = 2;", "b = 2; # explain b");
+        assert_eq!(merge3(base, &ours, &theirs).unwrap(), "{\n  a = 10;\n  b = 2; # explain b\n}");
+        let theirs = base.replace("b = 2;\n", "b = 2;\n  # the end\n");
+        assert_eq!(merge3(base, &ours, &theirs).unwrap(), "{\n  a = 10;\n  b = 2;\n  # the end\n}");
+
+        // Comments around the code outside of bindings
+        assert_eq!(merge3("# x\n1", "# x\n2", "# y\n1").unwrap(), "# y\n2");
+        assert_eq!(merge3("x: x", "x: x + 1", "x: /* y */ x").unwrap(), "x: /* y */ x + 1");
+        assert_eq!(
+            merge3("# x\n1", "# y\n1", "# z\n1"),
+            Err(vec![MergeConflict {
+                path: Vec::new(),
+                base: Some("# x\n".into()),
+                ours: Some("# y\n".into()),
+                theirs: Some("# z\n".into()),
+            }])
+        );
+        let ours = base.replace("  b = 2", "  # b\n  b = 2");
+        let theirs = base.replace("  b = 2", "  # explain b\n  b = 2");
+        assert_eq!(merge3(base, &ours, &theirs).unwrap_err()[0].path, ["b"]);
+    }
+
+    #[test]
+    fn conflicts() {
+        let ours = BASE.replace("\"base\"", "\"laptop\"");
+        let theirs = BASE.replace("\"base\"", "\"desktop\"");
+        assert_eq!(
+            merge3(BASE, &ours, &theirs),
+            Err(vec![MergeConflict {
+                path: vec!["networking".into(), "hostName".into()],
+                base: Some("\"base\"".into()),
+                ours: Some("\"laptop\"".into()),
+                theirs: Some("\"desktop\"".into()),
+            }])
+        );
+
+        // Changed by us, removed by them
+        let theirs = BASE.replace("  networking.hostName = \"base\";\n", "");
+        assert_eq!(
+            merge3(BASE, &ours, &theirs),
+            Err(vec![MergeConflict {
+                path: vec!["networking".into(), "hostName".into()],
+                base: Some("networking.hostName = \"base\";".into()),
+                ours: Some("networking.hostName = \"laptop\";".into()),
+                theirs: None,
+            }])
+        );
+
+        assert_eq!(merge3("{ }", "{ a = 1; }", "{ a = 1; }").unwrap(), "{ a = 1; }");
+        assert_eq!(merge3("{ }", "{ a = 1; }", "{ a = 2; }").unwrap_err().len(), 1);
+        assert_eq!(merge3("{ }", "{ a = ", "{ b = 2; }").unwrap_err()[0].path, [] as [&str; 0]);
+    }
+}
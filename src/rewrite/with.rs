@@ -0,0 +1,267 @@
+use std::fmt;
+
+use rowan::{ast::AstNode, TextRange};
+
+use crate::{
+    analysis::{is_global, strip_parens, Resolution, Resolver},
+    ast, SyntaxNode,
+};
+
+use super::TextEdit;
+
+/// The reason a `with` can't be eliminated
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum WithError {
+    /// The `with` at the given range is missing its namespace or body
+    Unsupported(TextRange),
+    /// The identifier at the given range may come from this `with` or from
+    /// another one around it
+    Ambiguous(TextRange),
+    /// The `inherit` at the given range inherits names from the `with`
+    /// together with others, which would need to be split
+    MixedInherit(TextRange),
+    /// An identifier in the namespace would refer to something else at the
+    /// identifier at the given range, because a binding or a `with` in
+    /// between shadows it
+    Captured(TextRange),
+    /// The namespace isn't a name or a selection from one and would be
+    /// copied into this many places
+    Duplicated(usize),
+}
+
+impl fmt::Display for WithError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (message, range) = match self {
+            WithError::Duplicated(count) => {
+                return write!(f, "namespace would be duplicated into {} places", count)
+            }
+            WithError::Unsupported(range) => ("with can't be eliminated", range),
+            WithError::Ambiguous(range) => ("identifier may come from several withs", range),
+            WithError::MixedInherit(range) => ("inherit mixes names from the with", range),
+            WithError::Captured(range) => ("namespace would be captured", range),
+        };
+        write!(f, "{} at {}..{}", message, usize::from(range.start()), usize::from(range.end()))
+    }
+}
+
+impl std::error::Error for WithError {}
+
+/// Replace `with pkgs; [ foo bar ]` by `[ pkgs.foo pkgs.bar ]`.
+///
+/// Every identifier taken from the `with` is prefixed with its namespace and
+/// `inherit foo;` becomes `inherit (pkgs) foo;`. Global names like `map`
+/// can't be shadowed by a `with` and are left alone. The change is refused if
+/// any identifier may come from another `with` as well, or if the namespace
+/// would refer to something else where it is inserted.
+pub fn eliminate_with(node: &ast::With) -> Result<Vec<TextEdit>, WithError> {
+    let root = node.syntax().ancestors().last().unwrap_or_else(|| node.syntax().clone());
+    eliminate(&Resolver::new(&root), node)
+}
+
+/// Eliminate all `with`s in a tree, see [`eliminate_with`], returning the
+/// edits for those which can be eliminated and the reasons why the others
+/// can't
+pub fn eliminate_withs(node: &SyntaxNode) -> (Vec<TextEdit>, Vec<WithError>) {
+    let resolver = Resolver::new(node);
+    let mut removed = Vec::new();
+    let mut edits = Vec::new();
+    let mut errors = Vec::new();
+    for with in node.descendants().filter_map(ast::With::cast) {
+        match eliminate(&resolver, &with) {
+            Ok(with_edits) => {
+                // The first edit removes `with namespace;`
+                removed.push(with_edits[0].range);
+                edits.extend(with_edits);
+            }
+            Err(error) => errors.push(error),
+        }
+    }
+    // Names in the namespace of a removed `with` don't need to be rewritten
+    edits.retain(|edit| {
+        !removed.iter().any(|range| range.contains_range(edit.range) && range != &edit.range)
+    });
+    (edits, errors)
+}
+
+fn eliminate(resolver: &Resolver, node: &ast::With) -> Result<Vec<TextEdit>, WithError> {
+    let (Some(with_token), Some(namespace), Some(body)) =
+        (node.with_token(), node.namespace(), node.body())
+    else {
+        return Err(WithError::Unsupported(node.syntax().text_range()));
+    };
+
+    let mut uses: Vec<(ast::Ident, usize)> = resolver
+        .resolutions()
+        .filter_map(|(ident, resolution)| match resolution {
+            Resolution::With(withs) if withs.contains(node) && !is_global(&ident.to_string()) => {
+                Some((ident, withs.len()))
+            }
+            _ => None,
+        })
+        .collect();
+    uses.sort_by_key(|(ident, _)| ident.syntax().text_range().start());
+
+    let mut references = Vec::new();
+    let mut inherits = Vec::new();
+    for (ident, withs) in uses {
+        if withs > 1 {
+            return Err(WithError::Ambiguous(ident.syntax().text_range()));
+        }
+        match ident.syntax().parent().and_then(ast::Inherit::cast) {
+            Some(inherit) => inherits.push(inherit),
+            None => references.push(ident),
+        }
+    }
+    inherits.dedup();
+
+    let trivial = is_trivial(&namespace);
+    let count = references.len() + inherits.len();
+    if count > 1 && !trivial {
+        return Err(WithError::Duplicated(count));
+    }
+    let places = references.iter().map(|ident| ident.syntax());
+    for place in places.chain(inherits.iter().map(|inherit| inherit.syntax())) {
+        check_captures(resolver, node, &namespace, place)?;
+    }
+
+    // A `with` only appears where a whole expression is expected, so its body
+    // can take its place as it is
+    let range = TextRange::new(with_token.text_range().start(), body.syntax().text_range().start());
+    let mut edits = vec![TextEdit::delete(range)];
+
+    let namespace_text = match strip_parens(namespace.clone()) {
+        ns if trivial => ns.syntax().to_string(),
+        ns => format!("({})", ns.syntax()),
+    };
+    for ident in references {
+        let range = ident.syntax().text_range();
+        edits.push(TextEdit::replace(range, format!("{}.{}", namespace_text, ident.syntax())));
+    }
+    for inherit in inherits {
+        let is_from_with = |attr: &ast::Attr| match attr {
+            ast::Attr::Ident(ident) => {
+                matches!(
+                    resolver.resolve(ident),
+                    Some(Resolution::With(withs)) if withs == std::slice::from_ref(node)
+                ) && !is_global(&ident.to_string())
+            }
+            _ => false,
+        };
+        if !inherit.attrs().all(|attr| is_from_with(&attr)) {
+            return Err(WithError::MixedInherit(inherit.syntax().text_range()));
+        }
+        let Some(token) = inherit.inherit_token() else {
+            return Err(WithError::Unsupported(inherit.syntax().text_range()));
+        };
+        let from =
+            if trivial { format!(" ({})", namespace_text) } else { format!(" {}", namespace_text) };
+        edits.push(TextEdit::insert(token.text_range().end(), from));
+    }
+    Ok(edits)
+}
+
+/// Whether the namespace is a name or a selection from one, which is cheap to
+/// repeat and doesn't need parentheses
+fn is_trivial(namespace: &ast::Expr) -> bool {
+    match strip_parens(namespace.clone()) {
+        ast::Expr::Ident(_) => true,
+        ast::Expr::Select(select) => {
+            select.or_token().is_none() && select.expr().is_some_and(|expr| is_trivial(&expr))
+        }
+        _ => false,
+    }
+}
+
+/// Check that every identifier in the namespace refers to the same thing at
+/// the place it is inserted
+fn check_captures(
+    resolver: &Resolver,
+    with: &ast::With,
+    namespace: &ast::Expr,
+    place: &SyntaxNode,
+) -> Result<(), WithError> {
+    let is_inside =
+        |node: &SyntaxNode, scope: &SyntaxNode| node.ancestors().any(|ancestor| &ancestor == scope);
+    for ident in namespace.syntax().descendants().filter_map(ast::Ident::cast) {
+        let Some(resolution) = resolver.resolve(&ident) else { continue };
+        let name = ident.to_string();
+        let shadowed = resolver.bindings().any(|other| {
+            other.name == name
+                && is_inside(place, &other.scope)
+                && !is_inside(with.syntax(), &other.scope)
+        });
+        // Names which aren't bound lexically may come from a nested `with`
+        let in_with = !matches!(resolution, Resolution::Binding(_))
+            && !is_global(&name)
+            && place.ancestors().filter_map(ast::With::cast).any(|other| {
+                !is_inside(with.syntax(), other.syntax())
+                    && other.body().is_some_and(|body| is_inside(place, body.syntax()))
+            });
+        if shadowed || in_with {
+            return Err(WithError::Captured(place.text_range()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rewrite::apply_edits, Root};
+
+    fn eliminate(src: &str) -> Result<String, WithError> {
+        let root = Root::parse(src).ok().unwrap();
+        let node = root.syntax().descendants().find_map(ast::With::cast).unwrap();
+        eliminate_with(&node).map(|edits| apply_edits(src, &edits))
+    }
+
+    #[test]
+    fn eliminates() {
+        assert_eq!(eliminate("with pkgs; [ foo bar ]").unwrap(), "[ pkgs.foo pkgs.bar ]");
+        assert_eq!(
+            eliminate("{ pkgs }:\nwith pkgs;\n{\n  buildInputs = [ hello ] ++ lib.optional x y;\n  inherit stdenv;\n}")
+                .unwrap(),
+            "{ pkgs }:\n{\n  buildInputs = [ pkgs.hello ] ++ pkgs.lib.optional pkgs.x pkgs.y;\n  inherit (pkgs) stdenv;\n}"
+        );
+        // Globals and lexical bindings can't come from the `with`
+        assert_eq!(
+            eliminate("let a = 1; in with lib; map toString [ a b ]").unwrap(),
+            "let a = 1; in map toString [ a lib.b ]"
+        );
+        assert_eq!(eliminate("with pkgs.lib; x").unwrap(), "pkgs.lib.x");
+        assert_eq!(eliminate("with (import ./lib.nix); x").unwrap(), "(import ./lib.nix).x");
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            eliminate("with a; with b; c"),
+            Err(WithError::Ambiguous(TextRange::new(16.into(), 17.into())))
+        );
+        assert_eq!(
+            eliminate("a: with pkgs; { inherit a b; }"),
+            Err(WithError::MixedInherit(TextRange::new(16.into(), 28.into())))
+        );
+        assert_eq!(
+            eliminate("with pkgs; pkgs: foo"),
+            Err(WithError::Captured(TextRange::new(17.into(), 20.into())))
+        );
+        assert_eq!(eliminate("with import ./lib.nix; [ a b ]"), Err(WithError::Duplicated(2)));
+    }
+
+    #[test]
+    fn eliminates_all() {
+        let src = "[ (with pkgs; [ (with python3Packages; [ ]) hello ]) (with a; with b; c) ]";
+        let root = Root::parse(src).ok().unwrap();
+        let (edits, errors) = eliminate_withs(root.syntax());
+        assert_eq!(apply_edits(src, &edits), "[ ([ ([ ]) pkgs.hello ]) (with a; with b; c) ]");
+        assert_eq!(
+            errors,
+            [
+                WithError::Ambiguous(TextRange::new(70.into(), 71.into())),
+                WithError::Ambiguous(TextRange::new(70.into(), 71.into())),
+            ]
+        );
+    }
+}
@@ -0,0 +1,237 @@
+//! Structural search: finding code matching a pattern, regardless of how it
+//! is formatted
+
+use std::collections::BTreeMap;
+
+use rowan::{ast::AstNode, TextRange};
+
+use crate::{
+    parser::ParseError, tokenize, NodeOrToken, Root, SyntaxElement, SyntaxKind::*, SyntaxNode,
+    SyntaxToken,
+};
+
+/// What `$name` is replaced with before parsing a pattern
+const METAVARIABLE_PREFIX: &str = "__rnix_search_";
+
+/// A parsed search pattern.
+///
+/// Patterns are Nix expressions in which `_` matches any expression or
+/// attribute name, and `$name` does the same while capturing what it matched.
+/// A metavariable used more than once has to match the same code each time.
+/// Whitespace and comments are ignored. An attribute set in a pattern matches
+/// attribute sets containing its entries in any order, along with any other
+/// entries, so `fetchurl { url = $url; }` matches every call to `fetchurl`
+/// with a `url`.
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    node: SyntaxNode,
+}
+
+/// A piece of code matching a pattern
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Match {
+    pub node: SyntaxNode,
+    /// What each metavariable matched, by name without the `$`
+    pub captures: BTreeMap<String, SyntaxNode>,
+}
+
+impl Match {
+    pub fn range(&self) -> TextRange {
+        self.node.text_range()
+    }
+}
+
+impl Pattern {
+    pub fn parse(source: &str) -> Result<Self, ParseError> {
+        let mut rewritten = String::with_capacity(source.len());
+        let mut tokens = tokenize(source).into_iter().peekable();
+        while let Some((kind, text)) = tokens.next() {
+            match tokens.peek() {
+                Some((TOKEN_IDENT, _)) if kind == TOKEN_ERROR && text == "$" => {
+                    rewritten.push_str(METAVARIABLE_PREFIX)
+                }
+                _ => rewritten.push_str(text),
+            }
+        }
+        let root = Root::parse(&rewritten).ok()?;
+        let node = root.expr().expect("parsed root has an expression").syntax().clone();
+        Ok(Self { node })
+    }
+
+    /// Find all nodes below and including `target` matching the pattern, in
+    /// the order they start. Matches can be nested in each other.
+    pub fn find(&self, target: &SyntaxNode) -> Vec<Match> {
+        target
+            .descendants()
+            .filter_map(|node| {
+                let mut captures = BTreeMap::new();
+                match_node(&self.node, &node, &mut captures).then_some(Match { node, captures })
+            })
+            .collect()
+    }
+}
+
+/// Parse a pattern and find all matches of it, see [`Pattern`]
+pub fn find(pattern: &str, target: &SyntaxNode) -> Result<Vec<Match>, ParseError> {
+    Ok(Pattern::parse(pattern)?.find(target))
+}
+
+/// `Some(None)` for `_`, and `Some(Some(name))` for `$name`
+fn wildcard(node: &SyntaxNode) -> Option<Option<String>> {
+    if node.kind() != NODE_IDENT {
+        return None;
+    }
+    let text = node.text().to_string();
+    match text.strip_prefix(METAVARIABLE_PREFIX) {
+        Some(name) => Some(Some(name.to_string())),
+        None => (text == "_").then_some(None),
+    }
+}
+
+fn match_node(
+    pattern: &SyntaxNode,
+    target: &SyntaxNode,
+    captures: &mut BTreeMap<String, SyntaxNode>,
+) -> bool {
+    match wildcard(pattern) {
+        Some(None) => return true,
+        Some(Some(name)) => {
+            return match captures.get(&name) {
+                Some(captured) => same(captured, target),
+                None => {
+                    captures.insert(name, target.clone());
+                    true
+                }
+            }
+        }
+        None => (),
+    }
+    if pattern.kind() != target.kind() {
+        return false;
+    }
+    let (pattern_children, target_children) = (significant(pattern), significant(target));
+    if pattern.kind() == NODE_ATTR_SET {
+        let (pattern_tokens, pattern_entries) = split_entries(pattern_children);
+        let (target_tokens, target_entries) = split_entries(target_children);
+        return pattern_tokens.len() == target_tokens.len()
+            && pattern_tokens.iter().zip(&target_tokens).all(|(p, t)| p.text() == t.text())
+            && match_entries(&pattern_entries, &target_entries, &mut Vec::new(), captures);
+    }
+    pattern_children.len() == target_children.len()
+        && pattern_children.iter().zip(&target_children).all(|pair| match pair {
+            (NodeOrToken::Node(p), NodeOrToken::Node(t)) => match_node(p, t, captures),
+            (NodeOrToken::Token(p), NodeOrToken::Token(t)) => {
+                p.kind() == t.kind() && p.text() == t.text()
+            }
+            _ => false,
+        })
+}
+
+/// Match each pattern entry to a different target entry, trying all
+/// assignments until one works
+fn match_entries(
+    patterns: &[SyntaxNode],
+    targets: &[SyntaxNode],
+    used: &mut Vec<usize>,
+    captures: &mut BTreeMap<String, SyntaxNode>,
+) -> bool {
+    let Some((pattern, rest)) = patterns.split_first() else {
+        return true;
+    };
+    for (i, target) in targets.iter().enumerate() {
+        if used.contains(&i) {
+            continue;
+        }
+        let mut attempt = captures.clone();
+        used.push(i);
+        if match_node(pattern, target, &mut attempt)
+            && match_entries(rest, targets, used, &mut attempt)
+        {
+            *captures = attempt;
+            return true;
+        }
+        used.pop();
+    }
+    false
+}
+
+fn significant(node: &SyntaxNode) -> Vec<SyntaxElement> {
+    node.children_with_tokens()
+        .filter(|element| !matches!(element.kind(), TOKEN_WHITESPACE | TOKEN_COMMENT))
+        .collect()
+}
+
+/// Split the children of an attribute set into the tokens like `rec` and the
+/// entries
+fn split_entries(children: Vec<SyntaxElement>) -> (Vec<SyntaxToken>, Vec<SyntaxNode>) {
+    let mut tokens = Vec::new();
+    let mut entries = Vec::new();
+    for child in children {
+        match child {
+            NodeOrToken::Node(node) => entries.push(node),
+            NodeOrToken::Token(token) => tokens.push(token),
+        }
+    }
+    (tokens, entries)
+}
+
+/// Whether two nodes are the same code, ignoring whitespace and comments
+fn same(a: &SyntaxNode, b: &SyntaxNode) -> bool {
+    let tokens = |node: &SyntaxNode| {
+        node.descendants_with_tokens()
+            .filter_map(NodeOrToken::into_token)
+            .filter(|token| !matches!(token.kind(), TOKEN_WHITESPACE | TOKEN_COMMENT))
+            .map(|token| (token.kind(), token.text().to_string()))
+            .collect::<Vec<_>>()
+    };
+    a.kind() == b.kind() && tokens(a) == tokens(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, target: &str) -> Vec<(String, Vec<(String, String)>)> {
+        let root = Root::parse(target).syntax();
+        find(pattern, &root)
+            .unwrap()
+            .into_iter()
+            .map(|m| {
+                let captures = m
+                    .captures
+                    .iter()
+                    .map(|(name, node)| (name.clone(), node.text().to_string()))
+                    .collect();
+                (m.node.text().to_string(), captures)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn wildcards() {
+        assert_eq!(
+            matches(
+                "lib.mkIf _ $x",
+                "{ a = lib.mkIf cond { b = 1; }; c = lib.mkIf (x) # c\n  2; }"
+            ),
+            [
+                ("lib.mkIf cond { b = 1; }".into(), vec![("x".into(), "{ b = 1; }".into())]),
+                ("lib.mkIf (x) # c\n  2".into(), vec![("x".into(), "2".into())]),
+            ]
+        );
+        assert_eq!(matches("$x + $x", "[ (a + a) (a + b) (f 1 + f  1) ]").len(), 2);
+        assert!(matches("f 1", "f 2").is_empty());
+    }
+
+    #[test]
+    fn attr_sets() {
+        let target = r#"fetchurl { sha256 = "abc"; url = "https://example.org"; }"#;
+        assert_eq!(
+            matches("fetchurl { url = $url; }", target),
+            [(target.into(), vec![("url".into(), r#""https://example.org""#.into())])]
+        );
+        assert_eq!(matches("{ $name = _; sha256 = _; }", target).len(), 1);
+        assert!(matches("fetchurl { hash = _; }", target).is_empty());
+        assert!(matches("rec { }", "{ }").is_empty());
+    }
+}
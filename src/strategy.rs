@@ -0,0 +1,254 @@
+//! [proptest] strategies generating well-formed Nix expressions, built with
+//! `--features proptest`.
+//!
+//! The expressions parse without errors and cover all kinds of expression
+//! nodes. Operands are parenthesized unless they are atomic, so the generated
+//! code doesn't depend on precedence, but parentheses in the generated code
+//! still appear in the tree as [`ast::Paren`](crate::ast::Paren) nodes.
+
+use proptest::{collection, option, prelude::*, sample};
+
+use crate::{
+    value::{escape_indented, escape_string},
+    Root,
+};
+
+const KEYWORDS: &[&str] =
+    &["assert", "else", "if", "in", "inherit", "let", "or", "rec", "then", "with"];
+
+/// Generated code and whether it can be used as an operand without
+/// parentheses
+#[derive(Clone, Debug)]
+struct Code {
+    source: String,
+    atomic: bool,
+}
+
+impl Code {
+    fn atomic(source: String) -> Self {
+        Self { source, atomic: true }
+    }
+    fn compound(source: String) -> Self {
+        Self { source, atomic: false }
+    }
+    /// The code, in parentheses unless it is atomic
+    fn operand(&self) -> String {
+        if self.atomic {
+            self.source.clone()
+        } else {
+            format!("({})", self.source)
+        }
+    }
+    /// The code, in parentheses unless it is atomic and can be directly
+    /// followed by `.` or preceded by `-` without changing how it is
+    /// tokenized, which isn't the case for numbers and paths
+    fn prefix_operand(&self) -> String {
+        if self.source.starts_with(|c: char| c.is_ascii_digit() || "./~".contains(c)) {
+            format!("({})", self.source)
+        } else {
+            self.operand()
+        }
+    }
+}
+
+/// An identifier which isn't a keyword
+pub fn ident() -> impl Strategy<Value = String> {
+    "[a-z_][a-zA-Z0-9_'-]{0,6}"
+        .prop_filter("keywords aren't identifiers", |ident| !KEYWORDS.contains(&ident.as_str()))
+}
+
+/// A Nix expression with at most `depth` levels of nesting
+pub fn expr(depth: u32) -> impl Strategy<Value = String> {
+    code(depth).prop_map(|code| code.source)
+}
+
+/// A parsed Nix expression with at most `depth` levels of nesting, see
+/// [`expr`]
+pub fn root(depth: u32) -> impl Strategy<Value = Root> {
+    expr(depth).prop_map(|source| Root::parse(&source).ok().expect("generated code is valid"))
+}
+
+fn leaf() -> impl Strategy<Value = Code> {
+    prop_oneof![
+        ident().prop_map(Code::atomic),
+        sample::select(vec!["true", "false", "null"]).prop_map(|s| Code::atomic(s.into())),
+        (0..10_000i64).prop_map(|integer| Code::atomic(integer.to_string())),
+        "[1-9][0-9]{0,2}\\.[0-9]{1,3}".prop_map(Code::atomic),
+        "[ -~\n\t]{0,10}".prop_map(|s| Code::atomic(format!("\"{}\"", escape_string(&s)))),
+        collection::vec("[ -~]{0,10}", 1..4).prop_map(|lines| {
+            let lines: Vec<String> = lines.iter().map(|line| escape_indented(line)).collect();
+            Code::atomic(format!("''\n  {}\n''", lines.join("\n  ")))
+        }),
+        "(\\.|\\.\\.|~)?(/[a-z0-9_.-]{1,5}){1,3}".prop_map(Code::atomic),
+        "[a-z]{1,6}(/[a-z]{1,4}){0,2}".prop_map(|path| Code::atomic(format!("<{}>", path))),
+    ]
+}
+
+/// A static attribute name, which is either an identifier or a string
+fn static_attr() -> impl Strategy<Value = String> {
+    prop_oneof![
+        4 => ident(),
+        1 => "[ -~]{0,6}".prop_map(|s| format!("\"{}\"", escape_string(&s))),
+    ]
+}
+
+/// An attribute name, which is either static or an interpolation
+fn attr(inner: BoxedStrategy<Code>) -> impl Strategy<Value = String> {
+    prop_oneof![
+        5 => static_attr(),
+        1 => inner.prop_map(|code| format!("${{{}}}", code.source)),
+    ]
+}
+
+/// The entries of an attribute set or `let`, with distinct names, each ending
+/// with a space. Only attribute sets can have interpolations in names.
+fn bindings(inner: BoxedStrategy<Code>, dynamic: bool) -> impl Strategy<Value = String> {
+    let values = collection::btree_map(ident(), inner.clone(), 0..4);
+    let attr = if dynamic { attr(inner.clone()).boxed() } else { static_attr().boxed() };
+    let nested = collection::vec((attr, inner.clone()), 0..2);
+    let inherits = collection::btree_set(ident(), 0..3);
+    (values, nested, inherits, option::of(inner)).prop_map(|(values, nested, inherits, from)| {
+        let mut out = String::new();
+        for (name, value) in &values {
+            out.push_str(&format!("{} = {}; ", name, value.source));
+        }
+        // Nested under a prefix which can't clash with the names above
+        for (i, (attr, value)) in nested.iter().enumerate() {
+            out.push_str(&format!("__nested{}.{} = {}; ", i, attr, value.source));
+        }
+        let inherits: Vec<&String> =
+            inherits.iter().filter(|name| !values.contains_key(*name)).collect();
+        if !inherits.is_empty() {
+            let from = from.map(|from| format!("({}) ", from.source)).unwrap_or_default();
+            let names: Vec<&str> = inherits.iter().map(|name| name.as_str()).collect();
+            out.push_str(&format!("inherit {}{}; ", from, names.join(" ")));
+        }
+        out
+    })
+}
+
+fn code(depth: u32) -> impl Strategy<Value = Code> {
+    leaf().prop_recursive(depth, 64, 4, |inner| {
+        let operators = sample::select(vec![
+            "+", "-", "*", "/", "++", "//", "==", "!=", "<", "<=", ">", ">=", "&&", "||", "->",
+        ]);
+        prop_oneof![
+            collection::vec(inner.clone(), 0..4).prop_map(|items| {
+                let items: Vec<String> = items.iter().map(Code::operand).collect();
+                Code::atomic(format!(
+                    "[ {} ]",
+                    items.iter().map(|item| format!("{} ", item)).collect::<String>()
+                ))
+            }),
+            (any::<bool>(), bindings(inner.clone(), true)).prop_map(|(rec, bindings)| {
+                Code::atomic(format!("{}{{ {}}}", if rec { "rec " } else { "" }, bindings))
+            }),
+            (inner.clone(), operators, inner.clone()).prop_map(|(lhs, op, rhs)| {
+                Code::compound(format!("{} {} {}", lhs.operand(), op, rhs.operand()))
+            }),
+            (sample::select(vec!["!", "-"]), inner.clone()).prop_map(|(op, operand)| {
+                Code::compound(format!("{}{}", op, operand.prefix_operand()))
+            }),
+            (inner.clone(), inner.clone()).prop_map(|(function, argument)| {
+                Code::compound(format!("{} {}", function.operand(), argument.operand()))
+            }),
+            (inner.clone(), collection::vec(attr(inner.clone()), 1..3), option::of(inner.clone()))
+                .prop_map(|(set, path, default)| {
+                    let select = format!("{}.{}", set.prefix_operand(), path.join("."));
+                    match default {
+                        Some(default) => {
+                            Code::compound(format!("{} or {}", select, default.operand()))
+                        }
+                        None => Code::atomic(select),
+                    }
+                }),
+            (inner.clone(), collection::vec(attr(inner.clone()), 1..3)).prop_map(|(set, path)| {
+                Code::compound(format!("{} ? {}", set.prefix_operand(), path.join(".")))
+            }),
+            (ident(), inner.clone())
+                .prop_map(|(param, body)| Code::compound(format!("{}: {}", param, body.source))),
+            (
+                collection::btree_map(ident(), option::of(inner.clone()), 0..3),
+                any::<bool>(),
+                option::of(ident()),
+                inner.clone()
+            )
+                .prop_map(|(formals, ellipsis, bind, body)| {
+                    let mut entries: Vec<String> = formals
+                        .iter()
+                        .map(|(name, default)| match default {
+                            Some(default) => format!("{} ? {}", name, default.source),
+                            None => name.clone(),
+                        })
+                        .collect();
+                    if ellipsis {
+                        entries.push("...".into());
+                    }
+                    let bind = bind
+                        .filter(|bind| !formals.contains_key(bind))
+                        .map(|bind| format!(" @ {}", bind))
+                        .unwrap_or_default();
+                    Code::compound(format!("{{ {} }}{}: {}", entries.join(", "), bind, body.source))
+                }),
+            (bindings(inner.clone(), false), inner.clone()).prop_map(|(bindings, body)| {
+                Code::compound(format!("let {}in {}", bindings, body.source))
+            }),
+            (inner.clone(), inner.clone(), inner.clone()).prop_map(|(cond, then, otherwise)| {
+                Code::compound(format!(
+                    "if {} then {} else {}",
+                    cond.source, then.source, otherwise.source
+                ))
+            }),
+            (sample::select(vec!["with", "assert"]), inner.clone(), inner.clone()).prop_map(
+                |(keyword, first, body)| {
+                    Code::compound(format!("{} {}; {}", keyword, first.source, body.source))
+                }
+            ),
+            ("[a-z ]{0,4}", inner.clone(), "[a-z ]{0,4}").prop_map(|(before, code, after)| {
+                Code::atomic(format!("\"{}${{{}}}{}\"", before, code.source, after))
+            }),
+            inner.prop_map(|code| Code::atomic(format!("({})", code.source))),
+        ]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{format, SyntaxKind, SyntaxNode};
+
+    fn tokens(node: &SyntaxNode) -> Vec<(SyntaxKind, String)> {
+        node.descendants_with_tokens()
+            .filter_map(|element| element.into_token())
+            .filter(|token| !token.kind().is_trivia())
+            .map(|token| (token.kind(), token.text().to_string()))
+            .collect()
+    }
+
+    proptest! {
+        #[test]
+        fn generated_code_parses(source in expr(4)) {
+            let parse = Root::parse(&source);
+            prop_assert!(parse.errors().is_empty(), "{:?} in {}", parse.errors(), source);
+            // Nix rejects interpolations in the names of `let` bindings
+            let dynamic_let = parse.syntax().descendants().any(|node| {
+                node.kind() == SyntaxKind::NODE_DYNAMIC
+                    && node.ancestors().skip(1).find_map(|node| match node.kind() {
+                        SyntaxKind::NODE_ATTRPATH_VALUE => None,
+                        SyntaxKind::NODE_ATTRPATH => None,
+                        kind => Some(kind),
+                    }) == Some(SyntaxKind::NODE_LET_IN)
+            });
+            prop_assert!(!dynamic_let, "dynamic attribute in let in {}", source);
+            prop_assert_eq!(parse.syntax().to_string(), source);
+        }
+
+        #[test]
+        fn formatting_keeps_tokens(source in expr(3)) {
+            let formatted = format::format(&source, &format::FormatOptions::default()).unwrap();
+            let reparsed = Root::parse(&formatted);
+            prop_assert!(reparsed.errors().is_empty(), "{:?} in {}", reparsed.errors(), formatted);
+            prop_assert_eq!(tokens(&reparsed.syntax()), tokens(&Root::parse(&source).syntax()));
+        }
+    }
+}
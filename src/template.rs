@@ -0,0 +1,356 @@
+//! Templates: Nix files with holes like `__RNIX_HOLE_version__`, which are
+//! filled with expressions or values while keeping the rest of the file as it
+//! is written
+
+use std::{collections::HashMap, fmt};
+
+use rowan::{ast::AstNode, TextRange};
+
+use crate::{
+    analysis::static_str,
+    ast,
+    parser::ParseError,
+    rewrite::{apply_edits, TextEdit},
+    tokenize,
+    value::{escape_indented, escape_string, Value},
+    Root,
+    SyntaxKind::*,
+    SyntaxNode,
+};
+
+const HOLE_PREFIX: &str = "__RNIX_HOLE_";
+const HOLE_SUFFIX: &str = "__";
+
+/// Where a hole is, which decides what it can be filled with
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HoleKind {
+    /// In place of an expression, like `version = __RNIX_HOLE_version__;`.
+    /// Filled expressions are wrapped in parentheses where needed.
+    Expr,
+    /// The only part of an interpolation, like `"v${__RNIX_HOLE_version__}"`.
+    /// Strings are inserted into the surrounding string, anything else is
+    /// interpolated.
+    Interpolation,
+    /// An attribute name, like `{ __RNIX_HOLE_name__ = 1; }` or
+    /// `inherit __RNIX_HOLE_name__;`, which can be filled with any string
+    Attr,
+    /// The name of a function argument, which has to be an identifier
+    Name,
+}
+
+/// A hole in a template
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Hole {
+    /// The name, without `__RNIX_HOLE_` and the trailing `__`
+    pub name: String,
+    pub range: TextRange,
+    pub kind: HoleKind,
+}
+
+/// What a hole is filled with
+#[derive(Clone, Debug)]
+pub enum Fill {
+    /// An expression, printed as written
+    Expr(ast::Expr),
+    /// A constant value, printed as a literal
+    Value(Value),
+}
+
+impl From<ast::Expr> for Fill {
+    fn from(expr: ast::Expr) -> Self {
+        Fill::Expr(expr)
+    }
+}
+
+impl From<Value> for Fill {
+    fn from(value: Value) -> Self {
+        Fill::Value(value)
+    }
+}
+
+/// The reason a template can't be filled
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TemplateError {
+    /// No fill was given for the hole with this name
+    Unfilled(String),
+    /// A fill was given for a name which has no hole
+    Unused(String),
+    /// The fill can't be put into the hole at the given range, like a list in
+    /// place of an attribute name
+    Mismatch { name: String, kind: HoleKind, range: TextRange },
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::Unfilled(name) => write!(f, "hole `{}` isn't filled", name),
+            TemplateError::Unused(name) => write!(f, "template has no hole `{}`", name),
+            TemplateError::Mismatch { name, kind, range } => write!(
+                f,
+                "hole `{}` at {}..{} can't be filled with this, expected {}",
+                name,
+                usize::from(range.start()),
+                usize::from(range.end()),
+                match kind {
+                    HoleKind::Expr | HoleKind::Interpolation => "an expression",
+                    HoleKind::Attr => "a string",
+                    HoleKind::Name => "an identifier",
+                }
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+/// A parsed template
+#[derive(Clone, Debug)]
+pub struct Template {
+    source: String,
+    root: SyntaxNode,
+    holes: Vec<Hole>,
+}
+
+impl Template {
+    /// Parse a template, which has to be valid Nix
+    pub fn parse(source: &str) -> Result<Self, ParseError> {
+        let root = Root::parse(source).ok()?.syntax().clone();
+        let holes = root
+            .descendants()
+            .filter(|node| node.kind() == NODE_IDENT)
+            .filter_map(|node| {
+                let name = hole_name(&node.text().to_string())?.to_string();
+                let parent = node.parent()?;
+                let kind = match parent.kind() {
+                    NODE_ATTRPATH | NODE_INHERIT => HoleKind::Attr,
+                    NODE_IDENT_PARAM | NODE_PAT_BIND => HoleKind::Name,
+                    // The default of `{ x ? y }` is an expression
+                    NODE_PAT_ENTRY if parent.first_child().as_ref() == Some(&node) => {
+                        HoleKind::Name
+                    }
+                    NODE_INTERPOL if parent.parent()?.kind() == NODE_STRING => {
+                        HoleKind::Interpolation
+                    }
+                    _ => HoleKind::Expr,
+                };
+                Some(Hole { name, range: node.text_range(), kind })
+            })
+            .collect();
+        Ok(Self { source: source.to_string(), root, holes })
+    }
+
+    /// All holes, in the order they appear. A name can be used for multiple
+    /// holes, which are all filled with the same fill.
+    pub fn holes(&self) -> &[Hole] {
+        &self.holes
+    }
+
+    /// Fill every hole, returning the resulting source code. There has to be
+    /// a fill for each hole name, and a hole for each fill.
+    pub fn fill(&self, fills: &HashMap<String, Fill>) -> Result<String, TemplateError> {
+        let mut unused: Vec<&String> =
+            fills.keys().filter(|name| self.holes.iter().all(|hole| hole.name != **name)).collect();
+        unused.sort();
+        if let Some(name) = unused.first() {
+            return Err(TemplateError::Unused(name.to_string()));
+        }
+
+        let mut edits = Vec::new();
+        for hole in &self.holes {
+            let fill = fills.get(&hole.name).ok_or(TemplateError::Unfilled(hole.name.clone()))?;
+            let mismatch = || TemplateError::Mismatch {
+                name: hole.name.clone(),
+                kind: hole.kind,
+                range: hole.range,
+            };
+            let text = match fill {
+                Fill::Expr(expr) => expr.syntax().to_string(),
+                Fill::Value(value) => value.to_string(),
+            };
+            let edit = match (hole.kind, fill) {
+                (HoleKind::Expr, _) if self.needs_parens(hole, fill) => {
+                    TextEdit::replace(hole.range, format!("({})", text))
+                }
+                (HoleKind::Expr, _) => TextEdit::replace(hole.range, text),
+                (HoleKind::Interpolation, Fill::Value(Value::String(string, _))) => {
+                    let interpol = self.node_at(hole).parent().expect("hole is interpolated");
+                    let string_node = interpol.parent().expect("interpolation is in a string");
+                    let escaped = match string_node.first_token() {
+                        Some(token) if token.text() == "''" => escape_indented(string),
+                        _ => escape_string(string),
+                    };
+                    TextEdit::replace(interpol.text_range(), escaped)
+                }
+                (HoleKind::Interpolation, _) => TextEdit::replace(hole.range, text),
+                (HoleKind::Attr, Fill::Value(Value::String(string, _))) if is_ident(string) => {
+                    TextEdit::replace(hole.range, string.clone())
+                }
+                (HoleKind::Attr, Fill::Value(Value::String(..))) => {
+                    TextEdit::replace(hole.range, text)
+                }
+                (HoleKind::Attr, Fill::Expr(ast::Expr::Ident(_))) => {
+                    TextEdit::replace(hole.range, text)
+                }
+                (HoleKind::Attr, Fill::Expr(ast::Expr::Str(string)))
+                    if static_str(string).is_some() =>
+                {
+                    TextEdit::replace(hole.range, text)
+                }
+                (HoleKind::Name, Fill::Value(Value::String(string, _))) if is_ident(string) => {
+                    TextEdit::replace(hole.range, string.clone())
+                }
+                (HoleKind::Name, Fill::Expr(ast::Expr::Ident(_))) => {
+                    TextEdit::replace(hole.range, text)
+                }
+                _ => return Err(mismatch()),
+            };
+            edits.push(edit);
+        }
+        Ok(apply_edits(&self.source, &edits))
+    }
+
+    fn node_at(&self, hole: &Hole) -> SyntaxNode {
+        self.root
+            .descendants()
+            .find(|node| node.kind() == NODE_IDENT && node.text_range() == hole.range)
+            .expect("hole is an identifier")
+    }
+
+    /// Whether an expression hole has to be filled with parentheses around
+    /// the fill, because it is an operand or argument and the fill isn't
+    /// atomic
+    fn needs_parens(&self, hole: &Hole, fill: &Fill) -> bool {
+        let atomic = match fill {
+            Fill::Expr(expr) => matches!(
+                expr,
+                ast::Expr::Ident(_)
+                    | ast::Expr::Literal(_)
+                    | ast::Expr::Str(_)
+                    | ast::Expr::Path(_)
+                    | ast::Expr::List(_)
+                    | ast::Expr::AttrSet(_)
+                    | ast::Expr::Paren(_)
+                    | ast::Expr::Select(_)
+            ),
+            Fill::Value(Value::Integer(integer, _)) => !integer.is_negative(),
+            // `-0.0` is printed with a minus too
+            Fill::Value(Value::Float(float, _)) => !float.is_sign_negative(),
+            Fill::Value(_) => true,
+        };
+        !atomic
+            && !matches!(
+                self.node_at(hole).parent().map(|parent| parent.kind()),
+                Some(
+                    NODE_ROOT
+                        | NODE_ATTRPATH_VALUE
+                        | NODE_PAREN
+                        | NODE_INTERPOL
+                        | NODE_LET_IN
+                        | NODE_LAMBDA
+                        | NODE_PAT_ENTRY
+                        | NODE_ASSERT
+                        | NODE_WITH
+                        | NODE_IF_ELSE
+                )
+            )
+    }
+}
+
+/// The name of a hole if the identifier is one
+fn hole_name(ident: &str) -> Option<&str> {
+    let name = ident.strip_prefix(HOLE_PREFIX)?.strip_suffix(HOLE_SUFFIX)?;
+    (!name.is_empty()).then_some(name)
+}
+
+pub(crate) fn is_ident(name: &str) -> bool {
+    matches!(tokenize(name)[..], [(TOKEN_IDENT, ident)] if ident == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expr(src: &str) -> Fill {
+        Fill::Expr(Root::parse(src).ok().unwrap().expr().unwrap())
+    }
+
+    #[test]
+    fn fill() {
+        let template = Template::parse(
+            r#"{ __RNIX_HOLE_pname__, lib, ... }:
+# Keep this comment
+{
+  __RNIX_HOLE_attr__ = __RNIX_HOLE_version__;
+  name = "${__RNIX_HOLE_pname__}-${__RNIX_HOLE_version__}";
+  deps = [ __RNIX_HOLE_dep__ ];
+  inherit __RNIX_HOLE_pname__;
+}
+"#,
+        )
+        .unwrap();
+        let kinds: Vec<_> = template.holes().iter().map(|hole| (&*hole.name, hole.kind)).collect();
+        assert_eq!(
+            kinds,
+            [
+                ("pname", HoleKind::Name),
+                ("attr", HoleKind::Attr),
+                ("version", HoleKind::Expr),
+                ("pname", HoleKind::Interpolation),
+                ("version", HoleKind::Interpolation),
+                ("dep", HoleKind::Expr),
+                ("pname", HoleKind::Attr),
+            ]
+        );
+
+        let mut fills = HashMap::new();
+        fills.insert("pname".to_string(), Fill::Value("hello".into()));
+        fills.insert("attr".to_string(), Fill::Value("with space".into()));
+        fills.insert("version".to_string(), expr("lib.version"));
+        fills.insert("dep".to_string(), expr("f x"));
+        assert_eq!(
+            template.fill(&fills).unwrap(),
+            r#"{ hello, lib, ... }:
+# Keep this comment
+{
+  "with space" = lib.version;
+  name = "hello-${lib.version}";
+  deps = [ (f x) ];
+  inherit hello;
+}
+"#
+        );
+
+        fills.insert("pname".to_string(), Fill::Value("a b".into()));
+        assert_eq!(
+            template.fill(&fills),
+            Err(TemplateError::Mismatch {
+                name: "pname".into(),
+                kind: HoleKind::Name,
+                range: TextRange::new(2.into(), 21.into())
+            })
+        );
+        fills.insert("pname".to_string(), Fill::Value("hello".into()));
+        fills.insert("other".to_string(), Fill::Value(1.into()));
+        assert_eq!(template.fill(&fills), Err(TemplateError::Unused("other".into())));
+        fills.remove("other");
+        fills.remove("dep");
+        assert_eq!(template.fill(&fills), Err(TemplateError::Unfilled("dep".into())));
+    }
+
+    #[test]
+    fn expressions() {
+        let template =
+            Template::parse("{ __RNIX_HOLE_x__ ? __RNIX_HOLE_y__ }: f __RNIX_HOLE_z__").unwrap();
+        let kinds: Vec<_> = template.holes().iter().map(|hole| (&*hole.name, hole.kind)).collect();
+        assert_eq!(kinds, [("x", HoleKind::Name), ("y", HoleKind::Expr), ("z", HoleKind::Expr)]);
+
+        let mut fills = HashMap::new();
+        fills.insert("x".to_string(), Fill::Value("a".into()));
+        fills.insert("y".to_string(), expr("g 1"));
+        fills.insert("z".to_string(), Fill::Value((-0.0).into()));
+        assert_eq!(template.fill(&fills).unwrap(), "{ a ? g 1 }: f (-0.0)");
+        fills.insert("z".to_string(), Fill::Value(0.0.into()));
+        assert_eq!(template.fill(&fills).unwrap(), "{ a ? g 1 }: f 0.0");
+    }
+}
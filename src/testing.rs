@@ -0,0 +1,281 @@
+//! Snapshot tests over a corpus of Nix files, for validating changes to the
+//! grammar against a large body of code like nixpkgs
+//!
+//! [`check_snapshots`] parses every `.nix` file in a directory and compares a
+//! dump of it with a stored `.expect` file, in the same format as the tests of
+//! this crate in `test_data/parser`:
+//!
+//! ```no_run
+//! use rnix::testing::{check_snapshots, parse_snapshot, SnapshotOptions};
+//!
+//! let options = SnapshotOptions {
+//!     snapshot_dir: Some("snapshots".into()),
+//!     update: std::env::var_os("UPDATE_EXPECT").is_some(),
+//! };
+//! let mismatches = check_snapshots("path/to/nixpkgs".as_ref(), &options, parse_snapshot)?;
+//! for mismatch in &mismatches {
+//!     eprintln!("{}", mismatch);
+//! }
+//! assert!(mismatches.is_empty());
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! [`roundtrip`] and [`check_reparse`] check that printing a tree and parsing
+//! it again gives back the same tree, for tests of code creating or editing
+//! trees.
+
+use std::{
+    error::Error,
+    fmt::{self, Write},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    dump::{diff, DumpDiff, DumpElement},
+    Root, SyntaxNode,
+};
+
+/// Where snapshots are stored and whether to update them
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SnapshotOptions {
+    /// The directory to store snapshots in, mirroring the layout of the
+    /// directory of sources. Without one, snapshots are stored next to the
+    /// sources.
+    pub snapshot_dir: Option<PathBuf>,
+    /// Write the snapshots instead of comparing them
+    pub update: bool,
+}
+
+/// A snapshot which differs from the stored one
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SnapshotMismatch {
+    pub source: PathBuf,
+    pub snapshot: PathBuf,
+    /// The stored snapshot, or `None` if there is none
+    pub expected: Option<String>,
+    pub actual: String,
+}
+
+impl fmt::Display for SnapshotMismatch {
+    /// Print the paths and the first differing line, with some lines before it
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(expected) = &self.expected else {
+            return write!(
+                f,
+                "{}: missing snapshot {}",
+                self.source.display(),
+                self.snapshot.display()
+            );
+        };
+        writeln!(f, "{}: differs from {}", self.source.display(), self.snapshot.display())?;
+        let expected: Vec<&str> = expected.lines().collect();
+        let actual: Vec<&str> = self.actual.lines().collect();
+        let first = expected.iter().zip(&actual).take_while(|(a, b)| a == b).count();
+        for line in &actual[first.saturating_sub(CONTEXT)..first] {
+            writeln!(f, "  {}", line)?;
+        }
+        match expected.get(first) {
+            Some(line) => writeln!(f, "- {}", line)?,
+            None => writeln!(f, "- <end of snapshot>")?,
+        }
+        match actual.get(first) {
+            Some(line) => write!(f, "+ {}", line),
+            None => write!(f, "+ <end of snapshot>"),
+        }
+    }
+}
+
+const CONTEXT: usize = 3;
+
+/// The parse errors of a source followed by its tree
+pub fn parse_snapshot(source: &str) -> String {
+    let parse = Root::parse(source);
+    let mut out = String::new();
+    for error in parse.errors() {
+        writeln!(out, "error: {}", error).unwrap();
+    }
+    writeln!(out, "{:#?}", parse.syntax()).unwrap();
+    out
+}
+
+/// Find all `.nix` files in a directory and its subdirectories, sorted
+pub fn nix_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "nix") {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Compare the snapshots of all `.nix` files in a directory with the stored
+/// ones, returning those which differ. The snapshot of `a/b.nix` is stored in
+/// `a/b.expect`. With [`SnapshotOptions::update`], the snapshots are written
+/// instead and nothing is returned.
+pub fn check_snapshots(
+    dir: &Path,
+    options: &SnapshotOptions,
+    snapshot: impl Fn(&str) -> String,
+) -> io::Result<Vec<SnapshotMismatch>> {
+    let mut mismatches = Vec::new();
+    for source in nix_files(dir)? {
+        let relative = source.strip_prefix(dir).unwrap_or(&source);
+        let path = match &options.snapshot_dir {
+            Some(snapshot_dir) => snapshot_dir.join(relative),
+            None => source.clone(),
+        }
+        .with_extension("expect");
+
+        let actual = snapshot(&fs::read_to_string(&source)?);
+        let expected = match fs::read_to_string(&path) {
+            Ok(expected) => Some(expected),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err),
+        };
+        if expected.as_ref() == Some(&actual) {
+            continue;
+        }
+        if options.update {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, &actual)?;
+        } else {
+            mismatches.push(SnapshotMismatch { source, snapshot: path, expected, actual });
+        }
+    }
+    Ok(mismatches)
+}
+
+/// Why printing a tree and parsing it again doesn't give back the same tree
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RoundtripError {
+    /// The printed tree differs from the source it was parsed from, starting
+    /// at a byte offset
+    TextChanged { printed: String, offset: usize },
+    /// Parsing the printed tree gives a tree with another structure
+    StructureChanged { printed: String, diff: Box<DumpDiff> },
+}
+
+impl fmt::Display for RoundtripError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RoundtripError::TextChanged { printed, offset } => {
+                write!(
+                    f,
+                    "printed text differs from the source at offset {}: {:?}",
+                    offset, printed
+                )
+            }
+            RoundtripError::StructureChanged { printed, diff } => {
+                write!(f, "reparsing {:?} changes the tree: {}", printed, diff)
+            }
+        }
+    }
+}
+
+impl Error for RoundtripError {}
+
+/// Check that parsing a source and printing the tree gives back the source,
+/// and that parsing it again gives the same tree
+pub fn roundtrip(source: &str) -> Result<(), RoundtripError> {
+    let tree = Root::parse(source).syntax();
+    let printed = tree.to_string();
+    if printed != source {
+        let offset = printed.bytes().zip(source.bytes()).take_while(|(a, b)| a == b).count();
+        return Err(RoundtripError::TextChanged { printed, offset });
+    }
+    check_reparse(&tree)
+}
+
+/// Check that parsing the text of a tree gives back the same tree. A tree
+/// edited or built by hand may not, like a list item `a b` which is parsed as
+/// a function call.
+pub fn check_reparse(tree: &SyntaxNode) -> Result<(), RoundtripError> {
+    let printed = tree.to_string();
+    let reparsed = Root::parse(&printed).syntax();
+    match diff(&DumpElement::from_syntax(tree), &DumpElement::from_syntax(&reparsed)) {
+        Some(diff) => Err(RoundtripError::StructureChanged { printed, diff: Box::new(diff) }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshots() {
+        let dir = std::env::temp_dir().join(format!("rnix-snapshots-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let sources = dir.join("sources");
+        fs::create_dir_all(sources.join("nested")).unwrap();
+        fs::write(sources.join("a.nix"), "1 + 2").unwrap();
+        fs::write(sources.join("nested/b.nix"), "{ a = 1; }").unwrap();
+        fs::write(sources.join("nested/c.txt"), "not nix").unwrap();
+        assert_eq!(
+            nix_files(&sources).unwrap(),
+            [sources.join("a.nix"), sources.join("nested/b.nix")]
+        );
+
+        let mut options =
+            SnapshotOptions { snapshot_dir: Some(dir.join("snapshots")), update: false };
+        let missing = check_snapshots(&sources, &options, parse_snapshot).unwrap();
+        assert_eq!(missing.len(), 2);
+        assert_eq!(missing[1].snapshot, dir.join("snapshots/nested/b.expect"));
+        assert_eq!(missing[1].expected, None);
+
+        options.update = true;
+        assert_eq!(check_snapshots(&sources, &options, parse_snapshot).unwrap(), []);
+        options.update = false;
+        assert_eq!(check_snapshots(&sources, &options, parse_snapshot).unwrap(), []);
+
+        fs::write(sources.join("a.nix"), "1 - 2").unwrap();
+        let changed = check_snapshots(&sources, &options, parse_snapshot).unwrap();
+        assert_eq!(changed.len(), 1);
+        let message = changed[0].to_string();
+        assert!(
+            message.ends_with("-     TOKEN_ADD@2..3 \"+\"\n+     TOKEN_SUB@2..3 \"-\""),
+            "{}",
+            message
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn roundtrips() {
+        use crate::{NixLanguage, SyntaxKind::*};
+        use rowan::{GreenNodeBuilder, Language};
+
+        for source in ["", "{ a = 1; } # comment", "let in", "[ (a: a) ''\n  b'' ]", "}{"] {
+            assert_eq!(roundtrip(source), Ok(()), "{:?}", source);
+        }
+
+        let mut builder = GreenNodeBuilder::new();
+        for kind in [NODE_ROOT, NODE_LIST] {
+            builder.start_node(NixLanguage::kind_to_raw(kind));
+        }
+        builder.token(NixLanguage::kind_to_raw(TOKEN_L_BRACK), "[");
+        builder.start_node(NixLanguage::kind_to_raw(NODE_IDENT));
+        builder.token(NixLanguage::kind_to_raw(TOKEN_IDENT), "a b");
+        builder.finish_node();
+        builder.token(NixLanguage::kind_to_raw(TOKEN_R_BRACK), "]");
+        builder.finish_node();
+        builder.finish_node();
+        let tree = SyntaxNode::new_root(builder.finish());
+        assert_eq!(
+            check_reparse(&tree).unwrap_err().to_string(),
+            "reparsing \"[a b]\" changes the tree: trees differ at root > NODE_LIST[0] > \
+             NODE_IDENT[1]: (NODE_IDENT 1..4) vs (NODE_IDENT 1..2)"
+        );
+    }
+}
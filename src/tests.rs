@@ -5,7 +5,9 @@ use rowan::ast::AstNode;
 
 use crate::{
     ast::{self, HasEntry},
-    tokenize, Root, SyntaxKind,
+    tokenize,
+    tokenizer::print_tokens,
+    Root, SyntaxKind,
 };
 
 #[test]
@@ -71,6 +73,51 @@ fn t_macro() {
     assert!(matches!(SyntaxKind::TOKEN_L_PAREN, T!['(']));
 }
 
+#[test]
+fn strict() {
+    use crate::parser::{ParseError, ParserOptions};
+    let errors = |source: &str| {
+        Root::parse_with_options(source, ParserOptions::strict())
+            .errors()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(errors("https://nixos.org"), ["deprecated URI literal at 0..17"]);
+    assert_eq!(errors("let { body = 1; }"), ["deprecated legacy let at 0..3"]);
+    assert_eq!(errors("{ a.b = 1; a.b = 2; }"), ["attribute `a.b` is duplicated in 11..14"]);
+    assert_eq!(
+        errors("let a = 1; a = 2; in { x, x }: a"),
+        ["attribute `a` is duplicated in 11..12", "argument `x` is duplicated in 26..27"]
+    );
+    assert!(errors("let a.b = 1; a.c = 2; in [ \"https://nixos.org\" ]").is_empty());
+    // The defaults and the other options still accept all of it
+    let options = ParserOptions { reject_duplicates: true, ..ParserOptions::default() };
+    let parse = Root::parse_with_options("https://nixos.org", options);
+    assert!(parse.errors().is_empty());
+    let parse = Root::parse("let { a = 1; a = 2; body = a; }");
+    assert!(parse.errors().is_empty());
+    assert!(matches!(
+        Root::parse_with_options("let { body = 1; }", ParserOptions::strict()).errors(),
+        [ParseError::LegacyLet(_)]
+    ));
+}
+
+#[test]
+fn shared_cache() {
+    let mut cache = crate::NodeCache::default();
+    let a = Root::parse_with_cache("{ system = \"x86_64-linux\"; }", &mut cache);
+    let b = Root::parse_with_cache("[ \"x86_64-linux\" ]", &mut cache);
+    let string = |parse: &crate::Parse<Root>| {
+        let token = parse.syntax().descendants_with_tokens().find_map(|element| {
+            element.into_token().filter(|token| token.kind() == SyntaxKind::TOKEN_STRING_CONTENT)
+        });
+        token.unwrap().green() as *const _
+    };
+    assert_eq!(string(&a), string(&b));
+    assert_eq!(a.syntax().to_string(), "{ system = \"x86_64-linux\"; }");
+}
+
 fn dir_tests<F>(dir: &str, get_actual: F)
 where
     F: Fn(String) -> String,
@@ -103,6 +150,7 @@ where
 #[test]
 fn parser_dir_tests() {
     dir_tests("parser", |code| {
+        assert_eq!(print_tokens(tokenize(&code)), code);
         let parse = Root::parse(&code);
 
         let mut actual = String::new();
@@ -118,6 +166,7 @@ fn parser_dir_tests() {
 #[test]
 fn tokenizer_dir_tests() {
     dir_tests("tokenizer", |code| {
+        assert_eq!(print_tokens(tokenize(&code)), code);
         let mut actual = String::new();
         for (kind, str) in tokenize(&code) {
             writeln!(actual, "{:?}, \"{}\"", kind, str).unwrap();
@@ -125,3 +174,43 @@ fn tokenizer_dir_tests() {
         actual
     })
 }
+
+#[test]
+fn print_tokens_without_trivia() {
+    assert_eq!(
+        print_tokens(tokenize("a/* c */b # d\n+ ''x'' ''y''").into_iter().filter(|(kind, _)| {
+            !matches!(kind, SyntaxKind::TOKEN_WHITESPACE | SyntaxKind::TOKEN_COMMENT)
+        })),
+        "a b+''x'' ''y''"
+    );
+    assert_eq!(
+        print_tokens([
+            (SyntaxKind::TOKEN_COMMENT, "# a"),
+            (SyntaxKind::TOKEN_WHITESPACE, " "),
+            (SyntaxKind::TOKEN_IDENT, "b"),
+        ]),
+        "# a\n b"
+    );
+
+    // Dropping all trivia keeps the other tokens of every test file
+    let is_trivia = |kind: &SyntaxKind| {
+        matches!(kind, SyntaxKind::TOKEN_WHITESPACE | SyntaxKind::TOKEN_COMMENT)
+    };
+    for dir in ["parser", "tokenizer"] {
+        let path: PathBuf =
+            [env!("CARGO_MANIFEST_DIR"), "test_data", dir, "success"].iter().collect();
+        for entry in path.read_dir().unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension() != Some(OsStr::new("nix")) {
+                continue;
+            }
+            let code = fs::read_to_string(&path).unwrap();
+            let tokens: Vec<_> =
+                tokenize(&code).into_iter().filter(|(kind, _)| !is_trivia(kind)).collect();
+            let printed = print_tokens(tokens.iter().copied());
+            let reparsed: Vec<_> =
+                tokenize(&printed).into_iter().filter(|(kind, _)| !is_trivia(kind)).collect();
+            assert_eq!(reparsed, tokens, "{}", path.display());
+        }
+    }
+}
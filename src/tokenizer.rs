@@ -46,11 +46,125 @@ impl Eq for State<'_> {}
 
 pub type Token<'a> = (SyntaxKind, &'a str);
 
+/// Find the first of the given ASCII bytes. These can't be part of a
+/// multi-byte character, so the result is always at a character boundary.
+#[cfg(feature = "simd")]
+fn find_any(haystack: &[u8], needles: &[u8]) -> Option<usize> {
+    match *needles {
+        [a] => memchr::memchr(a, haystack),
+        [a, b] => memchr::memchr2(a, b, haystack),
+        [a, b, c] => memchr::memchr3(a, b, c, haystack),
+        _ => haystack.iter().position(|byte| needles.contains(byte)),
+    }
+}
+
+#[cfg(not(feature = "simd"))]
+fn find_any(haystack: &[u8], needles: &[u8]) -> Option<usize> {
+    haystack.iter().position(|byte| needles.contains(byte))
+}
+
+#[cfg(feature = "simd")]
+fn find_comment_end(haystack: &[u8]) -> Option<usize> {
+    memchr::memmem::find(haystack, b"*/")
+}
+
+#[cfg(not(feature = "simd"))]
+fn find_comment_end(haystack: &[u8]) -> Option<usize> {
+    haystack.windows(2).position(|window| window == b"*/")
+}
+
 /// A convenience function for tokenizing the given input
 pub fn tokenize(input: &str) -> Vec<Token<'_>> {
     Tokenizer::new(input).collect()
 }
 
+/// Turn tokens back into source text. The text of the tokens is kept as it
+/// is, so the tokens of a source are printed as exactly that source.
+///
+/// Tokens can be removed or changed before printing, like comments by a tool
+/// stripping them. Where tokens which weren't next to each other would now be
+/// read as different tokens, like the names in `a/* c */b`, a space is put
+/// between them, or a newline after a `#` comment.
+pub fn print_tokens<S: AsRef<str>>(tokens: impl IntoIterator<Item = (SyntaxKind, S)>) -> String {
+    let tokens: Vec<(SyntaxKind, S)> = tokens.into_iter().collect();
+    let kind = |i: usize| tokens[i].0;
+    let text = |i: usize| tokens[i].1.as_ref();
+    // The separator in front of each token
+    let mut separators: Vec<Option<char>> = vec![None; tokens.len()];
+    loop {
+        let mut out = String::new();
+        let mut starts = Vec::with_capacity(tokens.len());
+        for (i, sep) in separators.iter().enumerate() {
+            out.extend(*sep);
+            starts.push(out.len());
+            out.push_str(text(i));
+        }
+        // Whether tokens merge depends on the context, like `}` and `.jpg`
+        // in a path, so the whole text is read again to find them
+        let read: Vec<(usize, SyntaxKind)> = tokenize(&out)
+            .iter()
+            .scan(0, |offset, &(kind, text)| {
+                let start = *offset;
+                *offset += text.len();
+                Some((start, kind))
+            })
+            .collect();
+        let read_at = |i: usize| {
+            let index = read.binary_search_by_key(&starts[i], |&(start, _)| start).ok()?;
+            Some(read[index].1)
+        };
+        let is_read = |i: usize| kind(i) == TOKEN_WHITESPACE || read_at(i) == Some(kind(i));
+
+        let mut changed = false;
+        let mut i = 1;
+        while i < tokens.len() {
+            if is_read(i) {
+                i += 1;
+                continue;
+            }
+            // Separate the tokens read as one, like `x`, `:` and `y` read as
+            // the URI `x:y`, or the token from the ones around it if it was
+            // read as a different kind
+            let merged = (0..=i).rev().find(|&i| read_at(i).is_some()).unwrap_or(0);
+            let end =
+                read.iter().find(|&&(start, _)| start > starts[merged]).map_or(out.len(), |r| r.0);
+            let split = (merged + 1..tokens.len())
+                .take_while(|&i| starts[i] < end)
+                .chain([merged, merged + 1])
+                .find(|&i| {
+                    (1..tokens.len()).contains(&i)
+                        && separators[i].is_none()
+                        && (kind(i - 1) == TOKEN_COMMENT || !is_joined(kind(i - 1), kind(i)))
+                });
+            if let Some(split) = split {
+                separators[split] = Some(if text(split - 1).starts_with('#') { '\n' } else { ' ' });
+                changed = true;
+            }
+            // Tokens after that are only looked at once they are read like
+            // before again, as they may only be read differently because of
+            // this one
+            i = (i + 1..tokens.len())
+                .find(|&i| starts[i] >= end && is_read(i))
+                .unwrap_or(tokens.len());
+        }
+        if !changed {
+            return out;
+        }
+    }
+}
+
+/// Whether tokens in strings and around interpolations, which can't be
+/// separated, are next to each other
+fn is_joined(prev: SyntaxKind, next: SyntaxKind) -> bool {
+    matches!(
+        prev,
+        TOKEN_STRING_START | TOKEN_STRING_CONTENT | TOKEN_INTERPOL_START | TOKEN_WHITESPACE
+    ) || matches!(
+        next,
+        TOKEN_STRING_CONTENT | TOKEN_STRING_END | TOKEN_INTERPOL_START | TOKEN_WHITESPACE
+    )
+}
+
 /// The tokenizer. You may want to use the `tokenize` convenience function from this module instead.
 pub struct Tokenizer<'a> {
     ctx: Vec<Context>,
@@ -106,8 +220,16 @@ impl Tokenizer<'_> {
         self.state.offset += len;
         len
     }
+    /// Skip to the next byte in `needles`, or to the end of the input
+    fn skip_until(&mut self, needles: &[u8]) {
+        let remaining = self.remaining().as_bytes();
+        self.state.offset += find_any(remaining, needles).unwrap_or(remaining.len());
+    }
     fn next_string(&mut self, multiline: bool) -> SyntaxKind {
         loop {
+            // Only these characters can end the content or start an escape
+            let needles: &[u8] = if multiline { b"'$" } else { b"\"\\$" };
+            self.skip_until(needles);
             let start = self.state;
             match self.next() {
                 None => {
@@ -120,33 +242,34 @@ impl Tokenizer<'_> {
                     self.push_ctx(Context::StringEnd);
                     return TOKEN_STRING_CONTENT;
                 }
-                Some('\\') if !multiline => match self.next() {
-                    None => return TOKEN_ERROR,
-                    Some(_) => (),
-                },
+                Some('\\') if !multiline => {
+                    if self.next().is_none() {
+                        return TOKEN_ERROR;
+                    }
+                }
 
                 Some('\'') if multiline => match self.peek() {
                     None => return TOKEN_ERROR,
-                    Some('\'') => match {
+                    Some('\'') => {
                         self.next();
-                        self.peek()
-                    } {
-                        Some('\'') | Some('$') => {
-                            self.next().unwrap();
-                        }
-                        Some('\\') => {
-                            self.next().unwrap();
-                            if let None = self.next() {
-                                return TOKEN_ERROR;
+                        match self.peek() {
+                            Some('\'') | Some('$') => {
+                                self.next().unwrap();
+                            }
+                            Some('\\') => {
+                                self.next().unwrap();
+                                if self.next().is_none() {
+                                    return TOKEN_ERROR;
+                                }
+                            }
+                            _ => {
+                                self.state = start;
+                                self.pop_ctx(Context::StringBody { multiline: true });
+                                self.push_ctx(Context::StringEnd);
+                                return TOKEN_STRING_CONTENT;
                             }
                         }
-                        _ => {
-                            self.state = start;
-                            self.pop_ctx(Context::StringBody { multiline: true });
-                            self.push_ctx(Context::StringEnd);
-                            return TOKEN_STRING_CONTENT;
-                        }
-                    },
+                    }
                     Some(_) => (),
                 },
 
@@ -197,7 +320,7 @@ impl Tokenizer<'_> {
                     if self.starts_with_bump("${") {
                         self.ctx.push(Context::Interpol { brackets: 0 });
                         return Some(TOKEN_INTERPOL_START);
-                    } else if self.peek().map_or(false, is_valid_path_char) {
+                    } else if self.peek().is_some_and(is_valid_path_char) {
                         return Some(self.check_path_since(start));
                     } else {
                         self.pop_ctx(Context::Path);
@@ -218,16 +341,15 @@ impl Tokenizer<'_> {
                             self.next().unwrap();
                             true
                         }
-                        Some('\'') => match {
+                        Some('\'') => {
                             self.next().unwrap();
-                            self.peek()
-                        } {
-                            Some('\'') => {
+                            if self.peek() == Some('\'') {
                                 self.next().unwrap();
                                 true
+                            } else {
+                                false
                             }
-                            _ => false,
-                        },
+                        }
                         _ => false,
                     };
                     return Some(if status { TOKEN_STRING_END } else { TOKEN_ERROR });
@@ -237,27 +359,29 @@ impl Tokenizer<'_> {
             break;
         }
 
-        if self.consume(char::is_whitespace) > 0 {
+        // Most whitespace is ASCII, which doesn't need decoding
+        let ascii = self.remaining().bytes().take_while(u8::is_ascii_whitespace).count();
+        self.state.offset += ascii;
+        if self.consume(char::is_whitespace) + ascii > 0 {
             return Some(TOKEN_WHITESPACE);
         }
 
         if self.peek() == Some('#') {
-            self.consume(|c| c != '\n');
+            self.skip_until(b"\n");
             return Some(TOKEN_COMMENT);
         }
         if self.starts_with_bump("/*") {
-            loop {
-                self.consume(|c| c != '*');
-                self.next(); // consume the '*', if any
-                match self.peek() {
-                    None => return Some(TOKEN_ERROR),
-                    Some('/') => {
-                        self.next().unwrap();
-                        return Some(TOKEN_COMMENT);
-                    }
-                    _ => (),
+            let remaining = self.remaining().as_bytes();
+            return Some(match find_comment_end(remaining) {
+                Some(end) => {
+                    self.state.offset += end + 2;
+                    TOKEN_COMMENT
                 }
-            }
+                None => {
+                    self.state.offset += remaining.len();
+                    TOKEN_ERROR
+                }
+            });
         }
 
         if self.starts_with_bump("...") {
@@ -336,8 +460,8 @@ impl Tokenizer<'_> {
             ':' => TOKEN_COLON,
             ',' => TOKEN_COMMA,
             '.' => {
-                if self.peek().map_or(false, |x| ('0'..='9').contains(&x)) {
-                    self.consume(|c| ('0'..='9').contains(&c));
+                if self.peek().is_some_and(|x| x.is_ascii_digit()) {
+                    self.consume(|c| c.is_ascii_digit());
                     self.consume_scientific()
                 } else {
                     TOKEN_DOT
@@ -380,6 +504,14 @@ impl Tokenizer<'_> {
                 self.next().unwrap();
                 TOKEN_OR_OR
             }
+            '|' if self.peek() == Some('>') => {
+                self.next().unwrap();
+                TOKEN_PIPE_RIGHT
+            }
+            '<' if self.peek() == Some('|') => {
+                self.next().unwrap();
+                TOKEN_PIPE_LEFT
+            }
             '<' if self.peek() == Some('=') => {
                 self.next().unwrap();
                 TOKEN_LESS_OR_EQ
@@ -436,10 +568,10 @@ impl Tokenizer<'_> {
                 TOKEN_STRING_START
             }
             '0'..='9' => {
-                self.consume(|c| ('0'..='9').contains(&c));
+                self.consume(|c| c.is_ascii_digit());
                 if self.peek() == Some('.') {
                     self.next().unwrap();
-                    self.consume(|c| ('0'..='9').contains(&c));
+                    self.consume(|c| c.is_ascii_digit());
                     self.consume_scientific()
                 } else {
                     TOKEN_INTEGER
@@ -455,7 +587,7 @@ impl Tokenizer<'_> {
             if self.peek() == Some('-') || self.peek() == Some('+') {
                 self.next().unwrap();
             }
-            if self.consume(|c| ('0'..='9').contains(&c)) == 0 {
+            if self.consume(|c| c.is_ascii_digit()) == 0 {
                 return TOKEN_ERROR;
             }
         }
@@ -0,0 +1,633 @@
+//! Constant values, as written in literals or computed by
+//! [`analysis::eval_const`](crate::analysis::eval_const)
+
+use std::{
+    fmt,
+    path::{Component, Path, PathBuf},
+};
+
+mod dedent;
+mod literal;
+
+pub use self::{
+    dedent::{dedent, DedentLine, Dedented},
+    literal::LiteralError,
+};
+
+/// The place a path is relative to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Anchor {
+    /// `/nix/store`
+    Absolute,
+    /// `./default.nix` or `foo/bar.nix`
+    Relative,
+    /// `~/.config`
+    Home,
+    /// `<nixpkgs>`, looked up in the search path
+    Store,
+}
+
+/// The source text of a literal a value was read from, which is kept to print
+/// the value the way it was written. It's ignored when comparing values, so
+/// `1.0e3` and `1000.0` are equal.
+#[derive(Clone, Debug, Default)]
+pub struct Raw(Option<Box<str>>);
+
+impl Raw {
+    pub fn new(text: &str) -> Self {
+        Raw(Some(text.into()))
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+}
+
+impl PartialEq for Raw {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+/// The directories paths are resolved against, see [`Value::resolve_path`]
+#[derive(Clone, Copy, Debug)]
+pub struct PathRoots<'a> {
+    /// What relative paths are relative to, the directory of the file they
+    /// are written in
+    pub base_dir: &'a Path,
+    /// What `~/` paths are relative to
+    pub home_dir: &'a Path,
+    /// Where `<...>` lookups are looked up, like an entry of `NIX_PATH`.
+    /// Without it, they can't be resolved.
+    pub search_dir: Option<&'a Path>,
+}
+
+/// A constant value. Values read from a literal keep its source text, see
+/// [`Value::raw`]; values created in code or computed have an empty [`Raw`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Float(f64, Raw),
+    Integer(i64, Raw),
+    String(String, Raw),
+    /// A path and what it is relative to. The path doesn't include the anchor,
+    /// so `./foo` is `Path(Anchor::Relative, "foo", _)` and `<nixpkgs/lib>` is
+    /// `Path(Anchor::Store, "nixpkgs/lib", _)`.
+    Path(Anchor, String, Raw),
+    /// An unquoted URI like `https://nixos.org`, which Nix treats as a string.
+    /// The text is kept as written.
+    Uri(String),
+    Bool(bool),
+    Null,
+}
+
+impl Value {
+    /// Split a path literal like `./foo` or `<nixpkgs>` into its anchor and
+    /// the rest of the path
+    pub fn from_path(path: &str) -> Self {
+        if let Some(store) = path.strip_prefix('<').and_then(|p| p.strip_suffix('>')) {
+            Value::Path(Anchor::Store, store.to_string(), Raw::default())
+        } else if let Some(home) = path.strip_prefix("~/") {
+            Value::Path(Anchor::Home, home.to_string(), Raw::default())
+        } else if let Some(absolute) = path.strip_prefix('/') {
+            Value::Path(Anchor::Absolute, absolute.to_string(), Raw::default())
+        } else {
+            let relative = path.strip_prefix("./").unwrap_or(path);
+            Value::Path(Anchor::Relative, relative.to_string(), Raw::default())
+        }
+    }
+
+    /// The value of the builtin `true`, `false` or `null`, which are
+    /// variables in Nix rather than keywords. To tell whether an identifier
+    /// refers to them and isn't shadowed, see
+    /// [`Resolver::builtin_constant`](crate::analysis::Resolver::builtin_constant).
+    pub fn from_keyword(name: &str) -> Option<Self> {
+        match name {
+            "true" => Some(Value::Bool(true)),
+            "false" => Some(Value::Bool(false)),
+            "null" => Some(Value::Null),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// The source text of the literal the value was read from, like `1.0e3`
+    /// for the float `1000.0` or `"a\nb"` for a string, including the quotes.
+    /// URIs are always kept as written.
+    pub fn raw(&self) -> Option<&str> {
+        match self {
+            Value::Float(_, raw)
+            | Value::Integer(_, raw)
+            | Value::String(_, raw)
+            | Value::Path(_, _, raw) => raw.as_str(),
+            Value::Uri(uri) => Some(uri),
+            Value::Bool(_) | Value::Null => None,
+        }
+    }
+
+    /// Set the source text of the literal the value was read from
+    pub fn with_raw(mut self, text: &str) -> Self {
+        match &mut self {
+            Value::Float(_, raw)
+            | Value::Integer(_, raw)
+            | Value::String(_, raw)
+            | Value::Path(_, _, raw) => *raw = Raw::new(text),
+            Value::Uri(_) | Value::Bool(_) | Value::Null => (),
+        }
+        self
+    }
+
+    /// Resolve a path value to the file it refers to, removing `.` and `..`
+    /// components like Nix does without looking at the file system. Returns
+    /// `None` for anything but paths, and for `<...>` lookups without a
+    /// [`search_dir`](PathRoots::search_dir).
+    pub fn resolve_path(&self, roots: &PathRoots) -> Option<PathBuf> {
+        let (anchor, path) = match self {
+            Value::Path(anchor, path, _) => (anchor, path),
+            _ => return None,
+        };
+        let root = match anchor {
+            Anchor::Absolute => Path::new("/"),
+            Anchor::Relative => roots.base_dir,
+            Anchor::Home => roots.home_dir,
+            Anchor::Store => roots.search_dir?,
+        };
+        Some(normalize(&root.join(path)))
+    }
+
+    /// Turn a file name into a path value to print, the inverse of
+    /// [`resolve_path`](Value::resolve_path). Files in the base directory
+    /// become relative paths, files in the home directory `~/` paths, and
+    /// other files absolute paths. Returns `None` for relative file names and
+    /// ones which aren't valid UTF-8.
+    pub fn relative_path(file: &Path, roots: &PathRoots) -> Option<Self> {
+        let file = normalize(file);
+        let (anchor, path) = if let Ok(path) = file.strip_prefix(normalize(roots.base_dir)) {
+            (Anchor::Relative, path)
+        } else if let Ok(path) = file.strip_prefix(normalize(roots.home_dir)) {
+            (Anchor::Home, path)
+        } else {
+            (Anchor::Absolute, file.strip_prefix("/").ok()?)
+        };
+        let path = match path.to_str()? {
+            "" if anchor == Anchor::Absolute => return None,
+            "" => ".",
+            path => path,
+        };
+        Some(Value::Path(anchor, path.to_string(), Raw::default()))
+    }
+
+    /// Remove `.` and `..` components and duplicate and trailing slashes from
+    /// a path value, like Nix does for path literals. `..` can't go above the
+    /// root of an absolute path, but is kept at the start of other paths.
+    /// Returns `None` for anything but paths.
+    pub fn normalize(&self) -> Option<Self> {
+        let (anchor, path) = match self {
+            Value::Path(anchor, path, _) => (*anchor, path),
+            _ => return None,
+        };
+        let mut segments: Vec<&str> = Vec::new();
+        for segment in path.split('/') {
+            match segment {
+                "" | "." => (),
+                ".." if segments.last().is_some_and(|last| *last != "..") => {
+                    segments.pop();
+                }
+                ".." if anchor == Anchor::Absolute => (),
+                segment => segments.push(segment),
+            }
+        }
+        let path = match segments.join("/") {
+            path if path.is_empty() && anchor != Anchor::Absolute => ".".to_string(),
+            path => path,
+        };
+        Some(Value::Path(anchor, path, Raw::default()))
+    }
+
+    /// Append a relative path to a path value and normalize the result, like
+    /// `./foo + "/bar"` in Nix. Unlike [`Path::join`], a leading slash doesn't
+    /// replace the path. Returns `None` for anything but paths.
+    pub fn join(&self, path: &str) -> Option<Self> {
+        match self {
+            Value::Path(anchor, base, _) => {
+                let joined = format!("{}/{}", base, path);
+                Value::Path(*anchor, joined, Raw::default()).normalize()
+            }
+            _ => None,
+        }
+    }
+
+    /// The directory containing a path value like `dirOf`, normalized. The
+    /// parent of `/` is `/` and the parent of `./.` is `../.`. Returns `None`
+    /// for anything but paths, and for search path lookups of a single
+    /// component like `<nixpkgs>`.
+    pub fn parent(&self) -> Option<Self> {
+        let Value::Path(anchor, path, _) = self.normalize()? else { return None };
+        let parent = match path.rsplit_once('/') {
+            Some((_, "..")) => format!("{}/..", path),
+            Some((parent, _)) => parent.to_string(),
+            None if anchor == Anchor::Store => return None,
+            None if anchor == Anchor::Absolute => String::new(),
+            None if path == "." => "..".to_string(),
+            None if path == ".." => "../..".to_string(),
+            None => ".".to_string(),
+        };
+        Some(Value::Path(anchor, parent, Raw::default()))
+    }
+
+    /// The name of the type as returned by `builtins.typeOf`
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Float(..) => "float",
+            Value::Integer(..) => "int",
+            Value::String(..) | Value::Uri(_) => "string",
+            Value::Path(..) => "path",
+            Value::Bool(_) => "bool",
+            Value::Null => "null",
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    /// Print the value as a Nix expression, as it was written if it was read
+    /// from a literal
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(raw) = self.raw() {
+            return f.write_str(raw);
+        }
+        match self {
+            Value::Float(value, _) => f.write_str(&float(*value)),
+            Value::Integer(value, _) => write!(f, "{}", value),
+            Value::String(value, _) => write!(f, "\"{}\"", escape_string(value)),
+            Value::Path(Anchor::Absolute, path, _) => write!(f, "/{}", path),
+            // `..` alone isn't a path literal
+            Value::Path(Anchor::Relative, path, _) if path == ".." => f.write_str("../."),
+            Value::Path(Anchor::Relative, path, _) if path.starts_with("../") => f.write_str(path),
+            Value::Path(Anchor::Relative, path, _) => write!(f, "./{}", path),
+            Value::Path(Anchor::Home, path, _) => write!(f, "~/{}", path),
+            Value::Path(Anchor::Store, path, _) => write!(f, "<{}>", path),
+            Value::Uri(uri) => f.write_str(uri),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Null => f.write_str("null"),
+        }
+    }
+}
+
+/// Print a float as the shortest literal which parses back to the same value,
+/// keeping the `.0` of integral values so it isn't read as an integer. Nix
+/// only accepts exponents after a decimal point, as in `1.0e-7`. Infinity and
+/// NaN, which can only be computed, are printed like Nix prints them.
+fn float(value: f64) -> String {
+    if value.is_nan() {
+        return "nan".into();
+    }
+    if value.is_infinite() {
+        return if value < 0.0 { "-inf" } else { "inf" }.into();
+    }
+    // Debug prints the shortest representation which round-trips, and
+    // switches to scientific notation for very large and small values
+    let printed = format!("{:?}", value);
+    match printed.split_once('e') {
+        Some((mantissa, exponent)) if !mantissa.contains('.') => {
+            format!("{}.0e{}", mantissa, exponent)
+        }
+        _ => printed,
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Integer(value, Raw::default())
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Float(value, Raw::default())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::String(value, Raw::default())
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(value.to_string(), Raw::default())
+    }
+}
+
+/// Remove `.` and `..` components from a path without looking at the file
+/// system. `..` at the root stays at the root.
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir | Component::Prefix(_)) => (),
+                _ => normalized.push(".."),
+            },
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Escape text to be put between the quotes of a `"` string
+pub fn escape_string(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            '$' if chars.peek() == Some(&'{') => output.push_str("\\$"),
+            c => output.push(c),
+        }
+    }
+    output
+}
+
+/// Interpret the escape sequences in the text between the quotes of a `"`
+/// string, the inverse of [`escape_string`]
+pub fn unescape_string(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => output.push('\n'),
+                Some('r') => output.push('\r'),
+                Some('t') => output.push('\t'),
+                Some(c) => output.push(c),
+                None => break,
+            },
+            c => output.push(c),
+        }
+    }
+    output
+}
+
+/// Escape text to be put between the quotes of a `''` string. This doesn't
+/// protect indentation, which Nix strips from `''` strings.
+pub fn escape_indented(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        let rest = &text[i + c.len_utf8()..];
+        match c {
+            '\'' if rest.starts_with('\'') => {
+                chars.next();
+                output.push_str("'''");
+            }
+            // Would be read as part of the closing quotes, or of the escape
+            // of an interpolation following it
+            '\'' if rest.is_empty() || rest.starts_with("${") => output.push_str("''\\'"),
+            '$' if rest.starts_with('{') => output.push_str("''$"),
+            c => output.push(c),
+        }
+    }
+    output
+}
+
+/// Interpret the escape sequences in the text between the quotes of a `''`
+/// string, the inverse of [`escape_indented`]. Indentation is kept as is, and
+/// the text ends at unescaped closing quotes.
+pub fn unescape_indented(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' if chars.peek() == Some(&'\'') => {
+                chars.next();
+                match chars.next() {
+                    Some('\'') => output.push_str("''"),
+                    Some('$') => output.push('$'),
+                    Some('\\') => match chars.next() {
+                        Some('n') => output.push('\n'),
+                        Some('r') => output.push('\r'),
+                        Some('t') => output.push('\t'),
+                        Some(c) => output.push(c),
+                        None => break,
+                    },
+                    _ => break,
+                }
+            }
+            c => output.push(c),
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths() {
+        for (path, value) in [
+            ("./foo/bar.nix", Value::Path(Anchor::Relative, "foo/bar.nix".into(), Raw::default())),
+            ("foo/bar.nix", Value::Path(Anchor::Relative, "foo/bar.nix".into(), Raw::default())),
+            ("../foo", Value::Path(Anchor::Relative, "../foo".into(), Raw::default())),
+            ("/nix/store", Value::Path(Anchor::Absolute, "nix/store".into(), Raw::default())),
+            ("~/.config", Value::Path(Anchor::Home, ".config".into(), Raw::default())),
+            ("<nixpkgs/lib>", Value::Path(Anchor::Store, "nixpkgs/lib".into(), Raw::default())),
+        ] {
+            assert_eq!(Value::from_path(path), value);
+        }
+        assert_eq!(Value::from_path("foo/bar.nix").to_string(), "./foo/bar.nix");
+        assert_eq!(Value::from_path("../foo").to_string(), "../foo");
+        assert_eq!(Value::from_path("..foo").to_string(), "./..foo");
+        assert_eq!(Value::from_path("./.../foo").to_string(), "./.../foo");
+        assert_eq!(Value::from_path("<nixpkgs>").to_string(), "<nixpkgs>");
+    }
+
+    #[test]
+    fn resolve_paths() {
+        let roots = PathRoots {
+            base_dir: Path::new("/src/pkgs"),
+            home_dir: Path::new("/home/user"),
+            search_dir: Some(Path::new("/nix/var/nixpkgs")),
+        };
+        for (path, resolved) in [
+            ("./default.nix", "/src/pkgs/default.nix"),
+            ("../lib/./a.nix", "/src/lib/a.nix"),
+            ("~/.config", "/home/user/.config"),
+            ("/../etc/hosts", "/etc/hosts"),
+            ("<nixpkgs/lib>", "/nix/var/nixpkgs/nixpkgs/lib"),
+        ] {
+            let value = Value::from_path(path);
+            assert_eq!(value.resolve_path(&roots), Some(PathBuf::from(resolved)), "{}", path);
+        }
+        let roots = PathRoots { search_dir: None, ..roots };
+        assert_eq!(Value::from_path("<nixpkgs>").resolve_path(&roots), None);
+        assert_eq!(Value::from("./a").resolve_path(&roots), None);
+
+        for (file, printed) in [
+            ("/src/pkgs/foo/default.nix", "./foo/default.nix"),
+            ("/src/pkgs", "./."),
+            ("/home/user/.config", "~/.config"),
+            ("/src/lib/../pkgs/a.nix", "./a.nix"),
+            ("/etc/hosts", "/etc/hosts"),
+        ] {
+            let value = Value::relative_path(Path::new(file), &roots).unwrap();
+            assert_eq!(value.to_string(), printed);
+            assert_eq!(value.resolve_path(&roots), Some(normalize(Path::new(file))));
+        }
+        assert_eq!(Value::relative_path(Path::new("a/b"), &roots), None);
+    }
+
+    #[test]
+    fn keywords() {
+        assert_eq!(Value::from_keyword("true"), Some(Value::from(true)));
+        assert_eq!(Value::from_keyword("truth"), None);
+        assert!(Value::from_keyword("null").is_some_and(|value| value.is_null()));
+        assert_eq!(Value::from(false).as_bool(), Some(false));
+        assert_eq!(Value::Null.as_bool(), None);
+        assert!(!Value::from("null").is_null());
+    }
+
+    #[test]
+    fn path_operations() {
+        let path = |text: &str| Value::from_path(text);
+        let printed = |value: Option<Value>| value.map(|value| value.to_string());
+        for (text, normalized) in [
+            ("./foo/../bar/./baz/", "./bar/baz"),
+            ("./a//..", "./."),
+            ("./a/../../b", "../b"),
+            ("../../a/..", "../.."),
+            ("/../a/../../b", "/b"),
+            ("/a/..", "/"),
+            ("~/a/../.config", "~/.config"),
+            ("<nixpkgs/lib/..>", "<nixpkgs>"),
+        ] {
+            assert_eq!(printed(path(text).normalize()).unwrap(), normalized, "{}", text);
+        }
+        assert_eq!(Value::from(1).normalize(), None);
+
+        assert_eq!(printed(path("./foo").join("bar/../baz.nix")).unwrap(), "./foo/baz.nix");
+        assert_eq!(printed(path("./foo").join("/bar")).unwrap(), "./foo/bar");
+        assert_eq!(printed(path("/").join("../etc")).unwrap(), "/etc");
+        assert_eq!(Value::from("a").join("b"), None);
+
+        for (text, parent) in [
+            ("./a/b.nix", Some("./a")),
+            ("./a", Some("./.")),
+            ("./.", Some("../.")),
+            ("../.", Some("../..")),
+            ("/a", Some("/")),
+            ("/", Some("/")),
+            ("~/.config", Some("~/.")),
+            ("<nixpkgs/lib>", Some("<nixpkgs>")),
+            ("<nixpkgs>", None),
+        ] {
+            assert_eq!(printed(path(text).parent()).as_deref(), parent, "{}", text);
+        }
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Value::from(-3).to_string(), "-3");
+        assert_eq!(Value::from("a\"${b}\n").to_string(), r#""a\"\${b}\n""#);
+    }
+
+    #[test]
+    fn floats() {
+        for (value, printed) in [
+            (1.0, "1.0"),
+            (0.1, "0.1"),
+            (-2.5, "-2.5"),
+            (0.1 + 0.2, "0.30000000000000004"),
+            (1e300, "1.0e300"),
+            (1.5e-7, "1.5e-7"),
+            (f64::INFINITY, "inf"),
+        ] {
+            assert_eq!(Value::from(value).to_string(), printed);
+        }
+        for value in
+            [1.0, 0.1, 1e22, 1e300, 1.5e-7, 123456.789, f64::MAX, f64::MIN_POSITIVE, 5e-324]
+        {
+            let printed = Value::from(value).to_string();
+            let root = crate::Root::parse(&printed).ok().unwrap();
+            let literal = crate::analysis::eval_const(&root.expr().unwrap());
+            assert_eq!(literal, Some(Value::from(value)), "{}", printed);
+        }
+    }
+
+    #[test]
+    fn string_unescapes() {
+        assert_eq!(unescape_string(r#"Hello\n\"World\" :D"#), "Hello\n\"World\" :D");
+        assert_eq!(unescape_string(r#"\"Hello\""#), "\"Hello\"");
+
+        assert_eq!(unescape_indented(r#"Hello''\n'''World''' :D"#), "Hello\n''World'' :D");
+        assert_eq!(unescape_indented(r#""Hello""#), "\"Hello\"");
+    }
+
+    /// Every string of up to four characters from an alphabet of the
+    /// characters which are special in strings
+    fn strings() -> Vec<String> {
+        let alphabet = ['a', ' ', '\'', '"', '$', '{', '\\', '\n', '\t', 'n'];
+        let mut strings = vec![String::new()];
+        let mut last = strings.clone();
+        for _ in 0..4 {
+            last = last
+                .iter()
+                .flat_map(|s| alphabet.iter().map(move |c| format!("{}{}", s, c)))
+                .collect();
+            strings.extend(last.iter().cloned());
+        }
+        strings
+    }
+
+    fn eval(src: &str) -> Option<Value> {
+        let root = crate::Root::parse(src).ok().ok()?;
+        crate::analysis::eval_const(&root.expr()?)
+    }
+
+    #[test]
+    fn escape_strings() {
+        for s in strings() {
+            let escaped = escape_string(&s);
+            assert_eq!(unescape_string(&escaped), s, "{:?}", escaped);
+            assert_eq!(eval(&format!("\"{}\"", escaped)), Some(Value::from(&*s)), "{:?}", escaped);
+        }
+    }
+
+    #[test]
+    fn escape_indented_strings() {
+        for s in strings() {
+            let escaped = escape_indented(&s);
+            assert_eq!(unescape_indented(&escaped), s, "{:?}", escaped);
+            // Surrounded by other characters so no indentation is stripped
+            let wrapped = Value::from(format!("x{}x", s));
+            assert_eq!(eval(&format!("''x{}x''", escaped)), Some(wrapped), "{:?}", escaped);
+            if !s.contains([' ', '\n']) {
+                assert_eq!(eval(&format!("''{}''", escaped)), Some(Value::from(&*s)), "{:?}", s);
+            }
+        }
+    }
+}
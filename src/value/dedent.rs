@@ -0,0 +1,181 @@
+use crate::ast::InterpolPart;
+
+use super::unescape_indented;
+
+/// A `''` string with its common indentation removed, see [`dedent`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Dedented {
+    /// The parts of the string, with the indentation removed and the escape
+    /// sequences of literals interpreted. Empty literals are left out.
+    pub parts: Vec<InterpolPart<String>>,
+    /// The number of spaces removed from the start of every line
+    pub indent: usize,
+    /// One entry for every line of the content between the quotes, in order
+    pub lines: Vec<DedentLine>,
+}
+
+/// How a line of a `''` string is treated when removing indentation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DedentLine {
+    /// The number of spaces the line starts with in the source
+    pub indent: usize,
+    /// Whether the line has anything but spaces, which means its indentation
+    /// counts towards the common indentation
+    pub counted: bool,
+    /// Whether the line is removed entirely, which happens to a first and a
+    /// last line consisting only of spaces
+    pub dropped: bool,
+}
+
+impl Dedented {
+    /// The decoded string, if it has no interpolations
+    pub fn as_literal(&self) -> Option<String> {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                InterpolPart::Literal(literal) => out.push_str(literal),
+                InterpolPart::Interpolation(_) => return None,
+            }
+        }
+        Some(out)
+    }
+}
+
+/// Remove the common indentation from the content of a `''` string, given as
+/// its literal parts as written and its interpolations, and interpret escape
+/// sequences afterwards. This follows Nix:
+///
+/// - A first line consisting only of spaces is removed, along with its
+///   newline
+/// - The common indentation is the smallest number of spaces any line starts
+///   with, ignoring lines consisting only of spaces. An interpolation counts
+///   as content, and escape sequences like `''$` as well.
+/// - Up to that many spaces are removed from the start of every line,
+///   including ones consisting only of spaces
+/// - A last line consisting only of spaces is removed, but not its newline
+pub fn dedent(parts: &[InterpolPart<&str>]) -> Dedented {
+    let mut parts = parts.to_vec();
+    let mut lines = Vec::new();
+
+    if let Some(InterpolPart::Literal(first)) = parts.first_mut() {
+        if let Some((line, rest)) = first.split_once('\n') {
+            if line.chars().all(|c| c == ' ') {
+                lines.push(DedentLine { indent: line.len(), counted: false, dropped: true });
+                *first = rest;
+            }
+        }
+    }
+
+    let mut line = DedentLine { indent: 0, counted: false, dropped: false };
+    for part in &parts {
+        match part {
+            InterpolPart::Interpolation(_) => line.counted = true,
+            InterpolPart::Literal(literal) => {
+                for c in literal.chars() {
+                    match c {
+                        '\n' => {
+                            lines.push(line);
+                            line = DedentLine { indent: 0, counted: false, dropped: false };
+                        }
+                        ' ' if !line.counted => line.indent += 1,
+                        _ => line.counted = true,
+                    }
+                }
+            }
+        }
+    }
+    lines.push(line);
+
+    // Without any content, the spaces on every line are removed
+    let indent = lines
+        .iter()
+        .filter(|line| line.counted)
+        .map(|line| line.indent)
+        .min()
+        .or_else(|| lines.iter().map(|line| line.indent).max())
+        .unwrap_or(0);
+
+    let mut stripped: Vec<InterpolPart<String>> = Vec::new();
+    let mut at_start_of_line = true;
+    let mut dropped = 0;
+    for part in parts {
+        match part {
+            InterpolPart::Interpolation(interpol) => {
+                at_start_of_line = false;
+                stripped.push(InterpolPart::Interpolation(interpol));
+            }
+            InterpolPart::Literal(literal) => {
+                let mut out = String::new();
+                for c in literal.chars() {
+                    if at_start_of_line && c == ' ' && dropped < indent {
+                        dropped += 1;
+                        continue;
+                    }
+                    at_start_of_line = c == '\n';
+                    dropped = 0;
+                    out.push(c);
+                }
+                stripped.push(InterpolPart::Literal(out));
+            }
+        }
+    }
+
+    if let Some(InterpolPart::Literal(last)) = stripped.last_mut() {
+        if let Some(newline) = last.rfind('\n') {
+            if last[newline + 1..].chars().all(|c| c == ' ') {
+                last.truncate(newline + 1);
+                if let Some(line) = lines.last_mut().filter(|line| !line.counted) {
+                    line.dropped = true;
+                }
+            }
+        }
+    }
+
+    let parts = stripped
+        .into_iter()
+        .filter_map(|part| match part {
+            InterpolPart::Literal(literal) if literal.is_empty() => None,
+            InterpolPart::Literal(literal) => {
+                Some(InterpolPart::Literal(unescape_indented(&literal)))
+            }
+            InterpolPart::Interpolation(interpol) => Some(InterpolPart::Interpolation(interpol)),
+        })
+        .collect();
+    Dedented { parts, indent, lines }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(indent: usize, counted: bool, dropped: bool) -> DedentLine {
+        DedentLine { indent, counted, dropped }
+    }
+
+    #[test]
+    fn lines() {
+        let dedented = dedent(&[InterpolPart::Literal("  \n    a\n\n      b\n  ''$c\n   ")]);
+        assert_eq!(dedented.as_literal().unwrap(), "  a\n\n    b\n$c\n");
+        assert_eq!(dedented.indent, 2);
+        assert_eq!(
+            dedented.lines,
+            [
+                line(2, false, true),
+                line(4, true, false),
+                line(0, false, false),
+                line(6, true, false),
+                line(2, true, false),
+                line(3, false, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_spaces() {
+        // The smallest indentation is that of the lines with content
+        assert_eq!(dedent(&[InterpolPart::Literal("   ")]).as_literal().unwrap(), "");
+        let dedented = dedent(&[InterpolPart::Literal("\n    \n  ")]);
+        assert_eq!(dedented.as_literal().unwrap(), "\n");
+        assert_eq!(dedented.indent, 4);
+    }
+}
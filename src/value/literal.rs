@@ -0,0 +1,276 @@
+use std::{error::Error, fmt, str::FromStr};
+
+use rowan::{TextRange, TextSize};
+
+use crate::ast::InterpolPart;
+
+use super::{dedent, Value};
+
+/// Why text isn't a valid literal, see [`Value::from_str`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LiteralError {
+    /// The text isn't a literal, or has something after it
+    Invalid(TextRange),
+    /// An integer larger than `i64::MAX`
+    IntegerOverflow(TextRange),
+    /// An interpolation in a string, which makes it an expression
+    Interpolation(TextRange),
+    /// A string without its closing quotes
+    Unterminated(TextRange),
+}
+
+impl LiteralError {
+    pub fn range(&self) -> TextRange {
+        match self {
+            LiteralError::Invalid(range)
+            | LiteralError::IntegerOverflow(range)
+            | LiteralError::Interpolation(range)
+            | LiteralError::Unterminated(range) => *range,
+        }
+    }
+}
+
+impl fmt::Display for LiteralError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let range = self.range();
+        let (start, end) = (usize::from(range.start()), usize::from(range.end()));
+        match self {
+            LiteralError::Invalid(_) => write!(f, "invalid literal at {}..{}", start, end),
+            LiteralError::IntegerOverflow(_) => {
+                write!(
+                    f,
+                    "integer at {}..{} is larger than the maximum of {}",
+                    start,
+                    end,
+                    i64::MAX
+                )
+            }
+            LiteralError::Interpolation(_) => {
+                write!(f, "interpolation at {}..{} isn't allowed in a literal", start, end)
+            }
+            LiteralError::Unterminated(_) => write!(f, "unterminated string at {}..{}", start, end),
+        }
+    }
+}
+
+impl Error for LiteralError {}
+
+impl FromStr for Value {
+    type Err = LiteralError;
+
+    /// Parse a single literal: a number, a `"` or `''` string without
+    /// interpolations, a path, a URI, `true`, `false` or `null`. Numbers can
+    /// be negative, unlike in Nix where `-1` is an expression. This doesn't
+    /// run the tokenizer, and keeps the text as the [`raw`](Value::raw) text
+    /// of the value.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let whole = TextRange::up_to(TextSize::of(text));
+        if let Some(value) = Value::from_keyword(text) {
+            return Ok(value);
+        }
+        let value = match text {
+            _ if text.starts_with('"') => Value::from(string(text)?),
+            _ if text.starts_with("''") => Value::from(indented(text)?),
+            _ if is_number(text) => number(text)?,
+            _ if is_path(text) => Value::from_path(text),
+            _ if is_uri(text) => return Ok(Value::Uri(text.to_string())),
+            _ => return Err(LiteralError::Invalid(whole)),
+        };
+        Ok(value.with_raw(text))
+    }
+}
+
+fn range(start: usize, len: usize) -> TextRange {
+    TextRange::at(TextSize::from(start as u32), TextSize::from(len as u32))
+}
+
+fn digits(text: &str) -> bool {
+    !text.is_empty() && text.bytes().all(|b| b.is_ascii_digit())
+}
+
+fn is_number(text: &str) -> bool {
+    let text = text.strip_prefix('-').unwrap_or(text);
+    digits(text) || is_float(text)
+}
+
+/// Whether the text is a float literal. Nix only accepts a leading zero
+/// directly in front of the decimal point, and exponents after the decimal
+/// point.
+fn is_float(text: &str) -> bool {
+    let (mantissa, exponent) = match text.find(['e', 'E']) {
+        Some(i) => (&text[..i], Some(&text[i + 1..])),
+        None => (text, None),
+    };
+    let exponent_valid = exponent
+        .is_none_or(|exponent| digits(exponent.strip_prefix(['+', '-']).unwrap_or(exponent)));
+    let mantissa_valid = match mantissa.split_once('.') {
+        Some((int, frac)) if int.is_empty() || int == "0" => digits(frac),
+        Some((int, frac)) => {
+            !int.starts_with('0') && digits(int) && (frac.is_empty() || digits(frac))
+        }
+        None => false,
+    };
+    mantissa_valid && exponent_valid
+}
+
+fn number(text: &str) -> Result<Value, LiteralError> {
+    if is_float(text.strip_prefix('-').unwrap_or(text)) {
+        // Rust accepts everything Nix does, like `.5` and `1.e3`
+        let float: f64 = text.parse().map_err(|_| LiteralError::Invalid(range(0, text.len())))?;
+        Ok(Value::from(float))
+    } else {
+        let integer: i64 =
+            text.parse().map_err(|_| LiteralError::IntegerOverflow(range(0, text.len())))?;
+        Ok(Value::from(integer))
+    }
+}
+
+fn path_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "._-+".contains(c)
+}
+
+fn segments(text: &str) -> bool {
+    text.split('/').all(|segment| !segment.is_empty() && segment.chars().all(path_char))
+}
+
+/// Whether the text is a path literal like `./a`, `/a`, `~/a`, `a/b` or
+/// `<a/b>`. Nix doesn't accept trailing slashes.
+fn is_path(text: &str) -> bool {
+    if let Some(search) = text.strip_prefix('<').and_then(|text| text.strip_suffix('>')) {
+        return segments(search);
+    }
+    let rest = match text.strip_prefix('~') {
+        Some(home) => home,
+        None => text.trim_start_matches(path_char),
+    };
+    rest.strip_prefix('/').is_some_and(segments)
+}
+
+fn is_uri(text: &str) -> bool {
+    let Some((scheme, rest)) = text.split_once(':') else { return false };
+    scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        && !rest.is_empty()
+        && rest.chars().all(|c| c.is_ascii_alphanumeric() || "%/?:@&=+$,-_.!~*'".contains(c))
+}
+
+/// Decode a `"` string
+fn string(text: &str) -> Result<String, LiteralError> {
+    let mut out = String::new();
+    let mut chars = text.char_indices().skip(1).peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' if i + 1 == text.len() => return Ok(out),
+            '"' => return Err(LiteralError::Invalid(range(i + 1, text.len() - i - 1))),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => out.push('\n'),
+                Some((_, 'r')) => out.push('\r'),
+                Some((_, 't')) => out.push('\t'),
+                Some((_, c)) => out.push(c),
+                None => break,
+            },
+            '$' => match chars.peek() {
+                Some((_, '{')) => return Err(LiteralError::Interpolation(range(i, 2))),
+                // `$${` is a literal `$${`
+                Some((_, '$')) => {
+                    chars.next();
+                    out.push_str("$$");
+                }
+                _ => out.push('$'),
+            },
+            c => out.push(c),
+        }
+    }
+    Err(LiteralError::Unterminated(range(0, text.len())))
+}
+
+/// Decode a `''` string, removing the indentation like Nix does
+fn indented(text: &str) -> Result<String, LiteralError> {
+    let body = &text[2..];
+    let mut end = None;
+    let mut i = 0;
+    while i < body.len() {
+        let rest = &body[i..];
+        if rest.starts_with("''$") || rest.starts_with("'''") {
+            i += 3;
+        } else if let Some(escaped) = rest.strip_prefix("''\\") {
+            i += 3 + escaped.chars().next().map_or(0, char::len_utf8);
+        } else if rest.starts_with("''") {
+            end = Some(i);
+            break;
+        } else if rest.starts_with("${") {
+            return Err(LiteralError::Interpolation(range(i + 2, 2)));
+        } else if rest.starts_with("$$") {
+            i += 2;
+        } else {
+            i += rest.chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    let Some(end) = end else { return Err(LiteralError::Unterminated(range(0, text.len()))) };
+    if end + 4 != text.len() {
+        return Err(LiteralError::Invalid(range(end + 4, text.len() - end - 4)));
+    }
+    Ok(dedent(&[InterpolPart::Literal(&body[..end])]).as_literal().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analysis::eval_const, value::Anchor, Root};
+
+    #[test]
+    fn literals() {
+        for (text, value) in [
+            ("42", Value::from(42)),
+            ("-3", Value::from(-3)),
+            ("1.5", Value::from(1.5)),
+            (".5", Value::from(0.5)),
+            ("1.0e3", Value::from(1000.0)),
+            ("true", Value::Bool(true)),
+            ("null", Value::Null),
+            (r#""a\n\${b}$${c}""#, Value::from("a\n${b}$${c}")),
+            ("./a/b", Value::from_path("a/b")),
+            ("<nixpkgs/lib>", Value::from_path("<nixpkgs/lib>")),
+            ("https://nixos.org/?a=1", Value::Uri("https://nixos.org/?a=1".into())),
+        ] {
+            let parsed = Value::from_str(text).unwrap();
+            assert_eq!(parsed, value, "{}", text);
+            assert_eq!(parsed.to_string(), text);
+        }
+        assert!(matches!(Value::from_str("~/a"), Ok(Value::Path(Anchor::Home, ..))));
+    }
+
+    #[test]
+    fn indented_strings() {
+        for text in [
+            "''\n  a\n    b\n  ''",
+            "''  a\n b''",
+            "''\n\n  a\n   \n  ''$''' ''\\t\n''",
+            "''\ta\n  b''",
+            "''''",
+            "''\n    \n  ''",
+        ] {
+            let expected = eval_const(&Root::parse(text).ok().unwrap().expr().unwrap());
+            assert_eq!(Value::from_str(text).ok(), expected, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let error = |text: &str| Value::from_str(text).unwrap_err();
+        let range = |start: u32, end: u32| TextRange::new(start.into(), end.into());
+        assert_eq!(error(""), LiteralError::Invalid(range(0, 0)));
+        assert_eq!(error("x"), LiteralError::Invalid(range(0, 1)));
+        assert_eq!(error("a/"), LiteralError::Invalid(range(0, 2)));
+        assert_eq!(error("1e3"), LiteralError::Invalid(range(0, 3)));
+        assert_eq!(error("9223372036854775808"), LiteralError::IntegerOverflow(range(0, 19)));
+        assert!(Value::from_str("-9223372036854775808").is_ok());
+        assert_eq!(error(r#""a${b}""#), LiteralError::Interpolation(range(2, 4)));
+        assert_eq!(error("''a${b}''"), LiteralError::Interpolation(range(3, 5)));
+        assert_eq!(error(r#""abc"#), LiteralError::Unterminated(range(0, 4)));
+        assert_eq!(error("''abc"), LiteralError::Unterminated(range(0, 5)));
+        assert_eq!(error(r#""a" b"#), LiteralError::Invalid(range(3, 5)));
+        assert_eq!(error("''a''x"), LiteralError::Invalid(range(5, 6)));
+    }
+}
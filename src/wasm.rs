@@ -0,0 +1,49 @@
+//! Bindings for using the parser from JavaScript, built with
+//! `cargo rustc --lib --target wasm32-unknown-unknown --features wasm --crate-type cdylib`
+//! and `wasm-bindgen`
+
+use serde::Serialize;
+use serde_json::Value;
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    dump,
+    format::{self, FormatOptions},
+    Root,
+};
+
+fn to_js(value: &Value) -> JsValue {
+    value
+        .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+        .expect("JSON values can always be converted")
+}
+
+/// Parse a source text into a tree as printed by [`dump::json`]
+#[wasm_bindgen]
+pub fn parse(source: &str) -> JsValue {
+    let tree = dump::json(&Root::parse(source).syntax());
+    to_js(&serde_json::from_str(&tree).expect("the printed tree is valid JSON"))
+}
+
+/// Collect the parse errors and the findings of the builtin lints, as a list
+/// of `{ span, severity, source, message }`
+#[wasm_bindgen]
+pub fn diagnostics(source: &str) -> JsValue {
+    let diagnostics = dump::diagnostics_json(source);
+    to_js(&serde_json::from_str(&diagnostics).expect("the printed diagnostics are valid JSON"))
+}
+
+/// Format a source text, see [`format::format`]. The options are an object
+/// like `{ indentWidth: 2 }`, where all fields are optional. Throws if the
+/// text doesn't parse.
+#[wasm_bindgen]
+pub fn format(source: &str, options: JsValue) -> Result<String, JsValue> {
+    let mut format_options = FormatOptions::default();
+    if !options.is_undefined() && !options.is_null() {
+        let options: Value = serde_wasm_bindgen::from_value(options)?;
+        if let Some(width) = options.get("indentWidth").and_then(Value::as_u64) {
+            format_options.indent_width = width as usize;
+        }
+    }
+    format::format(source, &format_options).map_err(|err| JsValue::from_str(&err.to_string()))
+}
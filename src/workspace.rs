@@ -0,0 +1,483 @@
+//! A set of files analyzed together, for tools looking at a whole project
+//! rather than a single file.
+//!
+//! A [`Workspace`] owns the sources and trees of its files, and analyzes each
+//! of them on its own when it is added or updated: the files it imports, the
+//! attributes it defines and the attributes it selects. Queries across files,
+//! like [`Workspace::importers`] or [`Workspace::attr_uses`], combine these
+//! results.
+//!
+//! Results depending on other files, like [`Workspace::import_closure`], are
+//! cached. When files change on disk, [`Workspace::notify_change`] reparses
+//! only the changed file and drops only the cached results of the files
+//! importing it. With the `watch` feature, the events of a `notify` watcher
+//! can be passed to [`Workspace::apply_event`].
+
+#[cfg(feature = "watch")]
+mod watch;
+
+use std::{
+    cell::OnceCell,
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use rowan::{ast::AstNode, TextRange};
+
+use crate::{
+    analysis::{attr_index, attrpath_names, Resolution, Resolver},
+    ast,
+    rewrite::import::imported_path,
+    value::{normalize, PathRoots},
+    Parse, Root, SyntaxNode,
+};
+
+/// A call of `import` with a path literal
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Import {
+    /// The range of the whole call
+    pub range: TextRange,
+    /// The imported path. Directories are kept as they are, see
+    /// [`Workspace::import_target`] for the file imported from them.
+    pub target: PathBuf,
+}
+
+/// A place in a file where an attribute path is defined or used
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AttrSite {
+    pub file: PathBuf,
+    pub path: Vec<String>,
+    pub range: TextRange,
+}
+
+/// A file in a [`Workspace`] and the results of analyzing it on its own
+#[derive(Clone)]
+pub struct SourceFile {
+    source: String,
+    parse: Parse<Root>,
+    imports: Vec<Import>,
+    defined: BTreeMap<Vec<String>, Vec<TextRange>>,
+    selected: Vec<(Vec<String>, TextRange)>,
+    /// The files imported directly or indirectly, computed when first needed
+    closure: OnceCell<Vec<PathBuf>>,
+}
+
+impl SourceFile {
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn parse(&self) -> &Parse<Root> {
+        &self.parse
+    }
+
+    /// The imports of the file, in order
+    pub fn imports(&self) -> &[Import] {
+        &self.imports
+    }
+
+    /// The attributes of the set the file evaluates to, see
+    /// [`analysis::attr_index`](crate::analysis::attr_index)
+    pub fn defined(&self) -> &BTreeMap<Vec<String>, Vec<TextRange>> {
+        &self.defined
+    }
+}
+
+/// Files and the imports between them
+#[derive(Clone, Default)]
+pub struct Workspace {
+    files: BTreeMap<PathBuf, SourceFile>,
+    home_dir: Option<PathBuf>,
+    search_dir: Option<PathBuf>,
+}
+
+impl Workspace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the directory `~/` paths are relative to. Without it, imports of
+    /// `~/` paths are ignored. Only files added afterwards are affected.
+    pub fn set_home_dir(&mut self, dir: impl Into<PathBuf>) -> &mut Self {
+        self.home_dir = Some(dir.into());
+        self
+    }
+
+    /// Set the directory `<...>` paths are looked up in. Without it, imports
+    /// of them are ignored. Only files added afterwards are affected.
+    pub fn set_search_dir(&mut self, dir: impl Into<PathBuf>) -> &mut Self {
+        self.search_dir = Some(dir.into());
+        self
+    }
+
+    /// Add a file, replacing it if it is already in the workspace. Relative
+    /// paths in the file are relative to the directory of `path`, which
+    /// should be absolute.
+    pub fn add_file(&mut self, path: impl AsRef<Path>, source: impl Into<String>) {
+        let path = normalize(path.as_ref());
+        let file = self.analyze(&path, source.into());
+        self.files.insert(path.clone(), file);
+        self.invalidate(&path);
+    }
+
+    /// Read a file from the file system and add it
+    pub fn load_file(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let source = fs::read_to_string(path.as_ref())?;
+        self.add_file(path, source);
+        Ok(())
+    }
+
+    /// Replace the source of a file. Returns `false` and doesn't add the
+    /// file if it isn't in the workspace.
+    pub fn update_file(&mut self, path: impl AsRef<Path>, source: impl Into<String>) -> bool {
+        let path = normalize(path.as_ref());
+        if !self.files.contains_key(&path) {
+            return false;
+        }
+        let file = self.analyze(&path, source.into());
+        self.files.insert(path.clone(), file);
+        self.invalidate(&path);
+        true
+    }
+
+    /// Remove a file, returning whether it was in the workspace
+    pub fn remove_file(&mut self, path: impl AsRef<Path>) -> bool {
+        let path = normalize(path.as_ref());
+        let affected = self.transitive_importers(&path);
+        let removed = self.files.remove(&path).is_some();
+        for path in affected {
+            self.reset(&path);
+        }
+        removed
+    }
+
+    /// Update the workspace after a file changed on disk: reread it if it is
+    /// in the workspace or imported by a file in it, and remove it if it was
+    /// deleted. Returns the files whose results may have changed, which are
+    /// the file and all files importing it directly or indirectly, or nothing
+    /// if the file is unrelated to the workspace or its content is the same.
+    pub fn notify_change(&mut self, path: impl AsRef<Path>) -> Vec<PathBuf> {
+        let path = normalize(path.as_ref());
+        let known = self.files.contains_key(&path);
+        if !known && self.importers(&path).is_empty() {
+            return Vec::new();
+        }
+        let affected = match fs::read_to_string(&path) {
+            Ok(source) if self.files.get(&path).is_some_and(|file| file.source == source) => {
+                return Vec::new();
+            }
+            Ok(source) => {
+                let file = self.analyze(&path, source);
+                self.files.insert(path.clone(), file);
+                self.transitive_importers(&path)
+            }
+            Err(_) if known => {
+                let affected = self.transitive_importers(&path);
+                self.files.remove(&path);
+                affected
+            }
+            Err(_) => return Vec::new(),
+        };
+        for path in &affected {
+            self.reset(path);
+        }
+        affected.into_iter().collect()
+    }
+
+    pub fn file(&self, path: impl AsRef<Path>) -> Option<&SourceFile> {
+        self.files.get(&normalize(path.as_ref()))
+    }
+
+    /// Iterate over all files, sorted by path
+    pub fn files(&self) -> impl Iterator<Item = (&Path, &SourceFile)> {
+        self.files.iter().map(|(path, file)| (path.as_path(), file))
+    }
+
+    /// The file imported by an import: the `default.nix` of imported
+    /// directories, which are looked up in the workspace first and on the
+    /// file system otherwise
+    pub fn import_target(&self, import: &Import) -> PathBuf {
+        let default = import.target.join("default.nix");
+        if self.files.contains_key(&import.target) {
+            import.target.clone()
+        } else if self.files.contains_key(&default) || import.target.is_dir() {
+            default
+        } else {
+            import.target.clone()
+        }
+    }
+
+    /// The files in the workspace importing a file, sorted by path
+    pub fn importers(&self, path: impl AsRef<Path>) -> Vec<&Path> {
+        let path = normalize(path.as_ref());
+        self.files()
+            .filter(|(_, file)| {
+                file.imports.iter().any(|import| self.import_target(import) == path)
+            })
+            .map(|(path, _)| path)
+            .collect()
+    }
+
+    /// All files in the workspace imported by a file, directly or indirectly,
+    /// sorted by path. The result is cached until one of them changes.
+    pub fn import_closure(&self, path: impl AsRef<Path>) -> Vec<PathBuf> {
+        let path = normalize(path.as_ref());
+        let Some(file) = self.files.get(&path) else { return Vec::new() };
+        let closure = file.closure.get_or_init(|| {
+            let mut closure = BTreeSet::new();
+            let mut queue = vec![path.clone()];
+            while let Some(path) = queue.pop() {
+                let Some(file) = self.files.get(&path) else { continue };
+                for import in &file.imports {
+                    let target = self.import_target(import);
+                    if self.files.contains_key(&target) && closure.insert(target.clone()) {
+                        queue.push(target);
+                    }
+                }
+            }
+            closure.into_iter().collect()
+        });
+        closure.clone()
+    }
+
+    /// All places defining an attribute path, in files sorted by path. See
+    /// [`SourceFile::defined`] for which attributes a file defines.
+    pub fn attr_definitions(&self, attr: &[String]) -> Vec<AttrSite> {
+        let mut sites = Vec::new();
+        for (path, file) in self.files() {
+            for &range in file.defined.get(attr).into_iter().flatten() {
+                sites.push(AttrSite { file: path.to_path_buf(), path: attr.to_vec(), range });
+            }
+        }
+        sites
+    }
+
+    /// All places which may use an attribute path, in files sorted by path.
+    ///
+    /// Without evaluating, the set an attribute is selected from can't be
+    /// known, so these are all selections starting with the path, like
+    /// `pkgs.hello.override` for `["hello"]`, `inherit (pkgs) hello;` and
+    /// `hello` in `with pkgs;` where it isn't bound otherwise.
+    pub fn attr_uses(&self, attr: &[String]) -> Vec<AttrSite> {
+        let mut sites = Vec::new();
+        for (path, file) in self.files() {
+            for (selected, range) in &file.selected {
+                if selected.starts_with(attr) {
+                    let (file, path) = (path.to_path_buf(), selected.clone());
+                    sites.push(AttrSite { file, path, range: *range });
+                }
+            }
+        }
+        sites
+    }
+
+    /// A file and all files importing it directly or indirectly
+    fn transitive_importers(&self, path: &Path) -> BTreeSet<PathBuf> {
+        let mut affected = BTreeSet::from([path.to_path_buf()]);
+        let mut queue = vec![path.to_path_buf()];
+        while let Some(path) = queue.pop() {
+            for importer in self.importers(&path) {
+                if affected.insert(importer.to_path_buf()) {
+                    queue.push(importer.to_path_buf());
+                }
+            }
+        }
+        affected
+    }
+
+    /// Drop the cached results of a file and the files importing it
+    fn invalidate(&mut self, path: &Path) {
+        for path in self.transitive_importers(path) {
+            self.reset(&path);
+        }
+    }
+
+    fn reset(&mut self, path: &Path) {
+        if let Some(file) = self.files.get_mut(path) {
+            file.closure.take();
+        }
+    }
+
+    fn analyze(&self, path: &Path, source: String) -> SourceFile {
+        let parse = Root::parse(&source);
+        let root = parse.syntax();
+        let resolver = Resolver::new(&root);
+        let base_dir = path.parent().unwrap_or(Path::new("/"));
+
+        // Without a home directory `~/` paths resolve to relative ones, which
+        // are left out
+        let home_dir = self.home_dir.as_deref().unwrap_or(Path::new(""));
+        let imports = self.imports(&resolver, &root, base_dir, home_dir);
+        let defined = attr_index(&parse.tree())
+            .into_iter()
+            .map(|(path, sites)| (path, sites.iter().map(|site| site.range).collect()))
+            .collect();
+        let selected = selections(&resolver, &root);
+        SourceFile { source, parse, imports, defined, selected, closure: OnceCell::new() }
+    }
+
+    fn imports(
+        &self,
+        resolver: &Resolver,
+        root: &SyntaxNode,
+        base_dir: &Path,
+        home_dir: &Path,
+    ) -> Vec<Import> {
+        let roots = PathRoots { base_dir, home_dir, search_dir: self.search_dir.as_deref() };
+        root.descendants()
+            .filter_map(ast::Apply::cast)
+            .filter_map(|apply| {
+                let target = imported_path(resolver, &apply, &roots).ok()?;
+                if !target.is_absolute() {
+                    return None;
+                }
+                Some(Import { range: apply.syntax().text_range(), target })
+            })
+            .collect()
+    }
+}
+
+/// The attribute paths selected in a file and their ranges
+fn selections(resolver: &Resolver, root: &SyntaxNode) -> Vec<(Vec<String>, TextRange)> {
+    let mut selected = Vec::new();
+    for node in root.descendants() {
+        if let Some(select) = ast::Select::cast(node.clone()) {
+            let Some(attrpath) = select.attrpath() else { continue };
+            if let Some(names) = attrpath_names(&attrpath) {
+                selected.push((names, attrpath.syntax().text_range()));
+            }
+        } else if let Some(inherit) = ast::Inherit::cast(node.clone()) {
+            if inherit.from().is_none() {
+                continue;
+            }
+            for attr in inherit.attrs() {
+                if let Some(name) = attr.static_name() {
+                    selected.push((vec![name], attr.syntax().text_range()));
+                }
+            }
+        } else if let Some(ident) = ast::Ident::cast(node) {
+            if let Some(Resolution::With(_)) = resolver.resolve(&ident) {
+                selected.push((vec![ident.to_string()], ident.syntax().text_range()));
+            }
+        }
+    }
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(paths: Vec<&Path>) -> Vec<String> {
+        paths.iter().map(|path| path.display().to_string()).collect()
+    }
+
+    #[test]
+    fn imports() {
+        let mut workspace = Workspace::new();
+        workspace.add_file("/p/default.nix", "{ a = import ./a.nix; b = import ./lib { }; }");
+        workspace.add_file("/p/lib/default.nix", "{ }: import ../a.nix");
+        workspace.add_file("/p/a.nix", "<nixpkgs>");
+        let targets: Vec<PathBuf> = workspace
+            .file("/p/default.nix")
+            .unwrap()
+            .imports()
+            .iter()
+            .map(|import| workspace.import_target(import))
+            .collect();
+        assert_eq!(targets, [PathBuf::from("/p/a.nix"), PathBuf::from("/p/lib/default.nix")]);
+        assert_eq!(
+            names(workspace.importers("/p/lib/../a.nix")),
+            ["/p/default.nix", "/p/lib/default.nix"]
+        );
+
+        assert!(workspace.update_file("/p/lib/default.nix", "{ }: { }"));
+        assert!(!workspace.update_file("/p/b.nix", "1"));
+        assert_eq!(names(workspace.importers("/p/a.nix")), ["/p/default.nix"]);
+        assert!(workspace.remove_file("/p/default.nix"));
+        assert!(workspace.importers("/p/a.nix").is_empty());
+    }
+
+    #[test]
+    fn changes() {
+        let dir = std::env::temp_dir().join(format!("rnix-workspace-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        let file = |name: &str| dir.join(name);
+        fs::write(file("default.nix"), "import ./lib").unwrap();
+        fs::write(file("lib/default.nix"), "import ../a.nix").unwrap();
+        fs::write(file("a.nix"), "1").unwrap();
+        fs::write(file("b.nix"), "2").unwrap();
+
+        let mut workspace = Workspace::new();
+        for name in ["default.nix", "lib/default.nix", "b.nix"] {
+            workspace.load_file(file(name)).unwrap();
+        }
+        assert_eq!(workspace.import_closure(file("default.nix")), [file("lib/default.nix")]);
+        // Imported files are picked up, unrelated ones and same contents ignored
+        assert_eq!(
+            workspace.notify_change(file("a.nix")),
+            [file("a.nix"), file("default.nix"), file("lib/default.nix")]
+        );
+        assert_eq!(
+            workspace.import_closure(file("default.nix")),
+            [file("a.nix"), file("lib/default.nix")]
+        );
+        assert_eq!(workspace.notify_change(file("a.nix")), Vec::<PathBuf>::new());
+        assert_eq!(workspace.notify_change(file("c.nix")), Vec::<PathBuf>::new());
+        fs::write(file("b.nix"), "3").unwrap();
+        assert_eq!(workspace.notify_change(file("b.nix")), [file("b.nix")]);
+        assert_eq!(workspace.file(file("b.nix")).unwrap().source(), "3");
+
+        fs::write(file("lib/default.nix"), "{ }").unwrap();
+        assert_eq!(
+            workspace.notify_change(file("lib/default.nix")),
+            [file("default.nix"), file("lib/default.nix")]
+        );
+        assert_eq!(workspace.import_closure(file("default.nix")), [file("lib/default.nix")]);
+        fs::remove_file(file("lib/default.nix")).unwrap();
+        assert_eq!(
+            workspace.notify_change(file("lib/default.nix")),
+            [file("default.nix"), file("lib/default.nix")]
+        );
+        assert!(workspace.file(file("lib/default.nix")).is_none());
+        assert_eq!(workspace.import_closure(file("default.nix")), Vec::<PathBuf>::new());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn attrs() {
+        let mut workspace = Workspace::new();
+        workspace
+            .add_file("/p/overlays/hello.nix", "final: prev: { hello = prev.hello.override { }; }");
+        workspace.add_file("/p/shell.nix", "{ pkgs }: with pkgs; [ hello pkgs.hello.out ]");
+        workspace.add_file("/p/module.nix", "{ pkgs, ... }: { inherit (pkgs) hello; }");
+        let sites = |sites: Vec<AttrSite>| -> Vec<(String, String)> {
+            sites
+                .into_iter()
+                .map(|site| {
+                    let source = workspace.file(&site.file).unwrap().source();
+                    (site.file.display().to_string(), source[site.range].to_string())
+                })
+                .collect()
+        };
+        let hello = ["hello".to_string()];
+        assert_eq!(
+            sites(workspace.attr_definitions(&hello)),
+            [
+                ("/p/module.nix".into(), "hello".into()),
+                ("/p/overlays/hello.nix".into(), "hello".into())
+            ]
+        );
+        assert_eq!(
+            sites(workspace.attr_uses(&hello)),
+            [
+                ("/p/module.nix".into(), "hello".into()),
+                ("/p/overlays/hello.nix".into(), "hello.override".into()),
+                ("/p/shell.nix".into(), "hello".into()),
+                ("/p/shell.nix".into(), "hello.out".into()),
+            ]
+        );
+    }
+}
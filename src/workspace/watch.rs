@@ -0,0 +1,63 @@
+use std::{collections::BTreeSet, path::PathBuf};
+
+use notify::{Event, EventKind};
+
+use super::Workspace;
+
+impl Workspace {
+    /// Update the workspace after an event of a `notify` watcher, see
+    /// [`Workspace::notify_change`]. Returns the files whose results may have
+    /// changed for any of the paths of the event.
+    ///
+    /// Only the files in the workspace and the files they import are reread,
+    /// so it is fine to watch whole directories recursively.
+    pub fn apply_event(&mut self, event: &Event) -> Vec<PathBuf> {
+        if matches!(event.kind, EventKind::Access(_)) {
+            return Vec::new();
+        }
+        let mut affected = BTreeSet::new();
+        for path in &event.paths {
+            affected.extend(self.notify_change(path));
+        }
+        affected.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use notify::event::{AccessKind, CreateKind, ModifyKind, RemoveKind, RenameMode};
+
+    use super::*;
+
+    #[test]
+    fn events() {
+        let dir = std::env::temp_dir().join(format!("rnix-watch-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let (main, old, new) = (dir.join("main.nix"), dir.join("old.nix"), dir.join("new.nix"));
+        std::fs::write(&main, "import ./new.nix").unwrap();
+        std::fs::write(&old, "1").unwrap();
+
+        let mut workspace = Workspace::new();
+        workspace.load_file(&main).unwrap();
+        let event = |kind| Event::new(kind).add_path(old.clone()).add_path(new.clone());
+        std::fs::rename(&old, &new).unwrap();
+        let rename = event(EventKind::Modify(ModifyKind::Name(RenameMode::Both)));
+        assert_eq!(workspace.apply_event(&rename), [main.clone(), new.clone()]);
+        assert!(workspace.file(&new).is_some());
+        assert_eq!(
+            workspace.apply_event(&event(EventKind::Access(AccessKind::Read))),
+            Vec::<PathBuf>::new()
+        );
+        std::fs::remove_file(&new).unwrap();
+        assert_eq!(
+            workspace.apply_event(&event(EventKind::Remove(RemoveKind::File))),
+            [main.clone(), new.clone()]
+        );
+        assert_eq!(
+            workspace.apply_event(&event(EventKind::Create(CreateKind::File))),
+            Vec::<PathBuf>::new()
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1 @@
+[ 9223372036854775807 (-9223372036854775808) 99999999999999999999 ]
@@ -0,0 +1 @@
+{ a, b; c, d ? 1, e }: a + e
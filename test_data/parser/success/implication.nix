@@ -0,0 +1,3 @@
+assert a -> b -> c -> d;
+assert a || b -> c && d -> !e -> f;
+x
@@ -0,0 +1,5 @@
+[
+  (a.b or c.d.e)
+  (x.y or z.w or q)
+  (a.b or c).d
+]
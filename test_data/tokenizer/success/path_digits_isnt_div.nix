@@ -0,0 +1 @@
+6/3
\ No newline at end of file